@@ -1,9 +1,8 @@
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, State};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::os::unix::fs::MetadataExt;
@@ -11,9 +10,10 @@ use std::os::unix::fs::MetadataExt;
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::MetadataExt;
 
-use crate::rufendec::{
-    operations::{encrypt_files, decrypt_files, pre_validate_source, recurse_dirs, create_dirs, DIR_LIST, FILE_LIST, FILES_SIZE_BYTES, SUCCESS_COUNT, FAILED_COUNT, VERBOSE, APP_HANDLE, Mode, Operation, HashMode, ECB_32BYTE_KEY, GCM_32BYTE_KEY},
-    secrets::{clear_keys, verify_keys_cleared},
+use rufendec::{
+    operations::{pre_validate_source, scan},
+    report::{FileResult, RunReport},
+    Builder, CancellationToken, Decryptor, Encryptor, HashMode, Mode, Observer, Operation,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,19 +37,21 @@ pub struct EncryptionOptions {
 pub struct EncryptionResult {
     pub success: bool,
     pub message: String,
-    pub success_count: u16,
-    pub failed_count: u16,
+    pub success_count: u64,
+    pub failed_count: u64,
+    pub cancelled: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProgressUpdate {
     pub current: u64,
     pub total: u64,
+    pub bytes_processed: u64,
+    pub total_bytes: u64,
     pub percentage: f64,
     pub message: String,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VerboseMessage {
     pub message: String,
@@ -65,398 +67,313 @@ struct OperationalInfo {
     operating_system: String,
 }
 
-// Progress tracking function that runs in a separate thread
-fn track_progress(app_handle: AppHandle, total_files: usize, stop_flag: Arc<AtomicBool>) {
-    std::thread::spawn(move || {
-        while !stop_flag.load(Ordering::Relaxed) {
-            let current = *SUCCESS_COUNT.lock().unwrap() as u64 + *FAILED_COUNT.lock().unwrap() as u64;
-            let total = total_files as u64;
-            let percentage = if total > 0 {
-                (current as f64 / total as f64) * 100.0
-            } else {
-                0.0
-            };
-
-            let progress = ProgressUpdate {
-                current,
-                total,
-                percentage,
-                message: format!("Processing file {} of {}", current, total),
-            };
-
-            let _ = app_handle.emit("progress-update", &progress);
-
-            std::thread::sleep(Duration::from_millis(100)); // Update every 100ms
+// The cancellation token of the job which is running, so the cancel_operation command can stop it
+#[derive(Default)]
+pub struct ActiveJob(Mutex<Option<CancellationToken>>);
+
+impl ActiveJob {
+    fn start(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.0.lock().unwrap() = Some(token.clone());
+        token
+    }
+
+    fn finish(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+
+    fn cancel(&self) -> bool {
+        match self.0.lock().unwrap().as_ref() {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
         }
-    });
+    }
 }
 
-#[tauri::command]
-pub async fn encrypt_directory(app_handle: AppHandle, options: EncryptionOptions) -> Result<EncryptionResult, String> {
-    let source_path = PathBuf::from(&options.source_path);
-    
-    // Validate source
-    let operation = match options.operation.as_str() {
-        "encrypt" => Operation::Encrypt,
-        "decrypt" => Operation::Decrypt,
-        _ => return Err("Invalid operation".to_string()),
+fn parse_operation(operation: &str) -> Result<Operation, String> {
+    match operation {
+        "encrypt" => Ok(Operation::Encrypt),
+        "decrypt" => Ok(Operation::Decrypt),
+        _ => Err("Invalid operation".to_string()),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<Mode, String> {
+    match mode {
+        "ecb" => Ok(Mode::ECB),
+        "gcm" => Ok(Mode::GCM),
+        _ => Err("Invalid mode".to_string()),
+    }
+}
+
+fn parse_hash_mode(hash_with: &str) -> HashMode {
+    match hash_with {
+        "pbkdf2" => HashMode::PBKDF2,
+        _ => HashMode::Argon2,
+    }
+}
+
+// Helper function to emit verbose messages
+fn emit_verbose(app_handle: &AppHandle, message: &str, level: &str) {
+    let verbose_msg = VerboseMessage {
+        message: message.to_string(),
+        level: level.to_string(),
     };
-    
-    // Set AppHandle for logger macro to emit events BEFORE validation
-    // This ensures validation errors appear in the UI
-    *APP_HANDLE.lock().unwrap() = Some(app_handle.clone());
-    
-    // Set verbose mode based on user preference (don't force it for validation)
-    *VERBOSE.write().unwrap() = options.verbose;
-    
-    // Validate source and handle errors
-    match pre_validate_source(&source_path, &operation) {
-        Ok(_) => {},
-        Err(e) => {
-            // Emit error to verbose log - always show errors even if verbose is disabled
-            use serde::{Serialize, Deserialize};
-            #[derive(Serialize, Deserialize)]
-            struct VerboseMsg {
-                message: String,
-                level: String,
-            }
-            let error_msg = VerboseMsg {
-                message: e.clone(),
-                level: "error".to_string(),
-            };
-            let _ = app_handle.emit("verbose-message", &error_msg);
-            let _ = app_handle.emit("show-verbose-container", &());
-            // Clear AppHandle
-            *APP_HANDLE.lock().unwrap() = None;
-            return Err(e);
+    let _ = app_handle.emit("verbose-message", &verbose_msg);
+}
+
+// Helper function to emit verbose error messages
+fn emit_verbose_error(app_handle: &AppHandle, message: &str) {
+    emit_verbose(app_handle, message, "error");
+}
+
+// The options shared by the Encryptor and the Decryptor
+fn configure<T>(builder: Builder<T>, options: &EncryptionOptions, source_dir: &str, target_dir: &str, threads: usize, cancel: CancellationToken) -> Builder<T> {
+    builder
+        .source_dir(source_dir)
+        .target_dir(target_dir)
+        .password(options.password.as_str(), options.salt.as_str())
+        .hash_with(parse_hash_mode(&options.hash_with))
+        .iterations(options.iterations)
+        .threads(threads)
+        .delete_src(options.delete_src)
+        .anon(options.anon)
+        .dry_run(options.dry_run)
+        .cancellation(cancel)
+}
+
+/* Emits the progress of a job as Tauri events. The percentage follows the bytes, so large files move the bar as much as they take time.
+Successes are verbose messages when verbose is enabled, and failures are always shown.
+*/
+struct TauriObserver {
+    app_handle: AppHandle,
+    verbose: bool,
+    total_files: AtomicU64,
+    total_bytes: AtomicU64,
+    done_files: AtomicU64,
+    done_bytes: AtomicU64,
+}
+
+impl TauriObserver {
+    fn new(app_handle: AppHandle, verbose: bool) -> Self {
+        TauriObserver {
+            app_handle,
+            verbose,
+            total_files: AtomicU64::new(0),
+            total_bytes: AtomicU64::new(0),
+            done_files: AtomicU64::new(0),
+            done_bytes: AtomicU64::new(0),
         }
     }
-    
-    // Clear previous state
-    DIR_LIST.lock().unwrap().clear();
-    FILE_LIST.lock().unwrap().clear();
-    *FILES_SIZE_BYTES.lock().unwrap() = 0;
-    *SUCCESS_COUNT.lock().unwrap() = 0;
-    *FAILED_COUNT.lock().unwrap() = 0;
-    
-    // Clear any existing keys from previous operations
-    clear_keys();
-    
-    // Add source directory to DIR_LIST
-    DIR_LIST.lock().unwrap().push(source_path.clone());
-    
-    // Recurse directories and collect files
-    recurse_dirs(&source_path);
-    
-    let total_files = FILE_LIST.lock().unwrap().len();
-    let stop_flag = Arc::new(AtomicBool::new(false));
-    
-    // Generate keys first (before starting progress tracking)
-    let mode = match options.mode.as_str() {
-        "ecb" => Mode::ECB,
-        "gcm" => Mode::GCM,
-        _ => return Err("Invalid mode".to_string()),
+
+    fn emit_progress(&self) {
+        let current = self.done_files.load(Ordering::Relaxed);
+        let total = self.total_files.load(Ordering::Relaxed);
+        let bytes_processed = self.done_bytes.load(Ordering::Relaxed);
+        let total_bytes = self.total_bytes.load(Ordering::Relaxed);
+
+        let percentage = if total_bytes > 0 {
+            (bytes_processed as f64 / total_bytes as f64) * 100.0
+        } else if total > 0 {
+            (current as f64 / total as f64) * 100.0
+        } else {
+            100.0
+        };
+
+        let progress = ProgressUpdate {
+            current,
+            total,
+            bytes_processed,
+            total_bytes,
+            percentage,
+            message: format!("Processing file {} of {}", current, total),
+        };
+        let _ = self.app_handle.emit("progress-update", &progress);
+    }
+}
+
+impl Observer for TauriObserver {
+    fn run_started(&self, total_files: u64, total_bytes: u64) {
+        self.total_files.store(total_files, Ordering::Relaxed);
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.emit_progress();
+    }
+
+    fn file_started(&self, path: &Path, _bytes: u64) {
+        if self.verbose {
+            emit_verbose(&self.app_handle, &format!("Processing {:?}", path), "info");
+        }
+    }
+
+    fn bytes_processed(&self, _path: &Path, bytes: u64) {
+        self.done_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.emit_progress();
+    }
+
+    fn file_finished(&self, result: &FileResult) {
+        self.done_files.fetch_add(1, Ordering::Relaxed);
+
+        if self.verbose {
+            let target = result.target.as_deref().unwrap_or(&result.source);
+            emit_verbose(&self.app_handle, &format!("{:?} => {:?}", result.source, target), "info");
+        }
+    }
+
+    fn file_failed(&self, result: &FileResult) {
+        self.done_files.fetch_add(1, Ordering::Relaxed);
+        emit_verbose_error(&self.app_handle, &format!("{:?} :: {}", result.source, result.cause.as_deref().unwrap_or_default()));
+    }
+
+    fn run_finished(&self, _report: &RunReport) {
+        self.emit_progress();
+    }
+}
+
+/* Runs the Encryptor or the Decryptor over the files. The key is zeroized once the job is dropped.
+The job is registered as the active job while it runs, so it can be cancelled
+*/
+#[allow(clippy::too_many_arguments)]
+fn run_job(
+    app_handle: &AppHandle,
+    active_job: &ActiveJob,
+    options: &EncryptionOptions,
+    source_dir: &str,
+    target_dir: &str,
+    threads: usize,
+    dirs: &[PathBuf],
+    files: &[PathBuf],
+) -> Result<RunReport, String> {
+    let operation = parse_operation(&options.operation)?;
+    let mode = parse_mode(&options.mode)?;
+    let observer = TauriObserver::new(app_handle.clone(), options.verbose);
+    let cancel = active_job.start();
+
+    let report = match operation {
+        Operation::Encrypt => configure(Encryptor::builder(), options, source_dir, target_dir, threads, cancel)
+            .mode(mode)
+            .observer(observer)
+            .build()
+            .and_then(|encryptor| {
+                encryptor.create_target_dirs(dirs);
+                encryptor.encrypt_files(files)
+            }),
+        Operation::Decrypt => configure(Decryptor::builder(), options, source_dir, target_dir, threads, cancel)
+            .mode(mode)
+            .observer(observer)
+            .build()
+            .and_then(|decryptor| {
+                decryptor.create_target_dirs(dirs);
+                decryptor.decrypt_files(files)
+            }),
     };
+
+    active_job.finish();
+
+    report.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn encrypt_directory(app_handle: AppHandle, active_job: State<'_, ActiveJob>, options: EncryptionOptions) -> Result<EncryptionResult, String> {
+    let source_path = PathBuf::from(&options.source_path);
     
-    // Generate keys and handle errors
-    match generate_keys_from_password(&options.password, &options.salt, mode, options.hash_with.as_str(), options.iterations) {
-        Ok(_) => {},
-        Err(e) => {
-            // Emit verbose error message
-            emit_verbose_error(&app_handle, &e);
-            // Clear keys on error
-            clear_keys();
-            // Clear AppHandle
-            *APP_HANDLE.lock().unwrap() = None;
-            return Err(e);
-        }
+    // Validate source
+    let operation = parse_operation(&options.operation)?;
+    
+    // Validate source and handle errors - always show errors even if verbose is disabled
+    if let Err(e) = pre_validate_source(&source_path, &operation, false) {
+        let e = e.to_string();
+        emit_verbose_error(&app_handle, &e);
+        let _ = app_handle.emit("show-verbose-container", &());
+        return Err(e);
     }
     
-    // Start progress tracking after successful key generation
-    track_progress(app_handle.clone(), total_files, stop_flag.clone());
+    // Recurse directories and collect files
+    let tree = scan(&source_path);
     
     let target_dir = options.target_path.as_ref().unwrap_or(&options.source_path);
     
-    // Create directories
-    if !options.dry_run {
-        create_dirs(
-            DIR_LIST.lock().unwrap().to_vec(),
-            &options.source_path,
-            target_dir,
-        );
-    }
-    
     // Perform encryption/decryption
-    match operation {
-        Operation::Encrypt => {
-            encrypt_files(
-                FILE_LIST.lock().unwrap().to_vec(),
-                options.threads,
-                &options.source_path,
-                target_dir,
-                mode,
-                options.delete_src,
-                &None, // shred options
-                options.anon,
-                options.dry_run,
-            );
-        }
-        Operation::Decrypt => {
-            decrypt_files(
-                FILE_LIST.lock().unwrap().to_vec(),
-                options.threads,
-                &options.source_path,
-                target_dir,
-                mode,
-                options.delete_src,
-                &None, // shred options
-                options.anon,
-                options.dry_run,
-            );
-        }
-    }
-    
-    // Clear keys
-    clear_keys();
-    verify_keys_cleared(mode);
+    let report = run_job(&app_handle, &active_job, &options, &options.source_path, target_dir, options.threads, &tree.dirs, &tree.files)
+        .inspect_err(|e| emit_verbose_error(&app_handle, e))?;
     
-    let success_count = *SUCCESS_COUNT.lock().unwrap();
-    let failed_count = *FAILED_COUNT.lock().unwrap();
+    let success_count = report.success_count;
+    let failed_count = report.failed_count;
     
     Ok(EncryptionResult {
-        success: failed_count == 0,
-        message: if failed_count == 0 {
+        success: failed_count == 0 && !report.cancelled,
+        message: if report.cancelled {
+            format!("Cancelled after {} files. {} files were skipped", success_count, report.skipped_count)
+        } else if failed_count == 0 {
             format!("Successfully {}ed {} files", options.operation, success_count)
         } else {
             format!("Completed with {} successes and {} failures", success_count, failed_count)
         },
         success_count,
         failed_count,
+        cancelled: report.cancelled,
     })
 }
 
 #[tauri::command]
-pub async fn encrypt_file(app_handle: AppHandle, options: EncryptionOptions) -> Result<EncryptionResult, String> {
+pub async fn encrypt_file(app_handle: AppHandle, active_job: State<'_, ActiveJob>, options: EncryptionOptions) -> Result<EncryptionResult, String> {
     let source_file = PathBuf::from(&options.source_path);
     
     if !source_file.exists() {
         return Err("Source file does not exist".to_string());
     }
     
-    *VERBOSE.write().unwrap() = options.verbose;
-    
-    // Set AppHandle for logger macro to emit events
-    *APP_HANDLE.lock().unwrap() = Some(app_handle.clone());
-    
-    // Clear previous state
-    DIR_LIST.lock().unwrap().clear();
-    FILE_LIST.lock().unwrap().clear();
-    *FILES_SIZE_BYTES.lock().unwrap() = 0;
-    *SUCCESS_COUNT.lock().unwrap() = 0;
-    *FAILED_COUNT.lock().unwrap() = 0;
-    
-    // Clear any existing keys from previous operations
-    clear_keys();
-    
-    let mode = match options.mode.as_str() {
-        "ecb" => Mode::ECB,
-        "gcm" => Mode::GCM,
-        _ => return Err("Invalid mode".to_string()),
-    };
-    
-    // Generate keys and handle errors
-    match generate_keys_from_password(&options.password, &options.salt, mode, options.hash_with.as_str(), options.iterations) {
-        Ok(_) => {},
-        Err(e) => {
-            // Emit verbose error message
-            emit_verbose_error(&app_handle, &e);
-            // Clear keys on error
-            clear_keys();
-            // Clear AppHandle
-            *APP_HANDLE.lock().unwrap() = None;
-            return Err(e);
-        }
-    }
-    
     let source_dir = source_file.parent().unwrap().to_str().unwrap();
-    let target_dir = options.target_path.as_ref().map(|s| s.as_str()).unwrap_or(source_dir);
-    
-    DIR_LIST.lock().unwrap().push(PathBuf::from(target_dir));
-    FILE_LIST.lock().unwrap().push(source_file.clone());
-    
-    let total_files = 1;
-    let stop_flag = Arc::new(AtomicBool::new(false));
-    
-    // Start progress tracking after successful key generation
-    track_progress(app_handle.clone(), total_files, stop_flag.clone());
-    
-    let operation = match options.operation.as_str() {
-        "encrypt" => Operation::Encrypt,
-        "decrypt" => Operation::Decrypt,
-        _ => return Err("Invalid operation".to_string()),
-    };
-    
-    // Create directories if needed
-    if !options.dry_run {
-        create_dirs(
-            DIR_LIST.lock().unwrap().to_vec(),
-            source_dir,
-            target_dir,
-        );
-    }
-    
-    // Perform encryption/decryption
-    match operation {
-        Operation::Encrypt => {
-            encrypt_files(
-                FILE_LIST.lock().unwrap().to_vec(),
-                1, // Single file, single thread
-                source_dir,
-                target_dir,
-                mode,
-                options.delete_src,
-                &None,
-                options.anon,
-                options.dry_run,
-            );
-        }
-        Operation::Decrypt => {
-            decrypt_files(
-                FILE_LIST.lock().unwrap().to_vec(),
-                1, // Single file, single thread
-                source_dir,
-                target_dir,
-                mode,
-                options.delete_src,
-                &None,
-                options.anon,
-                options.dry_run,
-            );
-        }
-    }
-    
-    // Stop progress tracking
-    stop_flag.store(true, Ordering::Relaxed);
-    
-    // Clear AppHandle
-    *APP_HANDLE.lock().unwrap() = None;
-    
-    // Clear keys
-    clear_keys();
-    verify_keys_cleared(mode);
-    
-    let success_count = *SUCCESS_COUNT.lock().unwrap();
-    let failed_count = *FAILED_COUNT.lock().unwrap();
+    let target_dir = options.target_path.as_deref().unwrap_or(source_dir);
+    
+    // Perform encryption/decryption. Single file, single thread
+    let report = run_job(
+        &app_handle,
+        &active_job,
+        &options,
+        source_dir,
+        target_dir,
+        1,
+        &[PathBuf::from(source_dir)],
+        std::slice::from_ref(&source_file),
+    )
+    .inspect_err(|e| emit_verbose_error(&app_handle, e))?;
+    
+    let success_count = report.success_count;
+    let failed_count = report.failed_count;
     
     // Emit final progress update
     let final_progress = ProgressUpdate {
-        current: (success_count + failed_count) as u64,
-        total: total_files as u64,
+        current: success_count + failed_count,
+        total: 1,
+        bytes_processed: report.bytes_processed,
+        total_bytes: report.bytes_processed,
         percentage: 100.0,
         message: format!("Completed: {} successful, {} failed", success_count, failed_count),
     };
     let _ = app_handle.emit("progress-update", &final_progress);
     
     Ok(EncryptionResult {
-        success: failed_count == 0,
-        message: if failed_count == 0 {
+        success: failed_count == 0 && !report.cancelled,
+        message: if report.cancelled {
+            "Operation cancelled".to_string()
+        } else if failed_count == 0 {
             format!("Successfully {}ed file", options.operation)
         } else {
             "Operation failed".to_string()
         },
         success_count,
         failed_count,
+        cancelled: report.cancelled,
     })
 }
 
-// Helper function to emit verbose error messages
-fn emit_verbose_error(app_handle: &AppHandle, message: &str) {
-    use serde::{Serialize, Deserialize};
-    #[derive(Serialize, Deserialize)]
-    struct VerboseMsg {
-        message: String,
-        level: String,
-    }
-    let verbose_msg = VerboseMsg {
-        message: message.to_string(),
-        level: "error".to_string(),
-    };
-    let _ = app_handle.emit("verbose-message", &verbose_msg);
-}
-
-// Helper function to generate keys from password/salt directly
-fn generate_keys_from_password(password: &str, salt: &str, mode: Mode, hash_with: &str, iterations: u32) -> Result<(), String> {
-    use aes_gcm::Aes256Gcm;
-    use aes_gcm::Key;
-    use pbkdf2::pbkdf2_hmac_array;
-    use sha2::Sha256;
-    use zeroize::Zeroize;
-    
-    let hash_mode = match hash_with {
-        "argon2" => HashMode::Argon2,
-        "pbkdf2" => HashMode::PBKDF2,
-        _ => HashMode::Argon2,
-    };
-    
-    let mut key = match hash_mode {
-        HashMode::Argon2 => {
-            let mut argon2_param_builder = argon2::ParamsBuilder::new();
-            let argon2_struct = match argon2_param_builder.t_cost(iterations).p_cost(4).build() {
-                Ok(o) => argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, o),
-                Err(e) => {
-                    return Err(format!("Failed to build Argon2 parameters: {}", e));
-                }
-            };
-            let mut key = [0u8; 32];
-            match argon2_struct.hash_password_into(
-                password.as_bytes(),
-                salt.as_bytes(),
-                &mut key,
-            ) {
-                Ok(_) => key,
-                Err(e) => {
-                    return Err(format!("Failed to generate key with Argon2ID: {}. Salt must be at least 8 bytes long.", e));
-                }
-            }
-        },
-        HashMode::PBKDF2 => {
-            pbkdf2_hmac_array::<Sha256, 32>(
-                password.as_bytes(),
-                salt.as_bytes(),
-                iterations,
-            )
-        },
-    };
-    
-    let key_gen = Key::<Aes256Gcm>::clone_from_slice(key.as_slice());
-    
-    // Clear any existing keys before setting new ones
-    match mode {
-        Mode::ECB => {
-            let mut ecb_keys = ECB_32BYTE_KEY.write().unwrap();
-            // Zeroize all existing keys before clearing
-            for existing_key in ecb_keys.iter_mut() {
-                existing_key.zeroize();
-            }
-            ecb_keys.clear();
-            ecb_keys.push(key_gen);
-        }
-        Mode::GCM => {
-            let mut gcm_keys = GCM_32BYTE_KEY.write().unwrap();
-            // Zeroize all existing keys before clearing
-            for existing_key in gcm_keys.iter_mut() {
-                existing_key.zeroize();
-            }
-            gcm_keys.clear();
-            gcm_keys.push(key_gen);
-        }
-    }
-    
-    key.zeroize();
-    // Note: key_gen is now owned by the vector, so we don't zeroize it here
-    // It will be zeroized when clear_keys() is called
-    
-    Ok(())
+// Cancels the running job. Returns false when no job is running
+#[tauri::command]
+pub async fn cancel_operation(active_job: State<'_, ActiveJob>) -> Result<bool, String> {
+    Ok(active_job.cancel())
 }
 
 #[tauri::command]
@@ -470,19 +387,13 @@ pub async fn scan_operational_info(app_handle: AppHandle, source_path: String, i
         return Err("Source path does not exist".to_string());
     }
     
-    // Clear previous state
-    DIR_LIST.lock().unwrap().clear();
-    FILE_LIST.lock().unwrap().clear();
-    *FILES_SIZE_BYTES.lock().unwrap() = 0;
-    
     if is_directory {
-        // Same logic as encrypt_directory - add source and recurse
-        DIR_LIST.lock().unwrap().push(path.clone());
-        recurse_dirs(&path);
+        // Same logic as encrypt_directory
+        let tree = scan(&path);
         
-        let file_count = FILE_LIST.lock().unwrap().len();
-        let folder_count = DIR_LIST.lock().unwrap().len().saturating_sub(1); // Subtract 1 for source directory
-        let total_size_bytes = *FILES_SIZE_BYTES.lock().unwrap();
+        let file_count = tree.files.len();
+        let folder_count = tree.dirs.len().saturating_sub(1); // Subtract 1 for source directory
+        let total_size_bytes = tree.total_bytes;
         
         let op_info = OperationalInfo {
             file_count,
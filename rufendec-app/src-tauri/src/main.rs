@@ -1,10 +1,9 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod rufendec;
 mod encryption;
 
-use encryption::{encrypt_directory, encrypt_file, scan_operational_info};
+use encryption::{cancel_operation, encrypt_directory, encrypt_file, scan_operational_info, ActiveJob};
 use tauri::{Manager, webview::WebviewWindowBuilder};
 use tauri::WebviewUrl;
 use url::Url;
@@ -38,7 +37,8 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
-        .invoke_handler(tauri::generate_handler![encrypt_directory, encrypt_file, scan_operational_info, open_github_window])
+        .manage(ActiveJob::default())
+        .invoke_handler(tauri::generate_handler![encrypt_directory, encrypt_file, scan_operational_info, cancel_operation, open_github_window])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
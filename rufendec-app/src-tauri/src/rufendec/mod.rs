@@ -1,12 +0,0 @@
-pub mod common;
-pub mod config;
-pub mod display;
-pub mod log;
-pub mod operations;
-pub mod secrets;
-
-// Re-export types that are used by other modules
-#[allow(unused_imports)]
-pub use operations::{Mode, Operation, HashMode};
-
-
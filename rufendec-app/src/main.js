@@ -16,6 +16,7 @@ let progressBar;
 let progressText;
 let progressDetails;
 let progressMessage;
+let cancelBtn;
 let verboseContainer;
 let verboseLog;
 let isOperationComplete = false;
@@ -125,6 +126,8 @@ async function execute() {
   progressText.textContent = '0%';
   progressDetails.textContent = '0 / 0 files';
   progressMessage.textContent = 'Initializing...';
+  cancelBtn.disabled = false;
+  cancelBtn.style.display = 'inline-block';
   
   // Show verbose log if verbose is enabled
   if (options.verbose) {
@@ -143,7 +146,16 @@ async function execute() {
       result = await invoke('encrypt_file', { options });
     }
 
-    if (result.success) {
+    if (result.cancelled) {
+      statusMsgEl.textContent = `⚠ ${result.message} (Success: ${result.success_count}, Failed: ${result.failed_count})`;
+      statusMsgEl.className = 'warning';
+      isOperationComplete = true;
+      progressMessage.textContent = 'Operation cancelled';
+
+      if (options.verbose) {
+        addVerboseLog('warn', `Operation cancelled: ${result.message}`);
+      }
+    } else if (result.success) {
       statusMsgEl.textContent = `✓ ${result.message} (Success: ${result.success_count}, Failed: ${result.failed_count})`;
       statusMsgEl.className = 'success';
       isOperationComplete = true;
@@ -196,6 +208,7 @@ async function execute() {
   } finally {
     executeBtn.disabled = false;
     executeBtn.textContent = 'Execute';
+    cancelBtn.style.display = 'none';
     
     // Clear sensitive fields for security
     passwordEl.value = '';
@@ -203,6 +216,18 @@ async function execute() {
   }
 }
 
+// Asks the running job to stop. The files in flight are finished, and the rest are skipped
+async function cancelOperation() {
+  cancelBtn.disabled = true;
+  progressMessage.textContent = 'Cancelling...';
+
+  try {
+    await invoke('cancel_operation');
+  } catch (error) {
+    console.error('Error cancelling operation:', error);
+  }
+}
+
 function addVerboseLog(level, message) {
   const entry = document.createElement('div');
   entry.className = `log-entry ${level}`;
@@ -229,6 +254,7 @@ window.addEventListener("DOMContentLoaded", async () => {
   progressText = document.getElementById('progress-text');
   progressDetails = document.getElementById('progress-details');
   progressMessage = document.getElementById('progress-message');
+  cancelBtn = document.getElementById('cancel-btn');
   
   // Verbose log elements
   verboseContainer = document.getElementById('verbose-container');
@@ -237,6 +263,7 @@ window.addEventListener("DOMContentLoaded", async () => {
   document.getElementById('browse-source').addEventListener('click', browseSource);
   document.getElementById('browse-target').addEventListener('click', browseTarget);
   executeBtn.addEventListener('click', execute);
+  cancelBtn.addEventListener('click', cancelOperation);
   
   // GitHub link - try opening in Tauri webview, fallback to browser
   const githubLink = document.getElementById('github-link');
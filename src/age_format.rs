@@ -0,0 +1,311 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use age::{
+    scrypt,
+    secrecy::SecretString,
+    x25519, DecryptError, Decryptor, Encryptor,
+};
+use std::{
+    io::{Read, Write},
+    iter,
+    sync::Mutex,
+};
+
+/* How is an age file laid out?
+-----------------------
+The age mode writes the files of age (the age-encryption.org/v1 format), so they open with "age --decrypt", rage, or any other age tool.
+1. The header: the "age-encryption.org/v1" line, one stanza for every recipient, and the "---" line with the HMAC of the header.
+   A stanza holds the random 16 byte file key, wrapped for its recipient:
+   - scrypt: the key derived from the password with scrypt (N = 2^18, as the age tool does) and a salt of its own. The header of a file
+     encrypted with the password holds that stanza alone.
+   - X25519: the key agreed with the public key of the recipient ("age1..."), which its secret key ("AGE-SECRET-KEY-1...") agrees again.
+2. The 16 byte nonce, from which the payload key is derived along with the file key.
+3. The payload: the content of the file in chunks of 64 KiB, each encrypted with ChaCha20-Poly1305.
+The salt of scrypt is picked for every file, so the key is derived for every file on encrypt and on decrypt. One derivation takes about
+256 MiB of memory, so derivations run one at a time even when the files are encrypted by many threads. X25519 derives nothing and is
+the one to pick for a directory of many files.
+*/
+
+// The work factor of the age tool (N = 2^18). Files which ask for more than 2^22 are refused, as the age tool does
+const SCRYPT_WORK_FACTOR: u8 = 18;
+const SCRYPT_MAX_WORK_FACTOR: u8 = 22;
+
+const HEADER_LEN: usize = "age-encryption.org/v1\n".len() + "--- ".len() + 43 + "\n".len();
+// "-> scrypt <salt> 18" and the wrapped file key, each in base64 on a line of their own
+const SCRYPT_STANZA_LEN: usize = "-> scrypt ".len() + 22 + " 18".len() + 1 + 43 + 1;
+// "-> X25519 <ephemeral share>" and the wrapped file key
+const X25519_STANZA_LEN: usize = "-> X25519 ".len() + 43 + 1 + 43 + 1;
+const NONCE_LEN: usize = 16;
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+// The X25519 keys of the age mode: the recipients files are encrypted to, and the identities which decrypt them
+#[derive(Default)]
+pub struct AgeKeys {
+    recipients: Vec<x25519::Recipient>,
+    identities: Vec<x25519::Identity>,
+}
+
+impl AgeKeys {
+    // Adds the recipients of a recipients file, which holds one public key per line. Empty lines and the ones starting with "#" are skipped
+    pub fn add_recipients(&mut self, text: &str) -> Result<(), String> {
+        for line in key_lines(text) {
+            let recipient = line.parse::<x25519::Recipient>().map_err(|_| {
+                format!("\"{}\" is not an age public key. Only the X25519 keys (\"age1...\") are supported", line)
+            })?;
+
+            self.recipients.push(recipient);
+        }
+
+        Ok(())
+    }
+
+    /* Adds the identities of an identity file, such as the one age-keygen writes. Files are encrypted to the public keys of the identities
+    as well, so the one identity file is enough to encrypt and to decrypt
+    */
+    pub fn add_identities(&mut self, text: &str) -> Result<(), String> {
+        let before = self.identities.len();
+
+        for line in key_lines(text) {
+            // The secret key is never part of the error
+            let identity = line.parse::<x25519::Identity>().map_err(|_| {
+                "The identity file holds a line which is not an age secret key. Only the X25519 keys (\"AGE-SECRET-KEY-1...\") are supported"
+                    .to_string()
+            })?;
+
+            self.recipients.push(identity.to_public());
+            self.identities.push(identity);
+        }
+
+        if self.identities.len() == before {
+            return Err("The identity file holds no age secret key".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipients.is_empty() && self.identities.is_empty()
+    }
+
+    // Whether there is a recipient to encrypt to, and an identity to decrypt with
+    pub fn can_encrypt(&self) -> bool {
+        !self.recipients.is_empty()
+    }
+
+    pub fn can_decrypt(&self) -> bool {
+        !self.identities.is_empty()
+    }
+}
+
+// The lines of a recipients or an identity file which hold a key
+fn key_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+// What the files are encrypted with: the password, or the X25519 keys
+enum Unlock {
+    Password {
+        recipient: scrypt::Recipient,
+        identity: scrypt::Identity,
+        // Held while scrypt runs, so the threads do not take 256 MiB each at the same time
+        scrypt: Mutex<()>,
+    },
+    Keys(AgeKeys),
+}
+
+pub struct Age {
+    unlock: Unlock,
+}
+
+impl Age {
+    // Files encrypted with the password, which age asks for with "age --decrypt"
+    pub fn with_password(password: &str) -> Self {
+        let mut recipient = scrypt::Recipient::new(SecretString::from(password.to_string()));
+        recipient.set_work_factor(SCRYPT_WORK_FACTOR);
+
+        let mut identity = scrypt::Identity::new(SecretString::from(password.to_string()));
+        identity.set_max_work_factor(SCRYPT_MAX_WORK_FACTOR);
+
+        Age { unlock: Unlock::Password { recipient, identity, scrypt: Mutex::new(()) } }
+    }
+
+    // Files encrypted to the X25519 recipients, and decrypted with the identities
+    pub fn with_keys(keys: AgeKeys) -> Self {
+        Age { unlock: Unlock::Keys(keys) }
+    }
+
+    // Whether the files it encrypts can be decrypted back, which takes the password or an identity
+    pub fn can_decrypt(&self) -> bool {
+        match &self.unlock {
+            Unlock::Password { .. } => true,
+            Unlock::Keys(keys) => keys.can_decrypt(),
+        }
+    }
+
+    // Encrypts the content into an age file. Returns None if there is no recipient
+    pub fn encrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let encryptor = match &self.unlock {
+            Unlock::Password { recipient, scrypt, .. } => {
+                let _scrypt = scrypt.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                Encryptor::with_recipients(iter::once(recipient as &dyn age::Recipient)).ok()?
+            }
+            Unlock::Keys(keys) if !keys.recipients.is_empty() => {
+                Encryptor::with_recipients(keys.recipients.iter().map(|recipient| recipient as &dyn age::Recipient)).ok()?
+            }
+            Unlock::Keys(_) => return None,
+        };
+
+        let mut writer = encryptor.wrap_output(Vec::with_capacity(self.encrypted_len(data.len()))).ok()?;
+        writer.write_all(data).ok()?;
+        writer.finish().ok()
+    }
+
+    // Reverse of encrypt. Decrypts an age file made by any age tool, as long as it is encrypted with the password or to one of the identities
+    pub fn decrypt(&self, file: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let decryptor = Decryptor::new_buffered(file).map_err(|error| self.error(error))?;
+
+        let mut reader = match &self.unlock {
+            Unlock::Password { identity, scrypt, .. } => {
+                if !decryptor.is_scrypt() {
+                    return Err("The age file is encrypted to public keys, not with a password. Give the identity which decrypts it");
+                }
+
+                let _scrypt = scrypt.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                decryptor.decrypt(iter::once(identity as &dyn age::Identity))
+            }
+            Unlock::Keys(keys) => {
+                if decryptor.is_scrypt() {
+                    return Err("The age file is encrypted with a password, not to public keys");
+                }
+
+                decryptor.decrypt(keys.identities.iter().map(|identity| identity as &dyn age::Identity))
+            }
+        }
+        .map_err(|error| self.error(error))?;
+
+        let mut content = Vec::with_capacity(file.len());
+
+        reader
+            .read_to_end(&mut content)
+            .map_err(|_| "The age file could not be authenticated. Its payload was modified or cut short")?;
+
+        Ok(content)
+    }
+
+    // The size of the age file of a content of the given size. The header of a file encrypted to X25519 recipients may also carry a grease
+    // stanza of random size, which is not counted
+    pub fn encrypted_len(&self, len: usize) -> usize {
+        let stanzas = match &self.unlock {
+            Unlock::Password { .. } => SCRYPT_STANZA_LEN,
+            Unlock::Keys(keys) => keys.recipients.len() * X25519_STANZA_LEN,
+        };
+
+        // An empty content still has one chunk
+        HEADER_LEN + stanzas + NONCE_LEN + len + len.div_ceil(CHUNK_LEN).max(1) * TAG_LEN
+    }
+
+    fn error(&self, error: DecryptError) -> &'static str {
+        match (error, &self.unlock) {
+            (DecryptError::DecryptionFailed | DecryptError::KeyDecryptionFailed, Unlock::Password { .. }) => {
+                "The age file could not be decrypted with the password"
+            }
+            (DecryptError::NoMatchingKeys, Unlock::Password { .. }) => "The age file could not be decrypted with the password",
+            (DecryptError::NoMatchingKeys, Unlock::Keys(_)) => "The age file is not encrypted to any of the identities",
+            (DecryptError::ExcessiveWork { .. }, _) => {
+                "The age file asks for a scrypt work factor above 2^22, which takes too long to be decrypted"
+            }
+            (DecryptError::InvalidMac, _) => "The header of the age file could not be authenticated. It was modified",
+            (DecryptError::UnknownFormat, _) => "The age file uses a version of age other than v1, which is not supported",
+            (DecryptError::InvalidHeader | DecryptError::Io(_), _) => "This is not an age file",
+            _ => "The age file could not be decrypted",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    // The identity file of a new key pair, as age-keygen writes it
+    fn identity_file() -> String {
+        format!("# created: today\n{}\n", x25519::Identity::generate().to_string().expose_secret())
+    }
+
+    fn keys(identity_file: &str) -> AgeKeys {
+        let mut keys = AgeKeys::default();
+        keys.add_identities(identity_file).unwrap();
+        keys
+    }
+
+    fn content() -> Vec<u8> {
+        b"an age file\n".repeat(10_000)
+    }
+
+    #[test]
+    fn age_round_trip() {
+        let identity_file = identity_file();
+        let age = Age::with_keys(keys(&identity_file));
+
+        for content in [Vec::new(), content()] {
+            let file = age.encrypt(&content).unwrap();
+
+            assert!(file.starts_with(b"age-encryption.org/v1\n"));
+            assert!(file.len() >= age.encrypted_len(content.len()));
+            assert_eq!(age.decrypt(&file).unwrap(), content);
+            // The identity file alone decrypts it
+            assert_eq!(Age::with_keys(keys(&identity_file)).decrypt(&file).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn recipients_only_encrypt() {
+        let identity_file = identity_file();
+        let public_key = keys(&identity_file).recipients[0].to_string();
+
+        let mut recipients = AgeKeys::default();
+        recipients.add_recipients(&format!("# the team\n\n{}\n", public_key)).unwrap();
+        assert!(recipients.can_encrypt() && !recipients.can_decrypt());
+
+        let age = Age::with_keys(recipients);
+        assert!(!age.can_decrypt());
+
+        let file = age.encrypt(&content()).unwrap();
+        assert_eq!(age.decrypt(&file), Err("The age file is not encrypted to any of the identities"));
+        assert_eq!(Age::with_keys(keys(&identity_file)).decrypt(&file).unwrap(), content());
+    }
+
+    #[test]
+    fn modified_age_file_is_rejected() {
+        let age = Age::with_keys(keys(&identity_file()));
+        let file = age.encrypt(&content()).unwrap();
+
+        let mut payload = file.clone();
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+        assert_eq!(age.decrypt(&payload), Err("The age file could not be authenticated. Its payload was modified or cut short"));
+
+        let cut = &file[..file.len() - TAG_LEN];
+        assert_eq!(age.decrypt(cut), Err("The age file could not be authenticated. Its payload was modified or cut short"));
+
+        // The MAC of the header is on the line starting with "---"
+        let mac = file.windows(4).position(|window| window == b"--- ").unwrap() + 4;
+        let mut header = file.clone();
+        header[mac] = if header[mac] == b'A' { b'B' } else { b'A' };
+        assert_eq!(age.decrypt(&header), Err("The header of the age file could not be authenticated. It was modified"));
+    }
+
+    #[test]
+    fn wrong_identity_is_rejected() {
+        let file = Age::with_keys(keys(&identity_file())).encrypt(&content()).unwrap();
+
+        assert_eq!(Age::with_keys(keys(&identity_file())).decrypt(&file), Err("The age file is not encrypted to any of the identities"));
+        assert_eq!(
+            Age::with_password("password").decrypt(&file),
+            Err("The age file is encrypted to public keys, not with a password. Give the identity which decrypts it")
+        );
+        assert!(AgeKeys::default().add_identities("AGE-SECRET-KEY-1NOTAKEY").is_err());
+        assert!(AgeKeys::default().add_recipients("age1notakey").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+/* How does the armor work?
+-----------------------
+An encrypted file is binary, so it cannot be pasted into an email or a chat. The armor turns it into text, the way PEM and OpenPGP do:
+1. The ARMOR_BEGIN line.
+2. The encrypted file (the cipher text, and the Nonce in GCM mode) in base64, cut into lines of 64 characters.
+3. The checksum line: "=" followed by the CRC-24 of the encrypted file (the one of OpenPGP, RFC 4880) in base64. It catches a message
+   which was damaged while it was copied, before the decryption is tried, and also in ECB mode which has no authentication.
+4. The ARMOR_END line.
+The armor is only a wrapper, so an armored file is decrypted the same way as a binary one. It is told apart by its first line.
+An OpenPGP message gets the lines of OpenPGP instead (PGP_ARMOR_BEGIN and PGP_ARMOR_END), so gpg reads it as well. The armor of a message
+made by gpg may also have header lines (such as "Comment: ...") after the first line, and may leave out the checksum, which RFC 9580 allows.
+An age file gets the lines of age (AGE_ARMOR_BEGIN and AGE_ARMOR_END) and no checksum, since the armor of age has none.
+*/
+
+pub const ARMOR_BEGIN: &str = "-----BEGIN RUFENDEC MESSAGE-----";
+pub const ARMOR_END: &str = "-----END RUFENDEC MESSAGE-----";
+pub const PGP_ARMOR_BEGIN: &str = "-----BEGIN PGP MESSAGE-----";
+pub const PGP_ARMOR_END: &str = "-----END PGP MESSAGE-----";
+pub const AGE_ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+pub const AGE_ARMOR_END: &str = "-----END AGE ENCRYPTED FILE-----";
+
+const LINE_LEN: usize = 64;
+
+const CRC24_INIT: u32 = 0x00B7_04CE;
+const CRC24_POLY: u32 = 0x0186_4CFB;
+
+fn crc24(data: &[u8]) -> [u8; 3] {
+    let mut crc = CRC24_INIT;
+
+    for byte in data {
+        crc ^= (*byte as u32) << 16;
+
+        for _ in 0..8 {
+            crc <<= 1;
+
+            if crc & 0x0100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+
+    let [_, high, middle, low] = crc.to_be_bytes();
+    [high, middle, low]
+}
+
+// Whether the encrypted file is armored. Whitespace before the first line is allowed
+pub fn is_armored(data: &[u8]) -> bool {
+    let start = data.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(data.len());
+    [ARMOR_BEGIN, PGP_ARMOR_BEGIN, AGE_ARMOR_BEGIN].iter().any(|begin| data[start..].starts_with(begin.as_bytes()))
+}
+
+// Whether the line ends an armored message
+pub fn is_armor_end(line: &str) -> bool {
+    matches!(line.trim(), ARMOR_END | PGP_ARMOR_END | AGE_ARMOR_END)
+}
+
+// Wraps the encrypted file in the armor
+pub fn armor(data: &[u8]) -> String {
+    armor_with(data, ARMOR_BEGIN, ARMOR_END)
+}
+
+// Wraps an OpenPGP message in the armor of OpenPGP
+pub fn armor_openpgp(data: &[u8]) -> String {
+    armor_with(data, PGP_ARMOR_BEGIN, PGP_ARMOR_END)
+}
+
+// Wraps an age file in the armor of age
+pub fn armor_age(data: &[u8]) -> String {
+    armor_with(data, AGE_ARMOR_BEGIN, AGE_ARMOR_END)
+}
+
+fn armor_with(data: &[u8], begin: &str, end: &str) -> String {
+    let encoded = BASE64_STANDARD.encode(data);
+
+    // OpenPGP puts an empty line after the header lines, of which there are none
+    let mut armored = if begin == PGP_ARMOR_BEGIN { format!("{}\n\n", begin) } else { format!("{}\n", begin) };
+
+    for line in encoded.as_bytes().chunks(LINE_LEN) {
+        // base64 is ASCII
+        armored.push_str(std::str::from_utf8(line).unwrap_or_default());
+        armored.push('\n');
+    }
+
+    if begin != AGE_ARMOR_BEGIN {
+        armored.push_str(&format!("={}\n", BASE64_STANDARD.encode(crc24(data))));
+    }
+
+    armored.push_str(&format!("{}\n", end));
+    armored
+}
+
+/* Reverse of armor. The armor is looked for anywhere in the text, so a message pasted along with the text around it works too.
+Whitespace around the lines and the "\r" of the Windows line endings are ignored
+*/
+pub fn dearmor(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let text = std::str::from_utf8(data).map_err(|_| "The armored message is not valid text")?;
+
+    let mut lines = text.lines().map(str::trim).skip_while(|line| ![ARMOR_BEGIN, PGP_ARMOR_BEGIN, AGE_ARMOR_BEGIN].contains(line));
+
+    let end = match lines.next() {
+        Some(ARMOR_BEGIN) => ARMOR_END,
+        Some(AGE_ARMOR_BEGIN) => AGE_ARMOR_END,
+        Some(_) => PGP_ARMOR_END,
+        None => return Err("No armored message was found"),
+    };
+
+    let mut encoded = String::new();
+    let mut checksum = None;
+
+    loop {
+        match lines.next() {
+            None => return Err("The armored message has no end line. It was not copied whole"),
+            Some(line) if line == end => break,
+            Some(line) if line.starts_with('=') && line.len() == 5 => checksum = Some(&line[1..]),
+            // The header lines of OpenPGP. A colon is never part of base64
+            Some(line) if end == PGP_ARMOR_END && line.contains(':') => {}
+            Some(line) => encoded.push_str(line),
+        }
+    }
+
+    if encoded.is_empty() {
+        return Err("The armored message is empty");
+    }
+
+    let decoded = BASE64_STANDARD.decode(encoded).map_err(|_| "The armored message is not valid base64. It was damaged while it was copied")?;
+
+    match checksum {
+        Some(checksum) if BASE64_STANDARD.decode(checksum).ok().as_deref() == Some(&crc24(&decoded)[..]) => Ok(decoded),
+        Some(_) => Err("The checksum of the armored message does not match. It was damaged while it was copied"),
+        None if end != ARMOR_END => Ok(decoded),
+        None => Err("The armored message has no checksum line. It was not copied whole"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..=255u8).cycle().take(1000).collect()
+    }
+
+    #[test]
+    fn crc24_matches_openpgp() {
+        assert_eq!(crc24(b""), [0xB7, 0x04, 0xCE]);
+        assert_eq!(crc24(b"123456789"), [0x21, 0xCF, 0x02]);
+    }
+
+    #[test]
+    fn armor_round_trip() {
+        let data = data();
+
+        for armored in [armor(&data), armor_openpgp(&data), armor_age(&data)] {
+            assert!(is_armored(armored.as_bytes()));
+            assert!(armored.lines().all(|line| line.len() <= LINE_LEN || line.starts_with("-----")));
+            assert!(is_armor_end(armored.lines().last().unwrap()));
+            assert_eq!(dearmor(armored.as_bytes()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn armor_is_found_inside_pasted_text() {
+        let data = data();
+        let pasted = format!("Here is the file:\r\n\r\n  {}\r\nThanks", armor(&data).replace('\n', "\r\n"));
+
+        assert_eq!(dearmor(pasted.as_bytes()).unwrap(), data);
+    }
+
+    #[test]
+    fn modified_armor_is_rejected() {
+        let armored = armor(&data());
+        let mut lines: Vec<String> = armored.lines().map(str::to_string).collect();
+        let first = lines[1].remove(0);
+        lines[1].insert(0, if first == 'A' { 'B' } else { 'A' });
+
+        let tampered = lines.join("\n");
+        assert_eq!(
+            dearmor(tampered.as_bytes()),
+            Err("The checksum of the armored message does not match. It was damaged while it was copied")
+        );
+    }
+
+    #[test]
+    fn cut_armor_is_rejected() {
+        let armored = armor(&data());
+
+        let without_end: String = armored.lines().filter(|line| *line != ARMOR_END).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(dearmor(without_end.as_bytes()), Err("The armored message has no end line. It was not copied whole"));
+
+        let without_checksum: String = armored.lines().filter(|line| !line.starts_with('=')).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(dearmor(without_checksum.as_bytes()), Err("The armored message has no checksum line. It was not copied whole"));
+    }
+
+    #[test]
+    fn openpgp_header_lines_are_skipped() {
+        let data = data();
+        let armored = armor_openpgp(&data).replacen("\n\n", "\nComment: made by gpg\n\n", 1);
+
+        assert_eq!(dearmor(armored.as_bytes()).unwrap(), data);
+        assert_eq!(dearmor(b"no armor here"), Err("No armored message was found"));
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{fmt, io::Read, str::FromStr};
+use serde::{Serialize, Serializer};
+
+/* How does the compression work?
+-----------------------
+Encrypted bytes look random and cannot be compressed, so the content has to be compressed before it is encrypted.
+With a compression codec, the content (and the anonymized file name, if any) is compressed, followed by the size of the content before
+compression (8 bytes, little endian). The padding, if any, goes after it.
+The codec, its level and whether the content was compressed are recorded in the header of the sealed content (see seal in operations.rs),
+which GCM authenticates along with it. So only the files which record it are decompressed, and a file which is encrypted back after
+an edit keeps its level.
+A file is left uncompressed when the compression does not make it smaller, and optionally when its content is already compressed
+(images, videos, archives), which is told by the first bytes of the content rather than by the extension.
+*/
+
+const TRAILER_LEN: usize = 8;
+
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+pub(crate) const ZSTD_LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+// The codec the content is compressed with before encryption. Given as "none", "zstd" or "zstd:<level>" on the command line
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd(i32),
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (codec, level) = match s.split_once(':') {
+            Some((codec, level)) => (codec, Some(level)),
+            None => (s, None),
+        };
+
+        match (codec.to_ascii_lowercase().as_str(), level) {
+            ("none", None) => Ok(Compression::None),
+            ("zstd", None) => Ok(Compression::Zstd(DEFAULT_ZSTD_LEVEL)),
+            ("zstd", Some(level)) => match level.parse::<i32>() {
+                Ok(level) if ZSTD_LEVELS.contains(&level) => Ok(Compression::Zstd(level)),
+                _ => Err(format!(
+                    "the zstd level must be a number from {} to {}",
+                    ZSTD_LEVELS.start(),
+                    ZSTD_LEVELS.end()
+                )),
+            },
+            _ => Err("expected none, zstd or zstd:<level>".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Zstd(level) => write!(f, "zstd:{}", level),
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// The magic numbers of formats which are compressed already, and the offset they are found at
+const COMPRESSED_SIGNATURES: &[(usize, &[u8])] = &[
+    (0, b"\xFF\xD8\xFF"),              // JPEG
+    (0, b"\x89PNG\r\n\x1A\n"),         // PNG
+    (0, b"GIF8"),                      // GIF
+    (8, b"WEBP"),                      // WebP
+    (4, b"ftyp"),                      // MP4, MOV, M4A, HEIC
+    (0, b"\x1A\x45\xDF\xA3"),          // MKV, WebM
+    (0, b"OggS"),                      // Ogg
+    (0, b"fLaC"),                      // FLAC
+    (0, b"ID3"),                       // MP3
+    (0, b"PK\x03\x04"),                // ZIP, JAR, DOCX, XLSX, APK
+    (0, b"\x1F\x8B"),                  // gzip
+    (0, b"\x28\xB5\x2F\xFD"),          // zstd
+    (0, b"\xFD7zXZ\x00"),              // xz
+    (0, b"BZh"),                       // bzip2
+    (0, b"7z\xBC\xAF\x27\x1C"),        // 7-Zip
+    (0, b"Rar!\x1A\x07"),              // RAR
+    (0, b"\x04\x22\x4D\x18"),          // LZ4
+];
+
+// Whether the content is in a format which is compressed already
+pub(crate) fn is_compressed(data: &[u8]) -> bool {
+    COMPRESSED_SIGNATURES
+        .iter()
+        .any(|(offset, signature)| data.get(*offset..offset + signature.len()) == Some(*signature))
+}
+
+// Compresses the content with zstd and appends its size. Returns None when the content does not get any smaller
+pub(crate) fn compress(data: &[u8], level: i32) -> Option<Vec<u8>> {
+    match zstd::bulk::compress(data, level) {
+        Ok(mut compressed) if compressed.len() + TRAILER_LEN < data.len() => {
+            compressed.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            Some(compressed)
+        }
+        _ => None,
+    }
+}
+
+/* Decompresses content which records that it was compressed with zstd.
+The decompression stops right after the size in the trailer, so the content cannot grow past it
+*/
+pub(crate) fn decompress(data: &mut Vec<u8>) -> Result<(), &'static str> {
+    let len_at = data.len().checked_sub(TRAILER_LEN).ok_or("The file could not be decompressed")?;
+    let original_len = u64::from_le_bytes(data[len_at..].try_into().unwrap());
+
+    let mut decompressed = Vec::new();
+
+    zstd::Decoder::new(&data[..len_at])
+        .and_then(|decoder| decoder.take(original_len.saturating_add(1)).read_to_end(&mut decompressed))
+        .map_err(|_| "The file could not be decompressed")?;
+
+    if decompressed.len() as u64 != original_len {
+        return Err("The decompressed file does not have the recorded size");
+    }
+
+    *data = decompressed;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text() -> Vec<u8> {
+        b"timestamp,level,message\n".repeat(500)
+    }
+
+    #[test]
+    fn compress_and_decompress_round_trip() {
+        let mut compressed = compress(&text(), 9).unwrap();
+        assert!(compressed.len() < text().len());
+
+        decompress(&mut compressed).unwrap();
+        assert_eq!(compressed, text());
+    }
+
+    #[test]
+    fn content_which_does_not_shrink_is_left_alone() {
+        assert!(compress(b"abc", DEFAULT_ZSTD_LEVEL).is_none());
+    }
+
+    #[test]
+    fn modified_size_is_rejected() {
+        let mut compressed = compress(&text(), DEFAULT_ZSTD_LEVEL).unwrap();
+        let len_at = compressed.len() - TRAILER_LEN;
+        compressed[len_at..].copy_from_slice(&10u64.to_le_bytes());
+
+        assert!(decompress(&mut compressed).is_err());
+    }
+
+    #[test]
+    fn modified_stream_is_rejected() {
+        let mut compressed = compress(&text(), DEFAULT_ZSTD_LEVEL).unwrap();
+        compressed[0] ^= 0xFF;
+
+        assert!(decompress(&mut compressed).is_err());
+    }
+
+    #[test]
+    fn levels_are_parsed() {
+        assert_eq!("zstd".parse::<Compression>(), Ok(Compression::Zstd(DEFAULT_ZSTD_LEVEL)));
+        assert_eq!("zstd:19".parse::<Compression>(), Ok(Compression::Zstd(19)));
+        assert!("zstd:23".parse::<Compression>().is_err());
+    }
+}
@@ -1,50 +1,92 @@
 // Copyright (c) 2023 Venkatesh Omkaram
 
-use crate::operations::{HashMode, Mode, Operation};
+use crate::output::OutputFormat;
+use rufendec::{
+    filter::{parse_size, parse_time, FileType, Predicates},
+    compression::Compression,
+    document::DocumentFormat,
+    links::SymlinkPolicy,
+    padding::PadPolicy,
+    HashMode, Mode, Operation, ShredOptions,
+};
 use clap::Parser;
+use std::{ffi::OsString, path::PathBuf, time::SystemTime};
 
 // Using Clap library to provide the user with CLI argument parser and help section.
 #[derive(clap::Args, Debug, Clone)]
 #[command(disable_version_flag = true)]
 pub struct DirOptions {
     /// Specify the Source Directory here
-    pub source_dir: String,
+    pub source_dir: PathBuf,
     /// Specify the Target Directory here.
     /// But if you do not provide this, the target files will be placed in the Source Directory.
-    pub target_dir: Option<String>,
-    /// Specify the password file with an extension ".omk". The first line in the file must have the password, and the second line must have the salt
-    #[arg(short = 'f', long)]
-    pub password_file: Option<String>,
-    /// Skip the password_file search on the machine in case you decided to not provide the password_file in the CLI options
-    #[clap(short = 'k', long, default_value_t = false)]
-    pub skip_passwd_file_search: bool,
+    pub target_dir: Option<PathBuf>,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
     /// Specify the Operation you want to perform on the Source Directory
     #[clap(short, long, value_enum)]
     pub operation: Operation,
-    /// Provide the mode of Encryption here
-    #[clap(short, long, value_enum, default_value_t = Mode::GCM)]
-    pub mode: Mode,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The X25519 keys of the age mode, which take the place of the password
+    #[command(flatten)]
+    pub age: AgeKeyOptions,
     /// Delete the source files in the Source Directory (Ignored if `shred` command is used)
     #[clap(short, long, default_value_t = false)]
     pub delete_src: bool,
     /// Threads to speed up the execution
     #[clap(short, long, default_value_t = 8)]
     pub threads: usize,
-    /// Generate the secure key with the specified hashing function algorithm
-    #[clap(short = 'x', long, value_enum, default_value_t = HashMode::Argon2)]
-    pub hash_with: HashMode,
-    /// Iterations for the choosen hashing function
-    #[clap(short, long, default_value_t = 10)]
-    pub iterations: u32,
     /// Print verbose output
     #[clap(short, long, default_value_t = false)]
     pub verbose: bool,
     /// Skip all file creation and deletion
     #[clap(short = 'r', long, default_value_t = false)]
     pub dry_run: bool,
+    /// Round the size of the encrypted files up to a bucket, so their exact size does not show. Padded files are decrypted without this option
+    #[clap(long, value_enum, default_value_t = PadPolicy::None)]
+    pub pad: PadPolicy,
+    /// Compress the files before encryption, given as "zstd" or "zstd:<level>" (1 to 22, 3 by default). Compressed files are decrypted without this option
+    #[clap(long, default_value_t = Compression::None)]
+    pub compress: Compression,
+    /// Leave the files whose content is compressed already (such as jpg, mp4 or zip) uncompressed while using "--compress"
+    #[clap(long)]
+    pub skip_compressed: bool,
+    /// Write the encrypted files as armored text (base64 with a checksum), which can be pasted into an email or a chat. Armored files are decrypted without this option
+    #[clap(long)]
+    pub armor: bool,
     /// Anonymize the source file names
     #[clap(short, long, default_value_t = false)]
     pub anon: bool,
+    /// Only take the files matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave out the files and directories matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// What to do with the symbolic links. skip leaves them out, follow takes what they point to, and preserve keeps the links themselves
+    #[clap(long, value_enum, default_value_t = SymlinkPolicy::Skip)]
+    pub symlinks: SymlinkPolicy,
+    /// Only take the files of at least this size (such as 500, 10K, 1.5M or 2G)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u64>,
+    /// Only take the files of at most this size (such as 500, 10K, 1.5M or 2G)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_size: Option<u64>,
+    /// Only take the files modified after this date (such as 2024-01-31 or "2024-01-31 18:30:00"), or within this age (such as 12h, 30d or 2w)
+    #[clap(long, value_name = "WHEN", value_parser = parse_time)]
+    pub newer: Option<SystemTime>,
+    /// Only take the files modified before this date (such as 2024-01-31 or "2024-01-31 18:30:00"), or older than this age (such as 12h, 30d or 2w)
+    #[clap(long, value_name = "WHEN", value_parser = parse_time)]
+    pub older: Option<SystemTime>,
+    /// Only take the files of this type. f for regular files, and l for symbolic links to files
+    #[clap(long = "type", value_enum, value_name = "TYPE")]
+    pub file_type: Option<FileType>,
+    /// Resume an interrupted run by skipping the files noted in the progress journal of the Target Directory
+    #[clap(long, default_value_t = false)]
+    pub resume: bool,
     #[command(subcommand)]
     pub shred: Option<Shred>,
 }
@@ -55,16 +97,13 @@ pub struct DirOptions {
 #[command(disable_version_flag = true)]
 pub struct FileOptions {
     /// Specify the Source file here (This is the file you want to either Encrypt or Decrypt)
-    pub source_file: String,
+    pub source_file: PathBuf,
     /// Specify the Target directory here.
     /// But if you do not provide this, the target file will be placed in the source file's Directory.
-    pub target_dir: Option<String>,
-    /// Specify the password file with an extension ".omk". The first line in the file must have the password, and the second line must have the salt
-    #[arg(short = 'f', long)]
-    pub password_file: Option<String>,
-    /// Skip the password_file search on the machine in case you decided to not provide the `password_file` in the CLI options
-    #[clap(short = 'k', long, default_value_t = false)]
-    pub skip_passwd_file_search: bool,
+    pub target_dir: Option<PathBuf>,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
     /// Specify the password (in case `password_file` is not provided and `suppress_terminal` is set to true)
     #[arg(short, long)]
     pub passwd: Option<String>,
@@ -74,18 +113,15 @@ pub struct FileOptions {
     /// Specify the Operation you want to perform on the Source file
     #[clap(short, long, value_enum)]
     pub operation: Operation,
-    /// Provide the mode of Encryption here
-    #[clap(short, long, value_enum, default_value_t = Mode::GCM)]
-    pub mode: Mode,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The X25519 keys of the age mode, which take the place of the password
+    #[command(flatten)]
+    pub age: AgeKeyOptions,
     /// Delete the source file (Ignored if `shred` command is used)
     #[clap(short, long, default_value_t = false)]
     pub delete_src: bool,
-    /// Generate the secure key with the specified hashing function algorithm
-    #[clap(short = 'x', long, value_enum, default_value_t = HashMode::Argon2)]
-    pub hash_with: HashMode,
-    /// Iterations for the choosen hashing function
-    #[clap(short, long, default_value_t = 10)]
-    pub iterations: u32,
     /// Suppress all CLI output
     #[clap(short = 'z', long, default_value_t = false)]
     pub suppress_terminal: bool,
@@ -95,6 +131,18 @@ pub struct FileOptions {
     /// Skip all file creation and deletion
     #[clap(short = 'r', long, default_value_t = false)]
     pub dry_run: bool,
+    /// Round the size of the encrypted files up to a bucket, so their exact size does not show. Padded files are decrypted without this option
+    #[clap(long, value_enum, default_value_t = PadPolicy::None)]
+    pub pad: PadPolicy,
+    /// Compress the files before encryption, given as "zstd" or "zstd:<level>" (1 to 22, 3 by default). Compressed files are decrypted without this option
+    #[clap(long, default_value_t = Compression::None)]
+    pub compress: Compression,
+    /// Leave the files whose content is compressed already (such as jpg, mp4 or zip) uncompressed while using "--compress"
+    #[clap(long)]
+    pub skip_compressed: bool,
+    /// Write the encrypted files as armored text (base64 with a checksum), which can be pasted into an email or a chat. Armored files are decrypted without this option
+    #[clap(long)]
+    pub armor: bool,
     /// Anonymize the source file name
     #[clap(short, long, default_value_t = false)]
     pub anon: bool,
@@ -103,15 +151,351 @@ pub struct FileOptions {
 }
 
 
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct SyncOptions {
+    /// Specify the Source Directory here (This is the directory with the files you want to keep encrypted)
+    pub source_dir: PathBuf,
+    /// Specify the Target Directory here. The encrypted files and the sync state database are placed here.
+    /// It must not be the Source Directory or be inside it.
+    pub target_dir: PathBuf,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// Remove the encrypted files in the Target Directory whose source files were deleted
+    #[clap(short, long, default_value_t = false)]
+    pub prune: bool,
+    /// Threads to speed up the execution
+    #[clap(short, long, default_value_t = 8)]
+    pub threads: usize,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// Skip all file creation and deletion
+    #[clap(short = 'r', long, default_value_t = false)]
+    pub dry_run: bool,
+    /// Round the size of the encrypted files up to a bucket, so their exact size does not show. Padded files are decrypted without this option
+    #[clap(long, value_enum, default_value_t = PadPolicy::None)]
+    pub pad: PadPolicy,
+    /// Compress the files before encryption, given as "zstd" or "zstd:<level>" (1 to 22, 3 by default). Compressed files are decrypted without this option
+    #[clap(long, default_value_t = Compression::None)]
+    pub compress: Compression,
+    /// Leave the files whose content is compressed already (such as jpg, mp4 or zip) uncompressed while using "--compress"
+    #[clap(long)]
+    pub skip_compressed: bool,
+    /// Write the encrypted files as armored text (base64 with a checksum), which can be pasted into an email or a chat. Armored files are decrypted without this option
+    #[clap(long)]
+    pub armor: bool,
+    /// Anonymize the source file names
+    #[clap(short, long, default_value_t = false)]
+    pub anon: bool,
+    /// What to do with the symbolic links. skip leaves them out, follow takes what they point to, and preserve keeps the links themselves
+    #[clap(long, value_enum, default_value_t = SymlinkPolicy::Skip)]
+    pub symlinks: SymlinkPolicy,
+    /// Only take the files matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave out the files and directories matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct WatchOptions {
+    /// Specify the Source Directory here (This is the directory where the new files appear)
+    pub source_dir: PathBuf,
+    /// Specify the Target Directory here. The encrypted files are placed here.
+    /// It must not be the Source Directory or be inside it.
+    pub target_dir: PathBuf,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// Delete the source files once they are encrypted (Ignored if `shred` command is used)
+    #[clap(short, long, default_value_t = false)]
+    pub delete_src: bool,
+    /// Threads to speed up the execution
+    #[clap(short, long, default_value_t = 8)]
+    pub threads: usize,
+    /// Milliseconds a file must stay unchanged before it is encrypted
+    #[clap(short = 'w', long, default_value_t = 2000)]
+    pub debounce_ms: u64,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// Round the size of the encrypted files up to a bucket, so their exact size does not show. Padded files are decrypted without this option
+    #[clap(long, value_enum, default_value_t = PadPolicy::None)]
+    pub pad: PadPolicy,
+    /// Compress the files before encryption, given as "zstd" or "zstd:<level>" (1 to 22, 3 by default). Compressed files are decrypted without this option
+    #[clap(long, default_value_t = Compression::None)]
+    pub compress: Compression,
+    /// Leave the files whose content is compressed already (such as jpg, mp4 or zip) uncompressed while using "--compress"
+    #[clap(long)]
+    pub skip_compressed: bool,
+    /// Write the encrypted files as armored text (base64 with a checksum), which can be pasted into an email or a chat. Armored files are decrypted without this option
+    #[clap(long)]
+    pub armor: bool,
+    /// Anonymize the source file names
+    #[clap(short, long, default_value_t = false)]
+    pub anon: bool,
+    /// Only take the files matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave out the files and directories matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    #[command(subcommand)]
+    pub shred: Option<Shred>,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct PackOptions {
+    /// Specify the Source Directory here (This is the directory you want to pack)
+    pub source_dir: PathBuf,
+    /// Specify the container file to create. It must not be inside the Source Directory
+    pub container: PathBuf,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The size of the encrypted chunks of the container, such as 64K or 4M (between 4K and 64M)
+    #[clap(long, value_parser = parse_size, default_value = "1M")]
+    pub chunk_size: u64,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// Skip all file creation and deletion
+    #[clap(short = 'r', long, default_value_t = false)]
+    pub dry_run: bool,
+    /// What to do with the symbolic links. skip leaves them out, follow takes what they point to, and preserve keeps the links themselves
+    #[clap(long, value_enum, default_value_t = SymlinkPolicy::Skip)]
+    pub symlinks: SymlinkPolicy,
+    /// Only take the files matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave out the files and directories matching this glob (gitignore-style, relative to the Source Directory). Can be given more than once
+    #[clap(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct UnpackOptions {
+    /// Specify the container file made by pack
+    pub container: PathBuf,
+    /// Specify the Target Directory here. But if you do not provide this, the entries are extracted into the current directory
+    pub target_dir: Option<PathBuf>,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// List the entries of the container instead of extracting them
+    #[clap(short, long, default_value_t = false)]
+    pub list: bool,
+    /// Only extract the entry at this path, or the entries under it if it is a directory. Can be given more than once
+    #[clap(short, long, value_name = "PATH")]
+    pub entry: Vec<PathBuf>,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// Skip all file creation and deletion
+    #[clap(short = 'r', long, default_value_t = false)]
+    pub dry_run: bool,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct StreamOptions {
+    /// Specify the Operation you want to perform on stdin
+    #[clap(short, long, value_enum)]
+    pub operation: Operation,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// Specify the password (in case `password_file` is not provided)
+    #[arg(short, long)]
+    pub passwd: Option<String>,
+    /// Specify the salt (in case `password_file` is not provided)
+    #[arg(short, long)]
+    pub salt: Option<String>,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The size of the encrypted chunks of the stream, such as 64K or 4M (between 4K and 64M). Only used to encrypt
+    #[clap(long, value_parser = parse_size, default_value = "1M")]
+    pub chunk_size: u64,
+    /// Print verbose output (to stderr)
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct MountOptions {
+    /// Specify the Encrypted Directory here (This is the directory with the ".enom" files)
+    pub encrypted_dir: PathBuf,
+    /// Specify the empty directory the decrypted view is mounted on
+    pub mountpoint: PathBuf,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// The files were encrypted with anonymized names. Every file is decrypted at mount time to find its name
+    #[clap(short, long, default_value_t = false)]
+    pub anon: bool,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
 #[derive(clap::Args, Debug, Clone)]
 #[command(disable_version_flag = true)]
-pub struct ShredOptions {
-    /// Fill the source file(s) with random bytes over multiple iterations
-    #[clap(short, long, default_value_t = 5)]
-    pub random_iterations: u32,
-    /// Rename the file(s) while shredding multiple times
-    #[clap(short = 't', long, default_value_t = 10)]
-    pub rename_times: u32
+pub struct EditOptions {
+    /// Specify the encrypted file to edit (This is the file with the ".enom" extension)
+    pub file: PathBuf,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// The file was encrypted with an anonymized name. It is encrypted back with the same name
+    #[clap(short, long, default_value_t = false)]
+    pub anon: bool,
+    /// Edit the values of a YAML, JSON, TOML or dotenv document encrypted with the "values" subcommand. Told by the file extension if not given
+    #[clap(long, value_enum)]
+    pub format: Option<DocumentFormat>,
+    /// How the decrypted file is shredded once the editor exits
+    #[command(flatten)]
+    pub shred: ShredOptions,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct ValuesOptions {
+    /// Specify the YAML, JSON, TOML or dotenv document here
+    pub source_file: PathBuf,
+    /// Specify the file to write the document to.
+    /// But if you do not provide this, the source file is replaced.
+    pub target_file: Option<PathBuf>,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// Specify the Operation you want to perform on the values of the document
+    #[clap(short, long, value_enum)]
+    pub operation: Operation,
+    /// The format of the document. Told by the file extension if not given
+    #[clap(long, value_enum)]
+    pub format: Option<DocumentFormat>,
+    /// Print verbose output
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct ExecOptions {
+    /// Specify the encrypted dotenv file (such as "secrets.env.enom"), or a dotenv document encrypted with the "values" subcommand.
+    /// Give it more than once to load several files. A variable set by a later file replaces the same one set by an earlier file
+    #[clap(short = 'e', long = "env-file", required = true)]
+    pub env_files: Vec<PathBuf>,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The encrypted dotenv files were encrypted with an anonymized name
+    #[clap(short, long, default_value_t = false)]
+    pub anon: bool,
+    /// Print verbose output (to stderr)
+    #[clap(short, long, default_value_t = false)]
+    pub verbose: bool,
+    /// The command to run with the decrypted variables, given after "--"
+    #[arg(last = true, required = true)]
+    pub command: Vec<OsString>,
+}
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct GitFilterOptions {
+    #[command(subcommand)]
+    pub action: GitFilterAction,
+}
+
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum GitFilterAction {
+    /// Sets up the repository: derives the key from the password and the salt, keeps it in .git/ and configures the filter
+    Init(CredentialArgs),
+    /// Sets up a clone of a repository which was set up with init, and decrypts its encrypted files in the working tree
+    Unlock(CredentialArgs),
+    /// Removes the key and the filter configuration, and leaves the encrypted files encrypted in the working tree
+    Lock,
+    /// The clean filter git runs: encrypts stdin to stdout
+    Clean,
+    /// The smudge filter git runs: decrypts stdin to stdout
+    Smudge,
+    /// The textconv git runs for diffs: decrypts the file to stdout
+    Textconv {
+        /// The file git gives
+        file: PathBuf,
+    },
+}
+
+
+
+// Using Clap library to provide the user with CLI argument parser and help section.
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct TextOptions {
+    /// Encrypt a message typed at the prompt (or given on stdin) into armored text, or decrypt an armored message pasted at the prompt (or given on stdin)
+    #[clap(short, long, value_enum, default_value_t = Operation::Encrypt)]
+    pub operation: Operation,
+    /// The credentials the key is derived from
+    #[command(flatten)]
+    pub credentials: CredentialArgs,
+    /// The mode of encryption
+    #[command(flatten)]
+    pub cipher: CipherArgs,
+    /// The X25519 keys of the age mode, which take the place of the password
+    #[command(flatten)]
+    pub age: AgeKeyOptions,
 }
 
 
@@ -122,6 +506,29 @@ pub enum Command {
     Dir(DirOptions),
     /// Targets on the file level
     File(FileOptions),
+    /// Encrypts only the new or changed files of a directory into the target, keeping an encrypted state database there
+    Sync(SyncOptions),
+    /// Watches a directory and encrypts the files into the target as soon as they appear (Linux only)
+    Watch(WatchOptions),
+    /// Packs a whole directory into a single encrypted container file
+    Pack(PackOptions),
+    /// Extracts or lists the entries of a container file made by pack
+    Unpack(UnpackOptions),
+    /// Encrypts or decrypts from stdin to stdout, for use in pipelines. The credentials are never prompted for
+    Stream(StreamOptions),
+    /// Mounts an encrypted directory as a read-only file system which decrypts the files as they are read (Linux only)
+    Mount(MountOptions),
+    /// Decrypts a file into memory, opens it in $EDITOR, and encrypts it back if it was changed (Linux only)
+    Edit(EditOptions),
+    /// Encrypts or decrypts the values inside a YAML, JSON, TOML or dotenv document, leaving the keys and the structure readable.
+    /// The comments of a YAML or TOML document are dropped when it is written back, while those of a dotenv document are kept
+    Values(ValuesOptions),
+    /// Decrypts dotenv files into memory and runs a command with the variables in its environment, without writing them to the disk
+    Exec(ExecOptions),
+    /// Encrypts selected files of a git repository when they are committed, and decrypts them when they are checked out
+    GitFilter(GitFilterOptions),
+    /// Encrypts a short message into armored text which can be pasted into an email or a chat, or decrypts one
+    Text(TextOptions),
 }
 
 
@@ -138,5 +545,134 @@ pub enum Shred {
 pub struct Args {
     #[command(subcommand)]
     pub command: Command,
+    /// Print the result as a JSON document (json), or as a stream of JSON lines while the run progresses (ndjson). The human readable text goes to stderr then
+    #[clap(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Also write the JSON run report to this file
+    #[clap(long, global = true)]
+    pub report: Option<String>,
+}
+
+
+impl DirOptions {
+    // The metadata predicates the files must satisfy
+    pub fn predicates(&self) -> Predicates {
+        Predicates {
+            min_size: self.min_size,
+            max_size: self.max_size,
+            newer: self.newer,
+            older: self.older,
+            file_type: self.file_type,
+        }
+    }
+}
+
+
+impl GitFilterOptions {
+    // The options of the actions which derive the key
+    pub fn key_options(&self) -> Option<&CredentialArgs> {
+        match &self.action {
+            GitFilterAction::Init(key_options) | GitFilterAction::Unlock(key_options) => Some(key_options),
+            _ => None,
+        }
+    }
+}
+
+
+// The password file and the key derivation, which every subcommand deriving the key takes
+#[derive(clap::Args, Debug, Clone)]
+pub struct CredentialArgs {
+    /// Specify the password file with an extension ".omk". The first line in the file must have the password, and the second line must have the salt
+    #[arg(short = 'f', long)]
+    pub password_file: Option<String>,
+    /// Skip the password_file search on the machine in case you decided to not provide the password_file in the CLI options.
+    /// stream, exec and text never search, since stdin or stdout is theirs
+    #[clap(short = 'k', long, default_value_t = false)]
+    pub skip_passwd_file_search: bool,
+    /// Generate the secure key with the specified hashing function algorithm
+    #[clap(short = 'x', long, value_enum, default_value_t = HashMode::Argon2)]
+    pub hash_with: HashMode,
+    /// Iterations for the choosen hashing function
+    #[clap(short, long, default_value_t = 10)]
+    pub iterations: u32,
+}
+
+
+// The mode of encryption, which every subcommand encrypting or decrypting whole files takes
+#[derive(clap::Args, Debug, Clone)]
+pub struct CipherArgs {
+    /// Provide the mode of Encryption here
+    #[clap(short, long, value_enum, default_value_t = Mode::GCM)]
+    pub mode: Mode,
 }
 
+
+// The X25519 keys of the age mode. Without any of them, the age mode encrypts with the password (as "age --passphrase" does)
+#[derive(clap::Args, Debug, Clone)]
+pub struct AgeKeyOptions {
+    /// Encrypt to this age public key ("age1...") in the age mode. Can be given more than once
+    #[clap(long = "recipient", value_name = "RECIPIENT")]
+    pub recipients: Vec<String>,
+    /// Encrypt to the age public keys listed in this file, one per line. Can be given more than once
+    #[clap(long = "recipients-file", value_name = "PATH")]
+    pub recipients_files: Vec<PathBuf>,
+    /// Decrypt with the age secret keys ("AGE-SECRET-KEY-1...") of this file, such as the one age-keygen writes. Files are also encrypted to them.
+    /// Can be given more than once
+    #[clap(long = "identity", value_name = "PATH")]
+    pub identities: Vec<PathBuf>,
+}
+
+impl AgeKeyOptions {
+    pub fn is_empty(&self) -> bool {
+        self.recipients.is_empty() && self.recipients_files.is_empty() && self.identities.is_empty()
+    }
+}
+
+impl Command {
+    // The credentials the subcommand derives the key from. The git filters other than init and unlock read the key kept in .git/
+    pub fn credentials(&self) -> Option<&CredentialArgs> {
+        match self {
+            Command::Dir(options) => Some(&options.credentials),
+            Command::File(options) => Some(&options.credentials),
+            Command::Sync(options) => Some(&options.credentials),
+            Command::Watch(options) => Some(&options.credentials),
+            Command::Pack(options) => Some(&options.credentials),
+            Command::Unpack(options) => Some(&options.credentials),
+            Command::Stream(options) => Some(&options.credentials),
+            Command::Mount(options) => Some(&options.credentials),
+            Command::Edit(options) => Some(&options.credentials),
+            Command::Values(options) => Some(&options.credentials),
+            Command::Exec(options) => Some(&options.credentials),
+            Command::GitFilter(options) => options.key_options(),
+            Command::Text(options) => Some(&options.credentials),
+        }
+    }
+
+    // The mode of encryption the subcommand was given, if it takes one
+    pub fn mode(&self) -> Option<Mode> {
+        let cipher = match self {
+            Command::Dir(options) => &options.cipher,
+            Command::File(options) => &options.cipher,
+            Command::Sync(options) => &options.cipher,
+            Command::Watch(options) => &options.cipher,
+            Command::Pack(options) => &options.cipher,
+            Command::Unpack(options) => &options.cipher,
+            Command::Stream(options) => &options.cipher,
+            Command::Mount(options) => &options.cipher,
+            Command::Edit(options) => &options.cipher,
+            Command::Exec(options) => &options.cipher,
+            Command::Text(options) => &options.cipher,
+            Command::Values(_) | Command::GitFilter(_) => return None,
+        };
+
+        Some(cipher.mode)
+    }
+}
+
+
+impl Shred {
+    // The shred options the library expects
+    pub fn options(shred: &Option<Shred>) -> Option<ShredOptions> {
+        shred.as_ref().map(|Shred::Shred(options)| options.clone())
+    }
+}
@@ -0,0 +1,915 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use rand::Rng;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    common::{decode_path, encode_path, relative_path, safe_path},
+    error::RufendecError,
+    job::Job,
+    links::{create_hard_link, create_symlink, SymlinkPolicy},
+    log::{log, LogLevel},
+    operations::{cancelled_error, write_atomic_with, Cipher, Mode, SourceTree, CHUNK_SIZE, TEMP_FILE_SUFFIX},
+    report::{FileResult, RunReport, Stage},
+};
+
+/* How is a container laid out?
+-----------------------
+pack streams a whole source directory into a single file, so neither the count, the sizes nor the names of the files show. Only the total size does.
+1. The header: CONTAINER_MAGIC, the format version, the mode, the chunk size and a random id of the container. It is not encrypted.
+2. The data chunks. The content of every file is appended to a single stream, which is cut into chunks of the chunk size, and each chunk is
+   encrypted on its own. So a single entry is read by decrypting only the chunks it lies in. The header and the index of a chunk are the
+   associated data of the chunk, so the chunks cannot be swapped, dropped or moved into another container without being noticed.
+3. The table of contents: the path, the kind, the size, the modified time and the offset in the stream of every entry, encrypted as JSON with
+   the header as the associated data.
+4. The footer: the size of the encrypted table of contents (8 bytes, little endian), followed by FOOTER_MAGIC.
+The container is written under a temporary name and renamed once it is complete, the same way the encrypted files are.
+Files are packed and extracted one chunk at a time, so a file is never held in memory as a whole.
+Containers are only packed in GCM mode. ECB cannot authenticate the chunks nor the table of contents, so a container could be edited unnoticed.
+*/
+
+const CONTAINER_MAGIC: &[u8] = b"RUFENDEC-PACK\0";
+const CONTAINER_VERSION: u8 = 1;
+const FOOTER_MAGIC: &[u8] = b"RFPK-END";
+// The magic, the version, the mode, the chunk size and the id
+const HEADER_LEN: usize = CONTAINER_MAGIC.len() + 1 + 1 + 4 + 16;
+const FOOTER_LEN: usize = 8 + FOOTER_MAGIC.len();
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+pub const MIN_CHUNK_SIZE: usize = 4 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+struct Header {
+    mode: Mode,
+    chunk_size: u32,
+    id: [u8; 16],
+}
+
+impl Header {
+    fn new(mode: Mode, chunk_size: usize) -> Self {
+        let mut id = [0u8; 16];
+        rand::rng().fill(&mut id);
+
+        Header {
+            mode,
+            chunk_size: chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE) as u32,
+            id,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mode = match self.mode {
+            Mode::GCM => 1u8,
+            _ => unreachable!("the other modes are refused before a container is packed"),
+        };
+
+        [
+            CONTAINER_MAGIC,
+            &[CONTAINER_VERSION, mode],
+            &self.chunk_size.to_le_bytes(),
+            &self.id,
+        ]
+        .concat()
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Header, RufendecError> {
+        let not_a_container = || RufendecError::Validation("The file is not a container made by pack".to_string());
+
+        let rest = bytes.strip_prefix(CONTAINER_MAGIC).ok_or_else(not_a_container)?;
+
+        let [version, mode, c0, c1, c2, c3, id @ ..] = rest else {
+            return Err(not_a_container());
+        };
+
+        if *version != CONTAINER_VERSION {
+            return Err(RufendecError::Validation(format!(
+                "The container has the format version {}, which this version of rufendec cannot read",
+                version
+            )));
+        }
+
+        let mode = match mode {
+            1 => Mode::GCM,
+            _ => return Err(not_a_container()),
+        };
+
+        let chunk_size = u32::from_le_bytes([*c0, *c1, *c2, *c3]);
+
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&(chunk_size as usize)) {
+            return Err(not_a_container());
+        }
+
+        Ok(Header {
+            mode,
+            chunk_size,
+            id: id.try_into().map_err(|_| not_a_container())?,
+        })
+    }
+
+    fn chunk_aad(&self, index: u64) -> Vec<u8> {
+        [self.to_bytes(), index.to_le_bytes().to_vec()].concat()
+    }
+
+    fn toc_aad(&self) -> Vec<u8> {
+        [self.to_bytes(), b"TOC".to_vec()].concat()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+    HardLink,
+}
+
+// The paths are kept as the base64 encoded bytes of the path, so any file name fits
+mod encoded_path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_path(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        decode_path(&encoded).ok_or_else(|| D::Error::custom("The path is not base64 encoded"))
+    }
+}
+
+mod encoded_optional_path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+        match path {
+            Some(path) => serializer.serialize_some(&encode_path(path)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|encoded| decode_path(&encoded).ok_or_else(|| D::Error::custom("The path is not base64 encoded")))
+            .transpose()
+    }
+}
+
+/* An entry of the table of contents. The path is relative to the packed directory. offset is where the content of a file starts
+in the data stream. target is what a symbolic link points to, or the path of the entry a hard link shares its content with
+*/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    #[serde(with = "encoded_path")]
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub offset: u64,
+    // Seconds since the Unix epoch
+    pub modified: Option<u64>,
+    #[serde(default, with = "encoded_optional_path", skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Toc {
+    data_len: u64,
+    chunk_count: u64,
+    entries: Vec<Entry>,
+}
+
+// Refuses the modes which cannot authenticate the chunks and the table of contents
+fn require_gcm(cipher: &Cipher) -> Result<(), RufendecError> {
+    match cipher.mode() {
+        Mode::GCM => Ok(()),
+        Mode::ECB => Err(RufendecError::Validation(
+            "Containers are only packed in the GCM mode, since ECB cannot authenticate their table of contents".to_string(),
+        )),
+        _ => cipher.require_chunked("Containers"),
+    }
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+}
+
+fn temp_container_path(container: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(container.file_name().unwrap_or_default());
+    name.push(TEMP_FILE_SUFFIX);
+    container.with_file_name(name)
+}
+
+// Appends bytes to the data stream, and encrypts and writes a chunk every time one fills up
+struct ChunkWriter<'a, W: Write> {
+    writer: W,
+    cipher: &'a Cipher,
+    header: &'a Header,
+    buffer: Vec<u8>,
+    data_len: u64,
+    chunk_count: u64,
+}
+
+impl<'a, W: Write> ChunkWriter<'a, W> {
+    fn new(writer: W, cipher: &'a Cipher, header: &'a Header) -> Self {
+        ChunkWriter {
+            writer,
+            cipher,
+            header,
+            buffer: Vec::with_capacity(header.chunk_size as usize),
+            data_len: 0,
+            chunk_count: 0,
+        }
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let chunk_size = self.header.chunk_size as usize;
+
+        while !data.is_empty() {
+            let take = (chunk_size - self.buffer.len()).min(data.len());
+
+            self.buffer.extend_from_slice(&data[..take]);
+            self.data_len += take as u64;
+            data = &data[take..];
+
+            if self.buffer.len() == chunk_size {
+                self.flush_chunk()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let encrypted = self
+            .cipher
+            .encrypt_bytes_with_aad(&self.buffer, &self.header.chunk_aad(self.chunk_count))
+            .ok_or_else(|| io::Error::other("A chunk could not be encrypted"))?;
+
+        self.writer.write_all(&encrypted)?;
+        self.chunk_count += 1;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    // Writes the last chunk, the table of contents and the footer
+    fn finish(mut self, entries: Vec<Entry>) -> io::Result<W> {
+        self.flush_chunk()?;
+
+        let toc = Toc {
+            data_len: self.data_len,
+            chunk_count: self.chunk_count,
+            entries,
+        };
+
+        let toc = serde_json::to_vec(&toc).map_err(io::Error::other)?;
+        let toc = self
+            .cipher
+            .encrypt_bytes_with_aad(&toc, &self.header.toc_aad())
+            .ok_or_else(|| io::Error::other("The table of contents could not be encrypted"))?;
+
+        self.writer.write_all(&toc)?;
+        self.writer.write_all(&(toc.len() as u64).to_le_bytes())?;
+        self.writer.write_all(FOOTER_MAGIC)?;
+
+        Ok(self.writer)
+    }
+}
+
+// Reads the entries of a container, decrypting only the chunks they lie in. The chunk read last is kept, as the next entry often starts in it
+pub(crate) struct ContainerReader<'a> {
+    file: fs::File,
+    cipher: &'a Cipher,
+    header: Header,
+    toc: Toc,
+    cached: Option<(u64, Vec<u8>)>,
+}
+
+impl<'a> ContainerReader<'a> {
+    pub(crate) fn open(container: &Path, cipher: &'a Cipher) -> Result<Self, RufendecError> {
+        require_gcm(cipher)?;
+
+        let damaged = |cause: &str| RufendecError::Runtime(format!("The container {:?} is damaged :: {}", container, cause));
+
+        let mut file = fs::File::open(container).map_err(|e| {
+            RufendecError::Validation(format!("The container {:?} cannot be opened :: {}", container, e))
+        })?;
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        let mut header = vec![0u8; HEADER_LEN];
+        file.read_exact(&mut header)
+            .map_err(|_| RufendecError::Validation("The file is not a container made by pack".to_string()))?;
+        let header = Header::parse(&header)?;
+
+        if header.mode != cipher.mode() {
+            return Err(RufendecError::Validation(format!(
+                "The container was packed in {} mode. Pass --mode {} to unpack it",
+                header.mode,
+                header.mode.to_string().to_lowercase()
+            )));
+        }
+
+        let mut footer = [0u8; FOOTER_LEN];
+        file.seek(SeekFrom::Start(len.saturating_sub(FOOTER_LEN as u64)))
+            .and_then(|_| file.read_exact(&mut footer))
+            .map_err(|_| damaged("the footer is missing"))?;
+
+        let (toc_len, magic) = footer.split_at(8);
+
+        if magic != FOOTER_MAGIC {
+            return Err(damaged("the footer is missing, the container may be truncated"));
+        }
+
+        let toc_len = u64::from_le_bytes(toc_len.try_into().unwrap());
+        let toc_start = len
+            .checked_sub(FOOTER_LEN as u64 + toc_len)
+            .filter(|start| *start >= HEADER_LEN as u64)
+            .ok_or_else(|| damaged("the table of contents does not fit"))?;
+
+        let mut toc = vec![0u8; toc_len as usize];
+        file.seek(SeekFrom::Start(toc_start))
+            .and_then(|_| file.read_exact(&mut toc))
+            .map_err(|e| damaged(&e.to_string()))?;
+
+        // A wrong key fails here, before anything is written
+        let toc: Toc = cipher
+            .decrypt_bytes_with_aad(&toc, &header.toc_aad())
+            .and_then(|toc| serde_json::from_slice(&toc).ok())
+            .ok_or_else(|| {
+                RufendecError::Credentials("The table of contents of the container could not be decrypted. Check the password, the salt and the hashing function".to_string())
+            })?;
+
+        let reader = ContainerReader {
+            file,
+            cipher,
+            header,
+            toc,
+            cached: None,
+        };
+
+        if toc_start - HEADER_LEN as u64 != reader.data_region_len() {
+            return Err(damaged("the size of the data chunks does not match the table of contents"));
+        }
+
+        Ok(reader)
+    }
+
+    pub(crate) fn entries(&self) -> &[Entry] {
+        &self.toc.entries
+    }
+
+    fn chunk_size(&self) -> u64 {
+        self.header.chunk_size as u64
+    }
+
+    // The size of the encrypted chunks, as the table of contents tells
+    fn data_region_len(&self) -> u64 {
+        let full_chunks = self.toc.data_len / self.chunk_size();
+        let last_chunk = self.toc.data_len % self.chunk_size();
+
+        full_chunks * self.cipher.encrypted_len(self.chunk_size() as usize) as u64
+            + if last_chunk > 0 {
+                self.cipher.encrypted_len(last_chunk as usize) as u64
+            } else {
+                0
+            }
+    }
+
+    fn read_chunk(&mut self, index: u64) -> Option<&[u8]> {
+        if self.cached.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let plain_len = self.chunk_size().min(self.toc.data_len.checked_sub(index * self.chunk_size())?);
+            let full_len = self.cipher.encrypted_len(self.chunk_size() as usize) as u64;
+
+            let mut encrypted = vec![0u8; self.cipher.encrypted_len(plain_len as usize)];
+            self.file.seek(SeekFrom::Start(HEADER_LEN as u64 + index * full_len)).ok()?;
+            self.file.read_exact(&mut encrypted).ok()?;
+
+            let chunk = self
+                .cipher
+                .decrypt_bytes_with_aad(&encrypted, &self.header.chunk_aad(index))
+                .filter(|chunk| chunk.len() as u64 == plain_len)?;
+
+            self.cached = Some((index, chunk));
+        }
+
+        self.cached.as_ref().map(|(_, chunk)| chunk.as_slice())
+    }
+
+    /* Writes the content of a file entry one chunk at a time. Fails with ErrorKind::InvalidData when one of its chunks cannot be authenticated,
+    and gives up between two chunks once cancelled returns true
+    */
+    pub(crate) fn copy_entry(&mut self, entry: &Entry, writer: &mut impl Write, cancelled: impl Fn() -> bool) -> io::Result<()> {
+        let unauthenticated = || io::Error::new(io::ErrorKind::InvalidData, "The chunks of the entry could not be authenticated");
+
+        let end = entry.offset.checked_add(entry.size).ok_or_else(unauthenticated)?;
+        let mut position = entry.offset;
+
+        while position < end {
+            if cancelled() {
+                return Err(cancelled_error());
+            }
+
+            let index = position / self.chunk_size();
+            let chunk_start = index * self.chunk_size();
+            let chunk = self.read_chunk(index).ok_or_else(unauthenticated)?;
+
+            let from = (position - chunk_start) as usize;
+            let to = ((end - chunk_start) as usize).min(chunk.len());
+
+            if from >= to {
+                return Err(unauthenticated());
+            }
+
+            writer.write_all(&chunk[from..to])?;
+            position = chunk_start + to as u64;
+        }
+
+        Ok(())
+    }
+}
+
+// What stopped a file from being packed. A failed write spoils the whole container, a failed read only the file
+enum PackError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+impl Job {
+    /* Packs the directories and the files of the tree into the container, and returns the report of the run.
+    The files are only reported once the container is complete, as an interrupted container is removed as a whole
+    */
+    pub(crate) fn pack(&self, tree: &SourceTree, container: &Path, chunk_size: usize) -> Result<RunReport, RufendecError> {
+        require_gcm(&self.cipher)?;
+
+        *self.report.lock().unwrap() = RunReport::default();
+        self.hard_links.clear();
+
+        self.notify(|o| o.run_started(tree.files.len() as u64, tree.total_bytes));
+
+        let header = Header::new(self.cipher.mode(), chunk_size);
+        let temp_path = temp_container_path(container);
+        let mut packed = Vec::new();
+
+        let result = if self.dry_run {
+            self.pack_into(tree, &header, io::sink(), &mut packed).map(|_| ())
+        } else {
+            (|| {
+                let file = fs::OpenOptions::new().write(true).create_new(true).open(&temp_path)?;
+                let file = self.pack_into(tree, &header, BufWriter::new(file), &mut packed)?;
+
+                if self.cancel.is_cancelled() {
+                    return Ok(());
+                }
+
+                file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                fs::rename(&temp_path, container)
+            })()
+        };
+
+        let cancelled = self.cancel.is_cancelled();
+
+        if result.is_err() || cancelled {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        if let Err(e) = result {
+            return Err(RufendecError::Runtime(format!("Failed to write the container {:?} :: {}", container, e)));
+        }
+
+        if cancelled {
+            self.report.lock().unwrap().skipped_count = tree.files.len() as u64;
+        } else {
+            for result in packed {
+                self.record(result);
+            }
+        }
+
+        self.report.lock().unwrap().cancelled = cancelled;
+
+        let report = self.report();
+
+        self.notify(|o| o.run_finished(&report));
+
+        Ok(report)
+    }
+
+    // Writes the header, the chunks and the table of contents. The files which cannot be read are recorded right away and left out
+    fn pack_into<W: Write>(
+        &self,
+        tree: &SourceTree,
+        header: &Header,
+        mut writer: W,
+        packed: &mut Vec<FileResult>,
+    ) -> io::Result<W> {
+        writer.write_all(&header.to_bytes())?;
+
+        let mut chunks = ChunkWriter::new(writer, &self.cipher, header);
+        let mut entries = Vec::new();
+
+        // The source directory itself comes first, and is the root of the container
+        for dir in tree.dirs.iter().skip(1) {
+            entries.push(Entry {
+                path: relative_path(dir, &self.source_dir),
+                kind: EntryKind::Dir,
+                size: 0,
+                offset: 0,
+                modified: fs::metadata(dir).ok().as_ref().and_then(modified_secs),
+                target: None,
+            });
+        }
+
+        for file in &tree.files {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
+            let started = Instant::now();
+
+            if let Some(entry) = self.pack_file(file, &mut chunks)? {
+                if self.verbose {
+                    crate::log::print(&format!("Packed :: {:?}", entry.path));
+                }
+
+                let elapsed_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+                packed.push(FileResult::success(file, &entry.path, Some(entry.size), elapsed_ms));
+                entries.push(entry);
+            }
+        }
+
+        chunks.finish(entries)
+    }
+
+    fn pack_file<W: Write>(&self, file: &Path, chunks: &mut ChunkWriter<W>) -> io::Result<Option<Entry>> {
+        let path = relative_path(file, &self.source_dir);
+
+        let metadata = match fs::symlink_metadata(file) {
+            Ok(metadata) if metadata.file_type().is_symlink() && self.symlinks == SymlinkPolicy::Preserve => {
+                let target = match fs::read_link(file) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        self.record(FileResult::failure(file, Stage::Read, format!("Failed to read the symbolic link :: {}", e), None, None));
+                        return Ok(None);
+                    }
+                };
+
+                return Ok(Some(Entry {
+                    path,
+                    kind: EntryKind::Symlink,
+                    size: 0,
+                    offset: 0,
+                    modified: modified_secs(&metadata),
+                    target: Some(target),
+                }));
+            }
+            _ => match fs::metadata(file) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    self.record(FileResult::failure(file, Stage::Read, format!("Failed to read the file metadata :: {}", e), None, None));
+                    return Ok(None);
+                }
+            },
+        };
+
+        // A second name of a hard-linked file only points to the first one
+        if let Some(first_name) = self.hard_links.first_name(file, &metadata) {
+            return Ok(Some(Entry {
+                path,
+                kind: EntryKind::HardLink,
+                size: metadata.len(),
+                offset: 0,
+                modified: modified_secs(&metadata),
+                target: Some(relative_path(&first_name, &self.source_dir)),
+            }));
+        }
+
+        self.notify(|o| o.file_started(file, metadata.len()));
+
+        let offset = chunks.data_len;
+
+        // The bytes of a file which fails midway stay in the data stream, where no entry points to them
+        let size = match self.pack_content(file, chunks) {
+            Ok(size) => size,
+            Err(PackError::Write(e)) => return Err(e),
+            Err(PackError::Read(_)) if self.cancel.is_cancelled() => return Ok(None),
+            Err(PackError::Read(e)) => {
+                self.record(FileResult::failure(file, Stage::Read, format!("Failed to read the file :: {}", e), Some(metadata.len()), None));
+                return Ok(None);
+            }
+        };
+
+        self.notify(|o| o.bytes_processed(file, size));
+
+        Ok(Some(Entry {
+            path,
+            kind: EntryKind::File,
+            size,
+            offset,
+            modified: modified_secs(&metadata),
+            target: None,
+        }))
+    }
+
+    // Appends the content of the file to the data stream one chunk at a time, and returns its size
+    fn pack_content<W: Write>(&self, file: &Path, chunks: &mut ChunkWriter<W>) -> Result<u64, PackError> {
+        let mut file = fs::File::open(file).map_err(PackError::Read)?;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut size = 0;
+
+        loop {
+            if self.cancel.is_cancelled() {
+                return Err(PackError::Read(cancelled_error()));
+            }
+
+            match file.read(&mut buffer) {
+                Ok(0) => return Ok(size),
+                Ok(n) => {
+                    chunks.write(&buffer[..n]).map_err(PackError::Write)?;
+                    size += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(PackError::Read(e)),
+            }
+        }
+    }
+
+    pub(crate) fn list_container(&self, container: &Path) -> Result<Vec<Entry>, RufendecError> {
+        Ok(ContainerReader::open(container, &self.cipher)?.entries().to_vec())
+    }
+
+    /* Extracts the entries of the container into the target directory, and returns the report of the run. With no paths, every entry is extracted.
+    Otherwise the entries at the paths and under them. The directories come first and the links last, once the files they point to are written
+    */
+    pub(crate) fn unpack(&self, container: &Path, paths: &[PathBuf]) -> Result<RunReport, RufendecError> {
+        *self.report.lock().unwrap() = RunReport::default();
+
+        let mut reader = ContainerReader::open(container, &self.cipher)?;
+
+        // A leading ./ is not part of the paths in the container
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| path.components().filter(|c| !matches!(c, Component::CurDir)).collect())
+            .collect();
+
+        let missing: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| !reader.entries().iter().any(|entry| entry.path.starts_with(path)))
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(RufendecError::Validation(format!("The container has no entries at {:?}", missing)));
+        }
+
+        let mut selected: Vec<Entry> = reader
+            .entries()
+            .iter()
+            .filter(|entry| paths.is_empty() || paths.iter().any(|path| entry.path.starts_with(path)))
+            .cloned()
+            .collect();
+
+        selected.sort_by_key(|entry| match entry.kind {
+            EntryKind::Dir => 0,
+            EntryKind::File => 1,
+            EntryKind::HardLink => 2,
+            EntryKind::Symlink => 3,
+        });
+
+        let files: Vec<&Entry> = selected.iter().filter(|entry| entry.kind != EntryKind::Dir).collect();
+        let total_bytes = files.iter().map(|entry| entry.size).sum();
+
+        self.notify(|o| o.run_started(files.len() as u64, total_bytes));
+
+        for entry in &selected {
+            if self.cancel.is_cancelled() {
+                if entry.kind != EntryKind::Dir {
+                    self.skip();
+                }
+                continue;
+            }
+
+            self.unpack_entry(&mut reader, entry);
+        }
+
+        self.report.lock().unwrap().cancelled = self.cancel.is_cancelled();
+
+        let report = self.report();
+
+        self.notify(|o| o.run_finished(&report));
+
+        Ok(report)
+    }
+
+    fn unpack_entry(&self, reader: &mut ContainerReader, entry: &Entry) {
+        let started = Instant::now();
+        let failure = |stage: Stage, cause: String| {
+            self.record(FileResult::failure(&entry.path, stage, cause, Some(entry.size), Some(started.elapsed().as_secs_f64() * 1000.0)));
+        };
+
+        let Some(path) = safe_path(&entry.path) else {
+            failure(Stage::Read, "The entry path leads out of the target directory, so it is not extracted".to_string());
+            return;
+        };
+
+        let target = self.target_dir.join(path);
+
+        if self.verbose {
+            crate::log::print(&format!("Extracted :: {:?}", target));
+        }
+
+        if let (false, EntryKind::Dir) = (self.dry_run, entry.kind) {
+            if let Err(e) = fs::create_dir_all(&target) {
+                log(LogLevel::ERROR, format!("Failed to create the directory {:?} :: {}", target, e).as_str());
+            }
+            return;
+        }
+
+        if !self.dry_run {
+            if let Some(parent) = target.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+        }
+
+        let result = match entry.kind {
+            EntryKind::Dir => return,
+            _ if self.dry_run => Ok(()),
+            EntryKind::File => {
+                self.notify(|o| o.file_started(&entry.path, entry.size));
+
+                self.write_entry(reader, entry, &target)
+            }
+            EntryKind::HardLink => {
+                let first_name = entry.target.as_deref().and_then(safe_path).map(|first_name| self.target_dir.join(first_name));
+
+                match first_name {
+                    Some(first_name) if first_name.exists() => create_hard_link(&first_name, &target),
+                    // The file it points to was not extracted along with it, so its content is written here instead
+                    _ => {
+                        let first_entry = reader
+                            .entries()
+                            .iter()
+                            .find(|e| e.kind == EntryKind::File && Some(&e.path) == entry.target.as_ref())
+                            .cloned();
+
+                        match first_entry {
+                            Some(first_entry) => self.write_entry(reader, &Entry { modified: entry.modified, ..first_entry }, &target),
+                            None => {
+                                failure(Stage::Decrypt, "The file the hard link points to could not be read from the container".to_string());
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+            EntryKind::Symlink => match &entry.target {
+                Some(link_target) => create_symlink(link_target, &target),
+                None => Err(io::Error::other("The symbolic link has no target")),
+            },
+        };
+
+        match result {
+            Ok(()) => {
+                self.notify(|o| o.bytes_processed(&entry.path, entry.size));
+                self.record(FileResult::success(&entry.path, &target, Some(entry.size), Some(started.elapsed().as_secs_f64() * 1000.0)));
+            }
+            Err(_) if self.cancel.is_cancelled() => self.skip(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => failure(Stage::Decrypt, e.to_string()),
+            Err(e) => failure(Stage::Write, format!("Failed to write {:?} :: {}", target, e)),
+        }
+    }
+
+    // Writes the file the same way a decrypted file is written, one chunk at a time, and gives it back its modified time
+    fn write_entry(&self, reader: &mut ContainerReader, entry: &Entry, target: &Path) -> io::Result<()> {
+        write_atomic_with(target, |file| reader.copy_entry(entry, file, || self.cancel.is_cancelled()))?;
+
+        if let Some(modified) = entry.modified {
+            let _ = fs::File::options()
+                .write(true)
+                .open(target)
+                .and_then(|file| file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified)));
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{operations::scan, secrets::Key, Decryptor, Encryptor};
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    fn pack(source: &Path, container: &Path, mode: Mode) -> Result<RunReport, RufendecError> {
+        Encryptor::builder()
+            .source_dir(source)
+            .target_dir(container.parent().unwrap())
+            .key(key())
+            .mode(mode)
+            .build()?
+            .pack(&scan(source), container, MIN_CHUNK_SIZE)
+    }
+
+    fn unpack(container: &Path, target: &Path) -> Result<RunReport, RufendecError> {
+        Decryptor::builder()
+            .source_dir(container.parent().unwrap())
+            .target_dir(target)
+            .key(key())
+            .build()?
+            .unpack(container, &[])
+    }
+
+    // A source directory with a file spanning several chunks, a small file and an empty one
+    fn source_dir(root: &Path) -> PathBuf {
+        let source = root.join("source");
+        fs::create_dir_all(source.join("nested")).unwrap();
+
+        let large: Vec<u8> = (0..3 * MIN_CHUNK_SIZE + 123).map(|i| (i % 251) as u8).collect();
+        fs::write(source.join("nested").join("large.bin"), large).unwrap();
+        fs::write(source.join("small.txt"), b"hello").unwrap();
+        fs::write(source.join("empty.txt"), b"").unwrap();
+
+        source
+    }
+
+    #[test]
+    fn pack_and_unpack_round_trip() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path());
+        let container = root.path().join("backup.rfpk");
+
+        let report = pack(&source, &container, Mode::GCM).unwrap();
+        assert_eq!(report.success_count, 3);
+
+        let target = root.path().join("target");
+        let report = unpack(&container, &target).unwrap();
+        assert_eq!((report.success_count, report.failed_count), (3, 0));
+
+        for file in ["nested/large.bin", "small.txt", "empty.txt"] {
+            assert_eq!(fs::read(source.join(file)).unwrap(), fs::read(target.join(file)).unwrap());
+        }
+    }
+
+    #[test]
+    fn modified_chunk_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path());
+        let container = root.path().join("backup.rfpk");
+
+        pack(&source, &container, Mode::GCM).unwrap();
+
+        let mut bytes = fs::read(&container).unwrap();
+        bytes[HEADER_LEN + 10] ^= 1;
+        fs::write(&container, bytes).unwrap();
+
+        let target = root.path().join("target");
+        let report = unpack(&container, &target).unwrap();
+
+        // The first chunk always holds a part of the large file, whichever file comes first
+        assert!(report.failed_count >= 1);
+        assert!(!target.join("nested/large.bin").exists());
+    }
+
+    #[test]
+    fn modified_table_of_contents_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path());
+        let container = root.path().join("backup.rfpk");
+
+        pack(&source, &container, Mode::GCM).unwrap();
+
+        let mut bytes = fs::read(&container).unwrap();
+        let toc_byte = bytes.len() - FOOTER_LEN - 1;
+        bytes[toc_byte] ^= 1;
+        fs::write(&container, bytes).unwrap();
+
+        assert!(matches!(unpack(&container, &root.path().join("target")), Err(RufendecError::Credentials(_))));
+    }
+
+    #[test]
+    fn ecb_is_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path());
+        let container = root.path().join("backup.rfpk");
+
+        assert!(matches!(pack(&source, &container, Mode::ECB), Err(RufendecError::Validation(_))));
+        assert!(!container.exists());
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rpassword::prompt_password;
+use std::{
+    env, fs,
+    io::{stdin, stdout, Write},
+    path::PathBuf,
+    time::Duration,
+};
+use walkdir::WalkDir;
+
+use rufendec::{
+    error::RufendecError,
+    log::{log, LogLevel},
+    secrets::derive_key,
+    AgeKeys, HashMode, Key, Mode, Operation,
+};
+
+use crate::{
+    config::{AgeKeyOptions, Command},
+    display::terminal_suppress,
+    output::{out, outln},
+};
+
+pub struct Secrets {
+    password_file: String,
+    passwd: Option<String>,
+    salt: Option<String>,
+    suppress_terminal: bool,
+    skip_passwd_file_search: bool,
+    iterations: u32,
+    hash_with: HashMode
+}
+
+pub fn passwd_salt_tuple_from_prompt(
+    secrets: &Secrets,
+) -> (Option<std::string::String>, Option<std::string::String>) {
+    {
+        if !secrets.skip_passwd_file_search {
+            probe_password_file(|| {
+                (
+                    Some(
+                        prompt_password("\nEnter the Password: ")
+                            .expect("You entered a bad password")
+                            .trim()
+                            .to_owned(),
+                    ),
+                    Some(
+                        prompt_password("\nEnter the Salt: ")
+                            .expect("You entered a bad salt")
+                            .trim()
+                            .to_owned(),
+                    ),
+                )
+            })
+        } else {
+            (
+                Some(
+                    prompt_password("\nEnter the Password: ")
+                        .expect("You entered a bad password")
+                        .trim()
+                        .to_owned(),
+                ),
+                Some(
+                    prompt_password("\nEnter the Salt: ")
+                        .expect("You entered a bad salt")
+                        .trim()
+                        .to_owned(),
+                ),
+            )
+        }
+    }
+}
+
+// The credentials a job is built with. The OpenPGP and age modes take the password (and leave the salt unused), or the age mode the X25519
+// keys, and every other mode the key
+pub enum Credentials {
+    Key(Key),
+    Password(String, String),
+    AgeKeys(AgeKeys),
+}
+
+// Gathers the password and the salt from the password file, the command-line or the prompt
+fn gather_secrets(command: &Command) -> (Secrets, String, String) {
+    let credentials = command.credentials().unwrap_or_else(|| {
+        // The filters read the key kept in .git/
+        RufendecError::Validation("Only init and unlock derive the key from the password and the salt".to_string()).exit()
+    });
+
+    // The password and the salt are only given on the command-line of file and stream
+    let (passwd, salt, suppress_terminal) = match command {
+        Command::File(file_options) => (file_options.passwd.clone(), file_options.salt.clone(), file_options.suppress_terminal),
+        // stdin carries the data, so the credentials cannot be prompted for
+        Command::Stream(stream_options) => (stream_options.passwd.clone(), stream_options.salt.clone(), true),
+        _ => (None, None, false),
+    };
+
+    let secrets = Secrets {
+        password_file: credentials.password_file.clone().unwrap_or_default(),
+        passwd,
+        salt,
+        suppress_terminal,
+        // The password file search prints to stdout, which belongs to the command run by exec, carries the message of text,
+        // and the data of stream. So only the prompt (on the terminal) is left
+        skip_passwd_file_search: credentials.skip_passwd_file_search
+            || matches!(command, Command::Exec(_) | Command::Text(_) | Command::Stream(_)),
+        iterations: credentials.iterations,
+        hash_with: credentials.hash_with,
+    };
+
+    // First look for credentials in a password file and grab the password and salt in variables as Strings
+    let (password, salt) = if let Ok(tmp) = fs::read_to_string(secrets.password_file.clone()) {
+        let file: String = tmp.clone();
+        let mut lines: std::str::Lines = file.trim().lines();
+
+        (
+            Some(
+                lines
+                    .next()
+                    .unwrap_or_else(|| {
+                        RufendecError::Credentials("Password is expected.".to_string()).exit()
+                    })
+                    .to_owned(),
+            ),
+            Some(
+                lines
+                    .next()
+                    .unwrap_or_else(|| {
+                        RufendecError::Credentials("Salt is expected in the password-file.".to_string()).exit()
+                    })
+                    .to_owned(),
+            ),
+        )
+    } else {
+        match command {
+            Command::Dir(_) | Command::Sync(_) | Command::Watch(_) | Command::Pack(_) | Command::Unpack(_) | Command::Mount(_) | Command::Edit(_) | Command::Values(_) | Command::Exec(_) | Command::GitFilter(_) | Command::Text(_) => {
+                passwd_salt_tuple_from_prompt(&secrets)
+            }
+            Command::File(_) | Command::Stream(_) => {
+                if !secrets.suppress_terminal && secrets.passwd.is_none() && secrets.salt.is_none()
+                {
+                    passwd_salt_tuple_from_prompt(&secrets)
+                } else {
+                    (Some(secrets.passwd.clone().unwrap_or_else(|| {
+                            RufendecError::Credentials(
+                                "Password is expected since you did not provide a password file and the terminal IO is suppressed.".to_string(),
+                            ).exit()
+                        })),
+                    Some(secrets.salt.clone().unwrap_or_else(|| {
+                            RufendecError::Credentials(
+                                "Salt is expected since you did not provide a password file and the terminal IO is suppressed.".to_string(),
+                            ).exit()
+                        }))
+                    )
+                }
+            }
+        }
+    };
+
+    (secrets, password.unwrap_or_default(), salt.unwrap_or_default())
+}
+
+/* Gathers the credentials for the mode. The key of an OpenPGP message or an age file is derived from the password with the salt it carries,
+so those modes get the password as it is, and the key is derived by the job. The age mode given the X25519 keys asks for no password
+*/
+pub fn generate_credentials(command: &Command, mode: Mode) -> Credentials {
+    match (mode, age_key_options(command)) {
+        (Mode::Age, Some((options, operation))) if !options.is_empty() => Credentials::AgeKeys(read_age_keys(options, operation)),
+        (Mode::OpenPGP | Mode::Age, _) => {
+            let (_, password, salt) = gather_secrets(command);
+            Credentials::Password(password, salt)
+        }
+        _ => Credentials::Key(generate_keys(command)),
+    }
+}
+
+// The X25519 keys of the age mode, for the subcommands which take them
+fn age_key_options(command: &Command) -> Option<(&AgeKeyOptions, &Operation)> {
+    match command {
+        Command::Dir(options) => Some((&options.age, &options.operation)),
+        Command::File(options) => Some((&options.age, &options.operation)),
+        Command::Text(options) => Some((&options.age, &options.operation)),
+        _ => None,
+    }
+}
+
+// Reads the recipients and the identity files of the age mode. Encrypting needs a recipient, and decrypting an identity
+fn read_age_keys(options: &AgeKeyOptions, operation: &Operation) -> AgeKeys {
+    let mut keys = AgeKeys::default();
+
+    let read = |path: &PathBuf| {
+        fs::read_to_string(path).unwrap_or_else(|e| {
+            RufendecError::Credentials(format!("The age key file {:?} could not be read. {}", path, e)).exit()
+        })
+    };
+
+    let added = keys
+        .add_recipients(&options.recipients.join("\n"))
+        .and_then(|_| options.recipients_files.iter().try_for_each(|path| keys.add_recipients(&read(path))))
+        .and_then(|_| options.identities.iter().try_for_each(|path| keys.add_identities(&read(path))));
+
+    if let Err(cause) = added {
+        RufendecError::Credentials(cause).exit()
+    }
+
+    match operation {
+        Operation::Encrypt if !keys.can_encrypt() => {
+            RufendecError::Credentials("The age mode encrypts to the recipients, so --recipient, --recipients-file or --identity is expected".to_string()).exit()
+        }
+        Operation::Decrypt if !keys.can_decrypt() => {
+            RufendecError::Credentials("The age mode decrypts with the identities, so --identity is expected".to_string()).exit()
+        }
+        _ => keys,
+    }
+}
+
+// Gathers the password and the salt from the password file, the command-line or the prompt, and derives the key
+pub fn generate_keys(command: &Command) -> Key {
+    let (secrets, password, salt) = gather_secrets(command);
+
+    // stdout and stderr of a stream, of the command exec runs, and of a message are left to the pipeline
+    let pb = match command {
+        Command::Stream(_) | Command::Exec(_) | Command::Text(_) => ProgressBar::hidden(),
+        _ => ProgressBar::new_spinner(),
+    };
+
+    pb.enable_steady_tick(Duration::from_millis(120));
+    pb.set_style(
+        ProgressStyle::with_template("\n{spinner:.blue} {msg} {spinner:.blue}")
+            .unwrap()
+            // For more spinners check out the cli-spinners project:
+            // https://github.com/sindresorhus/cli-spinners/blob/master/spinners.json
+            .tick_strings(&[
+                "▹▹▹▹▹",
+                "▸▹▹▹▹",
+                "▹▸▹▹▹",
+                "▹▹▸▹▹",
+                "▹▹▹▸▹",
+                "▹▹▹▹▸",
+                "▪▪▪▪▪",
+            ]),
+    );
+
+    match secrets.hash_with {
+        HashMode::Argon2 => {
+            pb.set_message("Generating a secure key based on Argon2ID PBKDF hashing function")
+        }
+        HashMode::PBKDF2 => {
+            pb.set_message("Generating a secure key based on PBKDF2 HMAC (SHA256) function")
+        }
+    }
+
+    let key = derive_key(
+        &password,
+        &salt,
+        secrets.hash_with,
+        secrets.iterations,
+    )
+    .unwrap_or_else(|e| e.exit());
+
+    terminal_suppress(command, || {
+        outln!("\n\nKey generation complete ...\n\n");
+    });
+
+    key
+}
+
+/* This function can be used for all sorts of confirmation input from the user. */
+pub fn get_confirmation() -> String {
+    let mut confirmation: String = String::new();
+
+    out!("\nPlease type Y for yes, and N for no : ");
+
+    let _ = stdout().flush();
+
+    stdin()
+        .read_line(&mut confirmation)
+        .expect("You entered incorrect response");
+
+    if let Some('\n') = confirmation.chars().next_back() {
+        confirmation.pop();
+    }
+
+    if let Some('\r') = confirmation.chars().next_back() {
+        confirmation.pop();
+    }
+
+    outln!("\nYou typed: {}\n", confirmation);
+
+    confirmation
+}
+
+// This will look for password file on you system
+pub fn probe_password_file<F>(f: F) -> (Option<std::string::String>, Option<std::string::String>)
+where
+    F: Fn() -> (Option<std::string::String>, Option<std::string::String>),
+{
+    let file: String;
+    let mut lines: std::str::Lines;
+
+    // If the password file is not found then look for a password file
+
+    log(LogLevel::WARN, "Sorry, I did not find a password-file provided as a command-line option. Maybe you provided, but forgot to pass the file with the '.omk' extension");
+    outln!("\nSearching for a password file on your machine. It ends with the extension '.omk'");
+
+    // find_password_file() helps to look for a password file
+    if let Some(o) = find_password_file() {
+        outln!("\nDo you wish to use this file?");
+        if get_confirmation() == "Y" {
+            if let Ok(k) = fs::read_to_string(o) {
+                file = k.clone();
+                lines = file.trim().lines();
+                (
+                    Some(lines.next().expect("Password is expected").to_owned()),
+                    Some(
+                        lines
+                            .next()
+                            .expect("Salt is expected in the password-file")
+                            .to_owned(),
+                    ),
+                )
+            } else {
+                // The user chosen to use the password file found by the program, but the read failed
+
+                outln!("Failed the read the password file");
+                outln!("\nYou need to manually enter the credentials. Credentials will not be visible as you type.");
+
+                // Prompt the user to input the password and salt manually
+                //password_prompt()
+                f()
+            }
+        } else {
+            // The password file is found in the system, but the user wished to not use it
+
+            outln!("\nYou need to manually enter the credentials. Credentials will not be visible as you type.");
+
+            // Prompt the user to input the password and salt manually
+            f()
+        }
+    } else {
+        // Prompt the user to input the password and salt manually because no password file is found on the system
+        outln!("\nYou need to manually enter the credentials. Credentials will not be visible as you type.");
+        f()
+    }
+}
+
+// This function helps to find a password file with ".omk" extension on the users system
+pub fn find_password_file() -> Option<PathBuf> {
+    let os_type = env::consts::OS;
+
+    // This specifies where to look for the file
+    let target_dir = match os_type {
+        "linux" => vec![".", "..", "../../", "/etc", "/root", "/home"],
+        "windows" => vec!["C:/WINDOWS/SYSTEM32/config", "."],
+        _ => vec!["."],
+    };
+
+    for i in target_dir {
+        let file_list: Vec<Result<walkdir::DirEntry, walkdir::Error>> =
+            WalkDir::new(i).into_iter().collect();
+        log(
+            LogLevel::INFO,
+            format!(
+                "Searching this many files : {:?}. Please be patient",
+                file_list.capacity()
+            )
+            .as_str(),
+        );
+
+        let bar = ProgressBar::new_spinner(); // Create a Spinner
+
+        for entry in WalkDir::new(i)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            bar.enable_steady_tick(Duration::from_millis(100)); // Steadily spin the spinner
+
+            let f_name = entry.file_name().to_string_lossy();
+
+            if f_name.ends_with(".omk") {
+                outln!("\nFound this => {:?}", entry.clone().into_path());
+                let file_path: PathBuf = entry.into_path().as_path().to_owned();
+                return Some(file_path);
+            }
+        } // end of inner for loop
+    }
+    None
+}
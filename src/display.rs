@@ -1,7 +1,17 @@
 // Copyright (c) 2023 Venkatesh Omkaram
 
-use std::{env, path::PathBuf};
-use crate::{config::Command, operations::{HashMode, DIR_LIST, FILES_SIZE_BYTES, FILE_LIST}};
+use std::env;
+use crate::{config::{AgeKeyOptions, Command}, output::outln};
+use chrono::{DateTime, Local};
+use rufendec::{
+    compression::Compression,
+    container::{Entry, EntryKind},
+    operations::SourceTree,
+    padding::PadPolicy,
+    report::RunReport,
+    openpgp, HashMode, Mode, Operation,
+};
+use serde::Serialize;
 use colored::Colorize;
 use human_bytes::human_bytes;
 
@@ -19,127 +29,522 @@ where F: Fn() {
                 f()
             }
         },
+        // stdout carries the data of a stream, of the git filters and of a message, and belongs to the command exec runs
+        Command::Stream(_) | Command::Exec(_) | Command::GitFilter(_) | Command::Text(_) => {},
         _ => {
             f()
         }
     }
 }
 
-pub fn display_operational_info(command: &Command) {
-    let binding: String;
-    let command_deconstruct: (
-        &str, 
-        &String, 
-        &String, 
-        bool, 
-        String, 
-        usize, 
-        &crate::operations::Operation, 
-        crate::operations::Mode, 
-        &Option<crate::config::Shred>, 
-        bool, 
-        bool,
-        HashMode,
-        u32,
-        bool
-    ) = match command {
-        Command::Dir(options) => (
-            "directory", 
-            &options.source_dir, 
-            {
-                binding = options.target_dir.clone().unwrap_or("Not Specified".to_string());
-                &binding
-            },
-            options.delete_src,
-            human_bytes(*FILES_SIZE_BYTES.lock().unwrap() as f64),
-            options.threads,
-            &options.operation,
-            options.mode,
-            &options.shred,
-            options.anon,
-            options.verbose,
-            options.hash_with,
-            options.iterations,
-            options.dry_run,
-        ),
-        Command::File(options) => (
-            "file", 
-            &options.source_file, 
-            {
-                binding = options.target_dir.clone().unwrap_or("Not Specified".to_string());
-                &binding
-            },            
-            options.delete_src,
-            {
-                let source_file = &PathBuf::from(&options.source_file);
-                let mut file_size= String::new();
+// Everything the user should know about the run before it starts. It is printed as the operational info, and is part of the JSON output
+#[derive(Serialize, Debug, Clone)]
+pub struct OperationalInfo {
+    pub os: &'static str,
+    pub source_type: &'static str,
+    pub source: String,
+    pub target: Option<String>,
+    pub operation: Operation,
+    pub mode: Mode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s2k: Option<&'static str>,
+    // What the files of the age mode are encrypted with: "scrypt" (the password) or "x25519" (the recipients and the identities)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_unlock: Option<&'static str>,
+    pub hash_with: HashMode,
+    pub iterations: u32,
+    pub threads: usize,
+    pub delete_src: bool,
+    pub shred: bool,
+    pub anon: bool,
+    pub pad: PadPolicy,
+    pub compress: Compression,
+    pub armor: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+    pub total_dirs: Option<usize>,
+    pub total_files: Option<usize>,
+    pub total_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_excluded: Option<usize>,
+}
+
+fn age_unlock(mode: Mode, keys: &AgeKeyOptions) -> Option<&'static str> {
+    (mode == Mode::Age).then_some(if keys.is_empty() { "scrypt" } else { "x25519" })
+}
+
+// The totals come from the scanned source tree, when there is one
+pub fn operational_info(command: &Command, tree: Option<&SourceTree>) -> OperationalInfo {
+    let total_dirs = tree.map(|tree| tree.dirs.len());
+    let total_files = tree.map(|tree| tree.files.len());
+    let total_size_bytes = tree.map(|tree| tree.total_bytes);
+    let total_excluded = tree.map(|tree| tree.excluded.len());
+
+    match command {
+        Command::Dir(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "directory",
+            source: options.source_dir.to_string_lossy().into_owned(),
+            target: options.target_dir.as_ref().map(|dir| dir.to_string_lossy().into_owned()),
+            operation: options.operation.clone(),
+            mode: options.cipher.mode,
+            s2k: (options.cipher.mode == Mode::OpenPGP).then_some(openpgp::S2K),
+            age_unlock: age_unlock(options.cipher.mode, &options.age),
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: options.threads,
+            delete_src: options.delete_src,
+            shred: options.shred.is_some(),
+            anon: options.anon,
+            pad: options.pad,
+            compress: options.compress,
+            armor: options.armor,
+            dry_run: options.dry_run,
+            verbose: options.verbose,
+            total_dirs,
+            total_files,
+            total_size_bytes,
+            total_excluded,
+        },
+        Command::File(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "file",
+            source: options.source_file.to_string_lossy().into_owned(),
+            target: options.target_dir.as_ref().map(|dir| dir.to_string_lossy().into_owned()),
+            operation: options.operation.clone(),
+            mode: options.cipher.mode,
+            s2k: (options.cipher.mode == Mode::OpenPGP).then_some(openpgp::S2K),
+            age_unlock: age_unlock(options.cipher.mode, &options.age),
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: options.delete_src,
+            shred: options.shred.is_some(),
+            anon: options.anon,
+            pad: options.pad,
+            compress: options.compress,
+            armor: options.armor,
+            dry_run: options.dry_run,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: Some(1),
+            total_size_bytes: {
+                let source_file = &options.source_file;
+                let mut file_size = None;
                 if let Ok(total_files_size) = source_file.metadata() {
 
 
                     if cfg!(unix) {
                         #[cfg(target_os = "linux")]
                         {
-                            file_size = human_bytes(total_files_size.size() as f64)
+                            file_size = Some(total_files_size.size())
                         }
                     } else if cfg!(windows) {
                         #[cfg(target_os = "windows")]
                         {
-                            file_size = human_bytes(total_files_size.file_size() as f64)
+                            file_size = Some(total_files_size.file_size())
                         }
                     }
-
-                    
-                } else {
-                    file_size = "NA".to_string()
                 }
 
                 file_size
             },
-            1,
-            &options.operation,
-            options.mode,
-            &options.shred,
-            options.anon,
-            options.verbose,
-            options.hash_with,
-            options.iterations,
-            options.dry_run,
-        )
-    };
+            total_excluded: None,
+        },
+        Command::Sync(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "directory",
+            source: options.source_dir.to_string_lossy().into_owned(),
+            target: Some(options.target_dir.to_string_lossy().into_owned()),
+            operation: Operation::Encrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: options.threads,
+            delete_src: false,
+            shred: false,
+            anon: options.anon,
+            pad: options.pad,
+            compress: options.compress,
+            armor: options.armor,
+            dry_run: options.dry_run,
+            verbose: options.verbose,
+            total_dirs,
+            total_files,
+            total_size_bytes,
+            total_excluded,
+        },
+        Command::Watch(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "directory",
+            source: options.source_dir.to_string_lossy().into_owned(),
+            target: Some(options.target_dir.to_string_lossy().into_owned()),
+            operation: Operation::Encrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: options.threads,
+            delete_src: options.delete_src,
+            shred: options.shred.is_some(),
+            anon: options.anon,
+            pad: options.pad,
+            compress: options.compress,
+            armor: options.armor,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Pack(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "directory",
+            source: options.source_dir.to_string_lossy().into_owned(),
+            target: Some(options.container.to_string_lossy().into_owned()),
+            operation: Operation::Encrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: options.dry_run,
+            verbose: options.verbose,
+            total_dirs,
+            total_files,
+            total_size_bytes,
+            total_excluded,
+        },
+        Command::Unpack(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "container",
+            source: options.container.to_string_lossy().into_owned(),
+            target: options.target_dir.as_ref().map(|dir| dir.to_string_lossy().into_owned()),
+            operation: Operation::Decrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: options.dry_run,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: options.container.metadata().ok().map(|metadata| metadata.len()),
+            total_excluded: None,
+        },
+        Command::Mount(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "directory",
+            source: options.encrypted_dir.to_string_lossy().into_owned(),
+            target: Some(options.mountpoint.to_string_lossy().into_owned()),
+            operation: Operation::Decrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: options.anon,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Edit(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "file",
+            source: options.file.to_string_lossy().into_owned(),
+            target: None,
+            operation: Operation::Decrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: true,
+            anon: options.anon,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Values(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "document",
+            source: options.source_file.to_string_lossy().into_owned(),
+            target: Some(options.target_file.as_ref().unwrap_or(&options.source_file).to_string_lossy().into_owned()),
+            operation: options.operation.clone(),
+            mode: Mode::GCM,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Exec(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "dotenv",
+            source: options.env_files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>().join(", "),
+            target: None,
+            operation: Operation::Decrypt,
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: options.anon,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        // The files are encrypted with AES-256-GCM-SIV, with the key derived once by init or unlock
+        Command::GitFilter(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "repository",
+            source: ".".to_string(),
+            target: None,
+            operation: Operation::Encrypt,
+            mode: Mode::GCM,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.key_options().map_or(HashMode::Argon2, |key_options| key_options.hash_with),
+            iterations: options.key_options().map_or(10, |key_options| key_options.iterations),
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: false,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Text(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "message",
+            source: "stdin".to_string(),
+            target: Some("stdout".to_string()),
+            operation: options.operation.clone(),
+            mode: options.cipher.mode,
+            s2k: (options.cipher.mode == Mode::OpenPGP).then_some(openpgp::S2K),
+            age_unlock: age_unlock(options.cipher.mode, &options.age),
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: true,
+            dry_run: false,
+            verbose: false,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+        Command::Stream(options) => OperationalInfo {
+            os: env::consts::OS,
+            source_type: "stream",
+            source: "stdin".to_string(),
+            target: Some("stdout".to_string()),
+            operation: options.operation.clone(),
+            mode: options.cipher.mode,
+            s2k: None,
+            age_unlock: None,
+            hash_with: options.credentials.hash_with,
+            iterations: options.credentials.iterations,
+            threads: 1,
+            delete_src: false,
+            shred: false,
+            anon: false,
+            pad: PadPolicy::None,
+            compress: Compression::None,
+            armor: false,
+            dry_run: false,
+            verbose: options.verbose,
+            total_dirs: None,
+            total_files: None,
+            total_size_bytes: None,
+            total_excluded: None,
+        },
+    }
+}
 
-    let padding = 12 - command_deconstruct.0.len(); // Calculate how many spaces to add
+pub fn display_operational_info(info: &OperationalInfo) {
+    let padding = 12 - info.source_type.len(); // Calculate how many spaces to add
 
-    println!("\nNote: This software is issued under the MIT or Apache 2.0 License. Understand what it means before use.\n");
-    println!("\n**** Operational Info ****\n");
-    println!("Operating system                                  : {}", env::consts::OS);
-    println!("The source {} you provided {:>width$}             : {}", command_deconstruct.0, " ".repeat(padding), command_deconstruct.1, width = padding);
-    println!("The target directory you provided                 : {}", command_deconstruct.2);
+    outln!("\nNote: This software is issued under the MIT or Apache 2.0 License. Understand what it means before use.\n");
+    outln!("\n**** Operational Info ****\n");
+    outln!("Operating system                                  : {}", info.os);
+    outln!("The source {} you provided {:>width$}             : {}", info.source_type, " ".repeat(padding), info.source, width = padding);
+    outln!("The target directory you provided                 : {}", info.target.as_deref().unwrap_or("Not Specified"));
 
-    let file_fate = if command_deconstruct.8.is_some() {
+    let file_fate = if info.shred {
         "Shred".to_string()
-    } else if command_deconstruct.3 {
+    } else if info.delete_src {
         "Delete".to_string()
     } else {
         "Neither (files won't be removed)".to_string()
     };
 
-    println!("Dry Run enabled?                                  : {}", command_deconstruct.13.to_string().bright_white().blink());
-    println!("Shred or Delete the source file(s)?               : {}", file_fate.bright_green().bold().blink());
-    println!("Anonymize the source file(s)?                     : {}", command_deconstruct.9);
-    println!("Verbose mode enabled?                             : {}", command_deconstruct.10.to_string().bright_white().blink());
+    outln!("Dry Run enabled?                                  : {}", info.dry_run.to_string().bright_white().blink());
+    outln!("Shred or Delete the source file(s)?               : {}", file_fate.bright_green().bold().blink());
+    outln!("Anonymize the source file(s)?                     : {}", info.anon);
+
+    if info.pad != PadPolicy::None {
+    outln!("Pad the encrypted file sizes with                 : {:?}", info.pad);
+    }
 
-    if let Command::Dir(_) = command {
-    println!("Total target sub-directories (to be created)      : {}", DIR_LIST.lock().unwrap().to_vec().capacity());
-    println!("Total target files (to be created)                : {}", FILE_LIST.lock().unwrap().to_vec().capacity());
+    if info.compress != Compression::None {
+    outln!("Compress the files before encryption with         : {}", info.compress);
     }
 
-    println!("Total size of source {} {:>width$}                : {}", command_deconstruct.0, " ".repeat(padding), command_deconstruct.4, width = padding);
-    println!("Total threads about to be used                    : {}", command_deconstruct.5);
-    println!("Hashing function employed                         : {:?}", command_deconstruct.11);
-    println!("Iterations for the hashing function               : {}", command_deconstruct.12);
-    println!("Operation chosen                                  : {}", command_deconstruct.6.to_str().bright_blue().bold().blink());
-    println!("Mode chosen                                       : AES-256-{:?}", command_deconstruct.7);
-    println!("\nThe encrypted files MUST be of '.enom' extension");
-    println!("\n**************************\n");
+    if info.armor {
+    outln!("Write the encrypted files as armored text?        : {}", info.armor);
+    }
+    outln!("Verbose mode enabled?                             : {}", info.verbose.to_string().bright_white().blink());
+
+    if let (Some(total_dirs), Some(total_files)) = (info.total_dirs, info.total_files) {
+        if info.source_type == "directory" {
+    outln!("Total target sub-directories (to be created)      : {}", total_dirs);
+    outln!("Total target files (to be created)                : {}", total_files);
+        }
+    }
+
+    if let Some(total_excluded) = info.total_excluded {
+        outln!("Total files and directories excluded              : {}", total_excluded);
+    }
+
+    let total_size = info.total_size_bytes.map(|size| human_bytes(size as f64)).unwrap_or("NA".to_string());
+
+    outln!("Total size of source {} {:>width$}                : {}", info.source_type, " ".repeat(padding), total_size, width = padding);
+    outln!("Total threads about to be used                    : {}", info.threads);
+    if let Some(s2k) = info.s2k {
+    outln!("Key derivation (S2K) of the OpenPGP messages      : {}", s2k);
+    } else if let Some(age_unlock) = info.age_unlock {
+    outln!("Recipients of the age files                       : {}", age_unlock);
+    } else {
+    outln!("Hashing function employed                         : {:?}", info.hash_with);
+    outln!("Iterations for the hashing function               : {}", info.iterations);
+    }
+    outln!("Operation chosen                                  : {}", info.operation.to_str().bright_blue().bold().blink());
+    if info.mode == Mode::Age {
+    outln!("Mode chosen                                       : age v1 (ChaCha20-Poly1305)");
+    } else {
+    outln!("Mode chosen                                       : AES-256-{:?}", info.mode);
+    }
+    outln!("\nThe encrypted files MUST be of '.enom' extension");
+    outln!("\n**************************\n");
     
 }
+
+// Lists the files and directories the include and exclude patterns or the ignore files left out
+pub fn display_excluded(tree: &SourceTree) {
+    if tree.excluded.is_empty() {
+        return;
+    }
+
+    outln!("Excluded files and directories:");
+
+    for path in &tree.excluded {
+        outln!("  {:?}", path);
+    }
+
+    outln!();
+}
+
+// Lists the entries of a container, one per line, the way ls -l does
+pub fn display_entries(entries: &[Entry]) {
+    for entry in entries {
+        let kind = match entry.kind {
+            EntryKind::Dir => "d",
+            EntryKind::File => "-",
+            EntryKind::Symlink => "l",
+            EntryKind::HardLink => "h",
+        };
+
+        let modified = entry
+            .modified
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, 0))
+            .map(|modified| modified.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+
+        let target = entry.target.as_ref().map(|target| format!(" -> {}", target.to_string_lossy())).unwrap_or_default();
+
+        outln!("{} {:>10} {:16} {}{}", kind, human_bytes(entry.size as f64), modified, entry.path.to_string_lossy(), target);
+    }
+
+    outln!("\nTotal entries: {}", entries.len());
+}
+
+// Lists every failed file of the run along with the stage it failed at and the cause
+pub fn display_failures(report: &RunReport) {
+    outln!("\nFailed files:");
+
+    for failure in report.failures() {
+        let stage = failure.stage.map(|stage| stage.to_string()).unwrap_or_default();
+        let cause = failure.cause.as_deref().unwrap_or_default();
+
+        outln!("  [{}] {:?} :: {}", stage.bright_red(), failure.source, cause);
+    }
+}
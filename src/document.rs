@@ -0,0 +1,836 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use base64::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::{
+    error::RufendecError,
+    operations::{Cipher, Mode},
+};
+
+/* How are the values of a document encrypted?
+-----------------------
+A YAML, JSON, TOML or .env document is parsed, and every scalar value in it (a string, a number, a boolean, a null or a date) is encrypted
+on its own with AES-256-GCM, while the keys, the nesting and the order stay readable. An encrypted value is written as a string:
+    ENC[AES256_GCM,data:<cipher text>,iv:<nonce>,tag:<tag>,type:<type>]
+where the type tells what the value was, so a number comes back as a number. The path of the value is the associated data, so a value
+cannot be moved to another key. It is made of the keys leading to it, each prefixed with its length in bytes, and of the list indexes in
+brackets, such as "8:database5:hosts[0]". So no two places in a document share a path, even when a key holds a colon or looks like an index.
+The document MAC is the SHA256 hash of the path, the type and the plain value of every value in the document order. It is encrypted the
+same way and kept in the metadata along with the format version: under a top-level "rufendec" key, or as the rufendec_version and
+rufendec_mac lines of a .env file. So a value which was removed, added, swapped or reordered is noticed on decrypt, even though every value
+on its own still decrypts.
+The comments of a .env file are kept, including the ones after a value. The comments of the other formats are not, since they are parsed into plain values.
+*/
+
+const METADATA_KEY: &str = "rufendec";
+const DOTENV_VERSION_KEY: &str = "rufendec_version";
+const DOTENV_MAC_KEY: &str = "rufendec_mac";
+const DOCUMENT_VERSION: i64 = 2;
+
+// The path the MAC is encrypted under. No value has it, since the path of every value starts with a length or a bracket
+const MAC_PATH: &str = "rufendec:mac:";
+
+const VALUE_PREFIX: &str = "ENC[AES256_GCM,";
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+// The formats whose values can be encrypted
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    Yaml,
+    Json,
+    Toml,
+    /// KEY=VALUE lines, such as a .env file
+    Dotenv,
+}
+
+impl DocumentFormat {
+    /* Tells the format from the file name. A file named ".env", ".env.<anything>" or "<anything>.env" is a dotenv file.
+    A ".enom" file is a whole file encrypted by rufendec, whatever name it had before, so it is never taken as a document
+    */
+    pub fn from_path(path: &Path) -> Option<DocumentFormat> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".enom") {
+            return None;
+        }
+
+        if name == ".env" || name.starts_with(".env.") {
+            return Some(DocumentFormat::Dotenv);
+        }
+
+        match name.rsplit_once('.')?.1 {
+            "yaml" | "yml" => Some(DocumentFormat::Yaml),
+            "json" => Some(DocumentFormat::Json),
+            "toml" => Some(DocumentFormat::Toml),
+            "env" => Some(DocumentFormat::Dotenv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ValueType {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Null,
+    Datetime,
+}
+
+impl ValueType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ValueType::Str => "str",
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Bool => "bool",
+            ValueType::Null => "null",
+            ValueType::Datetime => "datetime",
+        }
+    }
+
+    fn parse(value_type: &str) -> Option<ValueType> {
+        [ValueType::Str, ValueType::Int, ValueType::Float, ValueType::Bool, ValueType::Null, ValueType::Datetime]
+            .into_iter()
+            .find(|known| known.as_str() == value_type)
+    }
+}
+
+// A scalar value of any of the formats, as its type and its text
+struct Scalar {
+    value_type: ValueType,
+    text: String,
+}
+
+impl Scalar {
+    fn string(text: String) -> Scalar {
+        Scalar { value_type: ValueType::Str, text }
+    }
+}
+
+type Visitor<'a> = dyn FnMut(&str, Scalar) -> Result<Scalar, RufendecError> + 'a;
+
+struct Metadata {
+    version: i64,
+    mac: String,
+}
+
+// A parsed document. The values are visited in the document order, and each is replaced by what the visitor returns
+trait Document: Sized {
+    fn parse(text: &str) -> Result<Self, RufendecError>;
+    fn walk(&mut self, visit: &mut Visitor) -> Result<(), RufendecError>;
+    fn has_metadata(&self) -> bool;
+    fn take_metadata(&mut self) -> Option<Metadata>;
+    fn put_metadata(&mut self, metadata: Metadata);
+    fn render(&self) -> Result<String, RufendecError>;
+}
+
+fn invalid(format: &str, e: impl std::fmt::Display) -> RufendecError {
+    RufendecError::Validation(format!("The document is not valid {} :: {}", format, e))
+}
+
+fn bad_value(path: &str, text: &str, value_type: ValueType) -> RufendecError {
+    RufendecError::Runtime(format!("The value at {:?} is not a valid {} :: {:?}", path, value_type.as_str(), text))
+}
+
+// The path of a value under a key. The key is prefixed with its length, so it cannot run into the next one
+fn child_path(path: &str, key: &str) -> String {
+    format!("{}{}:{}", path, key.len(), key)
+}
+
+// The path of a value at a list index
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+impl Document for serde_json::Value {
+    fn parse(text: &str) -> Result<Self, RufendecError> {
+        match serde_json::from_str(text).map_err(|e| invalid("JSON", e))? {
+            value @ serde_json::Value::Object(_) => Ok(value),
+            _ => Err(invalid("JSON", "the document must be an object at the top")),
+        }
+    }
+
+    fn walk(&mut self, visit: &mut Visitor) -> Result<(), RufendecError> {
+        walk_json(self, "", visit)
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.get(METADATA_KEY).is_some()
+    }
+
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        let metadata = self.as_object_mut()?.shift_remove(METADATA_KEY)?;
+
+        Some(Metadata {
+            version: metadata.get("version")?.as_i64()?,
+            mac: metadata.get("mac")?.as_str()?.to_string(),
+        })
+    }
+
+    fn put_metadata(&mut self, metadata: Metadata) {
+        if let Some(object) = self.as_object_mut() {
+            object.insert(
+                METADATA_KEY.to_string(),
+                serde_json::json!({ "version": metadata.version, "mac": metadata.mac }),
+            );
+        }
+    }
+
+    fn render(&self) -> Result<String, RufendecError> {
+        serde_json::to_string_pretty(self)
+            .map(|text| text + "\n")
+            .map_err(|e| RufendecError::Runtime(format!("Failed to write the JSON document :: {}", e)))
+    }
+}
+
+fn walk_json(value: &mut serde_json::Value, path: &str, visit: &mut Visitor) -> Result<(), RufendecError> {
+    use serde_json::Value;
+
+    let scalar = match value {
+        Value::Object(object) => {
+            return object.iter_mut().try_for_each(|(key, value)| walk_json(value, &child_path(path, key), visit));
+        }
+        Value::Array(items) => {
+            return items.iter_mut().enumerate().try_for_each(|(index, value)| walk_json(value, &index_path(path, index), visit));
+        }
+        Value::String(text) => Scalar::string(text.clone()),
+        Value::Number(number) if number.is_f64() => Scalar { value_type: ValueType::Float, text: number.to_string() },
+        Value::Number(number) => Scalar { value_type: ValueType::Int, text: number.to_string() },
+        Value::Bool(flag) => Scalar { value_type: ValueType::Bool, text: flag.to_string() },
+        Value::Null => Scalar { value_type: ValueType::Null, text: String::new() },
+    };
+
+    let Scalar { value_type, text } = visit(path, scalar)?;
+
+    *value = match value_type {
+        ValueType::Str | ValueType::Datetime => Value::String(text),
+        ValueType::Int | ValueType::Float => {
+            Value::Number(serde_json::from_str(&text).map_err(|_| bad_value(path, &text, value_type))?)
+        }
+        ValueType::Bool => Value::Bool(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+        ValueType::Null => Value::Null,
+    };
+
+    Ok(())
+}
+
+impl Document for serde_norway::Value {
+    fn parse(text: &str) -> Result<Self, RufendecError> {
+        match serde_norway::from_str(text).map_err(|e| invalid("YAML", e))? {
+            value @ serde_norway::Value::Mapping(_) => Ok(value),
+            _ => Err(invalid("YAML", "the document must be a mapping at the top")),
+        }
+    }
+
+    fn walk(&mut self, visit: &mut Visitor) -> Result<(), RufendecError> {
+        walk_yaml(self, "", visit)
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.get(METADATA_KEY).is_some()
+    }
+
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        let metadata = self.as_mapping_mut()?.shift_remove(METADATA_KEY)?;
+
+        Some(Metadata {
+            version: metadata.get("version")?.as_i64()?,
+            mac: metadata.get("mac")?.as_str()?.to_string(),
+        })
+    }
+
+    fn put_metadata(&mut self, metadata: Metadata) {
+        if let Some(mapping) = self.as_mapping_mut() {
+            let mut entry = serde_norway::Mapping::new();
+            entry.insert("version".into(), metadata.version.into());
+            entry.insert("mac".into(), metadata.mac.into());
+
+            mapping.insert(METADATA_KEY.into(), serde_norway::Value::Mapping(entry));
+        }
+    }
+
+    fn render(&self) -> Result<String, RufendecError> {
+        serde_norway::to_string(self).map_err(|e| RufendecError::Runtime(format!("Failed to write the YAML document :: {}", e)))
+    }
+}
+
+// The text of a mapping key. Keys which are lists or mappings themselves cannot be part of a path
+fn yaml_key(key: &serde_norway::Value) -> Result<String, RufendecError> {
+    use serde_norway::Value;
+
+    match key {
+        Value::String(key) => Ok(key.clone()),
+        Value::Number(key) => Ok(key.to_string()),
+        Value::Bool(key) => Ok(key.to_string()),
+        Value::Null => Ok("null".to_string()),
+        _ => Err(invalid("YAML", "the keys must be strings, numbers or booleans")),
+    }
+}
+
+fn walk_yaml(value: &mut serde_norway::Value, path: &str, visit: &mut Visitor) -> Result<(), RufendecError> {
+    use serde_norway::Value;
+
+    let scalar = match value {
+        Value::Mapping(mapping) => {
+            return mapping.iter_mut().try_for_each(|(key, value)| walk_yaml(value, &child_path(path, &yaml_key(key)?), visit));
+        }
+        Value::Sequence(items) => {
+            return items.iter_mut().enumerate().try_for_each(|(index, value)| walk_yaml(value, &index_path(path, index), visit));
+        }
+        // The tag stays readable, and the value under it is encrypted
+        Value::Tagged(tagged) => return walk_yaml(&mut tagged.value, path, visit),
+        Value::String(text) => Scalar::string(text.clone()),
+        Value::Number(number) if number.is_f64() => Scalar { value_type: ValueType::Float, text: number.to_string() },
+        Value::Number(number) => Scalar { value_type: ValueType::Int, text: number.to_string() },
+        Value::Bool(flag) => Scalar { value_type: ValueType::Bool, text: flag.to_string() },
+        Value::Null => Scalar { value_type: ValueType::Null, text: String::new() },
+    };
+
+    let Scalar { value_type, text } = visit(path, scalar)?;
+
+    *value = match value_type {
+        ValueType::Str | ValueType::Datetime => Value::String(text),
+        ValueType::Int | ValueType::Float => {
+            Value::Number(serde_norway::from_str(&text).map_err(|_| bad_value(path, &text, value_type))?)
+        }
+        ValueType::Bool => Value::Bool(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+        ValueType::Null => Value::Null,
+    };
+
+    Ok(())
+}
+
+impl Document for toml::Table {
+    fn parse(text: &str) -> Result<Self, RufendecError> {
+        text.parse().map_err(|e| invalid("TOML", e))
+    }
+
+    fn walk(&mut self, visit: &mut Visitor) -> Result<(), RufendecError> {
+        self.iter_mut().try_for_each(|(key, value)| walk_toml(value, &child_path("", key), visit))
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.contains_key(METADATA_KEY)
+    }
+
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        let metadata = self.remove(METADATA_KEY)?;
+
+        Some(Metadata {
+            version: metadata.get("version")?.as_integer()?,
+            mac: metadata.get("mac")?.as_str()?.to_string(),
+        })
+    }
+
+    fn put_metadata(&mut self, metadata: Metadata) {
+        let mut entry = toml::Table::new();
+        entry.insert("version".to_string(), metadata.version.into());
+        entry.insert("mac".to_string(), metadata.mac.into());
+
+        self.insert(METADATA_KEY.to_string(), toml::Value::Table(entry));
+    }
+
+    fn render(&self) -> Result<String, RufendecError> {
+        toml::to_string(self).map_err(|e| RufendecError::Runtime(format!("Failed to write the TOML document :: {}", e)))
+    }
+}
+
+fn walk_toml(value: &mut toml::Value, path: &str, visit: &mut Visitor) -> Result<(), RufendecError> {
+    use toml::Value;
+
+    let scalar = match value {
+        Value::Table(table) => {
+            return table.iter_mut().try_for_each(|(key, value)| walk_toml(value, &child_path(path, key), visit));
+        }
+        Value::Array(items) => {
+            return items.iter_mut().enumerate().try_for_each(|(index, value)| walk_toml(value, &index_path(path, index), visit));
+        }
+        Value::String(text) => Scalar::string(text.clone()),
+        Value::Integer(number) => Scalar { value_type: ValueType::Int, text: number.to_string() },
+        Value::Float(number) => Scalar { value_type: ValueType::Float, text: number.to_string() },
+        Value::Boolean(flag) => Scalar { value_type: ValueType::Bool, text: flag.to_string() },
+        Value::Datetime(datetime) => Scalar { value_type: ValueType::Datetime, text: datetime.to_string() },
+    };
+
+    let Scalar { value_type, text } = visit(path, scalar)?;
+
+    *value = match value_type {
+        ValueType::Str | ValueType::Null => Value::String(text),
+        ValueType::Int => Value::Integer(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+        ValueType::Float => Value::Float(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+        ValueType::Bool => Value::Boolean(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+        ValueType::Datetime => Value::Datetime(text.parse().map_err(|_| bad_value(path, &text, value_type))?),
+    };
+
+    Ok(())
+}
+
+// The lines of a .env file. Blank lines and comments are kept as they are
+struct Dotenv {
+    lines: Vec<DotenvLine>,
+}
+
+enum DotenvLine {
+    Verbatim(String),
+    // The comment after the value, if any, is kept along with the spaces before it
+    Entry { export: bool, key: String, value: String, comment: String },
+}
+
+impl Dotenv {
+    fn entry_value(&self, wanted: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            DotenvLine::Entry { key, value, .. } if key == wanted => Some(value.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/* The value after the equals sign, and whatever follows it. A double quoted value understands the \n, \r, \t, \" and \\ escapes,
+a single quoted value is taken as it is, and an unquoted value ends at a " #" comment. Returns None for a quote which is not closed on
+the same line
+*/
+fn parse_dotenv_value(value: &str) -> Option<(String, String)> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = rest.char_indices();
+
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '"' => return Some((parsed, rest[at + 1..].to_string())),
+                '\\' => match chars.next()?.1 {
+                    'n' => parsed.push('\n'),
+                    'r' => parsed.push('\r'),
+                    't' => parsed.push('\t'),
+                    other => parsed.push(other),
+                },
+                c => parsed.push(c),
+            }
+        }
+
+        None
+    } else if let Some(rest) = value.strip_prefix('\'') {
+        rest.split_once('\'').map(|(value, comment)| (value.to_string(), comment.to_string()))
+    } else {
+        match value.find(" #") {
+            Some(at) => Some((value[..at].trim_end().to_string(), value[value[..at].trim_end().len()..].to_string())),
+            None => Some((value.to_string(), String::new())),
+        }
+    }
+}
+
+// Quotes the value only when it needs to be. Single quotes are preferred, since nothing inside them is expanded
+fn render_dotenv_value(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii_alphanumeric() || "_-.,:/@+=[]".contains(c)) {
+        value.to_string()
+    } else if !value.contains('\'') && !value.chars().any(char::is_control) {
+        format!("'{}'", value)
+    } else {
+        let mut quoted = String::from('"');
+
+        for c in value.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                '"' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c => quoted.push(c),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    }
+}
+
+impl Document for Dotenv {
+    fn parse(text: &str) -> Result<Self, RufendecError> {
+        let mut lines = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                lines.push(DotenvLine::Verbatim(line.to_string()));
+                continue;
+            }
+
+            let (export, rest) = match trimmed.strip_prefix("export ") {
+                Some(rest) => (true, rest.trim_start()),
+                None => (false, trimmed),
+            };
+
+            let Some((key, value)) = rest.split_once('=') else {
+                return Err(invalid("dotenv", format!("line {} is not a KEY=VALUE line", number + 1)));
+            };
+
+            let (value, comment) = parse_dotenv_value(value.trim()).ok_or_else(|| {
+                invalid("dotenv", format!("line {} has a quote which is not closed. Values spanning lines are not supported", number + 1))
+            })?;
+
+            lines.push(DotenvLine::Entry {
+                export,
+                key: key.trim().to_string(),
+                value,
+                comment,
+            });
+        }
+
+        Ok(Dotenv { lines })
+    }
+
+    fn walk(&mut self, visit: &mut Visitor) -> Result<(), RufendecError> {
+        for line in self.lines.iter_mut() {
+            if let DotenvLine::Entry { key, value, .. } = line {
+                *value = visit(&child_path("", key), Scalar::string(std::mem::take(value)))?.text;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.entry_value(DOTENV_MAC_KEY).is_some()
+    }
+
+    fn take_metadata(&mut self) -> Option<Metadata> {
+        let metadata = Metadata {
+            version: self.entry_value(DOTENV_VERSION_KEY)?.parse().ok()?,
+            mac: self.entry_value(DOTENV_MAC_KEY)?.to_string(),
+        };
+
+        self.lines.retain(|line| {
+            !matches!(line, DotenvLine::Entry { key, .. } if key == DOTENV_VERSION_KEY || key == DOTENV_MAC_KEY)
+        });
+
+        Some(metadata)
+    }
+
+    fn put_metadata(&mut self, metadata: Metadata) {
+        self.lines.push(DotenvLine::Entry {
+            export: false,
+            key: DOTENV_VERSION_KEY.to_string(),
+            value: metadata.version.to_string(),
+            comment: String::new(),
+        });
+        self.lines.push(DotenvLine::Entry {
+            export: false,
+            key: DOTENV_MAC_KEY.to_string(),
+            value: metadata.mac,
+            comment: String::new(),
+        });
+    }
+
+    fn render(&self) -> Result<String, RufendecError> {
+        Ok(self
+            .lines
+            .iter()
+            .map(|line| match line {
+                DotenvLine::Verbatim(line) => format!("{}\n", line),
+                DotenvLine::Entry { export, key, value, comment } => {
+                    format!("{}{}={}{}\n", if *export { "export " } else { "" }, key, render_dotenv_value(value), comment)
+                }
+            })
+            .collect())
+    }
+}
+
+// Adds a value to the document MAC. Every part is prefixed with its length, so the parts cannot run into each other
+fn hash_value(hasher: &mut Sha256, path: &str, scalar: &Scalar) {
+    for part in [path, scalar.value_type.as_str(), scalar.text.as_str()] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+}
+
+fn encrypt_value(cipher: &Cipher, path: &str, scalar: &Scalar) -> Result<String, RufendecError> {
+    // The cipher text, followed by the tag and the Nonce
+    let encrypted = cipher
+        .encrypt_bytes_with_aad(scalar.text.as_bytes(), path.as_bytes())
+        .ok_or_else(|| RufendecError::Runtime(format!("The value at {:?} could not be encrypted", path)))?;
+
+    let (data, rest) = encrypted.split_at(encrypted.len() - TAG_LEN - NONCE_LEN);
+    let (tag, nonce) = rest.split_at(TAG_LEN);
+
+    Ok(format!(
+        "{}data:{},iv:{},tag:{},type:{}]",
+        VALUE_PREFIX,
+        BASE64_STANDARD.encode(data),
+        BASE64_STANDARD.encode(nonce),
+        BASE64_STANDARD.encode(tag),
+        scalar.value_type.as_str()
+    ))
+}
+
+// Returns None when the text is not an encrypted value, or when it does not decrypt under the path
+fn decrypt_value(cipher: &Cipher, path: &str, text: &str) -> Option<Scalar> {
+    let fields = text.strip_prefix(VALUE_PREFIX)?.strip_suffix(']')?;
+
+    let mut data = None;
+    let mut nonce = None;
+    let mut tag = None;
+    let mut value_type = None;
+
+    for field in fields.split(',') {
+        match field.split_once(':')? {
+            ("data", value) => data = BASE64_STANDARD.decode(value).ok(),
+            ("iv", value) => nonce = BASE64_STANDARD.decode(value).ok(),
+            ("tag", value) => tag = BASE64_STANDARD.decode(value).ok(),
+            ("type", value) => value_type = ValueType::parse(value),
+            _ => return None,
+        }
+    }
+
+    let encrypted = [data?, tag?, nonce?].concat();
+    let decrypted = cipher.decrypt_bytes_with_aad(&encrypted, path.as_bytes())?;
+
+    Some(Scalar {
+        value_type: value_type?,
+        text: String::from_utf8(decrypted).ok()?,
+    })
+}
+
+fn require_gcm(cipher: &Cipher) -> Result<(), RufendecError> {
+    match cipher.mode() {
+        Mode::GCM => Ok(()),
+        Mode::ECB => Err(RufendecError::Validation(
+            "The values of a document are only encrypted in the GCM mode, since ECB cannot authenticate them".to_string(),
+        )),
+        Mode::OpenPGP | Mode::Age => Err(RufendecError::Validation(
+            "The values of a document are only encrypted in the GCM mode, since gpg and age cannot read them anyway".to_string(),
+        )),
+    }
+}
+
+fn encrypt<D: Document>(cipher: &Cipher, text: &str) -> Result<String, RufendecError> {
+    let mut document = D::parse(text)?;
+
+    if document.has_metadata() {
+        return Err(RufendecError::Validation("The values of the document are encrypted already".to_string()));
+    }
+
+    let mut hasher = Sha256::new();
+
+    document.walk(&mut |path, scalar| {
+        hash_value(&mut hasher, path, &scalar);
+        Ok(Scalar::string(encrypt_value(cipher, path, &scalar)?))
+    })?;
+
+    let digest = Scalar::string(format!("{:x}", hasher.finalize()));
+
+    document.put_metadata(Metadata {
+        version: DOCUMENT_VERSION,
+        mac: encrypt_value(cipher, MAC_PATH, &digest)?,
+    });
+
+    document.render()
+}
+
+fn decrypt<D: Document>(cipher: &Cipher, text: &str) -> Result<String, RufendecError> {
+    let mut document = D::parse(text)?;
+
+    let metadata = document
+        .take_metadata()
+        .ok_or_else(|| RufendecError::Validation("The values of the document were not encrypted by rufendec".to_string()))?;
+
+    if metadata.version != DOCUMENT_VERSION {
+        return Err(RufendecError::Validation(format!(
+            "The document has the format version {}, which this version of rufendec cannot read",
+            metadata.version
+        )));
+    }
+
+    // The MAC decrypts with the right key only, so a wrong key shows before any value is looked at
+    let mac = decrypt_value(cipher, MAC_PATH, &metadata.mac).ok_or_else(|| {
+        RufendecError::Credentials(
+            "The document could not be decrypted. Check the password, the salt and the hashing function".to_string(),
+        )
+    })?;
+
+    let mut hasher = Sha256::new();
+
+    document.walk(&mut |path, scalar| {
+        let decrypted = decrypt_value(cipher, path, &scalar.text).ok_or_else(|| {
+            RufendecError::Runtime(format!("The value at {:?} could not be authenticated, so the document was modified", path))
+        })?;
+
+        hash_value(&mut hasher, path, &decrypted);
+        Ok(decrypted)
+    })?;
+
+    if format!("{:x}", hasher.finalize()) != mac.text {
+        return Err(RufendecError::Runtime(
+            "The MAC of the document does not match, so values were added, removed or reordered".to_string(),
+        ));
+    }
+
+    document.render()
+}
+
+// The KEY=VALUE pairs of a dotenv document in the document order, with the quotes and the escapes of the values resolved
+pub fn parse_dotenv(text: &str) -> Result<Vec<(String, String)>, RufendecError> {
+    Ok(Dotenv::parse(text)?
+        .lines
+        .into_iter()
+        .filter_map(|line| match line {
+            DotenvLine::Entry { key, value, .. } => Some((key, value)),
+            DotenvLine::Verbatim(_) => None,
+        })
+        .collect())
+}
+
+// Encrypts every value of the document, and adds the metadata with the MAC. Only the GCM mode is supported
+pub fn encrypt_document(cipher: &Cipher, text: &str, format: DocumentFormat) -> Result<String, RufendecError> {
+    require_gcm(cipher)?;
+
+    match format {
+        DocumentFormat::Yaml => encrypt::<serde_norway::Value>(cipher, text),
+        DocumentFormat::Json => encrypt::<serde_json::Value>(cipher, text),
+        DocumentFormat::Toml => encrypt::<toml::Table>(cipher, text),
+        DocumentFormat::Dotenv => encrypt::<Dotenv>(cipher, text),
+    }
+}
+
+// Decrypts every value of the document after checking the MAC, and drops the metadata
+pub fn decrypt_document(cipher: &Cipher, text: &str, format: DocumentFormat) -> Result<String, RufendecError> {
+    require_gcm(cipher)?;
+
+    match format {
+        DocumentFormat::Yaml => decrypt::<serde_norway::Value>(cipher, text),
+        DocumentFormat::Json => decrypt::<serde_json::Value>(cipher, text),
+        DocumentFormat::Toml => decrypt::<toml::Table>(cipher, text),
+        DocumentFormat::Dotenv => decrypt::<Dotenv>(cipher, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Key;
+
+    const YAML: &str = "database:\n  host: db.local\n  port: 5432\n  hosts:\n  - a\n  - b\n";
+
+    fn cipher() -> Cipher {
+        Cipher::new(Mode::GCM, Key::from_bytes([7u8; 32]))
+    }
+
+    #[test]
+    fn document_round_trip() {
+        let cipher = cipher();
+
+        let documents = [
+            (DocumentFormat::Yaml, YAML),
+            (DocumentFormat::Json, "{\"database\":{\"port\":5432,\"hosts\":[\"a\",\"b\"],\"tls\":true}}"),
+            (DocumentFormat::Toml, "[database]\nport = 5432\nhosts = [\"a\", \"b\"]\n"),
+            (DocumentFormat::Dotenv, "# the database\nDB_HOST=db.local\nexport DB_PORT=5432 # default\n"),
+        ];
+
+        for (format, text) in documents {
+            let encrypted = encrypt_document(&cipher, text, format).unwrap();
+            assert!(!encrypted.contains("db.local") && !encrypted.contains("5432"));
+
+            let decrypted = decrypt_document(&cipher, &encrypted, format).unwrap();
+            assert_eq!(decrypt_document(&cipher, &encrypt_document(&cipher, &decrypted, format).unwrap(), format).unwrap(), decrypted);
+
+            match format {
+                DocumentFormat::Yaml => assert_eq!(
+                    serde_norway::from_str::<serde_norway::Value>(&decrypted).unwrap(),
+                    serde_norway::from_str::<serde_norway::Value>(text).unwrap()
+                ),
+                DocumentFormat::Json => assert_eq!(
+                    serde_json::from_str::<serde_json::Value>(&decrypted).unwrap(),
+                    serde_json::from_str::<serde_json::Value>(text).unwrap()
+                ),
+                DocumentFormat::Toml => assert_eq!(decrypted.parse::<toml::Table>().unwrap(), text.parse::<toml::Table>().unwrap()),
+                DocumentFormat::Dotenv => assert_eq!(decrypted, text),
+            }
+        }
+    }
+
+    #[test]
+    fn modified_value_is_rejected() {
+        let cipher = cipher();
+        let encrypted = encrypt_document(&cipher, YAML, DocumentFormat::Yaml).unwrap();
+
+        let mut document: serde_norway::Value = serde_norway::from_str(&encrypted).unwrap();
+        let host = document["database"]["host"].as_str().unwrap().to_string();
+        let data = host.split("data:").nth(1).unwrap();
+        let flipped = if data.starts_with('A') { "B" } else { "A" };
+        document["database"]["host"] =
+            serde_norway::Value::String(host.replacen(&format!("data:{}", &data[..1]), &format!("data:{}", flipped), 1));
+
+        let tampered = serde_norway::to_string(&document).unwrap();
+        assert!(matches!(decrypt_document(&cipher, &tampered, DocumentFormat::Yaml), Err(RufendecError::Runtime(_))));
+    }
+
+    #[test]
+    fn moved_value_is_rejected() {
+        let cipher = cipher();
+        let encrypted = encrypt_document(&cipher, "{\"a\":\"one\",\"b\":\"two\"}", DocumentFormat::Json).unwrap();
+
+        let mut document: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+        let a = document["a"].clone();
+        document["a"] = document["b"].clone();
+        document["b"] = a;
+
+        let tampered = serde_json::to_string(&document).unwrap();
+        assert!(matches!(decrypt_document(&cipher, &tampered, DocumentFormat::Json), Err(RufendecError::Runtime(_))));
+    }
+
+    #[test]
+    fn removed_value_is_rejected() {
+        let cipher = cipher();
+        let encrypted = encrypt_document(&cipher, "A=one\nB=two\n", DocumentFormat::Dotenv).unwrap();
+
+        let tampered: String = encrypted.lines().filter(|line| !line.starts_with("B=")).map(|line| format!("{}\n", line)).collect();
+        assert!(matches!(decrypt_document(&cipher, &tampered, DocumentFormat::Dotenv), Err(RufendecError::Runtime(_))));
+    }
+
+    #[test]
+    fn wrong_key_is_a_credentials_error() {
+        let encrypted = encrypt_document(&cipher(), YAML, DocumentFormat::Yaml).unwrap();
+        let other = Cipher::new(Mode::GCM, Key::from_bytes([8u8; 32]));
+
+        assert!(matches!(decrypt_document(&other, &encrypted, DocumentFormat::Yaml), Err(RufendecError::Credentials(_))));
+    }
+
+    #[test]
+    fn paths_never_collide() {
+        // Under the old colon-joined paths, both of these were "a:b:"
+        assert_ne!(child_path(&child_path("", "a"), "b"), child_path("", "a:b"));
+        // And both of these were "a:0:"
+        assert_ne!(index_path(&child_path("", "a"), 0), child_path(&child_path("", "a"), "0"));
+        assert_ne!(child_path("", "rufendec"), MAC_PATH);
+    }
+
+    #[test]
+    fn value_cannot_move_to_a_colliding_key() {
+        let cipher = cipher();
+        let encrypted = encrypt_document(&cipher, "{\"a\":{\"b\":\"one\"},\"a:b\":\"two\"}", DocumentFormat::Json).unwrap();
+
+        let mut document: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+        document["a:b"] = document["a"]["b"].clone();
+
+        let tampered = serde_json::to_string(&document).unwrap();
+        assert!(matches!(decrypt_document(&cipher, &tampered, DocumentFormat::Json), Err(RufendecError::Runtime(_))));
+    }
+
+    #[test]
+    fn format_is_told_by_the_name() {
+        assert_eq!(DocumentFormat::from_path(Path::new("config.yaml")), Some(DocumentFormat::Yaml));
+        assert_eq!(DocumentFormat::from_path(Path::new("secrets.env")), Some(DocumentFormat::Dotenv));
+        assert_eq!(DocumentFormat::from_path(Path::new(".env.production")), Some(DocumentFormat::Dotenv));
+        assert_eq!(DocumentFormat::from_path(Path::new(".env.enom")), None);
+        assert_eq!(DocumentFormat::from_path(Path::new(".env.production.enom")), None);
+        assert_eq!(DocumentFormat::from_path(Path::new("config.yaml.enom")), None);
+    }
+}
@@ -0,0 +1,357 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use rand::{distr::Alphanumeric, Rng};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use std::{
+    env,
+    ffi::{c_int, CString},
+    fs::{self, DirBuilder, OpenOptions},
+    io::Write,
+    mem,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, OpenOptionsExt},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use zeroize::Zeroizing;
+
+use rufendec::{
+    error::RufendecError,
+    log::{log, LogLevel},
+    document::{decrypt_document, encrypt_document, DocumentFormat},
+    operations::{shred_file, write_atomic, DecryptedContent, DecryptedFile},
+    Decryptor, ShredOptions,
+};
+
+/* How does the edit work?
+-----------------------
+1. The encrypted file is decrypted into memory, and written to a new file under a private directory (0700) on a tmpfs, which lives in
+   the memory and never reaches the disk. The file is only readable by the user (0600) and keeps its original name, so the editor
+   recognizes its type. A YAML, JSON, TOML or dotenv document whose values were encrypted by the values subcommand is written out with
+   all its values decrypted.
+2. $EDITOR (vi if it is not set) is launched on the file, and rufendec waits for it to exit. Ctrl+C is left to the editor meanwhile.
+3. If the content changed, it is encrypted with the same key and mode, and with the anonymized name, the padding and the compression the
+   file was found with. The values of a document are encrypted again, with a new MAC. The encrypted file is replaced atomically, and read
+   back to check that it decrypts.
+4. The temporary file (and any swap or backup file the editor left next to it) is shredded and the directory removed, whether the content
+   changed or not. Only when the edited content could not be encrypted back (such as a document which no longer parses) or written, the
+   temporary file is kept (and its path printed), so the edits are not lost.
+*/
+
+// The f_type statfs reports for a tmpfs
+const TMPFS_MAGIC: i64 = 0x0102_1994;
+
+const DEFAULT_EDITOR: &str = "vi";
+
+fn is_tmpfs(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: statfs only writes into the struct it is given
+    let mut stats: libc::statfs = unsafe { mem::zeroed() };
+
+    unsafe { libc::statfs(path.as_ptr(), &mut stats) == 0 && stats.f_type as i64 == TMPFS_MAGIC }
+}
+
+// /dev/shm, or $XDG_RUNTIME_DIR when /dev/shm is not a tmpfs
+fn tmpfs_dir() -> Result<PathBuf, RufendecError> {
+    find_tmpfs([Some(PathBuf::from("/dev/shm")), env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)])
+}
+
+// The first of the directories which is on a tmpfs
+fn find_tmpfs(candidates: [Option<PathBuf>; 2]) -> Result<PathBuf, RufendecError> {
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|dir| dir.is_dir() && is_tmpfs(dir))
+        .ok_or_else(|| {
+            RufendecError::Validation(
+                "Neither /dev/shm nor $XDG_RUNTIME_DIR is a tmpfs, so the file cannot be decrypted without touching the disk".to_string(),
+            )
+        })
+}
+
+// The decrypted file on the tmpfs. It is shredded and its directory removed when dropped, unless it is kept
+struct TempFile {
+    dir: PathBuf,
+    path: PathBuf,
+    shred_options: ShredOptions,
+    keep: bool,
+}
+
+impl TempFile {
+    fn create(name: &Path, content: &[u8], shred_options: &ShredOptions) -> Result<TempFile, RufendecError> {
+        let failed = |e: std::io::Error| RufendecError::Runtime(format!("Failed to create the temporary file :: {}", e));
+
+        let random: String = rand::rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect();
+        let dir = tmpfs_dir()?.join(format!("rufendec-edit-{}", random));
+
+        DirBuilder::new().mode(0o700).create(&dir).map_err(failed)?;
+
+        let temp_file = TempFile {
+            path: dir.join(name),
+            dir,
+            shred_options: shred_options.clone(),
+            keep: false,
+        };
+
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp_file.path)
+            .and_then(|mut file| file.write_all(content))
+            .map_err(failed)?;
+
+        Ok(temp_file)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+
+        // Editors may leave swap or backup files next to it, which hold the content as well
+        let files = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()));
+
+        for entry in files {
+            if let Err(e) = shred_file(&entry.path(), &self.shred_options) {
+                log(LogLevel::ERROR, &format!("{:?} :: {}. Remove it by hand", entry.path(), e));
+                return;
+            }
+        }
+
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            log(LogLevel::ERROR, &format!("Failed to remove {:?} :: {}", self.dir, e));
+        }
+    }
+}
+
+// Gives the signals back their default action. Unregistering the handlers alone would leave the signals ignored,
+// since signal-hook keeps its own handler installed
+fn restore_default_actions(signals: &[c_int]) {
+    for &signal in signals {
+        // SAFETY: emulate_default_handler is async-signal-safe, and is meant to be called from a signal handler
+        let _ = unsafe {
+            signal_hook::low_level::register(signal, move || {
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+            })
+        };
+    }
+}
+
+/* Launches the editor on the file and waits for it. SIGINT is ignored meanwhile, since Ctrl+C belongs to the editor.
+SIGTERM and SIGHUP are noted instead of ending rufendec, so the temporary file is still shredded. Returns whether one of them arrived
+*/
+fn run_editor(path: &Path) -> Result<bool, RufendecError> {
+    let editor = env::var("EDITOR").ok().filter(|editor| !editor.trim().is_empty()).unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+
+    let ignored = Arc::new(AtomicBool::new(false));
+    let terminated = Arc::new(AtomicBool::new(false));
+
+    let handlers: Vec<_> = [(SIGINT, &ignored), (SIGTERM, &terminated), (SIGHUP, &terminated)]
+        .into_iter()
+        .filter_map(|(signal, flag)| signal_hook::flag::register(signal, flag.clone()).ok())
+        .collect();
+
+    // Through the shell, so an $EDITOR with arguments (such as "code --wait") works
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status();
+
+    for handler in handlers {
+        signal_hook::low_level::unregister(handler);
+    }
+
+    restore_default_actions(&[SIGINT, SIGTERM, SIGHUP]);
+
+    match status {
+        Ok(status) if status.success() => Ok(terminated.load(Ordering::SeqCst)),
+        Ok(status) => Err(RufendecError::Runtime(format!(
+            "The editor \"{}\" exited with {}. The encrypted file is left as it is",
+            editor, status
+        ))),
+        Err(e) => Err(RufendecError::Runtime(format!("Failed to launch the editor \"{}\" :: {}", editor, e))),
+    }
+}
+
+/* Writes the content to the tmpfs, opens it in the editor, and hands the edited content to save if it changed. Returns whether it
+changed. The temporary file is kept when save fails, so the edits are not lost
+*/
+fn edit_in_tmpfs(
+    name: &Path,
+    content: &[u8],
+    shred_options: &ShredOptions,
+    verbose: bool,
+    save: impl FnOnce(&Zeroizing<Vec<u8>>) -> Result<(), RufendecError>,
+) -> Result<bool, RufendecError> {
+    let mut temp_file = TempFile::create(name, content, shred_options)?;
+
+    if verbose {
+        log(LogLevel::INFO, &format!("Decrypted to {:?}", temp_file.path));
+    }
+
+    if run_editor(&temp_file.path)? {
+        return Err(RufendecError::Runtime("rufendec was asked to stop. The encrypted file is left as it is".to_string()));
+    }
+
+    let edited = Zeroizing::new(
+        fs::read(&temp_file.path).map_err(|e| RufendecError::Runtime(format!("Failed to read back the edited file :: {}", e)))?,
+    );
+
+    if edited.as_slice() == content {
+        return Ok(false);
+    }
+
+    if let Err(e) = save(&edited) {
+        temp_file.keep = true;
+
+        return Err(RufendecError::Runtime(format!(
+            "{}. The edited file is kept at {:?} so the changes are not lost. Shred it once they are saved",
+            e, temp_file.path
+        )));
+    }
+
+    Ok(true)
+}
+
+// Edits an encrypted file, which is encrypted back with its anonymized name, padding and compression
+fn edit_file(decryptor: &Decryptor, file: &Path, shred_options: &ShredOptions, verbose: bool) -> Result<bool, RufendecError> {
+    let decrypted = decryptor.decrypt_to_memory(file).map_err(|e| match e {
+        RufendecError::Runtime(cause) => RufendecError::Credentials(format!(
+            "{}. Check the password, the salt, the hashing function, the mode, and whether the file was anonymized",
+            cause
+        )),
+        e => e,
+    })?;
+
+    let DecryptedContent::File(content) = &decrypted.content else {
+        return Err(RufendecError::Validation(format!("{:?} holds a symbolic link or a hard link, which cannot be edited", file)));
+    };
+
+    edit_in_tmpfs(Path::new(&decrypted.name), content, shred_options, verbose, |edited| {
+        let encrypted_bytes = decryptor.encrypt_to_memory(&DecryptedFile {
+            content: DecryptedContent::File(edited.clone()),
+            name: decrypted.name.clone(),
+            ..decrypted
+        })?;
+
+        write(file, &encrypted_bytes)?;
+
+        // The written file is read back, since the temporary file is shredded right after
+        match decryptor.decrypt_to_memory(file).map(|written| written.content) {
+            Ok(DecryptedContent::File(written)) if written == *edited => Ok(()),
+            _ => Err(RufendecError::Runtime(format!("The written file {:?} does not decrypt to the edited content", file))),
+        }
+    })
+}
+
+// Edits a document whose values were encrypted by the values subcommand. The whole document is shown decrypted
+fn edit_document(
+    decryptor: &Decryptor,
+    file: &Path,
+    format: DocumentFormat,
+    shred_options: &ShredOptions,
+    verbose: bool,
+) -> Result<bool, RufendecError> {
+    let read = |file: &Path| {
+        fs::read_to_string(file).map_err(|e| RufendecError::Validation(format!("The document {:?} cannot be read as text :: {}", file, e)))
+    };
+
+    let content = Zeroizing::new(decrypt_document(decryptor.cipher(), &read(file)?, format)?);
+    let name = Path::new(file.file_name().unwrap_or(file.as_os_str()));
+
+    edit_in_tmpfs(name, content.as_bytes(), shred_options, verbose, |edited| {
+        let edited = std::str::from_utf8(edited)
+            .map_err(|_| RufendecError::Validation("The edited document is not valid UTF-8".to_string()))?;
+
+        write(file, encrypt_document(decryptor.cipher(), edited, format)?.as_bytes())?;
+
+        // The written document is read back, since the temporary file is shredded right after
+        decrypt_document(decryptor.cipher(), &read(file)?, format).map(|_| ())
+    })
+}
+
+fn write(file: &Path, bytes: &[u8]) -> Result<(), RufendecError> {
+    write_atomic(file, bytes).map_err(|e| RufendecError::Runtime(format!("Failed to write {:?} :: {}", file, e)))
+}
+
+/* Decrypts the file (or the values of the document) to the tmpfs, opens it in the editor, and encrypts it back in place if it was changed.
+A YAML, JSON, TOML or dotenv file is taken as a document, anything else as a file encrypted by rufendec. Returns whether the file was replaced
+*/
+pub fn edit(
+    decryptor: &Decryptor,
+    file: &Path,
+    format: Option<DocumentFormat>,
+    shred_options: &ShredOptions,
+    verbose: bool,
+) -> Result<bool, RufendecError> {
+    match format.or_else(|| DocumentFormat::from_path(file)) {
+        Some(format) => edit_document(decryptor, file, format, shred_options, verbose),
+        None => edit_file(decryptor, file, shred_options, verbose),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rufendec::{padding::PadPolicy, Encryptor, Key};
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    #[test]
+    fn edited_file_is_encrypted_back() {
+        let root = tempfile::tempdir().unwrap();
+        let (source, target) = (root.path().join("source"), root.path().join("target"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join("notes.txt"), b"before\n").unwrap();
+
+        let encryptor = Encryptor::builder().source_dir(&source).target_dir(&target).key(key()).pad(PadPolicy::Padme).build().unwrap();
+        assert_eq!(encryptor.encrypt_files(&[source.join("notes.txt")]).unwrap().success_count, 1);
+
+        let decryptor = Decryptor::builder().source_dir(&target).key(key()).build().unwrap();
+        let file = target.join("notes.txt.enom");
+        let shred_options = ShredOptions { random_iterations: 1, rename_times: 1 };
+
+        // An editor which leaves the file as it is
+        env::set_var("EDITOR", "true");
+        let encrypted = fs::read(&file).unwrap();
+        assert!(!edit(&decryptor, &file, None, &shred_options, false).unwrap());
+        assert_eq!(fs::read(&file).unwrap(), encrypted);
+
+        env::set_var("EDITOR", "sed -i s/before/after/");
+        assert!(edit(&decryptor, &file, None, &shred_options, false).unwrap());
+
+        let edited = decryptor.decrypt_to_memory(&file).unwrap();
+        assert!(matches!(&edited.content, DecryptedContent::File(content) if content.as_slice() == b"after\n"));
+        assert_eq!(edited.pad, PadPolicy::Padme);
+    }
+
+    #[test]
+    fn only_a_tmpfs_is_used() {
+        let disk = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        assert!(!is_tmpfs(&disk));
+
+        assert!(matches!(find_tmpfs([Some(disk.clone()), None]), Err(RufendecError::Validation(_))));
+        assert_eq!(find_tmpfs([Some(disk), Some(PathBuf::from("/dev/shm"))]).unwrap(), Path::new("/dev/shm"));
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{fmt, process};
+
+use crate::log::{log, LogLevel};
+
+// Process exit codes. A run which fails on some files exits with EXIT_PARTIAL_FAILURE, and a run where not a single file
+// could be decrypted (which almost always means a wrong password, salt, hashing function or mode) exits with EXIT_CREDENTIALS
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_VALIDATION: i32 = 2;
+pub const EXIT_CREDENTIALS: i32 = 3;
+pub const EXIT_PARTIAL_FAILURE: i32 = 4;
+// Same as a shell reports for a program stopped with Ctrl+C
+pub const EXIT_CANCELLED: i32 = 130;
+
+#[derive(Debug)]
+pub enum RufendecError {
+    // The arguments or the source and target paths are not acceptable. Nothing was touched
+    Validation(String),
+    // The credentials are missing, or they cannot open the encrypted state of an earlier run
+    Credentials(String),
+    // Anything else which stops the run, such as a failing directory watch
+    Runtime(String),
+}
+
+impl RufendecError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RufendecError::Validation(_) => EXIT_VALIDATION,
+            RufendecError::Credentials(_) => EXIT_CREDENTIALS,
+            RufendecError::Runtime(_) => EXIT_FAILURE,
+        }
+    }
+
+    // Logs the error and terminates the program with the matching exit code
+    pub fn exit(self) -> ! {
+        log(LogLevel::ERROR, format!("{}\n", self).as_str());
+        process::exit(self.exit_code())
+    }
+}
+
+impl fmt::Display for RufendecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RufendecError::Validation(message)
+            | RufendecError::Credentials(message)
+            | RufendecError::Runtime(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RufendecError {}
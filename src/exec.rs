@@ -0,0 +1,153 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+use zeroize::Zeroizing;
+
+use rufendec::{
+    document::{decrypt_document, parse_dotenv, DocumentFormat},
+    error::RufendecError,
+    log::{log, LogLevel},
+    operations::DecryptedContent,
+    Decryptor,
+};
+
+/* How does the exec work?
+-----------------------
+1. Every env file is decrypted into memory. A file with the ".enom" extension is decrypted the same way the file and dir subcommands
+   decrypt it, and any other file is taken as a dotenv document whose values were encrypted by the values subcommand.
+2. The decrypted text is parsed as a dotenv file. A variable set by a later env file replaces the same one set by an earlier file, and
+   all of them replace the variables of the same name rufendec was started with.
+3. The key is zeroized, and the command is run with the variables added to its environment. On Unix rufendec replaces itself with the
+   command (execvp), so the exit code and the signals are the command's own. Elsewhere the command is run as a child, and rufendec exits
+   with its exit code.
+4. The decrypted content never touches the disk. Nothing is printed to stdout either, since it belongs to the command.
+*/
+
+// Decrypts an env file into its dotenv text
+fn decrypt_env_file(decryptor: &Decryptor, file: &Path) -> Result<Zeroizing<String>, RufendecError> {
+    if file.extension().is_some_and(|extension| extension == "enom") {
+        let decrypted = decryptor.decrypt_to_memory(file).map_err(|e| match e {
+            RufendecError::Runtime(cause) => RufendecError::Credentials(format!(
+                "{}. Check the password, the salt, the hashing function, the mode, and whether the file was anonymized",
+                cause
+            )),
+            e => e,
+        })?;
+
+        let DecryptedContent::File(content) = decrypted.content else {
+            return Err(RufendecError::Validation(format!("{:?} holds a symbolic link or a hard link, not a dotenv file", file)));
+        };
+
+        String::from_utf8(content.to_vec())
+            .map(Zeroizing::new)
+            .map_err(|_| RufendecError::Validation(format!("{:?} does not decrypt to a text file", file)))
+    } else {
+        let text = fs::read_to_string(file)
+            .map_err(|e| RufendecError::Validation(format!("The env file {:?} cannot be read as text :: {}", file, e)))?;
+
+        decrypt_document(decryptor.cipher(), &text, DocumentFormat::Dotenv).map(Zeroizing::new)
+    }
+}
+
+// The variables of all the env files, in order. A variable set again by a later file takes its place
+pub fn load_env(decryptor: &Decryptor, env_files: &[PathBuf], verbose: bool) -> Result<Vec<(String, String)>, RufendecError> {
+    let mut variables: Vec<(String, String)> = Vec::new();
+
+    for file in env_files {
+        let text = decrypt_env_file(decryptor, file)?;
+
+        let parsed = parse_dotenv(&text).map_err(|e| match e {
+            RufendecError::Validation(cause) => RufendecError::Validation(format!("{:?} :: {}", file, cause)),
+            e => e,
+        })?;
+
+        if verbose {
+            let names: Vec<&str> = parsed.iter().map(|(name, _)| name.as_str()).collect();
+            log(LogLevel::INFO, &format!("{:?} sets {}", file, names.join(", ")));
+        }
+
+        for (name, value) in parsed {
+            variables.retain(|(existing, _)| *existing != name);
+            variables.push((name, value));
+        }
+    }
+
+    Ok(variables)
+}
+
+// The program of the command, and the command with the variables added to its environment
+fn build_command(command: &[OsString], variables: Vec<(String, String)>) -> Result<(&OsString, process::Command), RufendecError> {
+    let Some((program, arguments)) = command.split_first() else {
+        return Err(RufendecError::Validation("The command to run is expected after \"--\"".to_string()));
+    };
+
+    let mut child = process::Command::new(program);
+    child.args(arguments).envs(variables);
+
+    Ok((program, child))
+}
+
+/* Runs the command with the variables in its environment. On Unix it replaces rufendec, so it only returns when the command
+could not be started. Elsewhere it waits for the command, and exits with its exit code
+*/
+pub fn exec(command: &[OsString], variables: Vec<(String, String)>) -> RufendecError {
+    let (program, mut child) = match build_command(command, variables) {
+        Ok(built) => built,
+        Err(e) => return e,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        RufendecError::Runtime(format!("Failed to run {:?} :: {}", program, child.exec()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        use rufendec::error::EXIT_FAILURE;
+
+        match child.status() {
+            Ok(status) => process::exit(status.code().unwrap_or(EXIT_FAILURE)),
+            Err(e) => RufendecError::Runtime(format!("Failed to run {:?} :: {}", program, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rufendec::{Encryptor, Key};
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    #[test]
+    fn variables_reach_the_command() {
+        let root = tempfile::tempdir().unwrap();
+        let (source, target) = (root.path().join("source"), root.path().join("target"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join("base.env"), "GREETING=hello\nNAME=world\n").unwrap();
+        fs::write(source.join("local.env"), "NAME=there\n").unwrap();
+
+        let encryptor = Encryptor::builder().source_dir(&source).target_dir(&target).key(key()).build().unwrap();
+        let report = encryptor.encrypt_files(&[source.join("base.env"), source.join("local.env")]).unwrap();
+        assert_eq!(report.success_count, 2);
+
+        let decryptor = Decryptor::builder().source_dir(&target).key(key()).build().unwrap();
+        let variables = load_env(&decryptor, &[target.join("base.env.enom"), target.join("local.env.enom")], false).unwrap();
+
+        let command = ["sh", "-c", "printf '%s %s' \"$GREETING\" \"$NAME\""].map(OsString::from);
+        let (_, mut child) = build_command(&command, variables).unwrap();
+
+        assert_eq!(child.output().unwrap().stdout, b"hello there");
+        assert!(matches!(build_command(&[], Vec::new()), Err(RufendecError::Validation(_))));
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use serde::Serialize;
+use std::{
+    fs::Metadata,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::RufendecError,
+    links::SymlinkPolicy,
+    log::{log, LogLevel},
+};
+
+/* How do the filters work?
+-----------------------
+While the source directory is walked, every entry is checked against the rules below, and it is left out if any of them excludes it.
+1. The IGNORE_FILE_NAME files found in the source tree. They follow the gitignore syntax, and the file of the deepest directory is asked first,
+   so a sub-directory can re-include (with !pattern) what a parent directory excluded.
+2. The --exclude patterns, which are also gitignore-style patterns relative to the source directory.
+3. The --include patterns. When any is given, only the files matching one of them (or lying under a matching directory) are taken.
+4. The predicates on the metadata of a file (size, modified time and type), the way find selects files.
+An excluded directory is not walked into at all, so its files are neither read nor listed one by one. The predicates never exclude a directory.
+*/
+
+pub const IGNORE_FILE_NAME: &str = ".rufendecignore";
+
+// The type of file to take. A symbolic link is told apart from the file it points to
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Serialize)]
+pub enum FileType {
+    /// Regular files
+    #[value(name = "f", alias = "file")]
+    File,
+    /// Symbolic links to files
+    #[value(name = "l", alias = "symlink")]
+    Symlink,
+}
+
+// Selects the files by their metadata. A file must satisfy every predicate which is set
+#[derive(Default, Debug, Clone)]
+pub struct Predicates {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    // Modified after this time
+    pub newer: Option<SystemTime>,
+    // Modified before this time
+    pub older: Option<SystemTime>,
+    pub file_type: Option<FileType>,
+}
+
+impl Predicates {
+    /* Whether the file is taken. is_symlink tells whether the entry itself is a symbolic link, and the metadata is that of the file
+    it points to, as the size and the modified time of the target are what gets encrypted
+    */
+    pub fn matches(&self, is_symlink: bool, metadata: &Metadata) -> bool {
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+
+        let file_type_matches = match self.file_type {
+            Some(FileType::File) => !is_symlink && metadata.is_file(),
+            Some(FileType::Symlink) => is_symlink,
+            None => true,
+        };
+
+        file_type_matches
+            && self.min_size.map_or(true, |min| size >= min)
+            && self.max_size.map_or(true, |max| size <= max)
+            && self.newer.map_or(true, |newer| modified.is_some_and(|m| m > newer))
+            && self.older.map_or(true, |older| modified.is_some_and(|m| m < older))
+    }
+}
+
+#[derive(Default)]
+pub struct Filter {
+    include: Option<Gitignore>,
+    exclude: Option<Gitignore>,
+    predicates: Predicates,
+    pub(crate) symlinks: SymlinkPolicy,
+}
+
+// Builds a matcher out of the patterns. The patterns are matched relative to the root
+fn matcher(root: &Path, patterns: &[String]) -> Result<Option<Gitignore>, RufendecError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GitignoreBuilder::new(root);
+
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| {
+            RufendecError::Validation(format!("The pattern {:?} is not a valid glob :: {}", pattern, e))
+        })?;
+    }
+
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| RufendecError::Validation(format!("The patterns could not be compiled :: {}", e)))
+}
+
+impl Filter {
+    // The IGNORE_FILE_NAME files are honoured even without any include or exclude patterns
+    pub fn new(source_dir: &Path, include: &[String], exclude: &[String]) -> Result<Self, RufendecError> {
+        Ok(Filter {
+            include: matcher(source_dir, include)?,
+            exclude: matcher(source_dir, exclude)?,
+            predicates: Predicates::default(),
+            symlinks: SymlinkPolicy::Skip,
+        })
+    }
+
+    // What the walk does with the symbolic links. They are skipped unless asked otherwise
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    pub fn predicates(mut self, predicates: Predicates) -> Self {
+        self.predicates = predicates;
+        self
+    }
+
+    // Whether a file is left out by the predicates
+    pub(crate) fn is_deselected(&self, is_symlink: bool, metadata: &Metadata) -> bool {
+        !metadata.is_dir() && !self.predicates.matches(is_symlink, metadata)
+    }
+
+    // Reads the IGNORE_FILE_NAME of a directory if it has one. A broken line is reported and skipped, the rest of the file still applies
+    pub(crate) fn ignore_file(dir: &Path) -> Option<Gitignore> {
+        let path = dir.join(IGNORE_FILE_NAME);
+
+        if !path.is_file() {
+            return None;
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+
+        if let Some(e) = builder.add(&path) {
+            log(
+                LogLevel::WARN,
+                format!("Some patterns of {:?} were skipped :: {}", path, e).as_str(),
+            );
+        }
+
+        builder.build().ok()
+    }
+
+    /* Whether the entry is excluded. ignore_files holds the IGNORE_FILE_NAME matchers of the directories from the source directory down to
+    the directory of the entry.
+    */
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool, ignore_files: &[Gitignore]) -> bool {
+        for ignore_file in ignore_files.iter().rev() {
+            match ignore_file.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => break,
+                Match::None => continue,
+            }
+        }
+
+        if let Some(exclude) = &self.exclude {
+            if exclude.matched(path, is_dir).is_ignore() {
+                return true;
+            }
+        }
+
+        // The include patterns only pick files. The directories are walked into, as the files under them may still be included
+        match (&self.include, is_dir) {
+            (Some(include), false) => !include.matched_path_or_any_parents(path, false).is_ignore(),
+            _ => false,
+        }
+    }
+
+    /* Whether a single file under the source directory is excluded, for when the files are not met through a walk (such as by the watcher).
+    The file is also excluded when one of its parent directories is
+    */
+    pub fn is_file_excluded(&self, source_dir: &Path, file: &Path) -> bool {
+        let Ok(relative) = file.strip_prefix(source_dir) else {
+            return false;
+        };
+
+        let mut ignore_files = Vec::new();
+        let mut dir = source_dir.to_path_buf();
+        let mut components = relative.components().peekable();
+
+        while let Some(component) = components.next() {
+            ignore_files.extend(Filter::ignore_file(&dir));
+            dir.push(component);
+
+            let is_dir = components.peek().is_some();
+
+            if self.is_excluded(&dir, is_dir, &ignore_files) {
+                return true;
+            }
+        }
+
+        match (file.symlink_metadata(), file.metadata()) {
+            (Ok(link), Ok(metadata)) => self.is_deselected(link.file_type().is_symlink(), &metadata),
+            _ => false,
+        }
+    }
+}
+
+/* Parses a size such as 500, 10K, 1.5M or 2G. The units are powers of 1024, and a trailing B or iB is allowed (so 1MB and 1MiB are also 1M) */
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let upper = upper.trim_end_matches("IB").trim_end_matches('B');
+
+    let (number, multiplier) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1u64 << 10),
+        Some('M') => (&upper[..upper.len() - 1], 1 << 20),
+        Some('G') => (&upper[..upper.len() - 1], 1 << 30),
+        Some('T') => (&upper[..upper.len() - 1], 1 << 40),
+        _ => (upper, 1),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok((number * multiplier as f64) as u64),
+        _ => Err(format!("{:?} is not a size. Use a number of bytes, or a number with K, M, G or T (such as 10K or 1.5M)", value)),
+    }
+}
+
+/* Parses a point in time. It can be a date (2024-01-31, taken as the local midnight), a local date and time (2024-01-31 18:30:00),
+or an age counted back from now (45s, 30m, 12h, 7d or 2w)
+*/
+pub fn parse_time(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+    let invalid = || {
+        format!("{:?} is not a date or an age. Use a date such as 2024-01-31, a date and time such as \"2024-01-31 18:30:00\", or an age such as 30d", value)
+    };
+
+    let age_unit = match value.chars().last() {
+        Some('s') => Some(1),
+        Some('m') => Some(60),
+        Some('h') => Some(60 * 60),
+        Some('d') => Some(24 * 60 * 60),
+        Some('w') => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+
+    if let Some(unit) = age_unit {
+        if let Ok(count) = value[..value.len() - 1].parse::<u64>() {
+            return SystemTime::now()
+                .checked_sub(Duration::from_secs(count.saturating_mul(unit)))
+                .ok_or_else(invalid);
+        }
+    }
+
+    let date_time = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.and_hms_opt(0, 0, 0).unwrap()))
+        .map_err(|_| invalid())?;
+
+    Local
+        .from_local_datetime(&date_time)
+        .earliest()
+        .map(SystemTime::from)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::scan_filtered;
+    use std::fs;
+
+    // A source tree with the files of the given sizes, relative to the root
+    fn tree(files: &[(&str, usize)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+
+        for (name, size) in files {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; *size]).unwrap();
+        }
+
+        root
+    }
+
+    // The names of the files taken, relative to the source directory
+    fn taken(source_dir: &Path, filter: &Filter) -> Vec<String> {
+        let mut names: Vec<String> = scan_filtered(source_dir, filter)
+            .files
+            .iter()
+            .map(|file| file.strip_prefix(source_dir).unwrap().to_string_lossy().into_owned())
+            .filter(|name| !name.ends_with(IGNORE_FILE_NAME))
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn include_and_exclude_patterns() {
+        let root = tree(&[("a.txt", 1), ("b.log", 1), ("docs/c.txt", 1), ("docs/d.pdf", 1), ("build/e.txt", 1)]);
+        let source_dir = root.path();
+
+        let filter = Filter::new(source_dir, &patterns(&["*.txt"]), &[]).unwrap();
+        assert_eq!(taken(source_dir, &filter), ["a.txt", "build/e.txt", "docs/c.txt"]);
+
+        let filter = Filter::new(source_dir, &[], &patterns(&["build/", "*.log"])).unwrap();
+        assert_eq!(taken(source_dir, &filter), ["a.txt", "docs/c.txt", "docs/d.pdf"]);
+
+        // An exclude wins over an include, and an included directory takes every file under it
+        let filter = Filter::new(source_dir, &patterns(&["docs/"]), &patterns(&["*.pdf"])).unwrap();
+        assert_eq!(taken(source_dir, &filter), ["docs/c.txt"]);
+
+        assert!(filter.is_file_excluded(source_dir, &source_dir.join("docs/d.pdf")));
+        assert!(filter.is_file_excluded(source_dir, &source_dir.join("a.txt")));
+        assert!(!filter.is_file_excluded(source_dir, &source_dir.join("docs/c.txt")));
+
+        assert!(Filter::new(source_dir, &patterns(&["{unclosed"]), &[]).is_err());
+    }
+
+    #[test]
+    fn ignore_files_apply_from_the_deepest_directory() {
+        let root = tree(&[("a.txt", 1), ("secret.key", 1), ("keys/public.key", 1), ("keys/private.key", 1)]);
+        let source_dir = root.path();
+
+        fs::write(source_dir.join(IGNORE_FILE_NAME), "*.key\n").unwrap();
+        fs::write(source_dir.join("keys").join(IGNORE_FILE_NAME), "!public.key\n").unwrap();
+
+        let filter = Filter::new(source_dir, &[], &[]).unwrap();
+        assert_eq!(taken(source_dir, &filter), ["a.txt", "keys/public.key"]);
+
+        assert!(filter.is_file_excluded(source_dir, &source_dir.join("keys/private.key")));
+        assert!(!filter.is_file_excluded(source_dir, &source_dir.join("keys/public.key")));
+    }
+
+    #[test]
+    fn size_and_time_predicates() {
+        let root = tree(&[("small.txt", 10), ("medium.txt", 2048), ("large.txt", 10 * 1024 * 1024)]);
+        let source_dir = root.path();
+
+        let by_size = |min_size, max_size| {
+            let predicates = Predicates { min_size, max_size, ..Predicates::default() };
+            taken(source_dir, &Filter::new(source_dir, &[], &[]).unwrap().predicates(predicates))
+        };
+
+        assert_eq!(by_size(Some(parse_size("1K").unwrap()), None), ["large.txt", "medium.txt"]);
+        assert_eq!(by_size(None, Some(parse_size("1.5MiB").unwrap())), ["medium.txt", "small.txt"]);
+        assert_eq!(by_size(Some(100), Some(4096)), ["medium.txt"]);
+
+        let old = source_dir.join("small.txt");
+        fs::File::options().write(true).open(&old).unwrap().set_modified(parse_time("2020-01-01").unwrap()).unwrap();
+
+        let by_time = |newer, older| {
+            let predicates = Predicates { newer, older, ..Predicates::default() };
+            taken(source_dir, &Filter::new(source_dir, &[], &[]).unwrap().predicates(predicates))
+        };
+
+        assert_eq!(by_time(Some(parse_time("30d").unwrap()), None), ["large.txt", "medium.txt"]);
+        assert_eq!(by_time(None, Some(parse_time("2021-06-01 12:00:00").unwrap())), ["small.txt"]);
+    }
+
+    #[test]
+    fn file_type_predicate() {
+        let root = tree(&[("file.txt", 1)]);
+        let source_dir = root.path();
+        std::os::unix::fs::symlink(source_dir.join("file.txt"), source_dir.join("link.txt")).unwrap();
+
+        let by_type = |file_type| {
+            let predicates = Predicates { file_type: Some(file_type), ..Predicates::default() };
+            let filter = Filter::new(source_dir, &[], &[]).unwrap().symlinks(SymlinkPolicy::Follow).predicates(predicates);
+            taken(source_dir, &filter)
+        };
+
+        assert_eq!(by_type(FileType::File), ["file.txt"]);
+        assert_eq!(by_type(FileType::Symlink), ["link.txt"]);
+    }
+
+    #[test]
+    fn sizes_and_times_are_parsed() {
+        assert_eq!(parse_size("500"), Ok(500));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5M"), Ok(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_size("2GB"), Ok(2 << 30));
+        assert!(parse_size("ten").is_err() && parse_size("-1K").is_err());
+
+        let week = parse_time("1w").unwrap();
+        assert!(SystemTime::now().duration_since(week).unwrap() >= Duration::from_secs(7 * 24 * 60 * 60));
+        assert!(parse_time("2024-01-31").unwrap() < parse_time("2024-01-31T18:30:00").unwrap());
+        assert!(parse_time("yesterday").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256GcmSiv, Nonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use crate::{error::RufendecError, secrets::Key};
+
+/* How is a file encrypted for git?
+-----------------------
+Git stores what the clean filter gives it, and tells whether a file changed by cleaning it again and comparing. So the same content must
+always encrypt to the same bytes, or every file would show as modified after a checkout. The random nonce of the GCM mode cannot be used.
+1. Two keys are derived from the key with HKDF-SHA256 under distinct labels: one for the nonce and one for the encryption. So the key
+   is never used for two jobs.
+2. The nonce is the HMAC-SHA256 of the content under the nonce key, cut to 12 bytes. The same content always gets the same nonce.
+3. The content is encrypted with AES-256-GCM-SIV under that nonce and the encryption key, with the header as the associated data. GCM-SIV
+   stays secure when a nonce repeats, so all that the deterministic encryption gives away is whether two files (or two versions of a file)
+   are the same.
+4. The blob is the header (GIT_MAGIC and the format version), the nonce and the cipher text with its 16 byte tag. The magic starts with
+   a NUL byte, so git takes the blob as binary and does not try to diff or merge it as text.
+A blob which does not start with the magic was committed before the file was marked for encryption, and is passed through as it is.
+*/
+
+pub const GIT_MAGIC: &[u8] = b"\0RUFENDEC-GIT\0";
+const GIT_VERSION: u8 = 2;
+const NONCE_KEY_LABEL: &[u8] = b"rufendec git nonce key";
+const CIPHER_KEY_LABEL: &[u8] = b"rufendec git cipher key";
+const HEADER_LEN: usize = GIT_MAGIC.len() + 1;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// Whether the blob was encrypted by encrypt_blob
+pub fn is_encrypted(blob: &[u8]) -> bool {
+    blob.starts_with(GIT_MAGIC)
+}
+
+// Derives a 32 byte key for one job from the key
+fn derive_key(key: &Key, label: &[u8]) -> Key {
+    let mut bytes = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key.as_bytes()).expand(label, &mut bytes).expect("HKDF gives 32 bytes");
+    Key::from_bytes(bytes)
+}
+
+// Encrypts the content of a file for git. The same content and key always give the same blob. An encrypted blob is returned as it is
+pub fn encrypt_blob(key: &Key, content: &[u8]) -> Result<Vec<u8>, RufendecError> {
+    if is_encrypted(content) {
+        return Ok(content.to_vec());
+    }
+
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(derive_key(key, NONCE_KEY_LABEL).as_bytes()).expect("HMAC takes a key of any size");
+    mac.update(content);
+    let digest = mac.finalize().into_bytes();
+    let nonce = Nonce::from_slice(&digest[..NONCE_LEN]);
+
+    let header = [GIT_MAGIC, &[GIT_VERSION]].concat();
+
+    let encrypted_bytes = Aes256GcmSiv::new(derive_key(key, CIPHER_KEY_LABEL).as_key32())
+        .encrypt(nonce, Payload { msg: content, aad: &header })
+        .map_err(|_| RufendecError::Runtime("Failed to encrypt the file".to_string()))?;
+
+    Ok([header.as_slice(), nonce.as_slice(), &encrypted_bytes].concat())
+}
+
+// Reverse of encrypt_blob. A blob which was not encrypted is returned as it is
+pub fn decrypt_blob(key: &Key, blob: &[u8]) -> Result<Zeroizing<Vec<u8>>, RufendecError> {
+    if !is_encrypted(blob) {
+        return Ok(Zeroizing::new(blob.to_vec()));
+    }
+
+    if blob.len() < HEADER_LEN + NONCE_LEN + TAG_LEN {
+        return Err(RufendecError::Validation("The encrypted blob is cut short".to_string()));
+    }
+
+    let (header, rest) = blob.split_at(HEADER_LEN);
+
+    let version = header[GIT_MAGIC.len()];
+
+    if version != GIT_VERSION {
+        return Err(RufendecError::Validation(format!(
+            "The blob was encrypted with version {} of the format, which this version of rufendec cannot read",
+            version
+        )));
+    }
+
+    let (nonce, encrypted_bytes) = rest.split_at(NONCE_LEN);
+
+    Aes256GcmSiv::new(derive_key(key, CIPHER_KEY_LABEL).as_key32())
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: encrypted_bytes, aad: header })
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            RufendecError::Credentials("The blob could not be authenticated. The key does not match, or the blob was modified".to_string())
+        })
+}
+
+// Writes the key to the key file, which only the user can read
+pub fn save_key(key: &Key, key_file: &Path) -> io::Result<()> {
+    if let Some(dir) = key_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(key_file)?.write_all(key.as_bytes())
+}
+
+// Reads the key written by save_key
+pub fn load_key(key_file: &Path) -> Result<Key, RufendecError> {
+    let bytes = Zeroizing::new(
+        fs::read(key_file).map_err(|e| RufendecError::Credentials(format!("The key file {:?} cannot be read :: {}", key_file, e)))?,
+    );
+
+    let bytes: [u8; 32] = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| RufendecError::Credentials(format!("The key file {:?} does not hold a 32 byte key", key_file)))?;
+
+    Ok(Key::from_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    #[test]
+    fn clean_and_smudge_round_trip() {
+        let content = b"API_TOKEN=secret\n";
+        let blob = encrypt_blob(&key(), content).unwrap();
+
+        assert!(is_encrypted(&blob));
+        assert!(!blob.windows(6).any(|window| window == b"secret"));
+        assert_eq!(decrypt_blob(&key(), &blob).unwrap().as_slice(), content);
+    }
+
+    #[test]
+    fn cleaning_twice_gives_the_same_blob() {
+        let content = b"API_TOKEN=secret\n";
+        let blob = encrypt_blob(&key(), content).unwrap();
+
+        assert_eq!(encrypt_blob(&key(), content).unwrap(), blob);
+        // A blob which is cleaned again is kept as it is
+        assert_eq!(encrypt_blob(&key(), &blob).unwrap(), blob);
+        assert_ne!(encrypt_blob(&key(), b"API_TOKEN=other\n").unwrap(), blob);
+    }
+
+    #[test]
+    fn subkeys_are_distinct() {
+        let key = key();
+        let nonce_key = derive_key(&key, NONCE_KEY_LABEL);
+        let cipher_key = derive_key(&key, CIPHER_KEY_LABEL);
+
+        assert_ne!(nonce_key.as_bytes(), cipher_key.as_bytes());
+        assert_ne!(nonce_key.as_bytes(), key.as_bytes());
+        assert_ne!(cipher_key.as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn modified_blob_is_rejected() {
+        let mut blob = encrypt_blob(&key(), b"API_TOKEN=secret\n").unwrap();
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+
+        assert!(matches!(decrypt_blob(&key(), &blob), Err(RufendecError::Credentials(_))));
+        assert!(matches!(
+            decrypt_blob(&Key::from_bytes([8u8; 32]), &encrypt_blob(&key(), b"a").unwrap()),
+            Err(RufendecError::Credentials(_))
+        ));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut blob = encrypt_blob(&key(), b"API_TOKEN=secret\n").unwrap();
+
+        for version in [1, 3] {
+            blob[GIT_MAGIC.len()] = version;
+            assert!(matches!(decrypt_blob(&key(), &blob), Err(RufendecError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn plain_blob_is_passed_through() {
+        assert_eq!(decrypt_blob(&key(), b"plain text").unwrap().as_slice(), b"plain text");
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{
+    env, fs,
+    io::{stdin, stdout, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    thread,
+};
+
+use rufendec::{
+    error::RufendecError,
+    git::{decrypt_blob, encrypt_blob, is_encrypted, load_key, save_key},
+    operations::shred_file,
+    Key, ShredOptions,
+};
+
+use crate::{config::GitFilterAction, output::outln};
+
+/* How does the git filter work?
+-----------------------
+1. init derives the key from the password and the salt, writes it to .git/rufendec/key (only readable by the user), and configures the
+   "rufendec" filter and diff driver in .git/config. The files to encrypt are marked with "filter=rufendec diff=rufendec" in .gitattributes.
+2. When a marked file is staged, git runs the clean filter, which encrypts it (see the git module on why the encryption is deterministic).
+   So the repository, and every remote it is pushed to, only ever holds the encrypted files.
+3. When a marked file is checked out, git runs the smudge filter, which decrypts it. The working tree holds the plain files. git diff and
+   git log -p decrypt both sides through the textconv of the diff driver.
+4. A clone holds the encrypted files until it is unlocked with the same password and salt. unlock checks the key against an encrypted file,
+   then does the same as init, and checks the marked files out again so they get decrypted.
+5. lock removes the filter configuration and shreds the key file, then checks the marked files out again, so the working tree holds them
+   encrypted. It refuses to run while a marked file has changes which are not staged, since they would be lost.
+*/
+
+const FILTER_NAME: &str = "rufendec";
+const KEY_FILE: &str = "rufendec/key";
+
+// The repository the current directory is in
+struct Repo {
+    top_level: PathBuf,
+    key_file: PathBuf,
+}
+
+impl Repo {
+    fn open() -> Result<Repo, RufendecError> {
+        let not_a_repo = |_| RufendecError::Validation("The current directory is not inside a git repository".to_string());
+
+        let top_level = git_line(Path::new("."), &["rev-parse", "--show-toplevel"]).map_err(not_a_repo)?;
+        let common_dir = git_line(Path::new("."), &["rev-parse", "--path-format=absolute", "--git-common-dir"]).map_err(not_a_repo)?;
+
+        Ok(Repo {
+            top_level: PathBuf::from(top_level),
+            key_file: PathBuf::from(common_dir).join(KEY_FILE),
+        })
+    }
+
+    fn git(&self, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>, RufendecError> {
+        git(&self.top_level, args, input)
+    }
+
+    // The tracked files which are marked with filter=rufendec, relative to the top level
+    fn marked_files(&self) -> Result<Vec<String>, RufendecError> {
+        let files = self.git(&["ls-files", "-z"], None)?;
+        let attributes = self.git(&["check-attr", "-z", "--stdin", "filter"], Some(&files))?;
+
+        // Every file gives three fields: the path, the attribute and its value
+        let fields: Vec<&[u8]> = attributes.split(|byte| *byte == 0).collect();
+
+        Ok(fields
+            .chunks_exact(3)
+            .filter(|field| field[2] == FILTER_NAME.as_bytes())
+            .map(|field| String::from_utf8_lossy(field[0]).into_owned())
+            .collect())
+    }
+
+    // Refuses when a marked file has changes which are not staged, since checking it out again would lose them
+    fn ensure_clean(&self, files: &[String]) -> Result<(), RufendecError> {
+        let changed = self.git(&["diff", "--name-only", "-z"], None)?;
+
+        if changed.split(|byte| *byte == 0).any(|changed| files.iter().any(|file| file.as_bytes() == changed)) {
+            Err(RufendecError::Validation(
+                "Some of the files marked for encryption have changes which are not staged. Stage, commit or stash them first".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Removes the marked files from the working tree and checks them out again, so they go through the filter as it is configured now
+    fn checkout_again(&self, files: &[String]) -> Result<(), RufendecError> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        for file in files {
+            let path = self.top_level.join(file);
+
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(RufendecError::Runtime(format!("Failed to remove {:?} :: {}", path, e)));
+                }
+            }
+        }
+
+        self.git(&["checkout", "--pathspec-from-file=-", "--pathspec-file-nul"], Some(&nul_joined(files)))
+            .map(|_| ())
+    }
+
+    fn configure(&self) -> Result<(), RufendecError> {
+        let program = shell_quote(&env::current_exe().map_err(|e| RufendecError::Runtime(format!("Failed to find rufendec :: {}", e)))?);
+
+        let settings = [
+            (format!("filter.{}.clean", FILTER_NAME), format!("{} git-filter clean", program)),
+            (format!("filter.{}.smudge", FILTER_NAME), format!("{} git-filter smudge", program)),
+            (format!("filter.{}.required", FILTER_NAME), "true".to_string()),
+            (format!("diff.{}.textconv", FILTER_NAME), format!("{} git-filter textconv", program)),
+        ];
+
+        for (name, value) in &settings {
+            self.git(&["config", name, value], None)?;
+        }
+
+        Ok(())
+    }
+
+    fn unconfigure(&self) {
+        // A section which is already gone is fine
+        for section in ["filter", "diff"] {
+            let _ = self.git(&["config", "--remove-section", &format!("{}.{}", section, FILTER_NAME)], None);
+        }
+    }
+}
+
+fn git(dir: &Path, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>, RufendecError> {
+    let failed = |e: std::io::Error| RufendecError::Runtime(format!("Failed to run git :: {}", e));
+
+    let mut child = process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        // The paths are given as they are, not as patterns
+        .env("GIT_LITERAL_PATHSPECS", "1")
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+
+    // The input is written from another thread, since git may fill stdout before it has read all of it
+    let output = thread::scope(|scope| {
+        if let (Some(input), Some(mut child_stdin)) = (input, child.stdin.take()) {
+            scope.spawn(move || child_stdin.write_all(input));
+        }
+
+        child.wait_with_output()
+    })
+    .map_err(failed)?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(RufendecError::Runtime(format!(
+            "git {} failed :: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+fn git_line(dir: &Path, args: &[&str]) -> Result<String, RufendecError> {
+    git(dir, args, None).map(|output| String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+fn nul_joined(files: &[String]) -> Vec<u8> {
+    files.iter().flat_map(|file| file.bytes().chain([0])).collect()
+}
+
+// git runs the filters through the shell
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', r"'\''"))
+}
+
+fn init(repo: &Repo, key: Key) -> Result<(), RufendecError> {
+    save_key(&key, &repo.key_file).map_err(|e| RufendecError::Runtime(format!("Failed to write the key file {:?} :: {}", repo.key_file, e)))?;
+    repo.configure()?;
+
+    outln!("The repository is set up, and the key is kept in {}", repo.key_file.display());
+    outln!("Mark the files to encrypt in .gitattributes, such as:\n\n    secrets/** filter={} diff={}\n", FILTER_NAME, FILTER_NAME);
+    outln!("Files which were committed before they were marked stay plain in the history. Run \"git add --renormalize .\" to encrypt them from the next commit on");
+
+    Ok(())
+}
+
+fn unlock(repo: &Repo, key: Key) -> Result<(), RufendecError> {
+    let files = repo.marked_files()?;
+    repo.ensure_clean(&files)?;
+
+    // The key is checked against the first encrypted file, so a wrong password does not leave the files undecryptable
+    for file in &files {
+        let blob = repo.git(&["cat-file", "blob", &format!(":{}", file)], None)?;
+
+        if is_encrypted(&blob) {
+            decrypt_blob(&key, &blob).map_err(|e| RufendecError::Credentials(format!("{:?} :: {}. Check the password and the salt", file, e)))?;
+            break;
+        }
+    }
+
+    save_key(&key, &repo.key_file).map_err(|e| RufendecError::Runtime(format!("Failed to write the key file {:?} :: {}", repo.key_file, e)))?;
+    repo.configure()?;
+    repo.checkout_again(&files)?;
+
+    outln!("The repository is unlocked, and {} marked files were decrypted in the working tree", files.len());
+
+    Ok(())
+}
+
+fn lock(repo: &Repo) -> Result<(), RufendecError> {
+    let files = repo.marked_files()?;
+    repo.ensure_clean(&files)?;
+
+    repo.unconfigure();
+
+    let shred_options = ShredOptions { random_iterations: 5, rename_times: 10 };
+    shred_file(&repo.key_file, &shred_options).map_err(|e| RufendecError::Runtime(format!("{:?} :: {}. Remove it by hand", repo.key_file, e)))?;
+
+    repo.checkout_again(&files)?;
+
+    outln!("The repository is locked, and {} marked files were left encrypted in the working tree", files.len());
+
+    Ok(())
+}
+
+// The clean and the smudge filters. git gives the content on stdin and takes the result from stdout
+fn filter(repo: &Repo, action: &GitFilterAction) -> Result<(), RufendecError> {
+    let key = load_key(&repo.key_file)?;
+
+    let mut content = Vec::new();
+    stdin()
+        .read_to_end(&mut content)
+        .map_err(|e| RufendecError::Runtime(format!("Failed to read stdin :: {}", e)))?;
+
+    let write = |bytes: &[u8]| {
+        let mut out = stdout().lock();
+        out.write_all(bytes)
+            .and_then(|_| out.flush())
+            .map_err(|e| RufendecError::Runtime(format!("Failed to write stdout :: {}", e)))
+    };
+
+    match action {
+        GitFilterAction::Clean => write(&encrypt_blob(&key, &content)?),
+        _ => write(&decrypt_blob(&key, &content)?),
+    }
+}
+
+// The textconv of the diff driver. git gives a file which holds the blob, which is decrypted to stdout
+fn textconv(repo: &Repo, file: &Path) -> Result<(), RufendecError> {
+    let key = load_key(&repo.key_file)?;
+    let blob = fs::read(file).map_err(|e| RufendecError::Runtime(format!("Failed to read {:?} :: {}", file, e)))?;
+
+    stdout()
+        .lock()
+        .write_all(&decrypt_blob(&key, &blob)?)
+        .map_err(|e| RufendecError::Runtime(format!("Failed to write stdout :: {}", e)))
+}
+
+// Runs the action in the repository of the current directory. The key is derived with derive_key for init and unlock only
+pub fn run(action: &GitFilterAction, derive_key: impl FnOnce() -> Key) -> Result<(), RufendecError> {
+    let repo = Repo::open()?;
+
+    match action {
+        GitFilterAction::Init(_) | GitFilterAction::Unlock(_) if repo.key_file.exists() => Err(RufendecError::Validation(format!(
+            "The repository is already set up, and the key is kept in {}. Run \"rufendec git-filter lock\" first to set it up again",
+            repo.key_file.display()
+        ))),
+        GitFilterAction::Init(_) => init(&repo, derive_key()),
+        GitFilterAction::Unlock(_) => unlock(&repo, derive_key()),
+        GitFilterAction::Lock if !repo.key_file.exists() => {
+            Err(RufendecError::Validation("The repository is not unlocked, so there is nothing to lock".to_string()))
+        }
+        GitFilterAction::Lock => lock(&repo),
+        GitFilterAction::Clean | GitFilterAction::Smudge => filter(&repo, action),
+        GitFilterAction::Textconv { file } => textconv(&repo, file),
+    }
+}
@@ -0,0 +1,580 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{
+    marker::PhantomData,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    age_format::AgeKeys,
+    compression::Compression,
+    container::Entry,
+    error::RufendecError,
+    journal::{skip_completed_files, Journal},
+    links::{HardLinks, PendingHardLink, SymlinkPolicy},
+    observer::Observer,
+    operations::{Cipher, DecryptedFile, HashMode, Mode, Operation, ShredOptions, SourceTree},
+    padding::PadPolicy,
+    report::{FileResult, FileStatus, RunReport},
+    secrets::{derive_key, Key},
+};
+
+/* How is a job put together?
+-----------------------
+Encryptor::builder() and Decryptor::builder() hand out a Builder which collects the directories, the credentials and the options.
+build() derives the key (unless a ready Key was given) and returns the Encryptor or Decryptor, which owns the key, the options and the run report.
+The OpenPGP mode takes the password instead of a key, since every message carries the salt of its own key (the salt given is not used).
+So does the age mode, unless it is given the X25519 keys to encrypt to and to decrypt with instead.
+Nothing is shared between two jobs, so they can run at the same time. The key is zeroized when the job is dropped.
+*/
+
+/* Stops a running job from another thread. The files which are being read or written when the token is cancelled are dropped
+between two chunks, their temporary files are removed, and the files which were not picked up yet are skipped.
+The files which were already written stay, and are listed in the report.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A token which is cancelled as soon as the flag is set, say by a signal handler
+    pub fn from_flag(flag: Arc<AtomicBool>) -> Self {
+        CancellationToken(flag)
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Job {
+    pub(crate) cipher: Cipher,
+    pub(crate) source_dir: PathBuf,
+    pub(crate) target_dir: PathBuf,
+    pub(crate) threads: usize,
+    pub(crate) delete_src: bool,
+    pub(crate) shred: Option<ShredOptions>,
+    pub(crate) anon: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) verbose: bool,
+    pub(crate) journal_enabled: bool,
+    pub(crate) resume: bool,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) pad: PadPolicy,
+    pub(crate) compression: Compression,
+    pub(crate) skip_compressed: bool,
+    pub(crate) armor: bool,
+    pub(crate) observers: Vec<Arc<dyn Observer>>,
+    pub(crate) cancel: CancellationToken,
+    pub(crate) journal: Mutex<Option<Journal>>,
+    pub(crate) report: Mutex<RunReport>,
+    pub(crate) hard_links: HardLinks,
+    pub(crate) pending_hard_links: Mutex<Vec<PendingHardLink>>,
+}
+
+pub struct Encryptor {
+    job: Job,
+}
+
+pub struct Decryptor {
+    job: Job,
+}
+
+pub struct Builder<T> {
+    source_dir: Option<PathBuf>,
+    target_dir: Option<PathBuf>,
+    key: Option<Key>,
+    password: Option<(String, String)>,
+    hash_with: HashMode,
+    iterations: u32,
+    mode: Mode,
+    age_keys: Option<AgeKeys>,
+    threads: usize,
+    delete_src: bool,
+    shred: Option<ShredOptions>,
+    anon: bool,
+    dry_run: bool,
+    verbose: bool,
+    journal: bool,
+    resume: bool,
+    symlinks: SymlinkPolicy,
+    pad: PadPolicy,
+    compression: Compression,
+    skip_compressed: bool,
+    armor: bool,
+    observers: Vec<Arc<dyn Observer>>,
+    cancel: CancellationToken,
+    _job: PhantomData<T>,
+}
+
+impl<T> Default for Builder<T> {
+    fn default() -> Self {
+        Builder {
+            source_dir: None,
+            target_dir: None,
+            key: None,
+            password: None,
+            hash_with: HashMode::Argon2,
+            iterations: 10,
+            mode: Mode::GCM,
+            age_keys: None,
+            threads: 8,
+            delete_src: false,
+            shred: None,
+            anon: false,
+            dry_run: false,
+            verbose: false,
+            journal: false,
+            resume: false,
+            symlinks: SymlinkPolicy::Skip,
+            pad: PadPolicy::None,
+            compression: Compression::None,
+            skip_compressed: false,
+            armor: false,
+            observers: Vec::new(),
+            cancel: CancellationToken::new(),
+            _job: PhantomData,
+        }
+    }
+}
+
+impl<T> Builder<T> {
+    // The directory the files are picked from. Target paths are made by replacing it with the target directory
+    pub fn source_dir(mut self, source_dir: impl Into<PathBuf>) -> Self {
+        self.source_dir = Some(source_dir.into());
+        self
+    }
+
+    // The directory the files are written to. Defaults to the source directory
+    pub fn target_dir(mut self, target_dir: impl Into<PathBuf>) -> Self {
+        self.target_dir = Some(target_dir.into());
+        self
+    }
+
+    // Use a key which was already derived
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    // Derive the key from the password and the salt when the job is built
+    pub fn password(mut self, password: impl Into<String>, salt: impl Into<String>) -> Self {
+        self.password = Some((password.into(), salt.into()));
+        self
+    }
+
+    pub fn hash_with(mut self, hash_with: HashMode) -> Self {
+        self.hash_with = hash_with;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // Encrypt to the X25519 recipients and decrypt with the identities in the age mode, instead of the password
+    pub fn age_keys(mut self, keys: AgeKeys) -> Self {
+        self.age_keys = Some(keys);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // Delete the source files once their target files are written. Ignored if shred is chosen
+    pub fn delete_src(mut self, delete_src: bool) -> Self {
+        self.delete_src = delete_src;
+        self
+    }
+
+    pub fn shred(mut self, shred: Option<ShredOptions>) -> Self {
+        self.shred = shred;
+        self
+    }
+
+    pub fn anon(mut self, anon: bool) -> Self {
+        self.anon = anon;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    // Keep a progress journal in the target directory, so an interrupted run can be resumed
+    pub fn journal(mut self, journal: bool) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    // Skip the files the progress journal of an interrupted run notes as completed
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    // What to do with the symbolic links among the files. Preserve encrypts the target of the link instead of the file it points to
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    // Rounds the size of the encrypted files up to a bucket, so their exact size does not show. Padded files are decrypted with any policy
+    pub fn pad(mut self, pad: PadPolicy) -> Self {
+        self.pad = pad;
+        self
+    }
+
+    // Compresses the content before it is encrypted. Compressed files are decrypted with any codec
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    // Leaves the files whose content is compressed already (images, videos, archives) uncompressed
+    pub fn skip_compressed(mut self, skip_compressed: bool) -> Self {
+        self.skip_compressed = skip_compressed;
+        self
+    }
+
+    // Writes the encrypted files as armored text, which can be pasted into an email or a chat. Armored files are decrypted without this option
+    pub fn armor(mut self, armor: bool) -> Self {
+        self.armor = armor;
+        self
+    }
+
+    // Adds an observer which gets every step of the run. Can be called more than once
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    // The token which stops the job. Each job gets its own token otherwise, see Job::cancellation_token
+    pub fn cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn build_job(self) -> Result<Job, RufendecError> {
+        let source_dir = self.source_dir.ok_or_else(|| {
+            RufendecError::Validation("The source directory is expected".to_string())
+        })?;
+
+        // The options each mode refuses. The padding, the compression and the kind of a link record are recorded in a header which only GCM
+        // authenticates (see seal in operations.rs), and age and gpg would hand the recipient that header, the encoded name or the link record
+        let unsupported = [
+            (self.anon, "anonymized file names", &[Mode::Age, Mode::OpenPGP][..]),
+            (self.pad != PadPolicy::None, "padding", &[Mode::ECB, Mode::Age, Mode::OpenPGP]),
+            (self.compression != Compression::None, "compression", &[Mode::ECB, Mode::Age, Mode::OpenPGP]),
+            (self.symlinks == SymlinkPolicy::Preserve, "preserved symbolic links", &[Mode::ECB, Mode::Age, Mode::OpenPGP]),
+        ];
+
+        if let Some((_, option, _)) = unsupported.iter().find(|(chosen, _, modes)| *chosen && modes.contains(&self.mode)) {
+            return Err(RufendecError::Validation(match self.mode {
+                Mode::ECB => format!("The ECB mode cannot authenticate the files, so it does not support {}. Use the GCM mode", option),
+                Mode::Age => format!("The age mode writes files which age can decrypt, so it does not support {}", option),
+                _ => format!("The OpenPGP mode writes messages which gpg can decrypt, so it does not support {}", option),
+            }));
+        }
+
+        let cipher = match (self.mode, self.key, self.password) {
+            (Mode::Age, _, _) if self.age_keys.as_ref().is_some_and(|keys| !keys.is_empty()) => {
+                Cipher::age_keys(self.age_keys.unwrap_or_default())
+            }
+            (Mode::Age, _, Some((password, _))) => Cipher::age_password(&password),
+            (Mode::Age, _, None) => {
+                return Err(RufendecError::Credentials(
+                    "The age mode encrypts with the password or to the X25519 keys, so either of them is expected instead of a key".to_string(),
+                ))
+            }
+            (Mode::OpenPGP, _, Some((password, _))) => Cipher::openpgp(&password),
+            (Mode::OpenPGP, _, None) => {
+                return Err(RufendecError::Credentials(
+                    "The OpenPGP mode derives the key of every message from the password, so a password is expected instead of a key".to_string(),
+                ))
+            }
+            (mode, Some(key), _) => Cipher::new(mode, key),
+            (mode, None, Some((password, salt))) => {
+                Cipher::new(mode, derive_key(&password, &salt, self.hash_with, self.iterations)?)
+            }
+            (_, None, None) => {
+                return Err(RufendecError::Credentials(
+                    "Either a key or a password and a salt are expected".to_string(),
+                ))
+            }
+        };
+
+        Ok(Job {
+            cipher,
+            target_dir: self.target_dir.unwrap_or_else(|| source_dir.clone()),
+            source_dir,
+            threads: self.threads.max(1),
+            delete_src: self.delete_src,
+            shred: self.shred,
+            anon: self.anon,
+            dry_run: self.dry_run,
+            verbose: self.verbose,
+            journal_enabled: self.journal,
+            resume: self.resume,
+            symlinks: self.symlinks,
+            pad: self.pad,
+            compression: self.compression,
+            skip_compressed: self.skip_compressed,
+            armor: self.armor,
+            observers: self.observers,
+            cancel: self.cancel,
+            journal: Mutex::new(None),
+            report: Mutex::new(RunReport::default()),
+            hard_links: HardLinks::default(),
+            pending_hard_links: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+impl Builder<Encryptor> {
+    pub fn build(self) -> Result<Encryptor, RufendecError> {
+        if self.age_keys.as_ref().is_some_and(|keys| !keys.is_empty() && !keys.can_encrypt()) {
+            return Err(RufendecError::Credentials("The age mode encrypts to the recipients, so a recipient is expected".to_string()));
+        }
+
+        Ok(Encryptor {
+            job: self.build_job()?,
+        })
+    }
+}
+
+impl Builder<Decryptor> {
+    pub fn build(self) -> Result<Decryptor, RufendecError> {
+        if self.age_keys.as_ref().is_some_and(|keys| !keys.is_empty() && !keys.can_decrypt()) {
+            return Err(RufendecError::Credentials("The age mode decrypts with the identities, so an identity file is expected".to_string()));
+        }
+
+        Ok(Decryptor {
+            job: self.build_job()?,
+        })
+    }
+}
+
+impl Job {
+    pub fn cipher(&self) -> &Cipher {
+        &self.cipher
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.cipher.mode()
+    }
+
+    pub fn source_dir(&self) -> &Path {
+        &self.source_dir
+    }
+
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn anon(&self) -> bool {
+        self.anon
+    }
+
+    // A handle to stop the job from another thread
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    // A snapshot of the report of the current (or the last) run. Can be called from another thread while the run progresses
+    pub fn report(&self) -> RunReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    // Hands the event to every observer of the job
+    pub(crate) fn notify(&self, event: impl Fn(&dyn Observer)) {
+        for observer in &self.observers {
+            event(observer.as_ref());
+        }
+    }
+
+    // Notes the outcome of a file in the run report and hands it to the observers
+    pub(crate) fn record(&self, result: FileResult) {
+        match result.status {
+            FileStatus::Success => self.notify(|o| o.file_finished(&result)),
+            FileStatus::Failed => self.notify(|o| o.file_failed(&result)),
+            FileStatus::Excluded => {}
+        }
+
+        self.report.lock().unwrap().record(result);
+    }
+
+    // Notes a file which was not processed because the run was cancelled
+    pub(crate) fn skip(&self) {
+        self.report.lock().unwrap().skipped_count += 1;
+    }
+
+    /* Processes the files with the progress journal around them, and returns the report of this run.
+    The report starts empty on every run.
+    */
+    fn run(&self, operation: Operation, files: &[PathBuf]) -> Result<RunReport, RufendecError> {
+        *self.report.lock().unwrap() = RunReport::default();
+        self.hard_links.clear();
+
+        let file_list = if self.journal_enabled && !self.dry_run {
+            let (journal, completed) = Journal::open(
+                &self.source_dir,
+                &self.target_dir,
+                &self.cipher,
+                self.resume,
+            )?;
+
+            *self.journal.lock().unwrap() = journal;
+
+            if self.resume {
+                skip_completed_files(self, files.to_vec(), &completed, &operation)
+            } else {
+                files.to_vec()
+            }
+        } else {
+            files.to_vec()
+        };
+
+        let total_bytes = file_list
+            .iter()
+            .filter_map(|file| file.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        self.notify(|o| o.run_started(file_list.len() as u64, total_bytes));
+
+        match operation {
+            Operation::Encrypt => self.cipher_init(&file_list, &operation, |data, file| self.encrypt_file(data, file)),
+            Operation::Decrypt => {
+                self.cipher_init(&file_list, &operation, |data, file| self.decrypt_file(data, file));
+                // The files the hard links point to are all written by now
+                self.restore_hard_links();
+            }
+        }
+
+        self.report.lock().unwrap().cancelled = self.cancel.is_cancelled();
+
+        let report = self.report();
+
+        self.notify(|o| o.run_finished(&report));
+
+        // The journal of a cancelled run is kept, so the run can be resumed
+        if let Some(journal) = self.journal.lock().unwrap().take() {
+            journal.close(report.failed_count == 0 && !report.cancelled);
+        }
+
+        Ok(report)
+    }
+}
+
+impl Encryptor {
+    pub fn builder() -> Builder<Encryptor> {
+        Builder::default()
+    }
+
+    // Creates the target directories for the source directories, unless it is a dry run
+    pub fn create_target_dirs(&self, dirs: &[PathBuf]) {
+        if !self.job.dry_run {
+            self.job.create_dirs(dirs);
+        }
+    }
+
+    // Encrypts the files into the target directory, and returns the report of the run
+    pub fn encrypt_files(&self, files: &[PathBuf]) -> Result<RunReport, RufendecError> {
+        self.job.run(Operation::Encrypt, files)
+    }
+
+    // Packs the whole tree into a single container file, and returns the report of the run. See the container module for the layout
+    pub fn pack(&self, tree: &SourceTree, container: &Path, chunk_size: usize) -> Result<RunReport, RufendecError> {
+        self.job.pack(tree, container, chunk_size)
+    }
+}
+
+impl Decryptor {
+    pub fn builder() -> Builder<Decryptor> {
+        Builder::default()
+    }
+
+    // Creates the target directories for the source directories, unless it is a dry run.
+    // Anonymized files carry their own directory, which is created when they are decrypted
+    pub fn create_target_dirs(&self, dirs: &[PathBuf]) {
+        if !self.job.dry_run && !self.job.anon {
+            self.job.create_dirs(dirs);
+        }
+    }
+
+    // Decrypts the files into the target directory, and returns the report of the run
+    pub fn decrypt_files(&self, files: &[PathBuf]) -> Result<RunReport, RufendecError> {
+        self.job.run(Operation::Decrypt, files)
+    }
+
+    // The entries of a container. Only the table of contents is decrypted
+    pub fn list_container(&self, container: &Path) -> Result<Vec<Entry>, RufendecError> {
+        self.job.list_container(container)
+    }
+
+    // Decrypts one encrypted file into memory, and returns the name it was encrypted from and its content. Nothing is written
+    pub fn decrypt_to_memory(&self, file: &Path) -> Result<DecryptedFile, RufendecError> {
+        self.job.decrypt_to_memory(file)
+    }
+
+    /* Encrypts a file returned by decrypt_to_memory back with the same key, mode and anonymized name, and with the padding and the
+    compression it was found with. Returns the encrypted bytes, which the caller writes in place of the encrypted file
+    */
+    pub fn encrypt_to_memory(&self, file: &DecryptedFile) -> Result<Vec<u8>, RufendecError> {
+        self.job.encrypt_to_memory(file)
+    }
+
+    /* Extracts the entries of a container into the target directory, and returns the report of the run.
+    With no paths every entry is extracted, otherwise the entries at the paths and under them
+    */
+    pub fn unpack(&self, container: &Path, paths: &[PathBuf]) -> Result<RunReport, RufendecError> {
+        self.job.unpack(container, paths)
+    }
+}
+
+impl Deref for Encryptor {
+    type Target = Job;
+
+    fn deref(&self) -> &Job {
+        &self.job
+    }
+}
+
+impl Deref for Decryptor {
+    type Target = Job;
+
+    fn deref(&self) -> &Job {
+        &self.job
+    }
+}
@@ -0,0 +1,423 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use base64::prelude::*;
+use rand::Rng;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    common::{decode_path, encode_path, relative_path, sha256_hex},
+    error::RufendecError,
+    job::Job,
+    log::{log, LogLevel},
+    operations::{Cipher, Mode, Operation},
+    secrets::Key,
+};
+
+/* How does the progress journal work?
+-----------------------
+While a directory is encrypted or decrypted, every finished file is appended to a journal named JOURNAL_FILE_NAME in the target directory.
+A line holds the source path and the target path (both relative to their directories, and base64 encoded so any file name fits in the line),
+and the size and the SHA256 hash of the target file.
+Each line is encrypted with the same key and mode as the files, so the journal does not leak the names of anonymized files.
+The age and OpenPGP modes are the exception. Every line would be a whole age file or OpenPGP message, with a header and (for age with the
+password) a scrypt run of its own. Their lines are encrypted in GCM mode with a random key instead, which the first line of the journal
+holds as an age file or an OpenPGP message. An age run given only recipients cannot decrypt that first line, so it keeps no journal.
+When an interrupted run is started again with --resume, the files whose target file still matches the journal are skipped.
+The journal is removed once a run finishes without failures.
+*/
+
+pub const JOURNAL_FILE_NAME: &str = ".rufendec-journal";
+
+pub(crate) struct Journal {
+    file: File,
+    path: PathBuf,
+    source_dir: PathBuf,
+    target_dir: PathBuf,
+    // The cipher of the lines, when the first line holds its key
+    line_cipher: Option<Cipher>,
+}
+
+// Whether the lines are encrypted with a key of their own, which the first line holds
+fn wraps_line_key(cipher: &Cipher) -> bool {
+    cipher.age_secret().is_some() || cipher.openpgp_secret().is_some()
+}
+
+// Reverse of the first line of a journal which wraps the key of its lines
+fn unwrap_line_key(line: &str, cipher: &Cipher) -> Option<Cipher> {
+    let key = Zeroizing::new(cipher.decrypt_bytes(&BASE64_STANDARD.decode(line).ok()?)?);
+    Some(Cipher::new(Mode::GCM, Key::from_bytes(key.as_slice().try_into().ok()?)))
+}
+
+/* Decodes a journal line into the source and target paths, and the size and hash of the target file. Older journals have the paths as plain text.
+Should such a path decode as base64 by chance, the target file is not found and the file is simply processed again
+*/
+fn decode_line(line: &str, cipher: &Cipher) -> Option<(PathBuf, PathBuf, usize, String)> {
+    let line = cipher.decrypt_bytes(&BASE64_STANDARD.decode(line).ok()?)?;
+    let line = String::from_utf8(line).ok()?;
+    let path = |field: &str| decode_path(field).unwrap_or_else(|| PathBuf::from(field));
+
+    match line.split('\t').collect::<Vec<&str>>().as_slice() {
+        [source, target, size, hash] => Some((
+            path(source),
+            path(target),
+            size.parse().ok()?,
+            hash.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/* Reads the journal of an interrupted run and returns the source files whose target file is still intact, mapped to their target file,
+along with the cipher of the lines when the first line holds its key
+*/
+fn read_journal(
+    path: &Path,
+    source_dir: &Path,
+    target_dir: &Path,
+    cipher: &Cipher,
+) -> Result<(HashMap<PathBuf, PathBuf>, Option<Cipher>), RufendecError> {
+    let mut completed = HashMap::new();
+
+    let Ok(journal) = fs::read_to_string(path) else {
+        return Ok((completed, None));
+    };
+
+    let mut lines: Vec<&str> = journal.lines().collect();
+
+    let line_cipher = match (wraps_line_key(cipher), lines.first()) {
+        (true, Some(line)) => {
+            let line_cipher = unwrap_line_key(line, cipher);
+
+            // The first line can be incomplete if the run was killed while it was written, and then there are no other lines
+            if line_cipher.is_none() && lines.len() > 1 {
+                return Err(RufendecError::Credentials(format!("Failed to read the progress journal {:?}.\n\nPlease check whether you provided the same password you used for the interrupted run", path)));
+            }
+
+            lines.remove(0);
+            line_cipher
+        }
+        _ => None,
+    };
+
+    for (index, line) in lines.iter().enumerate() {
+        match decode_line(line, line_cipher.as_ref().unwrap_or(cipher)) {
+            Some((source, target, size, hash)) => {
+                let target_file = target_dir.join(target);
+
+                // Verify the target file is the one which was written by the interrupted run
+                let verified = fs::read(&target_file)
+                    .map(|bytes| bytes.len() == size && sha256_hex(&bytes) == hash)
+                    .unwrap_or(false);
+
+                if verified {
+                    completed.insert(source_dir.join(source), target_file);
+                }
+            }
+            // The last line can be incomplete if the run was killed while it was written
+            None if index + 1 == lines.len() => {}
+            None => {
+                return Err(RufendecError::Credentials(format!("Failed to read the progress journal {:?}.\n\nPlease check whether you provided the same password, salt, hashing function and mode you used for the interrupted run", path)));
+            }
+        }
+    }
+
+    Ok((completed, line_cipher))
+}
+
+impl Journal {
+    /* Opens the progress journal in the target directory. With resume, the files completed by the interrupted run are returned.
+    Otherwise, any older journal is discarded and an empty map is returned. The journal is None if it could not be opened.
+    */
+    pub(crate) fn open(
+        source_dir: &Path,
+        target_dir: &Path,
+        cipher: &Cipher,
+        resume: bool,
+    ) -> Result<(Option<Journal>, HashMap<PathBuf, PathBuf>), RufendecError> {
+        let path = target_dir.join(JOURNAL_FILE_NAME);
+
+        if cipher.age_secret().is_some_and(|age| !age.can_decrypt()) {
+            log(LogLevel::WARN, "The age mode was given no identity, so the progress journal could not be read back. No journal is kept, and the run cannot be resumed if it is interrupted");
+            return Ok((None, HashMap::new()));
+        }
+
+        let (completed, line_cipher) = if resume {
+            read_journal(&path, source_dir, target_dir, cipher)?
+        } else {
+            if path.exists() {
+                log(LogLevel::WARN, "Found the progress journal of an interrupted run in the target directory. It will be discarded, since --resume was not passed");
+            }
+            (HashMap::new(), None)
+        };
+
+        // A journal without the key of its lines is started over, along with a new key
+        let append = resume && (line_cipher.is_some() || !wraps_line_key(cipher));
+
+        let _ = fs::create_dir_all(target_dir);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(append)
+            .write(true)
+            .truncate(!append)
+            .open(&path);
+
+        let journal = match file {
+            Ok(file) => {
+                let mut journal = Journal {
+                    file,
+                    path,
+                    source_dir: source_dir.into(),
+                    target_dir: target_dir.into(),
+                    line_cipher,
+                };
+
+                if wraps_line_key(cipher) && journal.line_cipher.is_none() {
+                    journal.wrap_line_key(cipher);
+                }
+
+                Some(journal)
+            }
+            Err(e) => {
+                log(
+                    LogLevel::WARN,
+                    format!("Failed to open the progress journal. The run cannot be resumed if it is interrupted :: {}", e).as_str(),
+                );
+                None
+            }
+        };
+
+        Ok((journal, completed))
+    }
+
+    // Notes a finished file in the journal
+    pub(crate) fn append(&mut self, cipher: &Cipher, source_file: &Path, target_file: &Path, written_bytes: &[u8]) {
+        let line = format!(
+            "{}\t{}\t{}\t{}",
+            encode_path(&relative_path(source_file, &self.source_dir)),
+            encode_path(&relative_path(target_file, &self.target_dir)),
+            written_bytes.len(),
+            sha256_hex(written_bytes)
+        );
+
+        if let Some(line) = self.line_cipher.as_ref().unwrap_or(cipher).encrypt_bytes(line.as_bytes()) {
+            let _ = self
+                .file
+                .write_all(format!("{}\n", BASE64_STANDARD.encode(line)).as_bytes());
+        }
+    }
+
+    // Picks the random key of the lines, and writes it as the first line
+    fn wrap_line_key(&mut self, cipher: &Cipher) {
+        let mut key = Zeroizing::new([0u8; 32]);
+        rand::rng().fill(&mut key[..]);
+
+        if let Some(line) = cipher.encrypt_bytes(&key[..]) {
+            let _ = self
+                .file
+                .write_all(format!("{}\n", BASE64_STANDARD.encode(line)).as_bytes());
+            self.line_cipher = Some(Cipher::new(Mode::GCM, Key::from_bytes(*key)));
+        }
+    }
+
+    // Closes the journal. It is removed if the run is complete, otherwise it is kept for the next --resume
+    pub(crate) fn close(self, complete: bool) {
+        if complete {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            log(
+                LogLevel::INFO,
+                format!("The progress journal is kept at {:?}. Pass --resume to retry the remaining files", self.path).as_str(),
+            );
+        }
+    }
+}
+
+/* Removes the files which were completed by the interrupted run from the file_list. Their source files are deleted or shredded
+if the interrupted run did not get to it. The target files of the interrupted run are removed from the file_list as well, since in-place
+runs find them in the source directory.
+*/
+pub(crate) fn skip_completed_files(
+    job: &Job,
+    file_list: Vec<PathBuf>,
+    completed: &HashMap<PathBuf, PathBuf>,
+    operation: &Operation,
+) -> Vec<PathBuf> {
+    let target_files: HashSet<&PathBuf> = completed.values().collect();
+
+    let pending: Vec<PathBuf> = file_list
+        .into_iter()
+        .filter(|file| {
+            if completed.contains_key(file) {
+                job.remove_source(file);
+                return false;
+            }
+
+            // Encrypted files which are not in the journal are left over by the interrupted run, and must not be encrypted twice
+            let leftover = matches!(operation, Operation::Encrypt)
+                && file.extension() == Some("enom".as_ref());
+
+            !target_files.contains(file) && !leftover
+        })
+        .collect();
+
+    log(
+        LogLevel::INFO,
+        format!("Resuming the interrupted run. Skipping {} files which were already completed", completed.len()).as_str(),
+    );
+
+    pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{age_format::AgeKeys, operations::scan, Encryptor};
+    use age::secrecy::ExposeSecret;
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    // An interrupted run: the first file was written and noted in the journal, the second was written but not noted
+    fn interrupted_run(source_dir: &Path, target_dir: &Path, cipher: &Cipher) {
+        let (journal, _) = Journal::open(source_dir, target_dir, cipher, false).unwrap();
+        let mut journal = journal.unwrap();
+
+        for name in ["a.txt", "b.txt"] {
+            let written = format!("encrypted {}", name).into_bytes();
+            let target_file = target_dir.join(format!("{}.enom", name));
+            fs::write(&target_file, &written).unwrap();
+
+            if name == "a.txt" {
+                journal.append(cipher, &source_dir.join(name), &target_file, &written);
+            }
+        }
+    }
+
+    #[test]
+    fn journal_resume_skips_completed_files() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        let cipher = Cipher::new(Mode::GCM, key());
+
+        interrupted_run(&source_dir, &target_dir, &cipher);
+
+        let (journal, completed) = Journal::open(&source_dir, &target_dir, &cipher, true).unwrap();
+        assert!(journal.is_some());
+        assert_eq!(completed, HashMap::from([(source_dir.join("a.txt"), target_dir.join("a.txt.enom"))]));
+
+        // The journal does not give away the names
+        assert!(!fs::read_to_string(target_dir.join(JOURNAL_FILE_NAME)).unwrap().contains("a.txt"));
+    }
+
+    #[test]
+    fn modified_target_file_is_processed_again() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        let cipher = Cipher::new(Mode::GCM, key());
+
+        interrupted_run(&source_dir, &target_dir, &cipher);
+        fs::write(target_dir.join("a.txt.enom"), b"encrypted a.txX").unwrap();
+
+        let (_, completed) = Journal::open(&source_dir, &target_dir, &cipher, true).unwrap();
+        assert!(completed.is_empty());
+    }
+
+    #[test]
+    fn modified_journal_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        let cipher = Cipher::new(Mode::GCM, key());
+
+        interrupted_run(&source_dir, &target_dir, &cipher);
+
+        // A line which does not decrypt, followed by one which does, is not an incomplete last line
+        let path = target_dir.join(JOURNAL_FILE_NAME);
+        let line = fs::read_to_string(&path).unwrap();
+        let tampered = format!("{}{}", line.replacen(&line[..1], if line.starts_with('A') { "B" } else { "A" }, 1), line);
+        fs::write(&path, tampered).unwrap();
+
+        assert!(matches!(Journal::open(&source_dir, &target_dir, &cipher, true), Err(RufendecError::Credentials(_))));
+    }
+
+    #[test]
+    fn age_journal_resumes_with_the_identity() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        let identity_file = format!("{}\n", age::x25519::Identity::generate().to_string().expose_secret());
+        let cipher = || {
+            let mut keys = AgeKeys::default();
+            keys.add_identities(&identity_file).unwrap();
+            Cipher::age_keys(keys)
+        };
+
+        interrupted_run(&source_dir, &target_dir, &cipher());
+
+        // The first line holds the key of the lines, as an age file
+        let journal = fs::read_to_string(target_dir.join(JOURNAL_FILE_NAME)).unwrap();
+        assert_eq!(journal.lines().count(), 2);
+
+        let (_, completed) = Journal::open(&source_dir, &target_dir, &cipher(), true).unwrap();
+        assert_eq!(completed, HashMap::from([(source_dir.join("a.txt"), target_dir.join("a.txt.enom"))]));
+    }
+
+    #[test]
+    fn openpgp_journal_wraps_the_line_key() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        let cipher = || Cipher::openpgp("password");
+
+        interrupted_run(&source_dir, &target_dir, &cipher());
+
+        // The first line holds the key of the lines as an OpenPGP message, and the other lines are encrypted with it
+        let journal = fs::read_to_string(target_dir.join(JOURNAL_FILE_NAME)).unwrap();
+        let lines: Vec<&str> = journal.lines().collect();
+        let line_cipher = unwrap_line_key(lines[0], &cipher()).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(decode_line(lines[1], &line_cipher).is_some());
+
+        let (_, completed) = Journal::open(&source_dir, &target_dir, &cipher(), true).unwrap();
+        assert_eq!(completed, HashMap::from([(source_dir.join("a.txt"), target_dir.join("a.txt.enom"))]));
+    }
+
+    #[test]
+    fn age_recipients_only_keeps_no_journal() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("a.txt"), b"first").unwrap();
+        fs::write(source_dir.join("b.txt"), b"second").unwrap();
+
+        let encrypt = || {
+            let mut keys = AgeKeys::default();
+            keys.add_recipients(&age::x25519::Identity::generate().to_public().to_string()).unwrap();
+
+            let encryptor = Encryptor::builder()
+                .source_dir(&source_dir)
+                .target_dir(&target_dir)
+                .mode(Mode::Age)
+                .age_keys(keys)
+                .journal(true)
+                .resume(true)
+                .build()
+                .unwrap();
+
+            let tree = scan(&source_dir);
+            encryptor.create_target_dirs(&tree.dirs);
+            encryptor.encrypt_files(&tree.files).unwrap()
+        };
+
+        // The run cannot read a journal back, so it writes none, and a resumed run processes every file
+        assert_eq!(encrypt().success_count, 2);
+        assert!(!target_dir.join(JOURNAL_FILE_NAME).exists());
+        assert_eq!(encrypt().success_count, 2);
+        assert!(!target_dir.join(JOURNAL_FILE_NAME).exists());
+    }
+}
@@ -0,0 +1,58 @@
+//! # Rufendec
+//!
+//! #### Developer: Venkatesh Omkaram
+//!
+//! Rufendec is a lightweight CLI tool for AES-256 encryption and decryption, preserving file names and directory structure.
+//! With support for ECB/GCM modes, it simplifies securing and restoring files with ease, all powered by Rust.
+//!
+//! The library behind the CLI and the desktop app. An [`Encryptor`] or a [`Decryptor`] owns its key, options and run report,
+//! so several jobs can run at once in one process.
+//!
+//! ```no_run
+//! use rufendec::{operations::scan, Encryptor, Mode};
+//!
+//! let tree = scan("./source-dir".as_ref());
+//!
+//! let encryptor = Encryptor::builder()
+//!     .source_dir("./source-dir")
+//!     .target_dir("./target-dir")
+//!     .password("password", "salt-of-at-least-8-bytes")
+//!     .mode(Mode::GCM)
+//!     .build()?;
+//!
+//! encryptor.create_target_dirs(&tree.dirs);
+//!
+//! let report = encryptor.encrypt_files(&tree.files)?;
+//! println!("{} encrypted, {} failed", report.success_count, report.failed_count);
+//! # Ok::<(), rufendec::error::RufendecError>(())
+//! ```
+
+// Copyright (c) 2023 Venkatesh Omkaram
+
+pub mod age_format;
+pub mod armor;
+pub mod common;
+pub mod compression;
+pub mod container;
+pub mod document;
+pub mod error;
+pub mod filter;
+pub mod git;
+pub mod job;
+pub mod journal;
+pub mod links;
+pub mod log;
+pub mod observer;
+pub mod openpgp;
+pub mod operations;
+pub mod padding;
+pub mod report;
+pub mod secrets;
+pub mod stream;
+pub mod sync;
+
+pub use age_format::AgeKeys;
+pub use job::{Builder, CancellationToken, Decryptor, Encryptor, Job};
+pub use observer::Observer;
+pub use operations::{HashMode, Mode, Operation, ShredOptions};
+pub use secrets::Key;
@@ -0,0 +1,178 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use rand::{distr::Alphanumeric, Rng};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    common::{path_from_bytes, path_to_bytes, safe_path},
+    operations::TEMP_FILE_SUFFIX,
+};
+
+/* How are the links kept?
+-----------------------
+A symbolic link (with the preserve policy) or a second name of a hard-linked file is not encrypted as a copy of the file. Instead, a link
+record is encrypted in its place, and it goes through the same cipher, anonymize and write steps as the content of any other file.
+A link record is the link target alone, and the kind of the link is a flag of the sealed header, so no content of a regular file
+can ever pass for a link. The target is:
+- For a symbolic link, the target exactly as the link holds it, relative or absolute.
+- For a hard link, the path of the first name of the file that was met, relative to the source directory. A path which could lead out of
+  the target directory is refused on decrypt.
+Only the GCM mode seals the content, so link records are only made in the GCM mode.
+On decrypt, a symbolic link is created right away. The hard links are created once every file of the run is written, as the file they point to
+must exist first. Both are created under a temporary name and renamed over the target, the same way the files are written.
+*/
+
+// What to do with the symbolic links met while walking the source directory
+#[derive(clap::ValueEnum, Clone, Debug, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Leave the symbolic links out
+    #[default]
+    Skip,
+    /// Take the file or directory the link points to, as if it were in place of the link
+    Follow,
+    /// Encrypt the target of the link, so the link itself is recreated on decrypt
+    Preserve,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LinkKind {
+    Symlink,
+    HardLink,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum LinkRecord {
+    Symlink(PathBuf),
+    HardLink(PathBuf),
+}
+
+impl LinkRecord {
+    pub(crate) fn kind(&self) -> LinkKind {
+        match self {
+            LinkRecord::Symlink(_) => LinkKind::Symlink,
+            LinkRecord::HardLink(_) => LinkKind::HardLink,
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            LinkRecord::Symlink(target) | LinkRecord::HardLink(target) => path_to_bytes(target),
+        }
+    }
+
+    // Returns None for a hard link whose first name is absolute or goes up a directory
+    pub(crate) fn parse(kind: LinkKind, bytes: &[u8]) -> Option<LinkRecord> {
+        let target = path_from_bytes(bytes);
+
+        match kind {
+            LinkKind::Symlink => Some(LinkRecord::Symlink(target)),
+            LinkKind::HardLink => safe_path(&target).is_some().then_some(LinkRecord::HardLink(target)),
+        }
+    }
+}
+
+// The device and inode of a file with more than one name. Hard links cannot be told apart on the other platforms
+#[cfg(unix)]
+fn hard_link_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn hard_link_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Whether the path is a symbolic link, or a file with other hard links. Such a path is deleted rather than shredded,
+// as shredding would wipe out the content of the file it shares its content with
+pub(crate) fn shares_content(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_type().is_symlink() || hard_link_id(&metadata).is_some())
+}
+
+// The first name met of every hard-linked file of a run. The threads race for it, and the one that comes first encrypts the content
+#[derive(Default)]
+pub(crate) struct HardLinks(Mutex<HashMap<(u64, u64), PathBuf>>);
+
+impl HardLinks {
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    // Returns the first name of the file, unless the path itself is the first name (or the file has a single name)
+    pub(crate) fn first_name(&self, path: &Path, metadata: &Metadata) -> Option<PathBuf> {
+        let id = hard_link_id(metadata)?;
+        let mut first_names = self.0.lock().unwrap();
+
+        match first_names.get(&id) {
+            Some(first_name) => Some(first_name.clone()),
+            None => {
+                first_names.insert(id, path.to_path_buf());
+                None
+            }
+        }
+    }
+}
+
+// A hard link which is created once the run is over. The source is the encrypted link record
+pub(crate) struct PendingHardLink {
+    pub(crate) source: PathBuf,
+    pub(crate) link: PathBuf,
+    pub(crate) target: PathBuf,
+    pub(crate) bytes: u64,
+}
+
+// A name next to the link, which the link is created under before it is renamed over the link
+fn temp_link_path(link: &Path) -> PathBuf {
+    let dir = match link.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let temp_name: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+
+    dir.join(format!(
+        ".{}.{}{}",
+        link.file_name().unwrap_or_default().to_string_lossy(),
+        temp_name,
+        TEMP_FILE_SUFFIX
+    ))
+}
+
+// Creates the link with the create closure under a temporary name, and renames it over the link
+fn create_link_atomic(link: &Path, create: impl Fn(&Path) -> std::io::Result<()>) -> std::io::Result<()> {
+    let temp_path = temp_link_path(link);
+
+    create(&temp_path)?;
+
+    fs::rename(&temp_path, link).map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        e
+    })
+}
+
+pub(crate) fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    let create = |temp_path: &Path| std::os::unix::fs::symlink(target, temp_path);
+
+    #[cfg(windows)]
+    let create = |temp_path: &Path| std::os::windows::fs::symlink_file(target, temp_path);
+
+    create_link_atomic(link, create)
+}
+
+pub(crate) fn create_hard_link(target: &Path, link: &Path) -> std::io::Result<()> {
+    create_link_atomic(link, |temp_path| fs::hard_link(target, temp_path))
+}
@@ -2,7 +2,25 @@
 
 use chrono::{DateTime, Local};
 use colored::Colorize;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+// When set, everything is printed to stderr, so stdout can be reserved for machine-readable output
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn redirect_to_stderr(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+// Prints a line without the timestamp and the level. Used for the verbose output
+pub fn print(message: &str) {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub enum LogLevel {
     INFO,
     ERROR,
@@ -27,7 +45,7 @@ pub fn log(level: LogLevel, message: &str) {
     );
 
     match level {
-        LogLevel::INFO => println!("{}", print),
+        LogLevel::INFO => self::print(&print),
         LogLevel::ERROR => eprintln!("{}", print),
         LogLevel::WARN => eprintln!("{}", print),
     }
@@ -8,74 +8,257 @@
 
 // Copyright (c) 2023 Venkatesh Omkaram
 
-mod common;
 mod config;
+mod credentials;
 mod display;
-mod log;
-mod operations;
-mod secrets;
-
-use crate::common::get_confirmation;
-use crate::config::{Args, Command};
-use crate::log::{log, LogLevel};
-use crate::operations::{
-    create_dirs, decrypt_files, encrypt_files, pre_validate_source, recurse_dirs, DIR_LIST,
-    FAILED_COUNT, FILES_SIZE_BYTES, FILE_LIST, SUCCESS_COUNT, VERBOSE,
-};
-use crate::operations::{Mode, Operation};
+#[cfg(target_os = "linux")]
+mod edit;
+mod exec;
+mod git_filter;
+mod output;
+#[cfg(all(target_os = "linux", feature = "mount"))]
+mod mount;
+#[cfg(target_os = "linux")]
+mod watch;
+
+use crate::config::{Args, Command, DirOptions, FileOptions, Shred};
+use crate::credentials::{generate_credentials, generate_keys, get_confirmation, Credentials};
 use clap::Parser;
+use rpassword::prompt_password;
 use colored::Colorize;
-use display::{display_operational_info, terminal_suppress};
+use display::{display_entries, display_excluded, display_failures, display_operational_info, operational_info, terminal_suppress};
 use human_bytes::human_bytes;
-use secrets::{clear_keys, generate_keys, verify_keys_cleared};
-use std::{borrow::Cow, path::PathBuf, time::Instant};
+use output::{emit_event, finish_run, is_machine, out, outln, print_entries, OutputFormat, Timings, OUTPUT_FORMAT};
+use rufendec::{
+    armor::{dearmor, is_armor_end},
+    error::{RufendecError, EXIT_CANCELLED, EXIT_SUCCESS},
+    log,
+    observer::{JsonLinesObserver, ProgressBarObserver},
+    filter::Filter,
+    document::{decrypt_document, encrypt_document, DocumentFormat},
+    operations::{pre_validate_source, pre_validate_target, scan_filtered, write_atomic, Cipher},
+    report::RunReport,
+    stream::{decrypt_stream, encrypt_stream},
+    sync::sync_files,
+    Builder, CancellationToken, Decryptor, Encryptor, Mode, Operation,
+};
+use std::{
+    borrow::Cow,
+    fs,
+    io::{stdin, stdout, BufWriter, IsTerminal, Read, Write},
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+use zeroize::Zeroizing;
+
+// Draws the progress bar if asked for, and with --output ndjson every step of the run is an NDJSON event
+fn observe<T>(builder: Builder<T>, progress_bar: bool) -> Builder<T> {
+    let builder = if progress_bar {
+        builder.observer(ProgressBarObserver::new())
+    } else {
+        builder
+    };
+
+    if *OUTPUT_FORMAT.read().unwrap() == OutputFormat::Ndjson {
+        builder.observer(JsonLinesObserver::new(stdout()))
+    } else {
+        builder
+    }
+}
+
+/* The first SIGINT or SIGTERM cancels the job, which finishes the files being written and skips the rest.
+A second one exits right away. Only set up once the user agreed to proceed, so Ctrl+C at the prompts still quits
+*/
+#[cfg(target_os = "linux")]
+fn cancel_on_signal() -> CancellationToken {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    let flag = Arc::new(AtomicBool::new(false));
 
+    for signal in [SIGINT, SIGTERM] {
+        let _ = signal_hook::flag::register_conditional_shutdown(signal, EXIT_CANCELLED, flag.clone());
+        let _ = signal_hook::flag::register(signal, flag.clone());
+    }
+
+    CancellationToken::from_flag(flag)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cancel_on_signal() -> CancellationToken {
+    CancellationToken::new()
+}
+
+// Tells the user how a cancelled run ended
+fn display_cancelled(report: &RunReport) {
+    if report.cancelled {
+        outln!(
+            "\nThe run was {}. {} files were completed, and {} files were skipped",
+            "cancelled".bright_red().bold(),
+            report.success_count,
+            report.skipped_count
+        );
+    }
+}
+
+// The key, the password of the OpenPGP and age modes, or the X25519 keys of the age mode, for the job
+fn with_credentials<T>(builder: Builder<T>, credentials: Credentials) -> Builder<T> {
+    match credentials {
+        Credentials::Key(key) => builder.key(key),
+        Credentials::Password(password, salt) => builder.password(password, salt),
+        Credentials::AgeKeys(keys) => builder.age_keys(keys),
+    }
+}
+
+// The Encryptor or the Decryptor for the dir subcommand
+fn dir_job<T>(options: &DirOptions, credentials: Credentials) -> Builder<T> {
+    with_credentials(Builder::default(), credentials)
+        .source_dir(&options.source_dir)
+        .target_dir(options.target_dir.as_ref().unwrap_or(&options.source_dir))
+        .mode(options.cipher.mode)
+        .threads(options.threads)
+        .delete_src(options.delete_src)
+        .shred(Shred::options(&options.shred))
+        .anon(options.anon)
+        .pad(options.pad)
+        .compression(options.compress)
+        .armor(options.armor)
+        .skip_compressed(options.skip_compressed)
+        .dry_run(options.dry_run)
+        .verbose(options.verbose)
+        // Note the finished files in the progress journal, so an interrupted run can be resumed
+        .journal(true)
+        .resume(options.resume)
+        .symlinks(options.symlinks)
+        .cancellation(cancel_on_signal())
+}
+
+// The Encryptor or the Decryptor for the file subcommand. The file is processed on a single thread
+fn file_job<T>(options: &FileOptions, credentials: Credentials, source_dir: &Path, target_dir: &Path) -> Builder<T> {
+    with_credentials(Builder::default(), credentials)
+        .source_dir(source_dir)
+        .target_dir(target_dir)
+        .mode(options.cipher.mode)
+        .threads(1)
+        .delete_src(options.delete_src)
+        .shred(Shred::options(&options.shred))
+        .anon(options.anon)
+        .pad(options.pad)
+        .compression(options.compress)
+        .armor(options.armor)
+        .skip_compressed(options.skip_compressed)
+        .dry_run(options.dry_run)
+        .verbose(options.verbose)
+        .cancellation(cancel_on_signal())
+}
+
+/* The message of the text subcommand. At the terminal, the message to encrypt is typed at a prompt which does not echo it, and the
+armored message to decrypt is pasted. Otherwise it is read from stdin
+*/
+fn read_message(operation: &Operation) -> Zeroizing<Vec<u8>> {
+    let mut input = Zeroizing::new(Vec::new());
+
+    let read = match (operation, stdin().is_terminal()) {
+        (Operation::Encrypt, true) => {
+            prompt_password("Enter the message: ").map(|message| input.extend_from_slice(Zeroizing::new(message).as_bytes()))
+        }
+        (Operation::Decrypt, true) => {
+            eprintln!("Paste the armored message:");
+
+            // Up to the end line, so an Enter after the paste is enough
+            for line in stdin().lines().map_while(Result::ok) {
+                input.extend_from_slice(line.as_bytes());
+                input.push(b'\n');
+
+                if is_armor_end(&line) {
+                    break;
+                }
+            }
+
+            Ok(())
+        }
+        (_, false) => stdin().read_to_end(&mut input).map(|_| ()),
+    };
+
+    read.unwrap_or_else(|e| RufendecError::Validation(format!("Failed to read the message :: {}", e)).exit());
+
+    if input.iter().all(u8::is_ascii_whitespace) {
+        RufendecError::Validation("The message is empty".to_string()).exit()
+    }
+
+    input
+}
 
 // Program execution begins here
 fn main() {
+    let program_start = Instant::now();
+
     // Get the input arguments and options from the CLI passed by the user
-    let command = Args::parse().command;
+    let args = Args::parse();
+    let command = args.command.clone();
+    let report_path = args.report.as_deref();
+
+    // With the json and ndjson output formats, stdout is reserved for the JSON and everything else goes to stderr
+    *OUTPUT_FORMAT.write().unwrap() = args.output;
+    // stdout carries the data of a stream, of the git filters and of a message (and belongs to the command exec runs), so the logs go to stderr as well
+    log::redirect_to_stderr(is_machine() || matches!(command, Command::Stream(_) | Command::Exec(_) | Command::GitFilter(_) | Command::Text(_)));
+
+    // An OpenPGP message and an age file are whole files which gpg and age read, so only the subcommands which write such files take those modes
+    if let Some(mode @ (Mode::OpenPGP | Mode::Age)) = command.mode() {
+        if !matches!(command, Command::Dir(_) | Command::File(_) | Command::Text(_)) {
+            RufendecError::Validation(format!(
+                "The {} mode is only supported by the dir, file and text subcommands",
+                mode.to_string().to_lowercase()
+            ))
+            .exit()
+        }
+    }
 
     terminal_suppress(&command, || {
-        println!(
+        outln!(
             "\n@@@@@@@@@@@@@@@@@@@ Rufendec ({}) @@@@@@@@@@@@@@@@@@@\n",
             "by Omkarium".green().bold()
         );
-        println!("\n{}\n", 
+        outln!("\n{}\n", 
             "[Please read the documentation at https://github.com/omkarium/rufendec before you use this program]".bright_magenta());
     });
 
-    match &command {
+    // Every subcommand evaluates to the exit code of the program
+    let exit_code = match &command {
         Command::Dir(options) => {
-            let path = PathBuf::from(&options.source_dir);
-
-            *VERBOSE.write().unwrap() = options.verbose;
+            let path = options.source_dir.clone();
 
-            /* DIR_LIST is the directory list. It is used to gather the list of sub directories the source directory has
-            later will be used to create the same directory structure in the target
-            The Source directory path first needs to be pushed to DIR_LIST. That way when the
-            create_dirs() function is called, the source directory base path will be replace by the target path specified.
-            */
-            DIR_LIST.lock().unwrap().push(path.clone());
+            if let (Operation::Encrypt, false) = (&options.operation, options.resume) {
+                outln!("\n\nValidating if the source directory has any encrypted files");
+            }
 
             // Validates whether any Illegal source dir path is provided
             // Validates whether any encrypted files are present in the source directory while the operation the user choose is to Encrypt
-            pre_validate_source(&path, &options.operation);
+            pre_validate_source(&path, &options.operation, options.resume)
+                .unwrap_or_else(|e| e.exit());
 
-            // Recursively walk through the source directory and list all the sub-directory names and push it to a collection
-            recurse_dirs(&path);
-
-            display_operational_info(&command);
+            /* Recursively walk through the source directory and list all the sub-directory names and the files.
+            The directories will be used to create the same directory structure in the target. The source directory comes first,
+            so its base path gets replaced by the target path specified. The excluded files and directories are left out.
+            */
+            let filter = Filter::new(&path, &options.include, &options.exclude)
+                .unwrap_or_else(|e| e.exit())
+                .predicates(options.predicates())
+                .symlinks(options.symlinks);
+            let tree = scan_filtered(&path, &filter);
 
-            let total_files_size = FILES_SIZE_BYTES.lock().unwrap();
+            let info = operational_info(&command, Some(&tree));
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
 
-            generate_keys(&command);
+            if options.dry_run || options.verbose {
+                display_excluded(&tree);
+            }
 
-            // Capture the target dir path by using the target_dir arg the user passed, if not then use the source directory to place the target files
-            let target_dir = match &options.target_dir {
-                Some(f) => f.as_str(),
-                None => &options.source_dir.as_str(),
-            };
+            let key_start = Instant::now();
+            let credentials = generate_credentials(&command, options.cipher.mode);
+            let key_derivation = key_start.elapsed();
 
             // Read the notice from the notice.txt file which resides in the binary file as bytes.
             // Print the notice text exactly the same way it is represented in the notice.txt file
@@ -83,9 +266,9 @@ fn main() {
                 .chars()
                 .for_each(|x| {
                     if Cow::<str>::Owned(x.to_string()) == "\n" {
-                        println!("");
+                        outln!();
                     } else {
-                        print!(
+                        out!(
                             "{}",
                             x.to_string()
                                 .bright_white()
@@ -96,223 +279,811 @@ fn main() {
                 });
 
             // Ask if the user wish to proceed for further. If No, quit the program
-            println!("\n\nDo you wish to proceed further?\n");
+            outln!("\n\nDo you wish to proceed further?\n");
 
             if get_confirmation() == "Y" {
                 // Capture the start time of the execution
                 let start_time = Instant::now();
-                match options.operation {
 
+                // Create the target directory and sub-directories first. Encrypt or Decrypt the files and place them in the target.
+                // The job owns the key, which is zeroized as soon as the job is dropped at the end of the block
+                let mut report = match options.operation {
                     Operation::Encrypt => {
-                        
-                        if !options.dry_run {
-                            // Create the target directory and sub-directories first. Encrypt the files and place them in the target
-                            create_dirs(
-                                DIR_LIST.lock().unwrap().to_vec(),
-                                options.source_dir.as_str(),
-                                target_dir,
-                            );
-                        }
-
-                        encrypt_files(
-                            FILE_LIST.lock().unwrap().to_vec(),
-                            options.threads,
-                            options.source_dir.as_str(),
-                            target_dir,
-                            options.mode,
-                            options.delete_src,
-                            &options.shred,
-                            options.anon,
-                            options.dry_run
-                        );
+                        let encryptor =
+                            observe(dir_job::<Encryptor>(options, credentials), !options.verbose)
+                                .build()
+                                .unwrap_or_else(|e| e.exit());
+                        encryptor.create_target_dirs(&tree.dirs);
+                        encryptor.encrypt_files(&tree.files)
                     }
                     Operation::Decrypt => {
-                        if !options.anon && !options.dry_run {
-                            // Create the target directory and sub-directories first. Decrypt the files and place them in the target
-                            create_dirs(
-                                DIR_LIST.lock().unwrap().to_vec(),
-                                options.source_dir.as_str(),
-                                target_dir,
-                            );
-                        }
-                        
-                        decrypt_files(
-                            FILE_LIST.lock().unwrap().to_vec(),
-                            options.threads,
-                            options.source_dir.as_str(),
-                            target_dir,
-                            options.mode,
-                            options.delete_src,
-                            &options.shred,
-                            options.anon,
-                            options.dry_run
-                        );
+                        let decryptor =
+                            observe(dir_job::<Decryptor>(options, credentials), !options.verbose)
+                                .build()
+                                .unwrap_or_else(|e| e.exit());
+                        decryptor.create_target_dirs(&tree.dirs);
+                        decryptor.decrypt_files(&tree.files)
                     }
                 }
+                .unwrap_or_else(|e| e.exit());
 
-                clear_keys();
-                verify_keys_cleared(options.mode);
+                report.record_excluded(&tree.excluded);
 
                 // Capture the elapsed time of the execution
-                let elapsed = Some(start_time.elapsed());
+                let elapsed = start_time.elapsed();
 
-                println!(
+                outln!(
                     "\n============== {} ===============\n",
                     "Result".bright_blue()
                 );
-                println!(
+                outln!(
                     "Finished {:?}ion in {:?}, at a rate of {}/sec",
                     options.operation,
-                    elapsed.unwrap(),
-                    human_bytes(*total_files_size as f64 / elapsed.unwrap().as_secs_f64())
+                    elapsed,
+                    human_bytes(tree.total_bytes as f64 / elapsed.as_secs_f64())
                 );
-                println!("\nSuccessfully cleared the credentials from the memory");
-
-                // Success and failed count of files which are either encrypted or decrypted is currently only possible mode GCM
-                if options.mode.to_string() == "GCM" {
-                    println!(
-                        "\nTotal Success count: {}",
-                        SUCCESS_COUNT
-                            .lock()
-                            .unwrap()
-                            .to_string()
-                            .bright_purple()
-                            .bold()
-                            .blink()
-                    );
-                    println!(
-                        "Total failure count: {}",
-                        FAILED_COUNT
-                            .lock()
-                            .unwrap()
-                            .to_string()
-                            .bright_purple()
-                            .bold()
-                            .blink()
-                    );
+                outln!("\nSuccessfully cleared the credentials from the memory");
+
+                outln!(
+                    "\nTotal Success count: {}",
+                    report
+                        .success_count
+                        .to_string()
+                        .bright_purple()
+                        .bold()
+                        .blink()
+                );
+                outln!(
+                    "Total failure count: {}",
+                    report
+                        .failed_count
+                        .to_string()
+                        .bright_purple()
+                        .bold()
+                        .blink()
+                );
+
+                display_cancelled(&report);
+                if report.cancelled {
+                    outln!("Run the same command with --resume to pick up the remaining files");
                 }
 
                 // Print if the failed file count is greater than 0
-                if *FAILED_COUNT.lock().unwrap() > 0 {
-                    println!("\nLooks like we got some failures 😰");
-                    println!("\nPlease check whether you provided the correct password (and the salt in case you are using GCM mode)");
-                    println!("\nCheck the Rules again!!! Especially Rule 1");
-                    println!("\nFailures can also occur when you have the target files already present in the target directory");
-                } else {
-                    match options.mode {
-                            Mode::GCM => {},
-                            Mode::ECB => println!("\nThe result cannot be determined for ECB mode. Manually check if the target file is created."),
+                if report.failed_count > 0 {
+                    display_failures(&report);
+                    outln!("\nLooks like we got some failures 😰");
+                    outln!("\nPlease check whether you provided the correct password (and the salt in case you are using GCM mode)");
+                    outln!("\nCheck the Rules again!!! Especially Rule 1");
+                    outln!("\nFailures can also occur when you have the target files already present in the target directory");
+                } else if !report.cancelled {
+                    match options.cipher.mode {
+                            Mode::GCM | Mode::OpenPGP | Mode::Age => {},
+                            Mode::ECB => outln!("\nThe result cannot be determined for ECB mode. Manually check if the target file is created."),
                         }
-                    println!("\nWe are done. Enjoy hacker!!! 😎");
+                    outln!("\nWe are done. Enjoy hacker!!! 😎");
                 }
 
-                println!("\n=================================\n");
+                outln!("\n=================================\n");
+
+                let exit_code = report.exit_code();
+
+                finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), None, true, exit_code, report_path);
+
+                exit_code
             } else {
-                println!(
+                drop(credentials);
+
+                outln!(
                     "\nPhew... You QUIT! Guess you really know what you are doing. Good choice.\n"
                 );
+
+                finish_run(&info, &RunReport::default(), Timings::new(key_derivation, Duration::ZERO, program_start.elapsed()), None, false, EXIT_SUCCESS, report_path);
+
+                EXIT_SUCCESS
             }
         }
         Command::File(options) => {
-            let source_file = &PathBuf::from(&options.source_file);
-            let mut source_file_path_vec: Vec<PathBuf> = Vec::new();
-            source_file_path_vec.push(source_file.to_path_buf());
+            let source_file = &options.source_file;
 
-            *VERBOSE.write().unwrap() = options.verbose;
+            if source_file.metadata().is_ok() {
+                let info = operational_info(&command, None);
+                terminal_suppress(&command, || display_operational_info(&info));
+                emit_event("operational_info", &info);
 
-            if let Ok(_) = source_file.metadata() {
-                terminal_suppress(&command, || display_operational_info(&command));
+                let key_start = Instant::now();
+                let credentials = generate_credentials(&command, options.cipher.mode);
+                let key_derivation = key_start.elapsed();
 
-                generate_keys(&command);
+                let start_time = Instant::now();
+
+                let mut report = RunReport::default();
 
                 if let Some(source_dir) = source_file.parent() {
-                    if let Some(source_dir) = source_dir.to_str() {
-                        // Capture the target dir path by using the target_dir arg the user passed, if not then use the source directory to place the target files
-                        let target_dir = match &options.target_dir {
-                            Some(f) => f.as_str(),
-                            None => &source_dir,
-                        };
-
-                        DIR_LIST.lock().unwrap().push(target_dir.into());
-
-                        match options.operation {
-                            Operation::Encrypt => {
-                                if !options.dry_run {
-                                    create_dirs(
-                                        DIR_LIST.lock().unwrap().to_vec(),
-                                        source_dir,
-                                        target_dir,
-                                    ); 
-                                }
-                                
-                                encrypt_files(
-                                    source_file_path_vec,
-                                    1,
-                                    source_dir,
-                                    target_dir,
-                                    options.mode,
-                                    options.delete_src,
-                                    &options.shred,
-                                    options.anon,
-                                    options.dry_run
-                                );
-                            }
-                            Operation::Decrypt => {
-                                if !options.anon && !options.dry_run {
-                                    create_dirs(
-                                        DIR_LIST.lock().unwrap().to_vec(),
-                                        source_dir,
-                                        target_dir,
-                                    ); 
-                                }
-
-                                decrypt_files(
-                                    source_file_path_vec,
-                                    1,
-                                    source_dir,
-                                    target_dir,
-                                    options.mode,
-                                    options.delete_src,
-                                    &options.shred,
-                                    options.anon,
-                                    options.dry_run
-                                );
-                            }
+                    // Capture the target dir path by using the target_dir arg the user passed, if not then use the source directory to place the target files
+                    let target_dir = options.target_dir.as_deref().unwrap_or(source_dir);
+
+                    let source_dirs = [source_dir.to_path_buf()];
+                    let source_files = [source_file.to_path_buf()];
+
+                    report = match options.operation {
+                        Operation::Encrypt => {
+                            let encryptor = observe(file_job::<Encryptor>(options, credentials, source_dir, target_dir), !options.verbose)
+                                .build()
+                                .unwrap_or_else(|e| e.exit());
+                            encryptor.create_target_dirs(&source_dirs);
+                            encryptor.encrypt_files(&source_files)
+                        }
+                        Operation::Decrypt => {
+                            let decryptor = observe(file_job::<Decryptor>(options, credentials, source_dir, target_dir), !options.verbose)
+                                .build()
+                                .unwrap_or_else(|e| e.exit());
+                            decryptor.create_target_dirs(&source_dirs);
+                            decryptor.decrypt_files(&source_files)
                         }
                     }
+                    .unwrap_or_else(|e| e.exit());
                 }
 
-                clear_keys();
-                verify_keys_cleared(options.mode);
+                let elapsed = start_time.elapsed();
 
                 terminal_suppress(&command, || {
-                    println!("Successfully cleared the credentials from the memory");
+                    outln!("Successfully cleared the credentials from the memory");
 
-                    println!(
+                    outln!(
                         "\nAES-256 {} {:?}ion is {}",
-                        &options.mode,
+                        &options.cipher.mode,
                         &options.operation,
                         "completed".to_string().bright_green().bold().blink()
                     );
 
+                    display_cancelled(&report);
+
                     // Print if the failed file count is greater than 0
-                    if *FAILED_COUNT.lock().unwrap() > 0 {
-                        println!("\nLooks like we had a failure 😰");
-                        println!("\nPlease check whether you provided the correct password (and the salt in case you are using GCM mode)");
-                    } else {
-                        match options.mode {
-                                Mode::GCM => println!("\nNo errors occurred 😎"),
-                                Mode::ECB => println!("\nThe result cannot be determined for ECB mode. Manually check if the target file is created."),
+                    if report.failed_count > 0 {
+                        outln!("\nLooks like we had a failure 😰");
+                        outln!("\nPlease check whether you provided the correct password (and the salt in case you are using GCM mode)");
+                    } else if !report.cancelled {
+                        match options.cipher.mode {
+                                Mode::GCM | Mode::OpenPGP | Mode::Age => outln!("\nNo errors occurred 😎"),
+                                Mode::ECB => outln!("\nThe result cannot be determined for ECB mode. Manually check if the target file is created."),
                             }
                     }
                 });
+
+                let exit_code = report.exit_code();
+
+                finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), None, true, exit_code, report_path);
+
+                exit_code
             } else {
-                log(
-                    LogLevel::ERROR,
-                    "The source_file specified cannot be found. \n",
+                RufendecError::Validation("The source_file specified cannot be found.".to_string()).exit()
+            }
+        }
+        Command::Sync(options) => {
+            let path = options.source_dir.clone();
+
+            outln!("\n\nValidating if the source directory has any encrypted files");
+
+            // The source directory is expected to hold only plain files, same as while encrypting a directory
+            pre_validate_source(&path, &Operation::Encrypt, false).unwrap_or_else(|e| e.exit());
+            pre_validate_target(&path, &options.target_dir)
+                .unwrap_or_else(|e| e.exit());
+
+            let filter = Filter::new(&path, &options.include, &options.exclude)
+                .unwrap_or_else(|e| e.exit())
+                .symlinks(options.symlinks);
+            let tree = scan_filtered(&path, &filter);
+
+            let info = operational_info(&command, Some(&tree));
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            if options.dry_run || options.verbose {
+                display_excluded(&tree);
+            }
+
+            let key_start = Instant::now();
+            let key = generate_keys(&command);
+            let key_derivation = key_start.elapsed();
+
+            outln!("\nDo you wish to proceed further?\n");
+
+            if get_confirmation() == "Y" {
+                let start_time = Instant::now();
+
+                let encryptor = Encryptor::builder()
+                    .source_dir(&options.source_dir)
+                    .target_dir(&options.target_dir)
+                    .key(key)
+                    .mode(options.cipher.mode)
+                    .threads(options.threads)
+                    .anon(options.anon)
+                    .pad(options.pad)
+                    .compression(options.compress)
+                    .armor(options.armor)
+                    .skip_compressed(options.skip_compressed)
+                    .dry_run(options.dry_run)
+                    .verbose(options.verbose)
+                    .symlinks(options.symlinks)
+                    .cancellation(cancel_on_signal());
+
+                let encryptor = observe(encryptor, !options.verbose)
+                    .build()
+                    .unwrap_or_else(|e| e.exit());
+
+                let result = sync_files(&encryptor, &tree, options.prune);
+
+                // Zeroizes the key
+                drop(encryptor);
+
+                let (summary, report) = result.unwrap_or_else(|e| e.exit());
+
+                let elapsed = start_time.elapsed();
+
+                outln!(
+                    "\n============== {} ===============\n",
+                    "Result".bright_blue()
                 );
+                outln!("Finished Sync in {:?}", elapsed);
+                outln!("\nSuccessfully cleared the credentials from the memory");
+                outln!("\nNew files encrypted       : {}", summary.added.to_string().bright_purple().bold());
+                outln!("Changed files encrypted   : {}", summary.modified.to_string().bright_purple().bold());
+                outln!("Unchanged files skipped   : {}", summary.unchanged.to_string().bright_purple().bold());
+                outln!("Deleted files pruned      : {}", summary.pruned.to_string().bright_purple().bold());
+                outln!("Total failure count       : {}", report.failed_count.to_string().bright_purple().bold());
+
+                display_cancelled(&report);
+
+                if report.failed_count > 0 {
+                    display_failures(&report);
+                    outln!("\nLooks like we got some failures 😰");
+                    outln!("\nThe failed files will be picked up again on the next sync");
+                } else if !report.cancelled {
+                    outln!("\nWe are done. Enjoy hacker!!! 😎");
+                }
+
+                outln!("\n=================================\n");
+
+                let exit_code = report.exit_code();
+
+                finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), Some(&summary), true, exit_code, report_path);
+
+                exit_code
+            } else {
+                drop(key);
+
+                outln!(
+                    "\nPhew... You QUIT! Guess you really know what you are doing. Good choice.\n"
+                );
+
+                finish_run(&info, &RunReport::default(), Timings::new(key_derivation, Duration::ZERO, program_start.elapsed()), None, false, EXIT_SUCCESS, report_path);
+
+                EXIT_SUCCESS
             }
         }
+        Command::Pack(options) => {
+            let path = options.source_dir.clone();
+
+            outln!("\n\nValidating if the source directory has any encrypted files");
+
+            pre_validate_source(&path, &Operation::Encrypt, false).unwrap_or_else(|e| e.exit());
+
+            // The container would otherwise be packed into itself on the next run
+            pre_validate_target(&path, &options.container).unwrap_or_else(|_| {
+                RufendecError::Validation("The container must not be inside the source directory".to_string()).exit()
+            });
+
+            if options.container.is_dir() {
+                RufendecError::Validation("The container must be a file, not a directory".to_string()).exit()
+            }
+
+            let filter = Filter::new(&path, &options.include, &options.exclude)
+                .unwrap_or_else(|e| e.exit())
+                .symlinks(options.symlinks);
+            let tree = scan_filtered(&path, &filter);
+
+            let info = operational_info(&command, Some(&tree));
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            if options.dry_run || options.verbose {
+                display_excluded(&tree);
+            }
+
+            let key_start = Instant::now();
+            let key = generate_keys(&command);
+            let key_derivation = key_start.elapsed();
+
+            outln!("\nDo you wish to proceed further?\n");
+
+            if get_confirmation() == "Y" {
+                let start_time = Instant::now();
+
+                let encryptor = Encryptor::builder()
+                    .source_dir(&options.source_dir)
+                    .target_dir(options.container.parent().unwrap_or(Path::new(".")))
+                    .key(key)
+                    .mode(options.cipher.mode)
+                    .dry_run(options.dry_run)
+                    .verbose(options.verbose)
+                    .symlinks(options.symlinks)
+                    .cancellation(cancel_on_signal());
+
+                let encryptor = observe(encryptor, !options.verbose)
+                    .build()
+                    .unwrap_or_else(|e| e.exit());
+
+                let result = encryptor.pack(&tree, &options.container, options.chunk_size as usize);
+
+                // Zeroizes the key
+                drop(encryptor);
+
+                let mut report = result.unwrap_or_else(|e| e.exit());
+                report.record_excluded(&tree.excluded);
+
+                let elapsed = start_time.elapsed();
+
+                outln!(
+                    "\n============== {} ===============\n",
+                    "Result".bright_blue()
+                );
+                outln!("Finished packing in {:?}, at a rate of {}/sec", elapsed, human_bytes(tree.total_bytes as f64 / elapsed.as_secs_f64()));
+                outln!("\nSuccessfully cleared the credentials from the memory");
+                outln!("\nFiles packed        : {}", report.success_count.to_string().bright_purple().bold());
+                outln!("Total failure count : {}", report.failed_count.to_string().bright_purple().bold());
+
+                if report.cancelled {
+                    outln!("\nThe run was {}. The unfinished container was removed", "cancelled".bright_red().bold());
+                } else if report.failed_count > 0 {
+                    display_failures(&report);
+                    outln!("\nLooks like we got some failures 😰");
+                    outln!("\nThe failed files were left out of the container");
+                } else if !options.dry_run {
+                    outln!("\nThe container is ready at {:?}. We are done. Enjoy hacker!!! 😎", options.container);
+                }
+
+                outln!("\n=================================\n");
+
+                let exit_code = report.exit_code();
+
+                finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), None, true, exit_code, report_path);
+
+                exit_code
+            } else {
+                drop(key);
+
+                outln!(
+                    "\nPhew... You QUIT! Guess you really know what you are doing. Good choice.\n"
+                );
+
+                finish_run(&info, &RunReport::default(), Timings::new(key_derivation, Duration::ZERO, program_start.elapsed()), None, false, EXIT_SUCCESS, report_path);
+
+                EXIT_SUCCESS
+            }
+        }
+        Command::Unpack(options) => {
+            if !options.container.is_file() {
+                RufendecError::Validation("The container specified cannot be found.".to_string()).exit()
+            }
+
+            let info = operational_info(&command, None);
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            let key_start = Instant::now();
+            let key = generate_keys(&command);
+            let key_derivation = key_start.elapsed();
+
+            let decryptor = Decryptor::builder()
+                .source_dir(options.container.parent().unwrap_or(Path::new(".")))
+                .target_dir(options.target_dir.as_deref().unwrap_or(Path::new(".")))
+                .key(key)
+                .mode(options.cipher.mode)
+                .dry_run(options.dry_run)
+                .verbose(options.verbose)
+                .cancellation(cancel_on_signal());
+
+            let decryptor = observe(decryptor, !options.verbose && !options.list)
+                .build()
+                .unwrap_or_else(|e| e.exit());
+
+            // Listing only decrypts the table of contents, so it does not ask for a confirmation
+            if options.list {
+                let entries = decryptor.list_container(&options.container).unwrap_or_else(|e| e.exit());
+
+                drop(decryptor);
+
+                if is_machine() {
+                    print_entries(&info, &entries);
+                } else {
+                    display_entries(&entries);
+                }
+
+                EXIT_SUCCESS
+            } else {
+                outln!("\nDo you wish to proceed further?\n");
+
+                if get_confirmation() == "Y" {
+                    let start_time = Instant::now();
+
+                    let result = decryptor.unpack(&options.container, &options.entry);
+
+                    // Zeroizes the key
+                    drop(decryptor);
+
+                    let report = result.unwrap_or_else(|e| e.exit());
+
+                    let elapsed = start_time.elapsed();
+
+                    outln!(
+                        "\n============== {} ===============\n",
+                        "Result".bright_blue()
+                    );
+                    outln!("Finished unpacking in {:?}", elapsed);
+                    outln!("\nSuccessfully cleared the credentials from the memory");
+                    outln!("\nEntries extracted   : {}", report.success_count.to_string().bright_purple().bold());
+                    outln!("Total failure count : {}", report.failed_count.to_string().bright_purple().bold());
+
+                    display_cancelled(&report);
+
+                    if report.failed_count > 0 {
+                        display_failures(&report);
+                        outln!("\nLooks like we got some failures 😰");
+                    } else if !report.cancelled {
+                        outln!("\nWe are done. Enjoy hacker!!! 😎");
+                    }
+
+                    outln!("\n=================================\n");
+
+                    let exit_code = report.exit_code();
+
+                    finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), None, true, exit_code, report_path);
+
+                    exit_code
+                } else {
+                    drop(decryptor);
+
+                    outln!(
+                        "\nPhew... You QUIT! Guess you really know what you are doing. Good choice.\n"
+                    );
+
+                    finish_run(&info, &RunReport::default(), Timings::new(key_derivation, Duration::ZERO, program_start.elapsed()), None, false, EXIT_SUCCESS, report_path);
+
+                    EXIT_SUCCESS
+                }
+            }
+        }
+        Command::Stream(options) => {
+            if is_machine() {
+                RufendecError::Validation("The stream subcommand writes the data to stdout, so it cannot be used with --output json or ndjson".to_string()).exit()
+            }
+
+            // Same as gpg and age, the encrypted bytes are not dumped on the terminal
+            if let (Operation::Encrypt, true) = (&options.operation, stdout().is_terminal()) {
+                RufendecError::Validation("The encrypted stream would be written to the terminal. Redirect stdout to a file or a pipe".to_string()).exit()
+            }
+
+            let key = generate_keys(&command);
+            let cipher = Cipher::new(options.cipher.mode, key);
+
+            let start_time = Instant::now();
+
+            let result = match options.operation {
+                Operation::Encrypt => encrypt_stream(&cipher, options.chunk_size as usize, stdin().lock(), BufWriter::new(stdout().lock())),
+                Operation::Decrypt => decrypt_stream(&cipher, stdin().lock(), BufWriter::new(stdout().lock())),
+            };
+
+            // Zeroizes the key
+            drop(cipher);
+
+            let total = result.unwrap_or_else(|e| e.exit());
+
+            if options.verbose {
+                log::log(
+                    log::LogLevel::INFO,
+                    format!("{}ed {} in {:?}", options.operation.to_str(), human_bytes(total as f64), start_time.elapsed()).as_str(),
+                );
+            }
+
+            EXIT_SUCCESS
+        }
+        Command::Values(options) => {
+            let source_file = &options.source_file;
+            let target_file = options.target_file.as_ref().unwrap_or(source_file);
+
+            let format = options.format.or_else(|| DocumentFormat::from_path(source_file)).unwrap_or_else(|| {
+                RufendecError::Validation("The format of the document cannot be told from its extension. Give it with --format".to_string()).exit()
+            });
+
+            let text = fs::read_to_string(source_file).unwrap_or_else(|e| {
+                RufendecError::Validation(format!("The document {} cannot be read as text :: {}", source_file.display(), e)).exit()
+            });
+
+            let info = operational_info(&command, None);
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            let key = generate_keys(&command);
+            let cipher = Cipher::new(Mode::GCM, key);
+
+            let result = match options.operation {
+                Operation::Encrypt => encrypt_document(&cipher, &text, format),
+                Operation::Decrypt => decrypt_document(&cipher, &text, format),
+            };
+
+            // Zeroizes the key
+            drop(cipher);
+
+            outln!("\nSuccessfully cleared the credentials from the memory\n");
+
+            let document = result.unwrap_or_else(|e| e.exit());
+
+            write_atomic(target_file, document.as_bytes()).unwrap_or_else(|e| {
+                RufendecError::Runtime(format!("Failed to write {} :: {}", target_file.display(), e)).exit()
+            });
+
+            outln!(
+                "The values of {} were {}ed into {}",
+                source_file.display(),
+                options.operation.to_str(),
+                target_file.display()
+            );
+
+            EXIT_SUCCESS
+        }
+        Command::Exec(options) => {
+            if is_machine() {
+                RufendecError::Validation("The exec subcommand leaves stdout to the command, so it cannot be used with --output json or ndjson".to_string()).exit()
+            }
+
+            if let Some(missing) = options.env_files.iter().find(|file| !file.is_file()) {
+                RufendecError::Validation(format!("The env file {} cannot be found", missing.display())).exit();
+            }
+
+            let key = generate_keys(&command);
+
+            let decryptor = Decryptor::builder()
+                .source_dir(".")
+                .key(key)
+                .mode(options.cipher.mode)
+                .anon(options.anon)
+                .verbose(options.verbose)
+                .build()
+                .unwrap_or_else(|e| e.exit());
+
+            let result = exec::load_env(&decryptor, &options.env_files, options.verbose);
+
+            // Zeroizes the key before the command gets to run
+            drop(decryptor);
+
+            let variables = result.unwrap_or_else(|e| e.exit());
+
+            exec::exec(&options.command, variables).exit()
+        }
+        Command::GitFilter(options) => {
+            git_filter::run(&options.action, || generate_keys(&command)).unwrap_or_else(|e| e.exit());
+
+            EXIT_SUCCESS
+        }
+        Command::Text(options) => {
+            if is_machine() {
+                RufendecError::Validation("The text subcommand writes the message to stdout, so it cannot be used with --output json or ndjson".to_string()).exit()
+            }
+
+            let input = read_message(&options.operation);
+
+            let cipher = match generate_credentials(&command, options.cipher.mode) {
+                Credentials::Key(key) => Cipher::new(options.cipher.mode, key),
+                Credentials::Password(password, _) if options.cipher.mode == Mode::Age => Cipher::age_password(&password),
+                Credentials::Password(password, _) => Cipher::openpgp(&password),
+                Credentials::AgeKeys(keys) => Cipher::age_keys(keys),
+            };
+
+            let result = match options.operation {
+                Operation::Encrypt => cipher
+                    .encrypt_bytes(&input)
+                    .map(|encrypted_bytes| cipher.armor(&encrypted_bytes).into_bytes())
+                    .ok_or_else(|| RufendecError::Runtime("The message could not be encrypted".to_string())),
+                Operation::Decrypt => dearmor(&input)
+                    .map_err(|cause| RufendecError::Validation(cause.to_string()))
+                    .and_then(|encrypted_bytes| {
+                        cipher.decrypt_bytes(&encrypted_bytes).map(Zeroizing::new).ok_or_else(|| {
+                            RufendecError::Credentials(
+                                "The message could not be decrypted. Check the password, the salt, the hashing function and the mode".to_string(),
+                            )
+                        })
+                    })
+                    .map(|message| message.to_vec()),
+            };
+
+            // Zeroizes the key
+            drop(cipher);
+
+            let output = Zeroizing::new(result.unwrap_or_else(|e| e.exit()));
+
+            let mut out = stdout().lock();
+            let written = out.write_all(&output).and_then(|_| {
+                // A message typed at the prompt has no line ending of its own
+                if !output.ends_with(b"\n") && out.is_terminal() {
+                    out.write_all(b"\n")
+                } else {
+                    Ok(())
+                }
+            });
+
+            written.and_then(|_| out.flush()).unwrap_or_else(|e| RufendecError::Runtime(format!("Failed to write stdout :: {}", e)).exit());
+
+            EXIT_SUCCESS
+        }
+        #[cfg(target_os = "linux")]
+        Command::Watch(options) => {
+            let path = options.source_dir.clone();
+
+            outln!("\n\nValidating if the source directory has any encrypted files");
+
+            pre_validate_source(&path, &Operation::Encrypt, false).unwrap_or_else(|e| e.exit());
+            pre_validate_target(&path, &options.target_dir)
+                .unwrap_or_else(|e| e.exit());
+
+            let info = operational_info(&command, None);
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            let key_start = Instant::now();
+            let key = generate_keys(&command);
+            let key_derivation = key_start.elapsed();
+
+            outln!("\nDo you wish to proceed further?\n");
+
+            if get_confirmation() == "Y" {
+                let start_time = Instant::now();
+
+                let encryptor = Encryptor::builder()
+                    .source_dir(&options.source_dir)
+                    .target_dir(&options.target_dir)
+                    .key(key)
+                    .mode(options.cipher.mode)
+                    .threads(options.threads)
+                    .delete_src(options.delete_src)
+                    .shred(Shred::options(&options.shred))
+                    .anon(options.anon)
+                    .pad(options.pad)
+                    .compression(options.compress)
+                    .armor(options.armor)
+                    .skip_compressed(options.skip_compressed)
+                    .verbose(options.verbose);
+
+                // The watcher logs every file it encrypts, so it draws no progress bar
+                let encryptor = observe(encryptor, false)
+                    .build()
+                    .unwrap_or_else(|e| e.exit());
+
+                // Runs until SIGINT or SIGTERM is received. The key stays in memory all along
+                let result = watch::watch_dir(&encryptor, options);
+
+                let elapsed = start_time.elapsed();
+
+                // Zeroizes the key
+                drop(encryptor);
+
+                outln!("\nSuccessfully cleared the credentials from the memory\n");
+
+                let report = result.unwrap_or_else(|e| e.exit());
+
+                let exit_code = report.exit_code();
+
+                finish_run(&info, &report, Timings::new(key_derivation, elapsed, program_start.elapsed()), None, true, exit_code, report_path);
+
+                exit_code
+            } else {
+                drop(key);
+
+                outln!(
+                    "\nPhew... You QUIT! Guess you really know what you are doing. Good choice.\n"
+                );
+
+                finish_run(&info, &RunReport::default(), Timings::new(key_derivation, Duration::ZERO, program_start.elapsed()), None, false, EXIT_SUCCESS, report_path);
+
+                EXIT_SUCCESS
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        Command::Watch(_) => {
+            RufendecError::Validation("The watch subcommand is only supported on Linux.".to_string()).exit()
+        }
+        #[cfg(all(target_os = "linux", feature = "mount"))]
+        Command::Mount(options) => {
+            if !options.encrypted_dir.is_dir() {
+                RufendecError::Validation(format!(
+                    "The encrypted directory {} does not exist",
+                    options.encrypted_dir.display()
+                ))
+                .exit();
+            }
+
+            if !options.mountpoint.is_dir() {
+                RufendecError::Validation(format!(
+                    "The mount point {} must be an existing directory",
+                    options.mountpoint.display()
+                ))
+                .exit();
+            }
+
+            let info = operational_info(&command, None);
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            let key = generate_keys(&command);
+
+            let decryptor = Decryptor::builder()
+                .source_dir(&options.encrypted_dir)
+                .key(key)
+                .mode(options.cipher.mode)
+                .anon(options.anon)
+                .verbose(options.verbose)
+                .cancellation(cancel_on_signal())
+                .build()
+                .unwrap_or_else(|e| e.exit());
+
+            // Serves the file system until it is unmounted or Ctrl+C is pressed. The key stays in memory all along
+            let result = mount::mount(&decryptor, &options.encrypted_dir, &options.mountpoint, options.verbose);
+
+            // Zeroizes the key
+            drop(decryptor);
+
+            outln!("\nSuccessfully cleared the credentials from the memory\n");
+
+            match result {
+                Ok(()) => EXIT_SUCCESS,
+                Err(e) => e.exit(),
+            }
+        }
+        #[cfg(not(all(target_os = "linux", feature = "mount")))]
+        Command::Mount(_) => {
+            RufendecError::Validation("The mount subcommand is only supported on Linux, in builds with the mount feature.".to_string()).exit()
+        }
+        #[cfg(target_os = "linux")]
+        Command::Edit(options) => {
+            if !options.file.is_file() {
+                RufendecError::Validation(format!("The file {} cannot be found", options.file.display())).exit();
+            }
+
+            let info = operational_info(&command, None);
+            display_operational_info(&info);
+            emit_event("operational_info", &info);
+
+            let key = generate_keys(&command);
+
+            let decryptor = Decryptor::builder()
+                .source_dir(options.file.parent().unwrap_or(Path::new(".")))
+                .key(key)
+                .mode(options.cipher.mode)
+                .anon(options.anon)
+                .verbose(options.verbose)
+                .build()
+                .unwrap_or_else(|e| e.exit());
+
+            let result = edit::edit(&decryptor, &options.file, options.format, &options.shred, options.verbose);
+
+            // Zeroizes the key
+            drop(decryptor);
+
+            outln!("\nSuccessfully cleared the credentials from the memory\n");
+
+            match result {
+                Ok(true) => outln!("{} was encrypted back with the changes", options.file.display()),
+                Ok(false) => outln!("Nothing was changed, so {} is left as it is", options.file.display()),
+                Err(e) => e.exit(),
+            }
+
+            EXIT_SUCCESS
+        }
+        #[cfg(not(target_os = "linux"))]
+        Command::Edit(_) => {
+            RufendecError::Validation("The edit subcommand is only supported on Linux.".to_string()).exit()
+        }
     };
+
+    process::exit(exit_code);
 }
 
@@ -0,0 +1,553 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use fuser::{
+    consts::FOPEN_KEEP_CACHE, FileAttr, FileType, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen,
+    ReplyStatfs, Request, Session, FUSE_ROOT_ID,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fs::{self, Metadata},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use walkdir::WalkDir;
+use zeroize::Zeroizing;
+
+use rufendec::{
+    common::safe_path,
+    error::RufendecError,
+    log::{log, LogLevel},
+    operations::{DecryptedContent, DecryptedFile},
+    Decryptor,
+};
+
+/* How does the mount work?
+-----------------------
+The encrypted directory is shown as a read-only file system through FUSE, with the original file names, so a single file can be read
+without decrypting the whole tree into a plaintext directory. The FUSE protocol is spoken by the fuser crate, which mounts with the mount
+system call as root, and through the fusermount3 (or fusermount) helper as any other user. It is built with the "mount" cargo feature.
+1. The tree is built once at mount time from the encrypted directory. Every ".enom" file becomes a file without the extension, and the
+   journal, the sync database and any other file are left out. In anonymized mode, every file is decrypted at mount time for its name.
+2. A file is decrypted the first time its attributes are asked for, since the padding, the compression and the link records hide the size
+   until then. The attributes are kept with the node from then on, along with the content of the last file decrypted, as it is usually
+   opened next. Any other file is decrypted again into memory when it is opened, and the content is zeroized once nothing holds it anymore.
+3. Preserved symbolic links are shown as symbolic links, and hard links show the content of the file they share it with.
+The key only ever stays in memory. Ctrl+C (or unmounting the directory) ends the mount, after which the caller drops the Decryptor to clear it.
+*/
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// How long the kernel may cache the names and the attributes. The tree does not change while it is mounted
+const TTL: Duration = Duration::from_secs(60);
+
+const BLOCK_SIZE: u32 = 4096;
+
+#[derive(Clone)]
+enum FileInfo {
+    // The size, and the node whose encrypted file holds the content (another node for a hard link)
+    Regular(u64, u64),
+    Symlink(PathBuf),
+    // The file could not be decrypted. It is shown as an empty file which cannot be read
+    Failed,
+}
+
+enum NodeKind {
+    Dir(BTreeMap<OsString, u64>),
+    // Not known until the file is decrypted
+    File(Option<FileInfo>),
+}
+
+struct Node {
+    parent: u64,
+    // The directory or the encrypted file in the encrypted tree
+    source: PathBuf,
+    kind: NodeKind,
+    metadata: Metadata,
+    // The attributes, once the file was decrypted for them
+    attr: Option<FileAttr>,
+}
+
+struct Filesystem<'a> {
+    decryptor: &'a Decryptor,
+    verbose: bool,
+    // The inode is the index plus one
+    nodes: Vec<Node>,
+    handles: HashMap<u64, Arc<Zeroizing<Vec<u8>>>>,
+    next_handle: u64,
+    // The content of the last file decrypted for its attributes, since it is usually opened right after
+    recent: Option<(u64, Arc<Zeroizing<Vec<u8>>>)>,
+}
+
+impl<'a> Filesystem<'a> {
+    fn new(decryptor: &'a Decryptor, encrypted_dir: &Path, verbose: bool) -> Result<Self, RufendecError> {
+        let metadata = fs::metadata(encrypted_dir)
+            .map_err(|e| RufendecError::Validation(format!("The encrypted directory cannot be read :: {}", e)))?;
+
+        let mut filesystem = Filesystem {
+            decryptor,
+            verbose,
+            nodes: vec![Node {
+                parent: FUSE_ROOT_ID,
+                source: encrypted_dir.to_path_buf(),
+                kind: NodeKind::Dir(BTreeMap::new()),
+                metadata,
+                attr: None,
+            }],
+            handles: HashMap::new(),
+            next_handle: 1,
+            recent: None,
+        };
+
+        filesystem.scan(encrypted_dir);
+
+        Ok(filesystem)
+    }
+
+    // Builds the tree out of the directories and the ".enom" files under the encrypted directory
+    fn scan(&mut self, encrypted_dir: &Path) {
+        let mut dir_ids: HashMap<PathBuf, u64> = HashMap::from([(encrypted_dir.to_path_buf(), FUSE_ROOT_ID)]);
+
+        for entry in WalkDir::new(encrypted_dir).min_depth(1).sort_by_file_name().into_iter().flatten() {
+            let Some(&parent) = entry.path().parent().and_then(|parent| dir_ids.get(parent)) else {
+                continue;
+            };
+
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+
+            let id = self.nodes.len() as u64 + 1;
+
+            let (name, kind) = if metadata.is_dir() {
+                (entry.file_name().to_os_string(), NodeKind::Dir(BTreeMap::new()))
+            } else if metadata.is_file() && entry.path().extension().is_some_and(|extension| extension == "enom") {
+                if self.decryptor.anon() {
+                    // The name is only found in the decrypted content. Hard links are resolved once the whole tree is known
+                    let Some(decrypted) = self.decrypt(entry.path()) else {
+                        continue;
+                    };
+
+                    let info = match decrypted.content {
+                        DecryptedContent::File(content) => Some(FileInfo::Regular(content.len() as u64, id)),
+                        DecryptedContent::Symlink(link_target) => Some(FileInfo::Symlink(link_target)),
+                        DecryptedContent::HardLink(_) => None,
+                    };
+
+                    (decrypted.name, NodeKind::File(info))
+                } else {
+                    (entry.path().file_stem().unwrap_or_default().to_os_string(), NodeKind::File(None))
+                }
+            } else {
+                continue;
+            };
+
+            let NodeKind::Dir(children) = &mut self.nodes[(parent - 1) as usize].kind else {
+                continue;
+            };
+
+            if children.contains_key(&name) {
+                log(LogLevel::WARN, format!("Leaving out {:?}, since another file decrypts to the same name {:?}", entry.path(), name).as_str());
+                continue;
+            }
+
+            children.insert(name, id);
+
+            if metadata.is_dir() {
+                dir_ids.insert(entry.path().to_path_buf(), id);
+            }
+
+            self.nodes.push(Node { parent, source: entry.path().to_path_buf(), kind, metadata, attr: None });
+        }
+    }
+
+    fn node(&self, id: u64) -> Option<&Node> {
+        id.checked_sub(1).and_then(|index| self.nodes.get(index as usize))
+    }
+
+    fn decrypt(&self, file: &Path) -> Option<DecryptedFile> {
+        match self.decryptor.decrypt_to_memory(file) {
+            Ok(decrypted) => {
+                if self.verbose {
+                    log(LogLevel::INFO, format!("Decrypted :: {:?}", file).as_str());
+                }
+                Some(decrypted)
+            }
+            Err(e) => {
+                log(LogLevel::WARN, format!("Failed to decrypt {}", e).as_str());
+                None
+            }
+        }
+    }
+
+    // The node at a path relative to the root, as the hard link records keep it. A path which could lead out of the root is not looked up
+    fn find(&self, path: &Path) -> Option<u64> {
+        safe_path(path)?
+            .components().try_fold(FUSE_ROOT_ID, |id, component| match (component, &self.node(id)?.kind) {
+            (Component::Normal(name), NodeKind::Dir(children)) => children.get(name).copied(),
+            _ => None,
+        })
+    }
+
+    /* Decrypts the file the first time it is asked for. A hard link takes the content of the file it shares it with.
+    The node is marked as failed while it is resolved, so hard link records which point at each other cannot go round in circles
+    */
+    fn resolve(&mut self, id: u64) -> Option<FileInfo> {
+        let node = self.node(id)?;
+
+        let source = match &node.kind {
+            NodeKind::Dir(_) => return None,
+            NodeKind::File(Some(info)) => return Some(info.clone()),
+            NodeKind::File(None) => node.source.clone(),
+        };
+
+        self.nodes[(id - 1) as usize].kind = NodeKind::File(Some(FileInfo::Failed));
+
+        let info = match self.decrypt(&source).map(|decrypted| decrypted.content) {
+            Some(DecryptedContent::File(content)) => {
+                let info = FileInfo::Regular(content.len() as u64, id);
+                self.recent = Some((id, Arc::new(content)));
+                info
+            }
+            Some(DecryptedContent::Symlink(link_target)) => FileInfo::Symlink(link_target),
+            Some(DecryptedContent::HardLink(first_name)) => match self.find(&first_name).and_then(|target| self.resolve(target)) {
+                Some(FileInfo::Regular(size, content_id)) => FileInfo::Regular(size, content_id),
+                _ => FileInfo::Failed,
+            },
+            None => FileInfo::Failed,
+        };
+
+        self.nodes[(id - 1) as usize].kind = NodeKind::File(Some(info.clone()));
+
+        Some(info)
+    }
+
+    // The decrypted content of a regular file
+    fn content(&mut self, id: u64) -> Option<Arc<Zeroizing<Vec<u8>>>> {
+        if let Some((recent_id, content)) = &self.recent {
+            if *recent_id == id {
+                return Some(content.clone());
+            }
+        }
+
+        let node = self.node(id)?;
+
+        let content = match self.decrypt(&node.source)?.content {
+            DecryptedContent::File(content) => Arc::new(content),
+            _ => return None,
+        };
+
+        self.recent = Some((id, content.clone()));
+
+        Some(content)
+    }
+
+    // The attributes of a node, which are worked out once and kept with it
+    fn attr(&mut self, id: u64) -> Option<FileAttr> {
+        if let Some(attr) = self.node(id)?.attr {
+            return Some(attr);
+        }
+
+        let (kind, size) = match self.resolve(id) {
+            None => (FileType::Directory, 0),
+            Some(FileInfo::Regular(size, _)) => (FileType::RegularFile, size),
+            Some(FileInfo::Symlink(link_target)) => (FileType::Symlink, link_target.as_os_str().len() as u64),
+            Some(FileInfo::Failed) => (FileType::RegularFile, 0),
+        };
+
+        let metadata = &self.node(id)?.metadata;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let perm = match kind {
+            FileType::Directory => metadata.mode() & 0o555,
+            FileType::Symlink => 0o777,
+            _ => metadata.mode() & 0o444,
+        };
+
+        let attr = FileAttr {
+            ino: id,
+            size,
+            blocks: size.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: modified,
+            kind,
+            perm: perm as u16,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        };
+
+        self.nodes[(id - 1) as usize].attr = Some(attr);
+
+        Some(attr)
+    }
+
+    // The type of a node without decrypting it, for the directory entries. A file not decrypted yet is listed as a regular file
+    fn kind(&self, id: u64) -> FileType {
+        match self.node(id).map(|node| &node.kind) {
+            Some(NodeKind::Dir(_)) => FileType::Directory,
+            Some(NodeKind::File(Some(FileInfo::Symlink(_)))) => FileType::Symlink,
+            _ => FileType::RegularFile,
+        }
+    }
+}
+
+// Writing is left to the defaults of fuser, and the mount is read-only, so the kernel refuses any change with EROFS
+impl fuser::Filesystem for Filesystem<'_> {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let child = match self.node(parent).map(|node| &node.kind) {
+            Some(NodeKind::Dir(children)) => children.get(name).copied(),
+            Some(_) => return reply.error(libc::ENOTDIR),
+            None => None,
+        };
+
+        match child.and_then(|child| self.attr(child)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.resolve(ino) {
+            Some(FileInfo::Symlink(link_target)) => reply.data(link_target.as_os_str().as_bytes()),
+            _ => reply.error(libc::EINVAL),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+
+        match self.resolve(ino) {
+            Some(FileInfo::Regular(_, content_id)) => match self.content(content_id) {
+                Some(content) => {
+                    let handle = self.next_handle;
+                    self.next_handle += 1;
+                    self.handles.insert(handle, content);
+
+                    reply.opened(handle, FOPEN_KEEP_CACHE);
+                }
+                None => reply.error(libc::EIO),
+            },
+            Some(FileInfo::Failed) => reply.error(libc::EIO),
+            Some(FileInfo::Symlink(_)) => reply.error(libc::ELOOP),
+            None => reply.error(libc::EISDIR),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.handles.get(&fh) {
+            Some(content) => {
+                let start = (offset.max(0) as usize).min(content.len());
+                let end = start.saturating_add(size as usize).min(content.len());
+
+                reply.data(&content[start..end]);
+            }
+            None => reply.error(libc::EBADF),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        // The content is zeroized once no handle nor the recent file holds it
+        self.handles.remove(&fh);
+        reply.ok();
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.node(ino).map(|node| &node.kind) {
+            Some(NodeKind::Dir(_)) => reply.opened(0, 0),
+            Some(_) => reply.error(libc::ENOTDIR),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let Some(node) = self.node(ino) else {
+            return reply.error(libc::ENOENT);
+        };
+
+        let NodeKind::Dir(children) = &node.kind else {
+            return reply.error(libc::ENOTDIR);
+        };
+
+        let dots = [(OsStr::new("."), ino), (OsStr::new(".."), node.parent)];
+        let entries = dots.into_iter().chain(children.iter().map(|(name, child)| (name.as_os_str(), *child)));
+
+        // The offset of an entry is the one the next read starts from
+        for (index, (name, child)) in entries.enumerate().skip(offset.max(0) as usize) {
+            if reply.add(child, index as i64 + 1, self.kind(child), name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        reply.statfs(0, 0, 0, self.nodes.len() as u64, 0, BLOCK_SIZE, 255, BLOCK_SIZE);
+    }
+}
+
+/* Mounts the encrypted directory read-only at the mount point, and serves it until it is unmounted or the job is cancelled.
+The decrypted contents are zeroized before it returns
+*/
+pub fn mount(decryptor: &Decryptor, encrypted_dir: &Path, mountpoint: &Path, verbose: bool) -> Result<(), RufendecError> {
+    let filesystem = Filesystem::new(decryptor, encrypted_dir, verbose)?;
+
+    let options = [
+        MountOption::RO,
+        MountOption::NoSuid,
+        MountOption::NoDev,
+        MountOption::DefaultPermissions,
+        MountOption::FSName("rufendec".to_string()),
+        MountOption::Subtype("rufendec".to_string()),
+    ];
+
+    let mut session = Session::new(filesystem, mountpoint, &options)
+        .map_err(|e| RufendecError::Runtime(format!("Failed to mount {:?} :: {}", mountpoint, e)))?;
+
+    log(
+        LogLevel::INFO,
+        format!("Mounted {:?} at {:?} (read-only). Press Ctrl+C or unmount it to stop", encrypted_dir, mountpoint).as_str(),
+    );
+
+    // Ctrl+C cancels the job, which unmounts the file system and so ends the session
+    let finished = Arc::new(AtomicBool::new(false));
+    let unmounter = {
+        let finished = finished.clone();
+        let cancel = decryptor.cancellation_token();
+        let mut unmounter = session.unmount_callable();
+
+        thread::spawn(move || {
+            while !finished.load(Ordering::Relaxed) {
+                if cancel.is_cancelled() {
+                    if let Err(e) = unmounter.unmount() {
+                        log(LogLevel::ERROR, format!("Failed to unmount :: {}", e).as_str());
+                    }
+                    return;
+                }
+
+                thread::sleep(POLL_INTERVAL);
+            }
+        })
+    };
+
+    let result = session
+        .run()
+        .map_err(|e| RufendecError::Runtime(format!("Failed to serve {:?} :: {}", mountpoint, e)));
+
+    finished.store(true, Ordering::Relaxed);
+    let _ = unmounter.join();
+
+    // Unmounts the file system if it is still mounted, and drops the decrypted contents
+    drop(session);
+
+    log(LogLevel::INFO, format!("Unmounted {:?}", mountpoint).as_str());
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rufendec::{operations::scan, Encryptor, Key};
+    use std::time::Instant;
+
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    // Waits for the file to show up under the mount point. None if it does not within a few seconds
+    fn read_mounted(file: &Path) -> Option<Vec<u8>> {
+        let started = Instant::now();
+
+        while started.elapsed() < Duration::from_secs(5) {
+            if let Ok(content) = fs::read(file) {
+                return Some(content);
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        None
+    }
+
+    // Needs /dev/fuse and fusermount. Run it with cargo test -- --ignored where FUSE is available
+    #[test]
+    #[ignore = "needs FUSE"]
+    fn mount_read_and_unmount() {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("source");
+        let encrypted = root.path().join("encrypted");
+        let mountpoint = root.path().join("mnt");
+
+        for dir in [source.join("nested"), encrypted.clone(), mountpoint.clone()] {
+            fs::create_dir_all(dir).unwrap();
+        }
+
+        let content = b"read through the mount\n".repeat(1000);
+        fs::write(source.join("nested").join("notes.txt"), &content).unwrap();
+
+        let tree = scan(&source);
+        let encryptor = Encryptor::builder().source_dir(&source).target_dir(&encrypted).key(key()).build().unwrap();
+        encryptor.create_target_dirs(&tree.dirs);
+        assert_eq!(encryptor.encrypt_files(&tree.files).unwrap().success_count, 1);
+
+        let decryptor = Decryptor::builder().source_dir(&encrypted).key(key()).build().unwrap();
+        let cancel = decryptor.cancellation_token();
+
+        thread::scope(|scope| {
+            let mounted = scope.spawn(|| mount(&decryptor, &encrypted, &mountpoint, false));
+
+            let read = read_mounted(&mountpoint.join("nested").join("notes.txt"));
+            let written = fs::write(mountpoint.join("new.txt"), b"refused");
+
+            // Unmounts it, whether it was mounted or not
+            cancel.cancel();
+
+            mounted.join().unwrap().unwrap();
+            assert_eq!(read, Some(content));
+            assert!(written.is_err());
+        });
+
+        assert!(fs::read_dir(&mountpoint).unwrap().next().is_none());
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::report::{FileResult, RunReport};
+
+/* How do the observers work?
+-----------------------
+A job hands every step of a run to its observers: the run starting (with the file count and the total size), a file being picked up,
+the bytes of a file being processed, a file finishing or failing, and the run finishing. The events of different files arrive from the
+Rayon threads in any order, so an observer must be Send and Sync. The CLI draws the indicatif bar and prints the NDJSON stream through
+the observers below, and the desktop app emits its Tauri events through its own observer.
+*/
+
+pub trait Observer: Send + Sync {
+    fn run_started(&self, _total_files: u64, _total_bytes: u64) {}
+
+    fn file_started(&self, _path: &Path, _bytes: u64) {}
+
+    fn bytes_processed(&self, _path: &Path, _bytes: u64) {}
+
+    fn file_finished(&self, _result: &FileResult) {}
+
+    fn file_failed(&self, _result: &FileResult) {}
+
+    fn run_finished(&self, _report: &RunReport) {}
+}
+
+// Draws an indicatif progress bar on the terminal. The bar moves with the bytes, and the message counts the files
+#[derive(Default)]
+pub struct ProgressBarObserver {
+    bar: Mutex<Option<ProgressBar>>,
+    total_files: AtomicU64,
+    done_files: AtomicU64,
+}
+
+impl ProgressBarObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn file_done(&self) {
+        let done = self.done_files.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if let Some(bar) = self.bar.lock().unwrap().as_ref() {
+            bar.set_message(format!("{}/{} files", done, self.total_files.load(Ordering::Relaxed)));
+        }
+    }
+}
+
+impl Observer for ProgressBarObserver {
+    fn run_started(&self, total_files: u64, total_bytes: u64) {
+        let bar = ProgressBar::new(total_bytes);
+
+        bar.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} /{percent}% {msg} completed ({eta_precise})")
+                .unwrap()
+                .with_key("eta", |state: &ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
+                .progress_chars("#>-"));
+        bar.set_message(format!("0/{} files", total_files));
+
+        self.total_files.store(total_files, Ordering::Relaxed);
+        self.done_files.store(0, Ordering::Relaxed);
+        *self.bar.lock().unwrap() = Some(bar);
+    }
+
+    fn bytes_processed(&self, _path: &Path, bytes: u64) {
+        if let Some(bar) = self.bar.lock().unwrap().as_ref() {
+            bar.inc(bytes);
+        }
+    }
+
+    fn file_finished(&self, _result: &FileResult) {
+        self.file_done();
+    }
+
+    fn file_failed(&self, _result: &FileResult) {
+        self.file_done();
+    }
+
+    fn run_finished(&self, _report: &RunReport) {
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish();
+        }
+    }
+}
+
+/* Writes every event as one JSON object per line, with an "event" field naming it: run_started, file_started, bytes_processed,
+file (for a finished or a failed file, told apart by its status) and run_finished.
+*/
+pub struct JsonLinesObserver<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesObserver<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesObserver {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    fn write_event<T: Serialize>(&self, event: &str, data: &T) {
+        let mut line = Map::new();
+        line.insert("event".to_string(), Value::from(event));
+
+        match serde_json::to_value(data) {
+            Ok(Value::Object(fields)) => line.extend(fields),
+            Ok(value) => {
+                line.insert("data".to_string(), value);
+            }
+            Err(_) => return,
+        }
+
+        // The line is written at once, so the lines of different threads never mix
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writer.write_all(format!("{}\n", Value::Object(line)).as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+impl<W: Write + Send> Observer for JsonLinesObserver<W> {
+    fn run_started(&self, total_files: u64, total_bytes: u64) {
+        self.write_event("run_started", &json!({ "total_files": total_files, "total_bytes": total_bytes }));
+    }
+
+    fn file_started(&self, path: &Path, bytes: u64) {
+        self.write_event("file_started", &json!({ "source": path.to_string_lossy(), "bytes": bytes }));
+    }
+
+    fn bytes_processed(&self, path: &Path, bytes: u64) {
+        self.write_event("bytes_processed", &json!({ "source": path.to_string_lossy(), "bytes": bytes }));
+    }
+
+    fn file_finished(&self, result: &FileResult) {
+        self.write_event("file", result);
+    }
+
+    fn file_failed(&self, result: &FileResult) {
+        self.write_event("file", result);
+    }
+
+    fn run_finished(&self, report: &RunReport) {
+        self.write_event(
+            "run_finished",
+            &json!({
+                "success_count": report.success_count,
+                "failed_count": report.failed_count,
+                "skipped_count": report.skipped_count,
+                "bytes_processed": report.bytes_processed,
+                "cancelled": report.cancelled,
+            }),
+        );
+    }
+}
@@ -0,0 +1,688 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use aes::{Aes128, Aes192, Aes256};
+use cfb_mode::{
+    cipher::{AsyncStreamCipher, KeyIvInit},
+    Decryptor, Encryptor,
+};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+/* How is an OpenPGP message laid out?
+-----------------------
+The openpgp mode writes the messages which "gpg --symmetric" writes (RFC 4880, and RFC 9580), so they open with "gpg --decrypt" and the password.
+A message is a sequence of packets, each of which is a tag and a length followed by the body.
+1. The Symmetric-Key Encrypted Session Key (SKESK) packet, version 4. It names the cipher (AES-256) and the S2K, which is how the key is
+   derived from the password: the iterated and salted S2K (SHA-256 over the salt and the password, repeated to 65011712 bytes), which
+   every version of gpg reads. The derived key is the session key, so no encrypted session key follows.
+2. The Symmetrically Encrypted Integrity Protected Data (SEIPD) packet, version 1. Its body is encrypted with AES-256 in CFB mode with a
+   zero IV, and holds a random block (with its last two bytes repeated), a Literal Data packet with the content of the file, and the
+   Modification Detection Code (MDC) packet, which is the SHA-1 hash of everything before it. The MDC is what detects a modified message.
+The salt of the S2K is picked once for the run, so the key is derived once and not for every file. A message made by gpg has a salt of its
+own, so the key is derived for every S2K found, and kept for the run.
+On decrypt, a message made by gpg may also carry an encrypted session key, use AES-128 or AES-192, the Argon2 S2K (RFC 9580), have lengths
+which are split into parts, and a Compressed Data packet (ZIP or ZLIB) around the Literal Data packet. The decompressed data is capped at
+MAX_COMPRESSION_RATIO times the size of the decrypted packets, however deep the Compressed Data packets are nested (at most
+MAX_COMPRESSION_DEPTH), so a small message cannot take up all the memory. Argon2 derivations run one at a time for the same reason.
+A message encrypted to a public key, one without integrity
+protection (the SED packet), one using AEAD (the AEAD packet, or version 2 of the SEIPD packet) or one compressed with BZip2 is not supported.
+*/
+
+const TAG_PKESK: u8 = 1;
+const TAG_SIGNATURE: u8 = 2;
+const TAG_SKESK: u8 = 3;
+const TAG_ONE_PASS_SIGNATURE: u8 = 4;
+const TAG_COMPRESSED: u8 = 8;
+const TAG_SED: u8 = 9;
+const TAG_MARKER: u8 = 10;
+const TAG_LITERAL: u8 = 11;
+const TAG_SEIPD: u8 = 18;
+const TAG_AEAD: u8 = 20;
+
+const CIPHER_AES128: u8 = 7;
+const CIPHER_AES192: u8 = 8;
+const CIPHER_AES256: u8 = 9;
+
+const HASH_SHA1: u8 = 2;
+const HASH_SHA256: u8 = 8;
+const HASH_SHA384: u8 = 9;
+const HASH_SHA512: u8 = 10;
+const HASH_SHA224: u8 = 11;
+
+const S2K_SIMPLE: u8 = 0;
+const S2K_SALTED: u8 = 1;
+const S2K_ITERATED: u8 = 3;
+const S2K_ARGON2: u8 = 4;
+
+// 65011712 bytes, the most the count can code for
+const ITERATED_COUNT: u8 = 0xFF;
+// 2 GiB, the first choice RFC 9580 recommends. A message asking for more is refused
+const MAX_ARGON2_MEMORY_EXPONENT: u8 = 21;
+
+const BLOCK_LEN: usize = 16;
+// The tag and the length of the MDC packet, and the SHA-1 hash
+const MDC_HEADER: [u8; 2] = [0xD3, 0x14];
+const MDC_LEN: usize = 22;
+
+// gpg compresses once, so a message compressed more than twice is refused
+const MAX_COMPRESSION_DEPTH: usize = 2;
+// The most deflate can compress by, so a message compressed once is never refused
+const MAX_COMPRESSION_RATIO: usize = 1032;
+
+const MALFORMED: &str = "The OpenPGP message is malformed";
+const WRONG_PASSWORD: &str = "The OpenPGP message could not be decrypted. Check the password";
+
+// How the key of a message is derived from the password on encrypt. Messages are decrypted with any S2K
+pub const S2K: &str = "iterated and salted SHA-256";
+
+// Encrypts and decrypts OpenPGP messages with a password
+pub struct OpenPgp {
+    passphrase: Zeroizing<Vec<u8>>,
+    // The S2K specifier the messages are encrypted with. Its salt is picked once
+    s2k: Vec<u8>,
+    // The keys derived so far, by the cipher and the S2K specifier
+    keys: Mutex<HashMap<Vec<u8>, Zeroizing<Vec<u8>>>>,
+    // Held while Argon2 runs, so the threads do not take up to 2 GiB each at the same time
+    argon2: Mutex<()>,
+}
+
+impl OpenPgp {
+    pub fn new(passphrase: &str) -> OpenPgp {
+        let mut salt = [0u8; 8];
+        rand::rng().fill(&mut salt);
+
+        OpenPgp {
+            passphrase: Zeroizing::new(passphrase.as_bytes().to_vec()),
+            s2k: [&[S2K_ITERATED, HASH_SHA256][..], &salt, &[ITERATED_COUNT]].concat(),
+            keys: Mutex::new(HashMap::new()),
+            argon2: Mutex::new(()),
+        }
+    }
+
+    // The key the S2K specifier derives from the password for the cipher
+    fn derive(&self, cipher: u8, s2k: &[u8]) -> Result<Zeroizing<Vec<u8>>, &'static str> {
+        let id = [&[cipher], s2k].concat();
+        let derived = || self.keys.lock().unwrap().get(&id).cloned();
+
+        if let Some(key) = derived() {
+            return Ok(key);
+        }
+
+        let _argon2 = (s2k.first() == Some(&S2K_ARGON2)).then(|| self.argon2.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+
+        // Another thread may have derived it while this one waited
+        if let Some(key) = derived() {
+            return Ok(key);
+        }
+
+        let key = derive_key(&self.passphrase, s2k, key_len(cipher)?)?;
+        self.keys.lock().unwrap().insert(id, key.clone());
+
+        Ok(key)
+    }
+
+    // Encrypts the content into an OpenPGP message. Returns None only if the content is too large for a message
+    pub fn encrypt(&self, content: &[u8]) -> Option<Vec<u8>> {
+        let key = self.derive(CIPHER_AES256, &self.s2k).ok()?;
+
+        let date = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as u32);
+
+        // Binary data, with no file name
+        let mut literal = Zeroizing::new(Vec::with_capacity(content.len() + 6));
+        literal.extend_from_slice(&[b'b', 0]);
+        literal.extend_from_slice(&date.to_be_bytes());
+        literal.extend_from_slice(content);
+
+        let mut packets = Zeroizing::new(Vec::with_capacity(literal.len() + 6));
+        write_packet(&mut packets, TAG_LITERAL, &literal)?;
+
+        self.encrypt_packets(&key, &packets)
+    }
+
+    // Wraps the packets in the SEIPD packet, after the SKESK packet
+    fn encrypt_packets(&self, key: &[u8], packets: &[u8]) -> Option<Vec<u8>> {
+        let mut prefix = [0u8; BLOCK_LEN];
+        rand::rng().fill(&mut prefix);
+
+        let mut plain = Zeroizing::new(Vec::with_capacity(packets.len() + 64));
+        plain.extend_from_slice(&prefix);
+        plain.extend_from_slice(&prefix[BLOCK_LEN - 2..]);
+        plain.extend_from_slice(packets);
+        plain.extend_from_slice(&MDC_HEADER);
+
+        let mdc = Sha1::digest(plain.as_slice());
+        plain.extend_from_slice(&mdc);
+
+        let mut seipd = [&[1u8][..], &plain].concat();
+        Encryptor::<Aes256>::new_from_slices(key, &[0u8; BLOCK_LEN]).ok()?.encrypt(&mut seipd[1..]);
+
+        let mut message = Vec::with_capacity(seipd.len() + 32);
+        write_packet(&mut message, TAG_SKESK, &[&[4, CIPHER_AES256][..], &self.s2k].concat())?;
+        write_packet(&mut message, TAG_SEIPD, &seipd)?;
+
+        Some(message)
+    }
+
+    // The size of the message encrypt writes for content of the given size
+    pub fn encrypted_len(&self, len: usize) -> usize {
+        let literal = packet_len(len + 6);
+        let seipd = packet_len(1 + BLOCK_LEN + 2 + literal + MDC_LEN);
+
+        packet_len(2 + self.s2k.len()) + seipd
+    }
+
+    // Decrypts an OpenPGP message encrypted with a password, and returns the content of its Literal Data packet
+    pub fn decrypt(&self, message: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let mut data = message;
+        let mut skesks = Vec::new();
+        let mut public_key = false;
+
+        let encrypted = loop {
+            if data.is_empty() {
+                return Err(if public_key {
+                    "The OpenPGP message was encrypted to a public key, not with a password"
+                } else {
+                    "The OpenPGP message holds no encrypted data"
+                });
+            }
+
+            match read_packet(&mut data).map_err(|_| "This is not an OpenPGP message")? {
+                (TAG_SKESK, body) => skesks.push(body),
+                (TAG_PKESK, _) => public_key = true,
+                (TAG_MARKER, _) => {}
+                (TAG_SEIPD, body) => break body,
+                (TAG_SED, _) => return Err("The OpenPGP message has no integrity protection, so it is not decrypted"),
+                (TAG_AEAD, _) => return Err("The OpenPGP message uses the AEAD Encrypted Data packet, which is not supported"),
+                _ => return Err("This is not an OpenPGP message encrypted with a password"),
+            }
+        };
+
+        if skesks.is_empty() {
+            return Err("The OpenPGP message was encrypted to a public key, not with a password");
+        }
+
+        // Any of the session key packets may be the one for the password
+        let mut cause = WRONG_PASSWORD;
+
+        for skesk in &skesks {
+            match self.session_key(skesk).and_then(|(cipher, key)| decrypt_seipd(cipher, &key, &encrypted)) {
+                Ok(packets) => return literal_data(&packets, 0, packets.len().saturating_mul(MAX_COMPRESSION_RATIO)),
+                Err(e) => cause = e,
+            }
+        }
+
+        Err(cause)
+    }
+
+    // The cipher and the session key of a SKESK packet
+    fn session_key(&self, skesk: &[u8]) -> Result<(u8, Zeroizing<Vec<u8>>), &'static str> {
+        let [version, cipher, specifier @ ..] = skesk else {
+            return Err(MALFORMED);
+        };
+
+        if *version != 4 {
+            return Err("The OpenPGP message uses a version of the SKESK packet other than 4, which is not supported");
+        }
+
+        let (s2k, encrypted_session_key) = specifier.split_at(s2k_len(specifier)?.min(specifier.len()));
+        let key = self.derive(*cipher, s2k)?;
+
+        if encrypted_session_key.is_empty() {
+            return Ok((*cipher, key));
+        }
+
+        let mut session_key = Zeroizing::new(encrypted_session_key.to_vec());
+        cfb_decrypt(*cipher, &key, &mut session_key)?;
+
+        let (session_cipher, session_key) = session_key.split_first().ok_or(MALFORMED)?;
+
+        // A wrong password decrypts to noise
+        match key_len(*session_cipher) {
+            Ok(len) if len == session_key.len() => Ok((*session_cipher, Zeroizing::new(session_key.to_vec()))),
+            _ => Err(WRONG_PASSWORD),
+        }
+    }
+}
+
+fn key_len(cipher: u8) -> Result<usize, &'static str> {
+    match cipher {
+        CIPHER_AES128 => Ok(16),
+        CIPHER_AES192 => Ok(24),
+        CIPHER_AES256 => Ok(32),
+        _ => Err("The OpenPGP message uses a cipher other than AES, which is not supported"),
+    }
+}
+
+fn cfb_decrypt(cipher: u8, key: &[u8], data: &mut [u8]) -> Result<(), &'static str> {
+    let iv = [0u8; BLOCK_LEN];
+
+    match cipher {
+        CIPHER_AES128 => Decryptor::<Aes128>::new_from_slices(key, &iv).map_err(|_| MALFORMED)?.decrypt(data),
+        CIPHER_AES192 => Decryptor::<Aes192>::new_from_slices(key, &iv).map_err(|_| MALFORMED)?.decrypt(data),
+        CIPHER_AES256 => Decryptor::<Aes256>::new_from_slices(key, &iv).map_err(|_| MALFORMED)?.decrypt(data),
+        _ => return Err(key_len(cipher).err().unwrap_or(MALFORMED)),
+    }
+
+    Ok(())
+}
+
+// The length of the S2K specifier at the start of the bytes
+fn s2k_len(specifier: &[u8]) -> Result<usize, &'static str> {
+    match specifier.first() {
+        Some(&S2K_SIMPLE) => Ok(2),
+        Some(&S2K_SALTED) => Ok(10),
+        Some(&S2K_ITERATED) => Ok(11),
+        Some(&S2K_ARGON2) => Ok(20),
+        Some(_) => Err("The OpenPGP message uses an S2K which is not supported"),
+        None => Err(MALFORMED),
+    }
+}
+
+// Derives the key of the given length from the password with the S2K specifier
+fn derive_key(passphrase: &[u8], s2k: &[u8], key_len: usize) -> Result<Zeroizing<Vec<u8>>, &'static str> {
+    if s2k.len() != s2k_len(s2k)? {
+        return Err(MALFORMED);
+    }
+
+    match s2k[0] {
+        S2K_ARGON2 => {
+            let (salt, [passes, parallelism, memory_exponent]) = (&s2k[1..17], [s2k[17], s2k[18], s2k[19]]);
+
+            if memory_exponent > MAX_ARGON2_MEMORY_EXPONENT {
+                return Err("The Argon2 S2K of the OpenPGP message asks for more memory than is allowed");
+            }
+
+            let params = argon2::Params::new(1 << memory_exponent, passes as u32, parallelism as u32, Some(key_len))
+                .map_err(|_| "The Argon2 S2K of the OpenPGP message has invalid parameters")?;
+
+            let mut key = Zeroizing::new(vec![0u8; key_len]);
+
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .hash_password_into(passphrase, salt, &mut key)
+                .map_err(|_| "The Argon2 S2K of the OpenPGP message has invalid parameters")?;
+
+            Ok(key)
+        }
+        kind => {
+            let salt = if kind == S2K_SIMPLE { &[][..] } else { &s2k[2..10] };
+            let count = if kind == S2K_ITERATED { (16 + (s2k[10] as usize & 15)) << ((s2k[10] >> 4) + 6) } else { 0 };
+
+            match s2k[1] {
+                HASH_SHA1 => Ok(hash_s2k::<Sha1>(passphrase, salt, count, key_len)),
+                HASH_SHA224 => Ok(hash_s2k::<Sha224>(passphrase, salt, count, key_len)),
+                HASH_SHA256 => Ok(hash_s2k::<Sha256>(passphrase, salt, count, key_len)),
+                HASH_SHA384 => Ok(hash_s2k::<Sha384>(passphrase, salt, count, key_len)),
+                HASH_SHA512 => Ok(hash_s2k::<Sha512>(passphrase, salt, count, key_len)),
+                _ => Err("The S2K of the OpenPGP message uses a hash function which is not supported"),
+            }
+        }
+    }
+}
+
+/* The simple, salted and iterated S2K. The salt and the password are hashed over and over up to the count (or once, if they are longer).
+A key longer than the hash takes more hashes, each of which starts with one more zero byte
+*/
+fn hash_s2k<D: Digest>(passphrase: &[u8], salt: &[u8], count: usize, key_len: usize) -> Zeroizing<Vec<u8>> {
+    let data = Zeroizing::new([salt, passphrase].concat());
+    let total = count.max(data.len());
+
+    // The salt and the password repeated, so the hash is fed in large slices
+    let repeated = Zeroizing::new(data.repeat((64 * 1024 / data.len().max(1)).max(1)));
+
+    let mut key = Zeroizing::new(Vec::with_capacity(key_len + <D as Digest>::output_size()));
+
+    for preload in 0.. {
+        if key.len() >= key_len {
+            break;
+        }
+
+        let mut hasher = D::new();
+        hasher.update(vec![0u8; preload]);
+
+        let mut remaining = total;
+
+        while remaining > 0 {
+            let len = remaining.min(repeated.len());
+            hasher.update(&repeated[..len]);
+            remaining -= len;
+        }
+
+        key.extend_from_slice(&hasher.finalize());
+    }
+
+    key.truncate(key_len);
+    key
+}
+
+// Removes the random block and the MDC of the decrypted SEIPD packet once they check out, and returns the packets within
+fn decrypt_seipd(cipher: u8, key: &[u8], body: &[u8]) -> Result<Zeroizing<Vec<u8>>, &'static str> {
+    let (&version, encrypted) = body.split_first().ok_or(MALFORMED)?;
+
+    if version != 1 {
+        return Err("The OpenPGP message uses a version of the SEIPD packet other than 1, which is not supported");
+    }
+
+    if encrypted.len() < BLOCK_LEN + 2 + MDC_LEN {
+        return Err(MALFORMED);
+    }
+
+    let mut plain = Zeroizing::new(encrypted.to_vec());
+    cfb_decrypt(cipher, key, &mut plain)?;
+
+    // The quick check. The last two bytes of the random block are repeated, which a wrong key almost never gives
+    if plain[BLOCK_LEN - 2..BLOCK_LEN] != plain[BLOCK_LEN..BLOCK_LEN + 2] {
+        return Err(WRONG_PASSWORD);
+    }
+
+    let (hashed, mdc) = plain.split_at(plain.len() - (MDC_LEN - 2));
+
+    if !hashed.ends_with(&MDC_HEADER) || Sha1::digest(hashed).as_slice() != mdc {
+        return Err("The OpenPGP message could not be authenticated. Its MDC does not match, so it was modified");
+    }
+
+    Ok(Zeroizing::new(plain[BLOCK_LEN + 2..plain.len() - MDC_LEN].to_vec()))
+}
+
+/* The content of the Literal Data packet among the packets, which may be compressed. Signatures are passed over, and not checked.
+The depth is how many Compressed Data packets the packets were found in, and the limit is the most any of them may decompress to
+*/
+fn literal_data(packets: &[u8], depth: usize, limit: usize) -> Result<Vec<u8>, &'static str> {
+    let mut data = packets;
+
+    while !data.is_empty() {
+        match read_packet(&mut data)? {
+            (TAG_LITERAL, body) => {
+                // The format, the file name and the date come before the content
+                let (_, rest) = body.split_first().ok_or(MALFORMED)?;
+                let (&name_len, rest) = rest.split_first().ok_or(MALFORMED)?;
+
+                return rest.get(name_len as usize + 4..).map(<[u8]>::to_vec).ok_or(MALFORMED);
+            }
+            (TAG_COMPRESSED, body) => {
+                if depth == MAX_COMPRESSION_DEPTH {
+                    return Err("The OpenPGP message is compressed too many times over");
+                }
+
+                let (&algorithm, compressed) = body.split_first().ok_or(MALFORMED)?;
+
+                let decompressed = Zeroizing::new(match algorithm {
+                    0 => compressed.to_vec(),
+                    1 => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, limit).map_err(decompress_error)?,
+                    2 => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, limit).map_err(decompress_error)?,
+                    3 => return Err("The OpenPGP message is compressed with BZip2, which is not supported"),
+                    _ => return Err("The OpenPGP message is compressed with an unknown algorithm"),
+                });
+
+                return literal_data(&decompressed, depth + 1, limit);
+            }
+            (TAG_ONE_PASS_SIGNATURE | TAG_SIGNATURE | TAG_MARKER, _) => {}
+            _ => return Err(MALFORMED),
+        }
+    }
+
+    Err("The OpenPGP message holds no literal data")
+}
+
+fn decompress_error(e: miniz_oxide::inflate::DecompressError) -> &'static str {
+    match e.status {
+        miniz_oxide::inflate::TINFLStatus::HasMoreOutput => "The OpenPGP message decompresses to more than is allowed",
+        _ => "The OpenPGP message could not be decompressed",
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], &'static str> {
+    if data.len() < len {
+        return Err(MALFORMED);
+    }
+
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+
+    Ok(taken)
+}
+
+/* Reads the next packet, with the old or the new format of the header. The parts of a body whose length is split into parts (which gpg
+writes when it encrypts a stream) are joined
+*/
+fn read_packet<'a>(data: &mut &'a [u8]) -> Result<(u8, Cow<'a, [u8]>), &'static str> {
+    let header = take(data, 1)?[0];
+
+    if header & 0x80 == 0 {
+        return Err(MALFORMED);
+    }
+
+    // The old format, where the tag and the size of the length share the first byte
+    if header & 0x40 == 0 {
+        let len = match header & 3 {
+            0 => take(data, 1)?[0] as usize,
+            1 => u16::from_be_bytes(take(data, 2)?.try_into().unwrap()) as usize,
+            2 => u32::from_be_bytes(take(data, 4)?.try_into().unwrap()) as usize,
+            // The body runs to the end
+            _ => data.len(),
+        };
+
+        return Ok(((header >> 2) & 0x0F, Cow::Borrowed(take(data, len)?)));
+    }
+
+    let tag = header & 0x3F;
+    let mut parts = Vec::new();
+
+    loop {
+        let first = take(data, 1)?[0];
+
+        let (len, partial) = match first {
+            0..=191 => (first as usize, false),
+            192..=223 => ((((first as usize) - 192) << 8) + take(data, 1)?[0] as usize + 192, false),
+            255 => (u32::from_be_bytes(take(data, 4)?.try_into().unwrap()) as usize, false),
+            _ => (1usize << (first & 0x1F), true),
+        };
+
+        let body = take(data, len)?;
+
+        match (partial, parts.is_empty()) {
+            (true, _) => parts.extend_from_slice(body),
+            (false, true) => return Ok((tag, Cow::Borrowed(body))),
+            (false, false) => {
+                parts.extend_from_slice(body);
+                return Ok((tag, Cow::Owned(parts)));
+            }
+        }
+    }
+}
+
+// The size of a packet written by write_packet with a body of the given size
+fn packet_len(body_len: usize) -> usize {
+    let header_len = match body_len {
+        len if len < 192 => 2,
+        len if len < 8384 => 3,
+        _ => 6,
+    };
+
+    header_len + body_len
+}
+
+// Writes a packet with the new format of the header. Returns None if the body is too large for a packet
+fn write_packet(out: &mut Vec<u8>, tag: u8, body: &[u8]) -> Option<()> {
+    out.push(0xC0 | tag);
+
+    match body.len() {
+        len if len < 192 => out.push(len as u8),
+        len if len < 8384 => out.extend_from_slice(&[((len - 192) >> 8) as u8 + 192, ((len - 192) & 0xFF) as u8]),
+        len => {
+            out.push(255);
+            out.extend_from_slice(&u32::try_from(len).ok()?.to_be_bytes());
+        }
+    }
+
+    out.extend_from_slice(body);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path, process};
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn content() -> Vec<u8> {
+        b"an OpenPGP message\n".repeat(500)
+    }
+
+    // Runs gpg with a home directory of its own
+    fn gpg(home: &Path, args: &[&str]) -> process::Output {
+        process::Command::new("gpg")
+            .arg("--homedir")
+            .arg(home)
+            .args(["--batch", "--quiet", "--pinentry-mode", "loopback", "--passphrase", PASSPHRASE])
+            .args(args)
+            .output()
+            .expect("gpg is installed")
+    }
+
+    // A message around the packets, under the session key derived from the password
+    fn message(openpgp: &OpenPgp, packets: &[u8]) -> Vec<u8> {
+        let key = openpgp.derive(CIPHER_AES256, &openpgp.s2k).unwrap();
+        openpgp.encrypt_packets(&key, packets).unwrap()
+    }
+
+    fn compressed(packets: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        write_packet(&mut packet, TAG_COMPRESSED, &[&[1u8][..], &miniz_oxide::deflate::compress_to_vec(packets, 6)].concat()).unwrap();
+        packet
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let openpgp = OpenPgp::new(PASSPHRASE);
+        let content = content();
+        let message = openpgp.encrypt(&content).unwrap();
+
+        assert_eq!(message.len(), openpgp.encrypted_len(content.len()));
+        assert_eq!(openpgp.decrypt(&message).unwrap(), content);
+        // Any OpenPgp with the password decrypts it, whatever salt it picked
+        assert_eq!(OpenPgp::new(PASSPHRASE).decrypt(&message).unwrap(), content);
+    }
+
+    #[test]
+    fn modified_message_is_rejected() {
+        let openpgp = OpenPgp::new(PASSPHRASE);
+        let mut message = openpgp.encrypt(&content()).unwrap();
+        let middle = message.len() / 2;
+        message[middle] ^= 1;
+
+        assert_eq!(
+            openpgp.decrypt(&message),
+            Err("The OpenPGP message could not be authenticated. Its MDC does not match, so it was modified")
+        );
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let message = OpenPgp::new(PASSPHRASE).encrypt(&content()).unwrap();
+
+        assert_eq!(OpenPgp::new("wrong").decrypt(&message), Err(WRONG_PASSWORD));
+    }
+
+    #[test]
+    fn compressed_message_is_decompressed() {
+        let openpgp = OpenPgp::new(PASSPHRASE);
+        let mut literal = Vec::new();
+        write_packet(&mut literal, TAG_LITERAL, &[&[b'b', 0, 0, 0, 0, 0][..], &content()].concat()).unwrap();
+
+        assert_eq!(openpgp.decrypt(&message(&openpgp, &compressed(&literal))).unwrap(), content());
+        assert_eq!(openpgp.decrypt(&message(&openpgp, &compressed(&compressed(&literal)))).unwrap(), content());
+        assert_eq!(
+            openpgp.decrypt(&message(&openpgp, &compressed(&compressed(&compressed(&literal))))),
+            Err("The OpenPGP message is compressed too many times over")
+        );
+    }
+
+    #[test]
+    fn compression_bomb_is_refused() {
+        let openpgp = OpenPgp::new(PASSPHRASE);
+        let mut literal = Vec::new();
+        write_packet(&mut literal, TAG_LITERAL, &[&[b'b', 0, 0, 0, 0, 0][..], &vec![0u8; 16 << 20]].concat()).unwrap();
+
+        // Compressing twice over gives far more than one layer of deflate could
+        assert_eq!(
+            openpgp.decrypt(&message(&openpgp, &compressed(&compressed(&literal)))),
+            Err("The OpenPGP message decompresses to more than is allowed")
+        );
+    }
+
+    #[test]
+    fn argon2_message_is_decrypted() {
+        let openpgp = OpenPgp::new(PASSPHRASE);
+        // 1 MiB of memory, so the test is quick
+        let s2k = [&[S2K_ARGON2][..], &[5u8; 16], &[1, 4, 10]].concat();
+        let key = openpgp.derive(CIPHER_AES256, &s2k).unwrap();
+
+        let mut literal = Vec::new();
+        write_packet(&mut literal, TAG_LITERAL, &[&[b'b', 0, 0, 0, 0, 0][..], &content()].concat()).unwrap();
+        let encrypted = openpgp.encrypt_packets(&key, &literal).unwrap();
+
+        // The SKESK packet written by encrypt_packets names the iterated S2K, so it is swapped for one naming Argon2
+        let mut data = encrypted.as_slice();
+        read_packet(&mut data).unwrap();
+        let mut message = Vec::new();
+        write_packet(&mut message, TAG_SKESK, &[&[4, CIPHER_AES256][..], &s2k].concat()).unwrap();
+        message.extend_from_slice(data);
+
+        assert_eq!(OpenPgp::new(PASSPHRASE).decrypt(&message).unwrap(), content());
+    }
+
+    // The gpg tests need gpg 2.1 or later. Run them with cargo test -- --ignored where it is installed
+    #[test]
+    #[ignore = "needs gpg"]
+    fn gpg_decrypts_our_messages() {
+        let home = tempfile::tempdir().unwrap();
+        let message = home.path().join("message.gpg");
+        fs::write(&message, OpenPgp::new(PASSPHRASE).encrypt(&content()).unwrap()).unwrap();
+
+        let output = gpg(home.path(), &["--decrypt", message.to_str().unwrap()]);
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(output.stdout, content());
+    }
+
+    #[test]
+    #[ignore = "needs gpg"]
+    fn gpg_messages_are_decrypted() {
+        let home = tempfile::tempdir().unwrap();
+        let plain = home.path().join("plain.txt");
+        fs::write(&plain, content()).unwrap();
+
+        let openpgp = OpenPgp::new(PASSPHRASE);
+
+        for (name, args) in [
+            ("default.gpg", &[][..]),
+            ("zlib.gpg", &["--compress-algo", "zlib"][..]),
+            ("aes128.gpg", &["--cipher-algo", "AES128", "--compress-algo", "none"][..]),
+            ("armored.asc", &["--armor"][..]),
+        ] {
+            let message = home.path().join(name);
+            let encrypt = [args, &["--symmetric", "--output", message.to_str().unwrap(), plain.to_str().unwrap()]].concat();
+
+            let output = gpg(home.path(), &encrypt);
+
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+            let mut data = fs::read(&message).unwrap();
+
+            if crate::armor::is_armored(&data) {
+                data = crate::armor::dearmor(&data).unwrap();
+            }
+
+            assert_eq!(openpgp.decrypt(&data).unwrap(), content(), "{}", name);
+        }
+    }
+}
@@ -1,93 +1,98 @@
 // Copyright (c) 2023 Venkatesh Omkaram
 
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     aes::cipher::{
         crypto_common::generic_array::GenericArray,
-        typenum::{UInt, UTerm, B0, B1, /*U32, U16*/ U12},
+        typenum::{U12, U32},
     },
     Aes256Gcm, //, Nonce, Key // Or `Aes128Gcm`
 };
 use base64::prelude::*;
 use byte_aes::Aes256Cryptor;
 use file_shred::{shred, ShredConfig, Verbosity};
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use lazy_static::lazy_static;
+use ignore::gitignore::Gitignore;
 use rand::{distr::Alphanumeric, Rng};
-use rayon;
-pub use std::sync::Mutex;
+use serde::Serialize;
 use std::{
-    fmt::Write,
+    borrow::Cow,
+    cell::Cell,
+    ffi::OsString,
     fs,
     path::{Path, PathBuf},
-    process,
-    sync::{Arc, RwLock},
+    time::Instant,
 };
 use walkdir::WalkDir;
-
-#[cfg(target_os = "linux")]
-use std::os::unix::fs::MetadataExt;
-
-#[cfg(target_os = "windows")]
-use std::os::windows::fs::MetadataExt;
+use zeroize::Zeroizing;
 
 use crate::{
-    config::Shred,
+    age_format::{Age, AgeKeys},
+    armor::{armor, armor_age, armor_openpgp, dearmor, is_armored},
+    common::{path_from_bytes, path_to_bytes},
+    compression::{compress, decompress, is_compressed, Compression, ZSTD_LEVELS},
+    error::RufendecError,
+    filter::{Filter, IGNORE_FILE_NAME},
+    job::Job,
+    links::{create_hard_link, create_symlink, shares_content, LinkKind, LinkRecord, PendingHardLink, SymlinkPolicy},
     log::{log, LogLevel},
+    journal::JOURNAL_FILE_NAME,
+    openpgp::OpenPgp,
+    padding::{pad, unpad, PadPolicy},
+    report::{FileResult, Stage},
+    secrets::Key,
+    sync::SYNC_DB_FILE_NAME,
 };
 
 /* What do the above imports do?
 -----------------------
 aes_gcm - Has the functions which helps to encrypt and decrypt the files for GCM mode
 byte_aes - Has the functions which helps to encrypt and decrypt the files for ECB mode
-lazy_static - A rust way to have Global variables
 rayon - Helps to make the cipher operations multi-threaded
-std - Has some standard core features to find Operation system, read and write files, find time, Atomic Reference Counter, process to forcefully exit the program execution
+std - Has some standard core features to find Operation system, read and write files, find time
 walkdir - Helps to walk through a given folder path
-indicatif - Has some fancy ProgressBar and Spinners to print on the screen
 file_shred - A basic file shred crate
 
 Read the Cargo.toml and Attributions to see which versions and the Authors who made these crates
 */
 
-// Specify the Global Variables. These variables are initialized using lazy_static macro and can be accessed anywhere in code
-// Mutex is required to access these variables inside Rayon threads
-lazy_static! {
-    pub static ref ECB_32BYTE_KEY: RwLock<Vec<GenericArray<u8, UInt<UInt<UInt<UInt<UInt<UInt<UTerm, B1>, B0>, B0>, B0>, B0>, B0>>>> =
-        RwLock::new(Vec::new());
-    pub static ref GCM_32BYTE_KEY: RwLock<Vec<GenericArray<u8, UInt<UInt<UInt<UInt<UInt<UInt<UTerm, B1>, B0>, B0>, B0>, B0>, B0>>>> =
-        RwLock::new(Vec::new());
-    pub static ref DIR_LIST: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
-    pub static ref FILE_LIST: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
-    pub static ref FILES_SIZE_BYTES: Mutex<u64> = Mutex::new(0);
-    pub static ref FAILED_COUNT: Mutex<u16> = Mutex::new(0);
-    pub static ref SUCCESS_COUNT: Mutex<u16> = Mutex::new(0);
-    pub static ref VERBOSE: RwLock<bool> = RwLock::new(false);
-}
-
-// A simple macro which prints only when verbose printing is specified using the -v program argument
+// The 32 byte key shared by the ECB and GCM modes
+pub type Key32 = GenericArray<u8, U32>;
+
+// A simple macro which prints only when verbose printing is chosen for the job
 macro_rules! logger {
-    // Match a format string and one or more arguments
-    ($value:literal, $($item:expr),*) => {
-        if *VERBOSE.read().unwrap() {
-            println!($value, $($item),*);
+    // Match the job, a format string and one or more arguments
+    ($job:expr, $value:literal, $($item:expr),*) => {
+        if $job.verbose {
+            crate::log::print(&format!($value, $($item),*));
         }
     };
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Clone, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Operation {
     Encrypt,
     Decrypt,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     ECB,
     GCM,
+    /// The symmetrically encrypted messages of OpenPGP (RFC 4880 and RFC 9580), which gpg decrypts with the password
+    #[value(name = "openpgp")]
+    #[serde(rename = "openpgp")]
+    OpenPGP,
+    /// The files of age (age-encryption.org/v1), encrypted with the password or to X25519 public keys, which any age tool decrypts
+    #[value(name = "age")]
+    #[serde(rename = "age")]
+    Age,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug, Copy)]
+#[derive(clap::ValueEnum, Clone, Debug, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HashMode {
     Argon2,
     PBKDF2,
@@ -108,672 +113,1240 @@ impl std::fmt::Display for Mode {
     }
 }
 
-// This function helps to Construct a ProgressBar for a given file count. But not to be used only when verbose printing is allowed.
-// ProgressBar needs to be Arc<Mutex<>> because it will be shared among threads
-fn progress_bar(file_count: u64) -> Option<Arc<Mutex<ProgressBar>>> {
-    if !(*VERBOSE.read().unwrap()) {
-        let pb = ProgressBar::new(file_count);
+#[derive(clap::Args, Debug, Clone)]
+#[command(disable_version_flag = true)]
+pub struct ShredOptions {
+    /// Fill the source file(s) with random bytes over multiple iterations
+    #[clap(short, long, default_value_t = 5)]
+    pub random_iterations: u32,
+    /// Rename the file(s) while shredding multiple times
+    #[clap(short = 't', long, default_value_t = 10)]
+    pub rename_times: u32
+}
+
+// What a Cipher encrypts with. The ECB and GCM modes use the derived key, the OpenPGP mode derives its own keys from the password,
+// and the age mode uses the password or the X25519 keys
+enum Secret {
+    Key(Key),
+    OpenPgp(OpenPgp),
+    Age(Age),
+}
+
+// The key together with the Mode it is used with
+pub struct Cipher {
+    mode: Mode,
+    secret: Secret,
+}
+
+impl Cipher {
+    pub fn new(mode: Mode, key: Key) -> Self {
+        Cipher { mode, secret: Secret::Key(key) }
+    }
+
+    // The cipher of the OpenPGP mode, which derives the key from the password with the S2K. There is no salt, since the S2K picks its own
+    pub fn openpgp(password: &str) -> Self {
+        Cipher { mode: Mode::OpenPGP, secret: Secret::OpenPgp(OpenPgp::new(password)) }
+    }
+
+    // The cipher of the age mode with the password, which scrypt derives a key from for every file
+    pub fn age_password(password: &str) -> Self {
+        Cipher { mode: Mode::Age, secret: Secret::Age(Age::with_password(password)) }
+    }
+
+    // The cipher of the age mode with the X25519 recipients and identities
+    pub fn age_keys(keys: AgeKeys) -> Self {
+        Cipher { mode: Mode::Age, secret: Secret::Age(Age::with_keys(keys)) }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub(crate) fn openpgp_secret(&self) -> Option<&OpenPgp> {
+        match &self.secret {
+            Secret::OpenPgp(openpgp) => Some(openpgp),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn age_secret(&self) -> Option<&Age> {
+        match &self.secret {
+            Secret::Age(age) => Some(age),
+            _ => None,
+        }
+    }
+
+    fn ecb(key: &Key) -> Aes256Cryptor {
+        let mut key_bytes = [0u8; 32];
+        key_bytes.copy_from_slice(key.as_bytes());
+        Aes256Cryptor::new(key_bytes)
+    }
+
+    // Encrypts a buffer. GCM output is laid out the same way as an encrypted file, which is the cipher text followed by the 12 byte Nonce
+    pub fn encrypt_bytes(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.encrypt_bytes_with_aad(data, &[])
+    }
+
+    // Reverse of encrypt_bytes. Returns None if the buffer cannot be decrypted with the key
+    pub fn decrypt_bytes(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.decrypt_bytes_with_aad(data, &[])
+    }
+
+    /* Same as encrypt_bytes, with the associated data bound to the cipher text, so it only decrypts along with the same associated data.
+    ECB has no authentication, and does not use the associated data. An OpenPGP message and an age file have no associated data, so they cannot be given any
+    */
+    pub fn encrypt_bytes_with_aad(&self, data: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        match (&self.secret, self.mode) {
+            (Secret::Key(key), Mode::ECB) => Some(Cipher::ecb(key).encrypt(data)),
+            (Secret::Key(key), Mode::GCM) => {
+                let cipher = Aes256Gcm::new(key.as_key32());
+                // Generate a random 12 byte Nonce
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per message
+
+                cipher
+                    .encrypt(&nonce, Payload { msg: data, aad })
+                    .ok()
+                    .map(|encrypted_bytes| [encrypted_bytes, nonce.to_vec()].concat())
+            }
+            (Secret::OpenPgp(openpgp), _) if aad.is_empty() => openpgp.encrypt(data),
+            (Secret::Age(age), _) if aad.is_empty() => age.encrypt(data),
+            _ => None,
+        }
+    }
+
+    pub fn decrypt_bytes_with_aad(&self, data: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        match (&self.secret, self.mode) {
+            (Secret::Key(key), Mode::ECB) => Cipher::ecb(key).decrypt(data.to_vec()).ok(),
+            (Secret::Key(key), Mode::GCM) => {
+                if data.len() < 12 {
+                    return None;
+                }
+
+                // The Nonce is the last 12 bytes of the buffer
+                let (encrypted_bytes, nonce) = data.split_at(data.len() - 12);
+                let cipher = Aes256Gcm::new(key.as_key32());
 
-        pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos} /{percent}% files completed ({eta_precise})")
-                .unwrap()
-                .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
-                .progress_chars("#>-"));
-        return Some(Arc::new(Mutex::new(pb)));
+                cipher
+                    .decrypt(GenericArray::<u8, U12>::from_slice(nonce), Payload { msg: encrypted_bytes, aad })
+                    .ok()
+            }
+            (Secret::OpenPgp(openpgp), _) if aad.is_empty() => openpgp.decrypt(data).ok(),
+            (Secret::Age(age), _) if aad.is_empty() => age.decrypt(data).ok(),
+            _ => None,
+        }
+    }
+
+    // The size of the cipher text of a buffer of the given size
+    pub fn encrypted_len(&self, len: usize) -> usize {
+        match (&self.secret, self.mode) {
+            (Secret::OpenPgp(openpgp), _) => openpgp.encrypted_len(len),
+            (Secret::Age(age), _) => age.encrypted_len(len),
+            // PKCS7 always pads, a full block when the buffer is a multiple of the block size
+            (_, Mode::ECB) => (len / 16 + 1) * 16,
+            // The 16 byte tag and the 12 byte Nonce
+            _ => len + 28,
+        }
+    }
+
+    // Refuses the OpenPGP and the age modes for the formats of rufendec which are made of chunks, since their files are whole files
+    pub(crate) fn require_chunked(&self, what: &str) -> Result<(), RufendecError> {
+        match self.mode {
+            Mode::OpenPGP | Mode::Age => Err(RufendecError::Validation(format!(
+                "{} only work in the ECB and GCM modes, since gpg and age could not read them anyway",
+                what
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // Wraps the encrypted bytes in the armor. An OpenPGP message and an age file get their own armor, so gpg and age read them as well
+    pub fn armor(&self, data: &[u8]) -> String {
+        match self.mode {
+            Mode::OpenPGP => armor_openpgp(data),
+            Mode::Age => armor_age(data),
+            _ => armor(data),
+        }
     }
-    None
 }
 
 // Validates whether there are any Illegal source dir path is provided
 // Validates whether any encrypted files are provided when the operation the user choose is to Encrypt
-pub fn pre_validate_source(source_dir: &PathBuf, operation: &Operation) {
+// When resuming an interrupted run, the encrypted files it left in the source directory are expected
+pub fn pre_validate_source(
+    source_dir: &PathBuf,
+    operation: &Operation,
+    resume: bool,
+) -> Result<(), RufendecError> {
     let illegal_locations = [
         "/", "/root", "/home", "/boot", "/usr", "/lib", "/lib64", "/lib32", "/libx32", "/mnt",
         "/dev", "/sys", "/run", "/bin", "/sbin", "/proc", "/media", "/var", "/etc", "/srv", "/opt",
         "C:", "c:",
     ];
 
-    if illegal_locations.contains(&source_dir.to_str().unwrap())
+    if source_dir.to_str().is_some_and(|dir| illegal_locations.contains(&dir))
         || illegal_locations.iter().any(|x| source_dir.starts_with(x))
     {
-        log(
-            LogLevel::ERROR,
-            format!("Hey Human, Are you trying to pass a illegal source path? That's a BIG NO NO.")
-                .as_str(),
-        );
-        println!(
-            "\nHere is the list of paths your source directory path must never start with : \n{:?}",
+        // Stop if an illegal path is observed.
+        return Err(RufendecError::Validation(format!(
+            "Hey Human, Are you trying to pass a illegal source path? That's a BIG NO NO.\n\nHere is the list of paths your source directory path must never start with : \n{:?}",
             illegal_locations
-        );
+        )));
+    }
 
-        process::exit(1); // Exit if an illegal path is observed.
+    if !source_dir.is_dir() {
+        return Err(RufendecError::Validation(format!(
+            "The source directory {:?} cannot be found",
+            source_dir
+        )));
     }
 
     // Validate if the Source path has any encrypted file while the operation chosen by the user is encrypt
-    if let Operation::Encrypt = operation {
-        println!("\n\nValidating if the source directory has any encrypted files");
-
+    if let (Operation::Encrypt, false) = (operation, resume) {
         for entry in WalkDir::new(source_dir)
             .follow_links(true)
             .into_iter()
             .filter_map(|e| e.ok())
         {
-            let f_name = entry.file_name().to_string_lossy();
-
             // Check for the .enom file extension in the file names. .enom is the encrypted files extension
-            if f_name.ends_with(".enom") {
+            if entry.path().extension() == Some("enom".as_ref()) {
                 let file_path: PathBuf = entry.into_path().as_path().to_owned();
 
-                log(LogLevel::ERROR, format!("Yikes! Found an encrypted file => {:?}, and there could be several.\n\nPlease ensure you are not encrypting already encrypted files. Doing double encryption won't help", file_path).as_str());
+                return Err(RufendecError::Validation(format!("Yikes! Found an encrypted file => {:?}, and there could be several.\n\nPlease ensure you are not encrypting already encrypted files. Doing double encryption won't help", file_path)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Canonicalizes the nearest existing ancestor of the path and joins the remaining components to it, since the target directory may not exist yet
+fn absolute_path(path: &Path) -> PathBuf {
+    let mut remaining = Vec::new();
+    let mut ancestor = path;
 
-                process::exit(1); // Exit the program execution forcefully
+    loop {
+        if let Ok(canonical) = ancestor.canonicalize() {
+            return remaining.iter().rev().fold(canonical, |acc, c| acc.join(c));
+        }
+
+        match (ancestor.parent(), ancestor.file_name()) {
+            (Some(parent), Some(name)) => {
+                remaining.push(name.to_owned());
+                ancestor = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
             }
+            _ => return path.to_path_buf(),
         }
     }
 }
 
-/* Recursively walk through the path provided and list all the sub-directory names and push it to a collection
-Gathers the directory names and file names under the path
-The DIR_LIST will be used to create the target directories
-The FILE_LIST will be used know which files to Encrypt or Decrypt
-FILE_SIZE_BYTES totals each file size
+// Validates that the target directory is neither the source directory nor placed inside it.
+// Used by the sync and watch subcommands, since the encrypted files would otherwise be picked up as new source files
+pub fn pre_validate_target(source_dir: &Path, target_dir: &Path) -> Result<(), RufendecError> {
+    let source_dir = absolute_path(source_dir);
+    let target_dir = absolute_path(target_dir);
+
+    if target_dir.starts_with(&source_dir) {
+        return Err(RufendecError::Validation(
+            "The target directory must not be the source directory or be inside it".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// The directories and the files found under a source directory
+#[derive(Debug, Default, Clone)]
+pub struct SourceTree {
+    // The source directory itself comes first
+    pub dirs: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub total_bytes: u64,
+    // The files and directories the filter left out. The content of an excluded directory is not listed
+    pub excluded: Vec<PathBuf>,
+}
+
+// An encrypted file decrypted into memory, see Decryptor::decrypt_to_memory
+pub struct DecryptedFile {
+    // The name of the file before it was encrypted. Only the name, since the file may have been moved since
+    pub name: OsString,
+    pub content: DecryptedContent,
+    // The padding, the compression and the armor the file was found with, so it can be encrypted back the same way
+    pub pad: PadPolicy,
+    pub compression: Compression,
+    pub armor: bool,
+}
+
+pub enum DecryptedContent {
+    File(Zeroizing<Vec<u8>>),
+    // A symbolic link which was preserved, and its target
+    Symlink(PathBuf),
+    // Another name of a hard-linked file, and the path of its first name relative to the source directory
+    HardLink(PathBuf),
+}
+
+/* Recursively walk through the path provided and list all the sub-directory names and file names under it
+The dirs will be used to create the target directories
+The files will be used know which files to Encrypt or Decrypt
+total_bytes totals each file size
+The IGNORE_FILE_NAME files of the source tree are honoured
 */
-pub fn recurse_dirs(item: &PathBuf) {
+pub fn scan(source_dir: &Path) -> SourceTree {
+    scan_filtered(source_dir, &Filter::default())
+}
+
+// Same as scan, but also leaves out the files and directories excluded by the patterns and the predicates of the filter
+pub fn scan_filtered(source_dir: &Path, filter: &Filter) -> SourceTree {
+    let mut tree = SourceTree {
+        dirs: vec![source_dir.to_path_buf()],
+        ..Default::default()
+    };
+
+    let mut ancestors: Vec<PathBuf> = source_dir.canonicalize().into_iter().collect();
+
+    recurse_dirs(source_dir, filter, &mut Vec::new(), &mut ancestors, &mut tree);
+
+    tree
+}
+
+/* The metadata of an entry of the walk, following the symbolic link when the policy says so. Returns None for the entries which are left out:
+the symbolic links which are skipped, the dangling ones which are followed, and the devices, FIFOs and sockets (reading those could block forever)
+*/
+fn entry_metadata(entry: &fs::DirEntry, symlinks: SymlinkPolicy) -> Option<(fs::Metadata, bool)> {
+    let path = entry.path();
+    let metadata = fs::symlink_metadata(&path).ok()?;
+    let is_symlink = metadata.file_type().is_symlink();
+
+    let metadata = match (is_symlink, symlinks) {
+        (true, SymlinkPolicy::Skip) => {
+            log(LogLevel::WARN, format!("Skipping the symbolic link {:?}. Pass --symlinks follow or preserve to take it", path).as_str());
+            return None;
+        }
+        (true, SymlinkPolicy::Follow) => match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log(LogLevel::WARN, format!("Skipping the symbolic link {:?}, which cannot be followed :: {}", path, e).as_str());
+                return None;
+            }
+        },
+        // The link itself is taken, whatever it points to
+        (true, SymlinkPolicy::Preserve) => return Some((metadata, true)),
+        (false, _) => metadata,
+    };
+
+    if !metadata.is_file() && !metadata.is_dir() {
+        log(LogLevel::WARN, format!("Skipping {:?}, which is a device, a FIFO or a socket", path).as_str());
+        return None;
+    }
+
+    Some((metadata, is_symlink))
+}
+
+/* ancestors holds the canonical paths of the directories from the source directory down to item. A followed symbolic link to one of them,
+or to a directory holding the source directory (such as /), would make the walk go round in circles, so it is skipped
+*/
+fn recurse_dirs(
+    item: &Path,
+    filter: &Filter,
+    ignore_files: &mut Vec<Gitignore>,
+    ancestors: &mut Vec<PathBuf>,
+    tree: &mut SourceTree,
+) {
     if item.is_dir() {
         if let Ok(paths) = fs::read_dir(item) {
-            for path in paths {
-                let metadata = path.as_ref().unwrap().metadata();
-                let entry = path.as_ref().unwrap();
-
-                if metadata.unwrap().is_dir() {
-                    let base_path = entry.path();
-                    DIR_LIST.lock().unwrap().push(base_path);
-                    recurse_dirs(&entry.path());
-                } else {
-                    FILE_LIST.lock().unwrap().push(entry.path());
-                    if cfg!(unix) {
-                        #[cfg(target_os = "linux")]
-                        {
-                            *FILES_SIZE_BYTES.lock().unwrap() +=
-                                entry.path().metadata().unwrap().size();
-                        }
-                    } else if cfg!(windows) {
-                        #[cfg(target_os = "windows")]
-                        {
-                            *FILES_SIZE_BYTES.lock().unwrap() +=
-                                entry.path().metadata().unwrap().file_size();
-                        }
+            let ignore_file = Filter::ignore_file(item);
+            let has_ignore_file = ignore_file.is_some();
+
+            ignore_files.extend(ignore_file);
+
+            for entry in paths.filter_map(|e| e.ok()) {
+                // The sync state database, the progress journal, the ignore files and the temporary files left over by an interrupted write
+                // are not files the user wants to Encrypt or Decrypt
+                if entry.file_name() == SYNC_DB_FILE_NAME
+                    || entry.file_name() == JOURNAL_FILE_NAME
+                    || entry.file_name() == IGNORE_FILE_NAME
+                    || entry.file_name().to_string_lossy().ends_with(TEMP_FILE_SUFFIX)
+                {
+                    continue;
+                }
+
+                let Some((metadata, is_symlink)) = entry_metadata(&entry, filter.symlinks) else {
+                    tree.excluded.push(entry.path());
+                    continue;
+                };
+
+                if filter.is_excluded(&entry.path(), metadata.is_dir(), ignore_files)
+                    || filter.is_deselected(is_symlink, &metadata)
+                {
+                    tree.excluded.push(entry.path());
+                    continue;
+                }
+
+                if metadata.is_dir() {
+                    let canonical = entry.path().canonicalize().unwrap_or_else(|_| entry.path());
+
+                    let holds_source = ancestors.first().is_some_and(|source| source.starts_with(&canonical));
+
+                    if is_symlink && (holds_source || ancestors.contains(&canonical)) {
+                        log(LogLevel::WARN, format!("Skipping the symbolic link {:?}, which loops back to {:?}", entry.path(), canonical).as_str());
+                        tree.excluded.push(entry.path());
+                        continue;
                     }
+
+                    tree.dirs.push(entry.path());
+
+                    ancestors.push(canonical);
+                    recurse_dirs(&entry.path(), filter, ignore_files, ancestors, tree);
+                    ancestors.pop();
+                } else {
+                    tree.files.push(entry.path());
+                    tree.total_bytes += metadata.len();
                 }
             } // end of for loop
+
+            if has_ignore_file {
+                ignore_files.pop();
+            }
         }
     }
 }
 
-// Creates the target directory and sub-directories by operating on the Paths.
-pub fn create_dirs(paths: Vec<PathBuf>, source_dir_name: &str, target_dir_name: &str) {
-    for parent in paths {
-        let destination = parent
-            .to_owned()
-            .as_mut_os_str()
-            .to_str()
-            .unwrap()
-            .replace(source_dir_name, target_dir_name);
+// The file a thread works on, when it was picked up, and its size. link is the kind of the link record read in place of the content.
+// skipped is set when the run was cancelled before the file was written
+pub(crate) struct FileContext<'a> {
+    path: &'a Path,
+    started: Instant,
+    bytes: u64,
+    link: Option<LinkKind>,
+    skipped: Cell<bool>,
+}
+
+impl Job {
+    /* Maps a path under the source directory to the same path under the target directory. Only the leading source directory components
+    are swapped, so a name which happens to contain the source directory (or any other text) is left as it is
+    */
+    fn target_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.source_dir) {
+            Ok(relative) if relative.as_os_str().is_empty() => self.target_dir.clone(),
+            Ok(relative) => self.target_dir.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    // Creates the target directory and sub-directories by operating on the Paths.
+    pub(crate) fn create_dirs(&self, paths: &[PathBuf]) {
+        for parent in paths {
+            let destination = self.target_path(parent);
 
-        logger!("Directory created => {:?}", destination);
+            logger!(self, "Directory created => {:?}", destination);
 
-        let _ = fs::create_dir_all(destination);
+            let _ = fs::create_dir_all(destination);
+        }
     }
-}
 
-struct PbGroup {
-    inner: Arc<Mutex<ProgressBar>>,
-    bool: bool,
-    increment: Arc<Mutex<u64>>,
-}
+    /* Reads the files in parallel based on the thread count, and hands each one to the closure for the encrypt or decrypt logic.
+    The observers hear about every file when it is picked up, and about its bytes once the closure is done with them, whatever the outcome.
+    Once the job is cancelled, no more files are picked up, and the files being read are dropped.
+    */
+    pub(crate) fn cipher_init<F>(&self, file_list: &[PathBuf], operation: &Operation, f: F)
+    where
+        F: Fn(Vec<u8>, &FileContext) + Sync,
+    {
+        // Construct a ThreadPool using Rayon
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .unwrap();
+
+        // For each element in file_list create a new thread and run it inside the thread scope
+        // Rayon only allows certain number of threads to be created and executed in parallel based on the thread count specified.
+        pool.install(|| {
+            rayon::scope(|s| {
+                for file in file_list {
+                    let f = &f;
+
+                    // Spawn the threads here
+                    s.spawn(move |_| {
+                        // The files which were not picked up before the run was cancelled are skipped
+                        if self.cancel.is_cancelled() {
+                            self.skip();
+                            return;
+                        }
+
+                        let started = Instant::now();
+
+                        let file_data = match operation {
+                            Operation::Encrypt => self.read_source(file),
+                            Operation::Decrypt => read_cancellable(file, || self.cancel.is_cancelled()).map(|file_data| (file_data, None)),
+                        };
+
+                        match file_data {
+                            Ok((file_data, link)) => {
+                                let context = FileContext {
+                                    path: file,
+                                    started,
+                                    bytes: file_data.len() as u64,
+                                    link,
+                                    skipped: Cell::new(false),
+                                };
+
+                                self.notify(|o| o.file_started(file, context.bytes));
+
+                                f(file_data, &context); // Closure call
+
+                                if !context.skipped.get() {
+                                    self.notify(|o| o.bytes_processed(file, context.bytes));
+                                }
+                            }
+                            Err(_) if self.cancel.is_cancelled() => self.skip(),
+                            Err(e) => self.record(FileResult::failure(
+                                file,
+                                Stage::Read,
+                                e,
+                                None,
+                                Some(started.elapsed().as_secs_f64() * 1000.0),
+                            )),
+                        }
+                    });
+                }
+            });
+        });
+    }
 
-// Construct a ProgressBar. ProgressBar is only available when verbose printing is not chosen. Hence it can come as None.
-// When PB is not constructed, mark the pb_bool as false. PB increment counter starting value is 1
-// This function expects a Closure logic for encrypt and decrypt operations
-fn cipher_init<F>(file_list: &Vec<PathBuf>, thread_count: usize, f: F)
-where
-    F: Fn(Vec<u8>, PbGroup, Arc<RwLock<&PathBuf>>) + std::marker::Send + Copy + std::marker::Sync,
-{
-    let (pb, pb_bool, pb_increment): (Arc<Mutex<ProgressBar>>, bool, Arc<Mutex<u64>>) =
-        match progress_bar(file_list.to_owned().capacity() as u64) {
-            Some(pb) => (pb, true, Arc::new(Mutex::new(1))),
-            None => (
-                Arc::new(Mutex::new(ProgressBar::new(0))),
-                false,
-                Arc::new(Mutex::new(1)),
-            ), // I hate this line. Unavoidable with my current knowledge.
+    /* The content to encrypt, along with the kind of the link record read instead of it. A preserved symbolic link and a second name of
+    a hard-linked file are encrypted as link records. The kind of a link record is kept in the sealed header, which only the GCM mode has,
+    so in the other modes every name of a hard-linked file is encrypted with its content
+    */
+    fn read_source(&self, file: &Path) -> std::io::Result<(Vec<u8>, Option<LinkKind>)> {
+        let metadata = fs::symlink_metadata(file)?;
+
+        let link = if metadata.file_type().is_symlink() && self.symlinks == SymlinkPolicy::Preserve {
+            Some(LinkRecord::Symlink(fs::read_link(file)?))
+        } else if self.cipher.mode() == Mode::GCM {
+            self.hard_links.first_name(file, &metadata).map(|first_name| {
+                let first_name = first_name.strip_prefix(&self.source_dir).unwrap_or(&first_name).to_path_buf();
+
+                logger!(self, "Hard link :: {:?} => {:?}", file, first_name);
+
+                LinkRecord::HardLink(first_name)
+            })
+        } else {
+            None
         };
-    // Construct a ThreadPool using Rayon
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(thread_count)
-        .build()
-        .unwrap();
-
-    // For each element in file_list create a new thread and run it inside the thread scope
-    // Rayon only allows certain number of threads to be created and executed in parallel based on the thread_count specified.
-    pool.install(|| {
-        rayon::scope(|s| {
-            for file in file_list {
-                let pb = PbGroup {
-                    inner: pb.clone(),
-                    increment: pb_increment.clone(),
-                    bool: pb_bool,
+
+        match link {
+            Some(link) => Ok((link.to_bytes(), Some(link.kind()))),
+            None => read_cancellable(file, || self.cancel.is_cancelled()).map(|file_data| (file_data, None)),
+        }
+    }
+
+    /* Encrypts a file and places it in the target directory by replacing the source directory with the target directory.
+    Deletes or shreds the source file if the job asks for it.
+    */
+    pub(crate) fn encrypt_file(&self, file_data: Vec<u8>, file: &FileContext) {
+        let (true_file_path, file_data) = if self.anon {
+            let (true_file_path, encoded_true_file_name) = self.encode_file_name_to_base64(file.path);
+
+            (Some(true_file_path), with_encoded_name(&file_data, &encoded_true_file_name))
+        } else {
+            (None, file_data)
+        };
+
+        let compression = if self.skip_compressed && self.compression != Compression::None && is_compressed(&file_data) {
+            logger!(self, "Not compressing the already compressed file :: {:?}", file.path);
+            Compression::None
+        } else {
+            self.compression
+        };
+
+        let (file_data, aad) = seal(file_data, SealedHeader { pad: self.pad, compression, link: file.link });
+
+        let Some(encrypted_bytes) = self.cipher.encrypt_bytes_with_aad(&file_data, aad) else {
+            // Note the failure since the encryption failed.
+            self.report_failure(file, Stage::Encrypt, "The file could not be encrypted");
+            return;
+        };
+
+        let encrypted_bytes = if self.armor {
+            self.cipher.armor(&encrypted_bytes).into_bytes()
+        } else {
+            encrypted_bytes
+        };
+
+        let new_file_name = match true_file_path {
+            Some(true_file_path) => generate_random_file_name(&true_file_path),
+            None => with_enom(&self.target_path(file.path)),
+        };
+
+        logger!(self, "Encrypted file :: {:?}", new_file_name);
+
+        // The source file is only removed once the written file decrypts back to the same bytes
+        if self.dry_run
+            || self.write_and_remove_source(file, &new_file_name, &encrypted_bytes, |written| {
+                let written = if self.armor {
+                    Cow::Owned(dearmor(written).unwrap_or_default())
+                } else {
+                    Cow::Borrowed(written)
                 };
 
-                let file = Arc::new(RwLock::new(file));
+                self.cipher.decrypt_bytes_with_aad(&written, aad).as_deref() == Some(file_data.as_slice())
+            })
+        {
+            self.report_success(file, &new_file_name);
+        }
+    }
 
-                // Spawn the threads here
-                s.spawn(move |_| {
-                    if let Ok(file_data) = fs::read(*file.clone().read().unwrap()) {
-                        f(file_data, pb, file); // Closure call
-                    }
+    /* Decrypts the content of an encrypted file, and strips the armor, the padding and the compression. The anonymized file name is left in.
+    Returns the sealed header which was found along with the content
+    */
+    pub(crate) fn decrypt_content(&self, file: &Path, file_data: &[u8]) -> Result<(Vec<u8>, SealedHeader), &'static str> {
+        let dearmored;
+
+        let file_data = if is_armored(file_data) {
+            dearmored = dearmor(file_data)?;
+            logger!(self, "Stripped the armor of :: {:?}", file);
+            dearmored.as_slice()
+        } else {
+            file_data
+        };
+
+        // An OpenPGP message may come from gpg, so it holds the content alone, and its own error tells what is wrong with it
+        if let Some(openpgp) = self.cipher.openpgp_secret() {
+            return Ok((openpgp.decrypt(file_data)?, SealedHeader::default()));
+        }
+
+        // So may an age file
+        if let Some(age) = self.cipher.age_secret() {
+            return Ok((age.decrypt(file_data)?, SealedHeader::default()));
+        }
+
+        // A file shorter than the Nonce cannot be one of ours
+        if let (Mode::GCM, true) = (self.cipher.mode(), file_data.len() < 12) {
+            return Err("The file is too short to be an encrypted file");
+        }
+
+        // Content which is neither padded nor compressed has no associated data. Only the content which decrypts along with SEALED_AAD
+        // has the header which tells how to unseal it
+        if let Some(decrypted_bytes) = self.cipher.decrypt_bytes(file_data) {
+            return Ok((decrypted_bytes, SealedHeader::default()));
+        }
+
+        let Some(mut decrypted_bytes) = self.cipher.decrypt_bytes_with_aad(file_data, SEALED_AAD) else {
+            return Err(match self.cipher.mode() {
+                Mode::ECB => "The file could not be decrypted",
+                _ => "The file could not be authenticated",
+            });
+        };
+
+        let header = unseal(&mut decrypted_bytes)?;
+
+        if header.pad != PadPolicy::None {
+            logger!(self, "Stripped the padding of :: {:?}", file);
+        }
+
+        if header.compression != Compression::None {
+            logger!(self, "Decompressed :: {:?}", file);
+        }
+
+        Ok((decrypted_bytes, header))
+    }
+
+    /* Decrypts an encrypted file into memory, along with the name it was encrypted from. Nothing is written.
+    The content is zeroized as soon as it is dropped
+    */
+    pub(crate) fn decrypt_to_memory(&self, file: &Path) -> Result<DecryptedFile, RufendecError> {
+        let failed = |cause: &str| RufendecError::Runtime(format!("{:?} :: {}", file, cause));
+
+        let file_data = fs::read(file).map_err(|e| failed(&e.to_string()))?;
+        let (decrypted_bytes, header) = self.decrypt_content(file, &file_data).map_err(failed)?;
+        let mut decrypted_bytes = Zeroizing::new(decrypted_bytes);
+
+        let name = if self.anon {
+            let (_, decoded_true_file_name) = decode_file_name_from_base64(file, &mut decrypted_bytes)
+                .ok_or_else(|| failed("The anonymized file name could not be recovered"))?;
+
+            decoded_true_file_name.file_name().map(|name| name.to_os_string())
+        } else {
+            without_enom(file).file_name().map(|name| name.to_os_string())
+        }
+        .ok_or_else(|| failed("The file has no name"))?;
+
+        let content = match link_record(header.link, &decrypted_bytes).map_err(failed)? {
+            Some(LinkRecord::Symlink(link_target)) => DecryptedContent::Symlink(link_target),
+            Some(LinkRecord::HardLink(first_name)) => DecryptedContent::HardLink(first_name),
+            None => DecryptedContent::File(decrypted_bytes),
+        };
+
+        Ok(DecryptedFile { name, content, pad: header.pad, compression: header.compression, armor: is_armored(&file_data) })
+    }
+
+    /* Encrypts a file decrypted by decrypt_to_memory back the way it was encrypted: with the anonymized name if the job is anonymized,
+    and with the padding, the compression and the armor it was found with. Only a regular file can be encrypted back. Nothing is written
+    */
+    pub(crate) fn encrypt_to_memory(&self, file: &DecryptedFile) -> Result<Vec<u8>, RufendecError> {
+        let DecryptedContent::File(content) = &file.content else {
+            return Err(RufendecError::Validation(format!("{:?} is a link, which cannot be encrypted back", file.name)));
+        };
+
+        let file_data = if self.anon {
+            let encoded_true_file_name = BASE64_STANDARD.encode(path_to_bytes(Path::new(&file.name)));
+            Zeroizing::new(with_encoded_name(content, &encoded_true_file_name))
+        } else {
+            content.clone()
+        };
+
+        let (file_data, aad) = seal(file_data.to_vec(), SealedHeader { pad: file.pad, compression: file.compression, link: None });
+        let file_data = Zeroizing::new(file_data);
+
+        let encrypted_bytes = self
+            .cipher
+            .encrypt_bytes_with_aad(&file_data, aad)
+            .ok_or_else(|| RufendecError::Runtime(format!("{:?} :: The file could not be encrypted", file.name)))?;
+
+        if file.armor {
+            Ok(self.cipher.armor(&encrypted_bytes).into_bytes())
+        } else {
+            Ok(encrypted_bytes)
+        }
+    }
+
+    /* Decrypts a file and places it in the target directory by replacing the source directory with the target directory.
+    Deletes or shreds the source file if the job asks for it.
+    */
+    pub(crate) fn decrypt_file(&self, file_data: Vec<u8>, file: &FileContext) {
+        let (mut decrypted_bytes, header) = match self.decrypt_content(file.path, &file_data) {
+            Ok(decrypted) => decrypted,
+            Err(cause) => {
+                self.report_failure(file, Stage::Decrypt, cause);
+                return;
+            }
+        };
+
+        let new_file_name = if self.anon {
+            let Some((old_file_name, decoded_true_file_name)) =
+                decode_file_name_from_base64(file.path, &mut decrypted_bytes)
+            else {
+                self.report_failure(file, Stage::Decrypt, "The anonymized file name could not be recovered");
+                return;
+            };
+
+            // Only the name is taken from the encoded path. The file is placed next to where the anonymized file maps to,
+            // so the directories may have been moved or renamed since it was encrypted
+            let Some(true_file_name) = decoded_true_file_name.file_name() else {
+                self.report_failure(file, Stage::Decrypt, "The anonymized file name could not be recovered");
+                return;
+            };
+
+            let decoded_true_file_name = self.target_path(file.path).with_file_name(true_file_name);
+
+            if let (false, Some(parent)) = (self.dry_run, decoded_true_file_name.parent()) {
+                let _ = fs::create_dir_all(parent);
+            }
+
+            logger!(
+                self,
+                "Decrypted file {:?} as :: {:?}",
+                old_file_name,
+                decoded_true_file_name
+            );
+
+            decoded_true_file_name
+        } else {
+            let new_file_name = without_enom(&self.target_path(file.path));
+
+            logger!(self, "Decrypted file :: {:?}", new_file_name);
+
+            new_file_name
+        };
+
+        let link_record = match link_record(header.link, &decrypted_bytes) {
+            Ok(link_record) => link_record,
+            Err(cause) => {
+                self.report_failure(file, Stage::Decrypt, cause);
+                return;
+            }
+        };
+
+        match link_record {
+            Some(LinkRecord::Symlink(link_target)) => {
+                self.restore_symlink(file, &new_file_name, &link_target);
+                return;
+            }
+            // The file the hard link points to may not be written yet
+            Some(LinkRecord::HardLink(first_name)) => {
+                self.pending_hard_links.lock().unwrap().push(PendingHardLink {
+                    source: file.path.to_path_buf(),
+                    link: new_file_name,
+                    target: self.target_dir.join(first_name),
+                    bytes: file.bytes,
                 });
+                return;
             }
-        });
-    });
-}
+            None => {}
+        }
 
-/* Encrypts the files in the file_list in parallel based on the thread_count and Mode. Places the files the target directory
-by replacing the source_dir_name with the target_dir_name.
-Delete the source directory if the delete_src is true.
-*/
-pub fn encrypt_files(
-    file_list: Vec<PathBuf>,
-    thread_count: usize,
-    source_dir_name: &str,
-    target_dir_name: &str,
-    mode: Mode,
-    delete_src: bool,
-    shred_options: &Option<Shred>,
-    anon: bool,
-    dry_run: bool
+        // The source file is only removed once the written file reads back the same bytes
+        if self.dry_run
+            || self.write_and_remove_source(file, &new_file_name, &decrypted_bytes, |written| {
+                written == decrypted_bytes.as_slice()
+            })
+        {
+            self.report_success(file, &new_file_name);
+        }
+    }
 
-) {
-    cipher_init(
-        &file_list,
-        thread_count,
-        |file_data: Vec<u8>, pb: PbGroup, file: Arc<RwLock<&PathBuf>>| {
-            match mode {
-                Mode::ECB => {
-                    //Create Aes256Cryptor Object
-                    let encrypt_obj = Aes256Cryptor::new({
-                        let mut key = [0u8; 32];
-                        key.copy_from_slice(ECB_32BYTE_KEY.read().unwrap()[0].as_slice());
-                        key
-                    });
+    // Creates the symbolic link in place of the target file, and then deletes or shreds the source file if the job asks for it
+    fn restore_symlink(&self, file: &FileContext, new_file_name: &Path, link_target: &Path) {
+        logger!(self, "Symbolic link :: {:?} => {:?}", new_file_name, link_target);
 
-                    let (true_file_path, file_data) = if anon {
-                        let (true_file_path, encoded_true_file_name, encoded_true_file_name_length) =
-                            encode_file_name_to_base64(
-                                file.clone(),
-                                &source_dir_name,
-                                target_dir_name,
-                            );
-
-                        let file_data = [
-                            file_data.as_ref(),
-                            encoded_true_file_name.as_bytes(),
-                            &encoded_true_file_name_length.to_ne_bytes(),
-                        ]
-                        .concat();
-
-                        (Some(true_file_path), Some(file_data))
-                    } else {
-                        (None, Some(file_data))
-                    };
-
-                    // Call the encrypt method on the Aes256Cryptor Object
-                    let encrypted_bytes = encrypt_obj.encrypt(file_data.unwrap()); // vec<u8>
-
-                    let new_file_name = if anon {
-                        generate_random_file_name(true_file_path.unwrap())
-                    } else {
-                        file.clone()
-                            .read()
-                            .unwrap()
-                            .as_os_str()
-                            .to_str()
-                            .expect("Found a bad file")
-                            .replace(source_dir_name, target_dir_name)
-                            .to_string()
-                            + ".enom"
-                    };
-
-                    logger!("Encrypted file :: {}", new_file_name);
-
-                    if !dry_run {
-                        // Write the encrypted bytes to new_file_name
-                        let _ = fs::write(new_file_name, encrypted_bytes);
-
-                        match &shred_options {
-                            Some(o) => match o {
-                                Shred::Shred(so) => {
-                                    if let Err(e) = shred(&ShredConfig::non_interactive(
-                                        vec![&*file.clone().read().unwrap()],
-                                        Verbosity::Quiet,
-                                        false,
-                                        so.random_iterations,
-                                        so.rename_times,
-                                    )) {
-                                        logger!("Failed to shred the file :: {}", e);
-                                    }
-                                }
-                            },
-                            None => {
-                                // Delete the source file if delete_src is true. Note: This is not a safe delete. The file count still exist and it is possible to retrieve
-                                if delete_src {
-                                    if let Err(e) = fs::remove_file(*file.clone().read().unwrap()) {
-                                        logger!("Failed to delete the file :: {}", e);
-                                    }
-                                }
-                            }
-                        };
-                    }
+        if self.dry_run {
+            self.report_success(file, new_file_name);
+            return;
+        }
 
-                    // Increment the ProgressBar if pb_bool is true. Happens when verbose printing is not chosen
-                    if pb.bool {
-                        pb.inner
-                            .lock()
-                            .unwrap()
-                            .set_position(*pb.increment.lock().unwrap());
-                        *pb.increment.lock().unwrap() += 1;
-                    }
+        if let Err(e) = create_symlink(link_target, new_file_name) {
+            self.report_failure(file, Stage::Write, format!("Failed to create the symbolic link {:?} :: {}. The source file is kept", new_file_name, e));
+            return;
+        }
+
+        if self.remove_source(file.path) {
+            self.report_success(file, new_file_name);
+        }
+    }
+
+    // Creates the hard links noted while decrypting, once the files they point to are written
+    pub(crate) fn restore_hard_links(&self) {
+        let pending = std::mem::take(&mut *self.pending_hard_links.lock().unwrap());
+
+        for hard_link in pending {
+            if self.cancel.is_cancelled() {
+                self.skip();
+                continue;
+            }
+
+            let started = Instant::now();
+
+            logger!(self, "Hard link :: {:?} => {:?}", hard_link.link, hard_link.target);
+
+            if !self.dry_run {
+                if let Err(e) = create_hard_link(&hard_link.target, &hard_link.link) {
+                    self.record(FileResult::failure(
+                        &hard_link.source,
+                        Stage::Write,
+                        format!("Failed to link {:?} to {:?} :: {}. The source file is kept", hard_link.link, hard_link.target, e),
+                        Some(hard_link.bytes),
+                        Some(started.elapsed().as_secs_f64() * 1000.0),
+                    ));
+                    continue;
                 }
 
-                Mode::GCM => {
-                    // Extract the 32 byte key from the Vec and construct a Aes256Gcm object
-                    let cipher: aes_gcm::AesGcm<aes_gcm::aes::Aes256, _, _> =
-                        Aes256Gcm::new(&GCM_32BYTE_KEY.read().unwrap().as_slice()[0]);
-
-                    // Generate a random 12 byte Nonce
-                    let nonce = Aes256Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per message
-
-                    let (true_file_path, file_data) = if anon {
-                        let (true_file_path, encoded_true_file_name, encoded_true_file_name_length) =
-                            encode_file_name_to_base64(
-                                file.clone(),
-                                &source_dir_name,
-                                target_dir_name,
-                            );
-
-                        let file_data = [
-                            file_data.as_ref(),
-                            encoded_true_file_name.as_bytes(),
-                            &encoded_true_file_name_length.to_ne_bytes(),
-                        ]
-                        .concat();
-
-                        (Some(true_file_path), Some(file_data))
-                    } else {
-                        (None, Some(file_data))
-                    };
-
-                    // Call the encrypt method on the Aes256Gcm object and see if was successful
-                    match cipher.encrypt(&nonce, file_data.unwrap().as_ref()) {
-                        Ok(encrypted_bytes) => {
-                            let new_file_name = if anon {
-                                generate_random_file_name(true_file_path.unwrap())
-                            } else {
-                                file.clone()
-                                    .read()
-                                    .unwrap()
-                                    .as_os_str()
-                                    .to_str()
-                                    .expect("Found a bad file")
-                                    .replace(source_dir_name, target_dir_name)
-                                    .to_string()
-                                    + ".enom"
-                            };
-
-                            logger!("Encrypted file :: {}", new_file_name);
-
-                            if !dry_run {
-                                // Concat the encrypted_bytes and Nonce and Write it to new_file_name
-                                let _ = fs::write(
-                                    new_file_name,
-                                    [encrypted_bytes, nonce.to_vec()].concat(),
-                                );
-                            }
-                            
-                            *SUCCESS_COUNT.lock().unwrap() += 1;
-
-                            if !dry_run {
-                                match &shred_options {
-                                    Some(o) => match o {
-                                        Shred::Shred(so) => {
-                                            if let Err(e) = shred(&ShredConfig::non_interactive(
-                                                vec![&*file.clone().read().unwrap()],
-                                                Verbosity::Quiet,
-                                                false,
-                                                so.random_iterations,
-                                                so.rename_times,
-                                            )) {
-                                                logger!("Failed to shred the file :: {}", e);
-                                            }
-                                        }
-                                    },
-                                    None => {
-                                        // Delete the source file if delete_src is true.
-                                        if delete_src {
-                                            if let Err(e) =
-                                                fs::remove_file(*file.clone().read().unwrap())
-                                            {
-                                                logger!("Failed to delete the file :: {}", e);
-                                            }
-                                        }
-                                    }
-                                };
-                            }
-                            
-                            // Increment the ProgressBar
-                            if pb.bool {
-                                pb.inner
-                                    .lock()
-                                    .unwrap()
-                                    .set_position(*pb.increment.lock().unwrap());
-                                *pb.increment.lock().unwrap() += 1;
-                            }
-                        }
-                        Err(_) => {
-                            // Increment the failed count by 1 since the encryption failed.
-                            *FAILED_COUNT.lock().unwrap() += 1;
-                        }
-                    } // vec<u8>
+                if !self.remove_source(&hard_link.source) {
+                    continue;
                 }
-            };
-        },
-    );
+            }
+
+            self.record(FileResult::success(
+                &hard_link.source,
+                &hard_link.link,
+                Some(hard_link.bytes),
+                Some(started.elapsed().as_secs_f64() * 1000.0),
+            ));
+        }
+    }
+
+    fn report_success(&self, file: &FileContext, new_file_name: &Path) {
+        self.record(FileResult::success(
+            file.path,
+            new_file_name,
+            Some(file.bytes),
+            Some(file.started.elapsed().as_secs_f64() * 1000.0),
+        ));
+    }
+
+    fn report_failure(&self, file: &FileContext, stage: Stage, cause: impl std::fmt::Display) {
+        self.record(FileResult::failure(
+            file.path,
+            stage,
+            cause,
+            Some(file.bytes),
+            Some(file.started.elapsed().as_secs_f64() * 1000.0),
+        ));
+    }
+
+    /* Deletes or shreds the source file once its target file is written. Shred takes precedence over delete_src.
+    A symbolic link or a file with other hard links is only deleted, as shredding it would wipe out the content it shares.
+    Returns false if the source file could not be removed, after noting the failure in the run report
+    */
+    pub(crate) fn remove_source(&self, file: &Path) -> bool {
+        let result = match &self.shred {
+            Some(_) if shares_content(file) => {
+                fs::remove_file(file).map_err(|e| format!("Failed to delete the link :: {}", e))
+            }
+            Some(so) => shred_file(file, so),
+            // Delete the source file if delete_src is true. Note: This is not a safe delete. The file count still exist and it is possible to retrieve
+            None if self.delete_src => {
+                fs::remove_file(file).map_err(|e| format!("Failed to delete the file :: {}", e))
+            }
+            None => Ok(()),
+        };
+
+        if let Err(e) = result {
+            self.record(FileResult::failure(file, Stage::Delete, e, None, None));
+            return false;
+        }
+
+        true
+    }
+
+    /* Writes the target file, reads it back and checks it with the verify closure, and only then deletes or shreds the source file.
+    The verification is skipped when the source file is kept anyway. Returns false when anything failed, after noting the failure in the run report.
+    */
+    fn write_and_remove_source<F>(
+        &self,
+        file: &FileContext,
+        new_file_name: &Path,
+        bytes: &[u8],
+        verify: F,
+    ) -> bool
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        if let Err(e) = write_atomic_cancellable(new_file_name, bytes, || self.cancel.is_cancelled()) {
+            // The temporary file is already gone, and the source file is kept
+            if self.cancel.is_cancelled() {
+                file.skipped.set(true);
+                self.skip();
+                return false;
+            }
+
+            self.report_failure(file, Stage::Write, format!("Failed to write the file {:?} :: {}. The source file is kept", new_file_name, e));
+            return false;
+        }
+
+        if self.delete_src || self.shred.is_some() {
+            match fs::read(new_file_name) {
+                Ok(written) if verify(&written) => {}
+                Ok(_) => {
+                    self.report_failure(file, Stage::Verify, format!("The written file {:?} does not match. The source file is kept", new_file_name));
+                    return false;
+                }
+                Err(e) => {
+                    self.report_failure(file, Stage::Verify, format!("Failed to read back the written file {:?} :: {}. The source file is kept", new_file_name, e));
+                    return false;
+                }
+            }
+        }
+
+        // Note the target file in the progress journal when one is open
+        if let Some(journal) = self.journal.lock().unwrap().as_mut() {
+            journal.append(&self.cipher, file.path, new_file_name, bytes);
+        }
+
+        self.remove_source(file.path)
+    }
+
+    // The raw bytes of the path are encoded, so a name which is not valid UTF-8 comes back the same
+    fn encode_file_name_to_base64(&self, file: &Path) -> (PathBuf, String) {
+        let true_file_name = self.target_path(file);
+
+        let true_file_path = self.target_path(file.parent().unwrap_or(Path::new("")));
+
+        let encoded_true_file_name = BASE64_STANDARD.encode(path_to_bytes(&true_file_name));
+
+        (true_file_path, encoded_true_file_name)
+    }
+}
+
+// Appends the anonymized file name and its length to the content
+fn with_encoded_name(file_data: &[u8], encoded_true_file_name: &str) -> Vec<u8> {
+    [
+        file_data,
+        encoded_true_file_name.as_bytes(),
+        &encoded_true_file_name.len().to_ne_bytes(),
+    ]
+    .concat()
 }
 
-/* Decrypts the files in the file_list in parallel based on the thread_count and Mode. Places the files the target directory
-by replacing the source_dir_name with the target_dir_name.
-Delete the source directory if the delete_src is true.
+/* How is a padded or compressed file sealed?
+-----------------------
+Content which is padded or compressed, and every link record, starts with a 2 byte header: the flags, which tell the padding policy,
+whether a codec was chosen, whether the content was compressed with it and the kind of the link record, followed by the zstd level. It is encrypted with SEALED_AAD as the associated data, so
+a file which does not decrypt without it is known to be sealed, and GCM authenticates the header along with the content.
+The trailers of the padding and the compression are only read when the flags say they are there. Nothing else is ever looked into for them.
+Sealing needs the authentication, so padding, compression and link records are only done in the GCM mode.
 */
-pub fn decrypt_files(
-    file_list: Vec<PathBuf>,
-    thread_count: usize,
-    source_dir_name: &str,
-    target_dir_name: &str,
-    mode: Mode,
-    delete_src: bool,
-    shred_options: &Option<Shred>,
-    anon: bool,
-    dry_run: bool
-) {
-    cipher_init(
-        &file_list,
-        thread_count,
-        |file_data: Vec<u8>, pb: PbGroup, file: Arc<RwLock<&PathBuf>>| {
-            if let Mode::GCM = mode {
-                let mut file_data: Vec<u8> = file_data;
+const SEALED_AAD: &[u8] = b"RUFENDEC-SEALED-1";
+
+const SEALED_PADME: u8 = 0x01;
+const SEALED_POW2: u8 = 0x02;
+const SEALED_ZSTD: u8 = 0x04;
+const SEALED_COMPRESSED: u8 = 0x08;
+const SEALED_COMPRESSED_ZSTD: u8 = SEALED_ZSTD | SEALED_COMPRESSED;
+const SEALED_SYMLINK: u8 = 0x10;
+const SEALED_HARD_LINK: u8 = 0x20;
+
+const SEALED_HEADER_LEN: usize = 2;
+
+// What the sealed header records about the content
+#[derive(Default)]
+pub(crate) struct SealedHeader {
+    pub(crate) pad: PadPolicy,
+    pub(crate) compression: Compression,
+    pub(crate) link: Option<LinkKind>,
+}
 
-                //let nonce = file_data.clone().into_iter().rev().take(12).rev().collect::<Vec<u8>>();
-                // Onc we have file_data to be decrypted we need to extract the Nonce which we used to Encrypt.
-                // The None is part of the file. It is the last 12 bytes in the encrypted file. We need to know where to Split
-                // saturating_sub helps to find the position at which the split needs to happen which varies based on the file_data length.
-                let final_length = file_data.len().saturating_sub(12);
+/* Compresses and pads the content before it is encrypted, and returns it along with the associated data to encrypt it with. The padding
+also covers the anonymized file name, whose length would otherwise show. Content which is neither padded nor compressed nor a link record
+is left as it is
+*/
+fn seal(file_data: Vec<u8>, header: SealedHeader) -> (Vec<u8>, &'static [u8]) {
+    let SealedHeader { pad: pad_policy, compression, link } = header;
 
-                // Splits at the final_length. This length is the end of the actual file content and the start of the Nonce. It then returns the Nonce in a new Vec<u8>
-                let nonce = file_data.split_off(final_length);
+    if compression == Compression::None && pad_policy == PadPolicy::None && link.is_none() {
+        return (file_data, &[]);
+    }
 
-                let cipher: aes_gcm::AesGcm<aes_gcm::aes::Aes256, _, _> =
-                    Aes256Gcm::new(&GCM_32BYTE_KEY.read().unwrap().as_slice()[0]);
-                //let nonce: GenericArray<u8, UInt<UInt<UInt<UInt<UTerm, B1>, B1>, B0>, B0>> = Aes256Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per message
+    let mut flags = match pad_policy {
+        PadPolicy::None => 0,
+        PadPolicy::Padme => SEALED_PADME,
+        PadPolicy::Pow2 => SEALED_POW2,
+    };
 
-                // We need the Nonce to be of type GenericArray to be used by the decrypt function
-                let nonce = GenericArray::<u8, U12>::from_slice(nonce.as_ref());
+    flags |= match link {
+        None => 0,
+        Some(LinkKind::Symlink) => SEALED_SYMLINK,
+        Some(LinkKind::HardLink) => SEALED_HARD_LINK,
+    };
 
-                // File is decrypted here
-                match cipher.decrypt(nonce, file_data.as_ref()) {
-                    Ok(mut res) => {
-                        let new_file_name = if anon {
-                            let (old_file_name, decoded_true_file_name) =
-                                decode_file_name_from_base64(file.clone(), &mut res);
+    let (level, compressed) = match compression {
+        Compression::Zstd(level) => {
+            flags |= SEALED_ZSTD;
+            (level, compress(&file_data, level))
+        }
+        Compression::None => (0, None),
+    };
 
-                            let decoded_true_file_name =
-                                decoded_true_file_name.replace(source_dir_name, target_dir_name);
+    if compressed.is_some() {
+        flags |= SEALED_COMPRESSED;
+    }
 
-                            let decoded_file_name_path = PathBuf::from(&decoded_true_file_name);
+    let content = compressed.as_deref().unwrap_or(&file_data);
 
-                            if !dry_run {
-                                let _ = std::fs::create_dir_all(decoded_file_name_path.parent().unwrap());
+    let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + content.len());
+    sealed.extend_from_slice(&[flags, level as u8]);
+    sealed.extend_from_slice(content);
 
-                            }
+    (pad(sealed, pad_policy), SEALED_AAD)
+}
 
-                            logger!(
-                                "Decrypted file {} as :: {}",
-                                old_file_name,
-                                decoded_true_file_name
-                            );
-                            
-                            decoded_true_file_name
-                        } else {
-                            let new_file_name = file
-                                .clone()
-                                .read()
-                                .unwrap()
-                                .as_os_str()
-                                .to_str()
-                                .unwrap()
-                                .replace(source_dir_name, target_dir_name)
-                                .replace(".enom", "")
-                                .to_string();
-
-                            logger!("Decrypted file :: {}", new_file_name);
-
-                            new_file_name
-                        };
+// Reverse of seal. Returns what the header records
+fn unseal(data: &mut Vec<u8>) -> Result<SealedHeader, &'static str> {
+    let (flags, level) = match data.get(..SEALED_HEADER_LEN) {
+        Some(&[flags, level]) => (flags, level),
+        _ => return Err("The header of the file is cut short"),
+    };
 
-                        if !dry_run {
-                            let _ = fs::write(new_file_name, res);
-                        }
+    if flags & !(SEALED_PADME | SEALED_POW2 | SEALED_ZSTD | SEALED_COMPRESSED | SEALED_SYMLINK | SEALED_HARD_LINK) != 0 {
+        return Err("The file was encrypted with options this version does not know");
+    }
 
-                        *SUCCESS_COUNT.lock().unwrap() += 1;
-
-                        if !dry_run {
-                            match &shred_options {
-                                Some(o) => match o {
-                                    Shred::Shred(so) => {
-                                        if let Err(e) = shred(&ShredConfig::non_interactive(
-                                            vec![&*file.clone().read().unwrap()],
-                                            Verbosity::Quiet,
-                                            false,
-                                            so.random_iterations,
-                                            so.rename_times,
-                                        )) {
-                                            logger!("Failed to shred the file :: {}", e);
-                                        }
-                                    }
-                                },
-                                None => {
-                                    // Delete the source file if delete_src is true. Note: This is not a safe delete. The file count still exist and it is possible to retrieve
-                                    if delete_src {
-                                        if let Err(e) = fs::remove_file(*file.clone().read().unwrap()) {
-                                            logger!("Failed to delete the file :: {}", e);
-                                        }
-                                    }
-                                }
-                            };
-                        }
-                        
-                        if pb.bool {
-                            pb.inner
-                                .lock()
-                                .unwrap()
-                                .set_position(*pb.increment.lock().unwrap());
-                            *pb.increment.lock().unwrap() += 1;
-                        }
-                    }
-                    Err(_) => {
-                        *FAILED_COUNT.lock().unwrap() += 1;
-                    }
-                }
-            } else {
-                //Create Aes256Cryptor Object
-                let decrypt_obj = Aes256Cryptor::new({
-                    let mut key = [0u8; 32];
-                    key.copy_from_slice(ECB_32BYTE_KEY.read().unwrap()[0].as_slice());
-                    key
-                });
+    let pad_policy = match flags & (SEALED_PADME | SEALED_POW2) {
+        0 => PadPolicy::None,
+        SEALED_PADME => PadPolicy::Padme,
+        SEALED_POW2 => PadPolicy::Pow2,
+        _ => return Err("The header of the file records two padding policies"),
+    };
 
-                let decrypted_result = decrypt_obj.decrypt(file_data);
+    let compression = match flags & (SEALED_ZSTD | SEALED_COMPRESSED) {
+        0 => Compression::None,
+        _ if !ZSTD_LEVELS.contains(&(level as i32)) => return Err("The header of the file records an unknown zstd level"),
+        SEALED_ZSTD | SEALED_COMPRESSED_ZSTD => Compression::Zstd(level as i32),
+        _ => return Err("The header of the file records compressed content without a codec"),
+    };
 
-                if let Ok(mut decrypted_bytes) = decrypted_result {
-                    let new_file_name = if anon {
-                        let (old_file_name, decoded_true_file_name) =
-                            decode_file_name_from_base64(file.clone(), &mut decrypted_bytes);
+    let link = match flags & (SEALED_SYMLINK | SEALED_HARD_LINK) {
+        0 => None,
+        SEALED_SYMLINK => Some(LinkKind::Symlink),
+        SEALED_HARD_LINK => Some(LinkKind::HardLink),
+        _ => return Err("The header of the file records two kinds of links"),
+    };
 
-                        let decoded_true_file_name =
-                            decoded_true_file_name.replace(source_dir_name, target_dir_name);
+    if pad_policy != PadPolicy::None {
+        unpad(data)?;
+    }
 
-                        let decoded_file_name_path = PathBuf::from(&decoded_true_file_name);
+    data.drain(..SEALED_HEADER_LEN);
 
-                        if !dry_run {
-                            let _ = std::fs::create_dir_all(decoded_file_name_path.parent().unwrap());
+    if flags & SEALED_COMPRESSED != 0 {
+        decompress(data)?;
+    }
 
-                        }
+    Ok(SealedHeader { pad: pad_policy, compression, link })
+}
 
-                        logger!(
-                            "Decrypted file {} as :: {}",
-                            old_file_name,
-                            decoded_true_file_name
-                        );
-
-                        decoded_true_file_name
-                    } else {
-                        let new_file_name = file
-                            .clone()
-                            .read()
-                            .unwrap()
-                            .as_os_str()
-                            .to_str()
-                            .unwrap()
-                            .replace(source_dir_name, target_dir_name)
-                            .replace(".enom", "")
-                            .to_string();
-
-                        logger!("Decrypted file :: {}", new_file_name);
-
-                        new_file_name
-                    };
-
-                    if !dry_run {
-                        let _ = fs::write(new_file_name, decrypted_bytes);
-
-                        match &shred_options {
-                            Some(o) => match o {
-                                Shred::Shred(so) => {
-                                    if let Err(e) = shred(&ShredConfig::non_interactive(
-                                        vec![&*file.clone().read().unwrap()],
-                                        Verbosity::Quiet,
-                                        false,
-                                        so.random_iterations,
-                                        so.rename_times,
-                                    )) {
-                                        logger!("Failed to shred the file :: {}", e);
-                                    }
-                                }
-                            },
-                            None => {
-                                // Delete the source file if delete_src is true. Note: This is not a safe delete. The file count still exist and it is possible to retrieve
-                                if delete_src {
-                                    if let Err(e) = fs::remove_file(*file.clone().read().unwrap()) {
-                                        logger!("Failed to delete the file :: {}", e);
-                                    }
-                                }
-                            }
-                        };
-                    }
+// The link record the decrypted content holds, if the sealed header says it is a link
+fn link_record(link: Option<LinkKind>, decrypted_bytes: &[u8]) -> Result<Option<LinkRecord>, &'static str> {
+    match link {
+        Some(kind) => LinkRecord::parse(kind, decrypted_bytes).map(Some).ok_or("The hard link points outside the target directory"),
+        None => Ok(None),
+    }
+}
 
-                    
+// Overwrites the file with random bytes and renames it a number of times before it is deleted
+pub fn shred_file(file: &Path, options: &ShredOptions) -> Result<(), String> {
+    shred(&ShredConfig::non_interactive(
+        vec![file],
+        Verbosity::Quiet,
+        false,
+        options.random_iterations,
+        options.rename_times,
+    ))
+    .map_err(|e| format!("Failed to shred the file :: {}", e))
+}
 
-                    if pb.bool {
-                        pb.inner
-                            .lock()
-                            .unwrap()
-                            .set_position(*pb.increment.lock().unwrap());
-                        *pb.increment.lock().unwrap() += 1;
-                    }
-                }
-            };
-        },
-    );
-}
-
-fn encode_file_name_to_base64(
-    file: Arc<RwLock<&PathBuf>>,
-    source_dir_name: &str,
-    target_dir_name: &str,
-) -> (String, String, usize) {
-    let true_file_name = file
-        .clone()
-        .read()
-        .unwrap()
-        .as_os_str()
-        .to_str()
-        .expect("Found a bad file")
-        .replace(source_dir_name, target_dir_name)
-        .to_string();
-
-    let true_file_path = file
-        .clone()
-        .read()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .as_os_str()
-        .to_str()
-        .expect("Found a bad file")
-        .replace(source_dir_name, target_dir_name)
-        .to_string();
-
-    let encoded_true_file_name = BASE64_STANDARD.encode(true_file_name);
-    let encoded_true_file_name_length = encoded_true_file_name.len();
-
-    (
-        true_file_path,
-        encoded_true_file_name,
-        encoded_true_file_name_length,
-    )
-}
-
-fn decode_file_name_from_base64(
-    file: Arc<RwLock<&PathBuf>>,
-    res: &mut Vec<u8>,
-) -> (String, String) {
-    let old_file_name = file
-        .clone()
-        .read()
-        .unwrap()
-        .file_name()
-        .expect("Failed to fetch file name of the source file")
-        .to_str()
-        .expect("Failed to fetch file name of the source file");
+// Suffix of the temporary files which hold the target files until they are completely written
+pub const TEMP_FILE_SUFFIX: &str = ".rftmp";
 
-    let system_usize = std::mem::size_of::<usize>();
-    let base64_length_splitoff = res.len().saturating_sub(system_usize);
-    let base64_length = res.split_off(base64_length_splitoff);
-    let base64_length = usize::from_ne_bytes(base64_length.try_into().unwrap());
+/* Writes the bytes to a temporary file in the directory of the target file, flushes it to the disk, and then renames it to the target file.
+So the target file is either absent or complete, even when the disk is full or the program is killed midway.
+*/
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    write_atomic_cancellable(path, bytes, || false)
+}
+
+// Files are read and written in chunks of this size, so a cancelled run stops without waiting for a large file
+pub(crate) const CHUNK_SIZE: usize = 1024 * 1024;
+
+// The error of a read or a write which was stopped by the cancellation token
+pub(crate) fn cancelled_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Interrupted, "The run was cancelled")
+}
+
+// Same as write_atomic, but gives up between two chunks once cancelled returns true. The temporary file is removed then
+pub fn write_atomic_cancellable(
+    path: &Path,
+    bytes: &[u8],
+    cancelled: impl Fn() -> bool,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    write_atomic_with(path, |temp_file| {
+        for chunk in bytes.chunks(CHUNK_SIZE) {
+            if cancelled() {
+                return Err(cancelled_error());
+            }
+
+            temp_file.write_all(chunk)?;
+        }
+
+        Ok(())
+    })
+}
+
+// Same as write_atomic, with the temporary file filled by fill, so the content does not have to be in memory at once
+pub(crate) fn write_atomic_with(path: &Path, fill: impl FnOnce(&mut fs::File) -> std::io::Result<()>) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let temp_name: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+
+    let mut temp_file_name = OsString::from(".");
+    temp_file_name.push(path.file_name().unwrap_or_default());
+    temp_file_name.push(format!(".{}{}", temp_name, TEMP_FILE_SUFFIX));
+
+    let temp_path = dir.join(temp_file_name);
+
+    let result = (|| {
+        let mut temp_file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
 
-    let base64_splitoff = res.len().saturating_sub(base64_length);
-    let base64 = res.split_off(base64_splitoff);
+        fill(&mut temp_file)?;
 
+        temp_file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+
+        // Persist the rename as well. Directories cannot be opened this way on Windows
+        #[cfg(unix)]
+        fs::File::open(dir)?.sync_all()?;
+
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+// Reads the whole file in chunks, and gives up between two chunks once cancelled returns true
+pub(crate) fn read_cancellable(path: &Path, cancelled: impl Fn() -> bool) -> std::io::Result<Vec<u8>> {
+    use std::io::{ErrorKind, Read};
+
+    let mut file = fs::File::open(path)?;
+    let mut data = Vec::with_capacity(file.metadata().map(|m| m.len() as usize).unwrap_or(0));
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        if cancelled() {
+            return Err(cancelled_error());
+        }
+
+        match file.read(&mut chunk) {
+            Ok(0) => return Ok(data),
+            Ok(n) => data.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Returns None if the decrypted bytes do not end with an encoded file name, which happens when the file was not anonymized
+fn decode_file_name_from_base64(file: &Path, res: &mut Vec<u8>) -> Option<(OsString, PathBuf)> {
+    let old_file_name = file.file_name()?.to_os_string();
+
+    let system_usize = std::mem::size_of::<usize>();
+    let base64_length_splitoff = res.len().checked_sub(system_usize)?;
+    let base64_length = usize::from_ne_bytes(res[base64_length_splitoff..].try_into().ok()?);
+
+    let base64_splitoff = base64_length_splitoff.checked_sub(base64_length)?;
     let decoded_true_file_name = BASE64_STANDARD
-        .decode(base64)
-        .expect("Failed to decode base64 file path for source files");
-    let decoded_true_file_name = String::from_utf8_lossy(&decoded_true_file_name);
+        .decode(&res[base64_splitoff..base64_length_splitoff])
+        .ok()?;
+    let decoded_true_file_name = path_from_bytes(&decoded_true_file_name);
+
+    res.truncate(base64_splitoff);
 
-    (
-        old_file_name.to_string(),
-        decoded_true_file_name.to_string(),
-    )
+    Some((old_file_name, decoded_true_file_name))
 }
 
-fn generate_random_file_name(true_file_path: String) -> String {
+// Appends the .enom extension to the full name of the file, so a.tar.gz becomes a.tar.gz.enom
+fn with_enom(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".enom");
+    PathBuf::from(name)
+}
+
+// Removes the trailing .enom extension of the file name, and nothing else. .enom in the middle of a name stays
+fn without_enom(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(extension) if extension == "enom" => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn generate_random_file_name(true_file_path: &Path) -> PathBuf {
     let random_suffix: String = rand::rng()
         .sample_iter(&Alphanumeric)
         .take(20)
@@ -781,12 +1354,203 @@ fn generate_random_file_name(true_file_path: String) -> String {
         .collect::<String>()
         + ".enom";
 
-    let new_random_file_name = Path::new(&true_file_path).join(random_suffix);
+    true_file_path.join(random_suffix)
+}
 
-    let new_random_file_name = new_random_file_name
-        .to_str()
-        .expect("Found a bad file path to rename");
 
-    new_random_file_name.to_string()
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{report::RunReport, Decryptor, Encryptor};
 
+    fn key() -> Key {
+        Key::from_bytes([7u8; 32])
+    }
+
+    fn encrypt(source: &Path, target: &Path, mode: Mode, pad: PadPolicy, compression: Compression) -> Result<RunReport, RufendecError> {
+        fs::create_dir_all(target).unwrap();
+
+        Encryptor::builder()
+            .source_dir(source)
+            .target_dir(target)
+            .key(key())
+            .mode(mode)
+            .pad(pad)
+            .compression(compression)
+            .build()?
+            .encrypt_files(&[source.join("log.txt")])
+    }
+
+    fn decryptor(target: &Path) -> Decryptor {
+        Decryptor::builder().source_dir(target).target_dir(target).key(key()).build().unwrap()
+    }
+
+    fn source_dir(root: &Path, content: &[u8]) -> PathBuf {
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("log.txt"), content).unwrap();
+        source
+    }
+
+    fn content_of(file: &DecryptedFile) -> &[u8] {
+        match &file.content {
+            DecryptedContent::File(content) => content,
+            _ => panic!("expected a regular file"),
+        }
+    }
+
+    #[test]
+    fn sealed_file_round_trip_keeps_its_options() {
+        let root = tempfile::tempdir().unwrap();
+        let content = b"timestamp,level,message\n".repeat(300);
+        let source = source_dir(root.path(), &content);
+        let target = root.path().join("target");
+
+        let report = encrypt(&source, &target, Mode::GCM, PadPolicy::Pow2, Compression::Zstd(9)).unwrap();
+        assert_eq!(report.success_count, 1);
+
+        let encrypted = target.join("log.txt.enom");
+        assert!(fs::metadata(&encrypted).unwrap().len() < content.len() as u64);
+
+        let decryptor = decryptor(&target);
+        let file = decryptor.decrypt_to_memory(&encrypted).unwrap();
+
+        assert_eq!(content_of(&file), content.as_slice());
+        assert_eq!((file.pad, file.compression), (PadPolicy::Pow2, Compression::Zstd(9)));
+
+        // An edited file is encrypted back at the same level
+        fs::write(&encrypted, decryptor.encrypt_to_memory(&file).unwrap()).unwrap();
+        let file = decryptor.decrypt_to_memory(&encrypted).unwrap();
+
+        assert_eq!(file.compression, Compression::Zstd(9));
+    }
+
+    #[test]
+    fn modified_sealed_file_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), &b"a line of text\n".repeat(100));
+        let target = root.path().join("target");
+
+        encrypt(&source, &target, Mode::GCM, PadPolicy::Padme, Compression::Zstd(3)).unwrap();
+
+        // The first byte of the cipher text is the encrypted flags of the header
+        let encrypted = target.join("log.txt.enom");
+        let mut bytes = fs::read(&encrypted).unwrap();
+        bytes[0] ^= SEALED_POW2;
+        fs::write(&encrypted, bytes).unwrap();
+
+        assert!(decryptor(&target).decrypt_to_memory(&encrypted).is_err());
+    }
+
+    #[test]
+    fn plain_file_is_never_unsealed() {
+        // Content which ends the way a padded file does: zero bytes and a count of them
+        let content = [b"data".as_slice(), &[0u8; 4], &4u64.to_le_bytes()].concat();
+
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), &content);
+        let target = root.path().join("target");
+
+        encrypt(&source, &target, Mode::GCM, PadPolicy::None, Compression::None).unwrap();
+
+        let file = decryptor(&target).decrypt_to_memory(&target.join("log.txt.enom")).unwrap();
+
+        assert_eq!(content_of(&file), content.as_slice());
+        assert_eq!((file.pad, file.compression), (PadPolicy::None, Compression::None));
+    }
+
+    #[test]
+    fn padding_and_compression_need_gcm() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), b"text");
+        let target = root.path().join("target");
+
+        for (pad, compression) in [(PadPolicy::Padme, Compression::None), (PadPolicy::None, Compression::Zstd(3))] {
+            assert!(matches!(
+                encrypt(&source, &target, Mode::ECB, pad, compression),
+                Err(RufendecError::Validation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn age_refuses_anonymized_names() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), b"text");
+
+        let built = Encryptor::builder().source_dir(&source).mode(Mode::Age).password("password", "salt").anon(true).build();
+        assert!(matches!(built, Err(RufendecError::Validation(message)) if message.contains("anonymized file names")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_round_trip() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), b"text");
+        let (encrypted, decrypted) = (root.path().join("encrypted"), root.path().join("decrypted"));
+        fs::create_dir_all(&encrypted).unwrap();
+        fs::create_dir_all(&decrypted).unwrap();
+        fs::hard_link(source.join("log.txt"), source.join("hard.txt")).unwrap();
+        std::os::unix::fs::symlink("log.txt", source.join("soft.txt")).unwrap();
+
+        let encryptor = Encryptor::builder().source_dir(&source).target_dir(&encrypted).key(key()).symlinks(SymlinkPolicy::Preserve);
+        let files = [source.join("log.txt"), source.join("hard.txt"), source.join("soft.txt")];
+        assert_eq!(encryptor.build().unwrap().encrypt_files(&files).unwrap().success_count, 3);
+
+        let decryptor = Decryptor::builder().source_dir(&encrypted).target_dir(&decrypted).key(key()).build().unwrap();
+        let files = ["log.txt.enom", "hard.txt.enom", "soft.txt.enom"].map(|name| encrypted.join(name));
+        assert_eq!(decryptor.decrypt_files(&files).unwrap().success_count, 3);
+
+        assert_eq!(fs::read_link(decrypted.join("soft.txt")).unwrap(), Path::new("log.txt"));
+        assert_eq!(fs::read(decrypted.join("hard.txt")).unwrap(), b"text");
+        assert_eq!(std::os::unix::fs::MetadataExt::nlink(&fs::metadata(decrypted.join("hard.txt")).unwrap()), 2);
+    }
+
+    #[test]
+    fn file_like_a_link_record_is_a_file() {
+        // A regular file which starts with the bytes the link records used to start with
+        let content = b"\0RUFENDEC-LINK\0S/etc/passwd";
+
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), content);
+        let target = root.path().join("target");
+
+        encrypt(&source, &target, Mode::GCM, PadPolicy::None, Compression::None).unwrap();
+
+        let file = decryptor(&target).decrypt_to_memory(&target.join("log.txt.enom")).unwrap();
+        assert_eq!(content_of(&file), content.as_slice());
+    }
+
+    #[test]
+    fn hard_link_outside_the_target_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let decryptor = decryptor(root.path());
+
+        for first_name in ["../outside", "/etc/passwd"] {
+            let header = SealedHeader { link: Some(LinkKind::HardLink), ..Default::default() };
+            let (record, aad) = seal(first_name.as_bytes().to_vec(), header);
+            let encrypted = root.path().join("link.txt.enom");
+            fs::write(&encrypted, decryptor.cipher().encrypt_bytes_with_aad(&record, aad).unwrap()).unwrap();
+
+            let decrypted = decryptor.decrypt_to_memory(&encrypted);
+            assert!(matches!(decrypted, Err(RufendecError::Runtime(message)) if message.contains("outside the target directory")));
+
+            let report = decryptor.decrypt_files(&[encrypted]).unwrap();
+            assert_eq!((report.success_count, report.failed_count), (0, 1));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_link_to_a_parent_is_skipped() {
+        let root = tempfile::tempdir().unwrap();
+        let source = source_dir(root.path(), b"text");
+        std::os::unix::fs::symlink(root.path(), source.join("parent")).unwrap();
+        std::os::unix::fs::symlink("/", source.join("root")).unwrap();
+
+        let tree = scan_filtered(&source, &Filter::default().symlinks(SymlinkPolicy::Follow));
+
+        assert_eq!(tree.files, [source.join("log.txt")]);
+        assert_eq!(tree.excluded.len(), 2);
+    }
+}
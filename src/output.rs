@@ -0,0 +1,237 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{path::Path, sync::RwLock, time::Duration};
+
+use rufendec::{
+    container::{Entry, EntryKind},
+    log::{log, LogLevel},
+    operations::write_atomic,
+    report::{FileResult, RunReport},
+    sync::SyncSummary,
+};
+
+use crate::display::OperationalInfo;
+
+/* How does the machine-readable output work?
+-----------------------
+With --output json, the human readable text (banner, operational info, prompts and the result) is written to stderr instead of stdout,
+and a single JSON document describing the whole run is printed to stdout once the run is over.
+With --output ndjson, the same goes to stderr, and stdout carries one JSON object per line as the run progresses: the operational info,
+the result of every file, and the summary at the end. Each object has an "event" field naming it.
+With --report <path>, the same JSON document is also written to the given file, whatever the output format is.
+*/
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+}
+
+lazy_static! {
+    pub static ref OUTPUT_FORMAT: RwLock<OutputFormat> = RwLock::new(OutputFormat::Text);
+}
+
+// True when stdout is reserved for JSON, and the human readable text must go to stderr
+pub fn is_machine() -> bool {
+    *OUTPUT_FORMAT.read().unwrap() != OutputFormat::Text
+}
+
+// Prints the human readable text. It goes to stderr with the json and ndjson output formats
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if $crate::output::is_machine() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+// Same as outln!, without the newline
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if $crate::output::is_machine() {
+            eprint!($($arg)*)
+        } else {
+            print!($($arg)*)
+        }
+    };
+}
+
+pub(crate) use {out, outln};
+
+// Prints one NDJSON event line to stdout. Nothing is printed with the other output formats
+pub fn emit_event<T: Serialize>(event: &str, data: &T) {
+    if *OUTPUT_FORMAT.read().unwrap() != OutputFormat::Ndjson {
+        return;
+    }
+
+    let mut line = Map::new();
+    line.insert("event".to_string(), Value::from(event));
+
+    match serde_json::to_value(data) {
+        Ok(Value::Object(fields)) => line.extend(fields),
+        Ok(value) => {
+            line.insert("data".to_string(), value);
+        }
+        Err(_) => return,
+    }
+
+    println!("{}", Value::Object(line));
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct Timings {
+    pub key_derivation_ms: f64,
+    pub processing_ms: f64,
+    pub total_ms: f64,
+}
+
+impl Timings {
+    pub fn new(key_derivation: Duration, processing: Duration, total: Duration) -> Self {
+        Timings {
+            key_derivation_ms: key_derivation.as_secs_f64() * 1000.0,
+            processing_ms: processing.as_secs_f64() * 1000.0,
+            total_ms: total.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+// The final counts of the run. It is the "summary" NDJSON event, and a part of the JSON document
+#[derive(Serialize, Debug)]
+pub struct RunSummary<'a> {
+    pub status: &'static str,
+    pub success_count: u64,
+    pub failed_count: u64,
+    pub skipped_count: u64,
+    pub excluded_count: u64,
+    pub bytes_processed: u64,
+    pub throughput_bytes_per_sec: f64,
+    pub timings: Timings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync: Option<&'a SyncSummary>,
+    pub exit_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+struct RunDocument<'a> {
+    operational_info: &'a OperationalInfo,
+    #[serde(flatten)]
+    summary: RunSummary<'a>,
+    files: &'a [FileResult],
+}
+
+/* Prints the JSON document or the NDJSON summary event of the run, and writes the report file if one was asked for.
+completed is false when the user chose to quit at the confirmation. A run stopped with Ctrl+C has the status cancelled.
+*/
+pub fn finish_run(
+    info: &OperationalInfo,
+    report: &RunReport,
+    timings: Timings,
+    sync: Option<&SyncSummary>,
+    completed: bool,
+    exit_code: i32,
+    report_path: Option<&str>,
+) {
+    let format = *OUTPUT_FORMAT.read().unwrap();
+
+    if format == OutputFormat::Text && report_path.is_none() {
+        return;
+    }
+
+    let processing_secs = timings.processing_ms / 1000.0;
+
+    let summary = RunSummary {
+        status: match (completed, report.cancelled) {
+            (false, _) => "quit",
+            (true, true) => "cancelled",
+            (true, false) => "completed",
+        },
+        success_count: report.success_count,
+        failed_count: report.failed_count,
+        skipped_count: report.skipped_count,
+        excluded_count: report.excluded_count,
+        bytes_processed: report.bytes_processed,
+        throughput_bytes_per_sec: if processing_secs > 0.0 {
+            report.bytes_processed as f64 / processing_secs
+        } else {
+            0.0
+        },
+        timings,
+        sync,
+        exit_code,
+    };
+
+    if format == OutputFormat::Ndjson {
+        emit_event("summary", &summary);
+    }
+
+    let document = RunDocument {
+        operational_info: info,
+        summary,
+        files: &report.files,
+    };
+
+    if format == OutputFormat::Json {
+        match serde_json::to_string_pretty(&document) {
+            Ok(json) => println!("{}", json),
+            Err(e) => log(LogLevel::ERROR, format!("Failed to serialize the run report :: {}", e).as_str()),
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        let written = serde_json::to_vec_pretty(&document)
+            .map_err(|e| e.to_string())
+            .and_then(|json| write_atomic(Path::new(report_path), &json).map_err(|e| e.to_string()));
+
+        if let Err(e) = written {
+            log(
+                LogLevel::ERROR,
+                format!("Failed to write the run report to {:?} :: {}", report_path, e).as_str(),
+            );
+        }
+    }
+}
+
+// An entry of a container as it is listed. The paths are written as text, and any non UTF-8 bytes are replaced
+#[derive(Serialize, Debug)]
+struct ListedEntry {
+    path: String,
+    kind: EntryKind,
+    size: u64,
+    modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+/* Prints the entries of a container as a JSON document, or as one "entry" NDJSON event each. Nothing is printed with the text output format */
+pub fn print_entries(info: &OperationalInfo, entries: &[Entry]) {
+    let entries: Vec<ListedEntry> = entries
+        .iter()
+        .map(|entry| ListedEntry {
+            path: entry.path.to_string_lossy().into_owned(),
+            kind: entry.kind,
+            size: entry.size,
+            modified: entry.modified,
+            target: entry.target.as_ref().map(|target| target.to_string_lossy().into_owned()),
+        })
+        .collect();
+
+    match *OUTPUT_FORMAT.read().unwrap() {
+        OutputFormat::Text => {}
+        OutputFormat::Ndjson => entries.iter().for_each(|entry| emit_event("entry", entry)),
+        OutputFormat::Json => {
+            let document = serde_json::json!({ "operational_info": info, "entries": entries });
+
+            match serde_json::to_string_pretty(&document) {
+                Ok(json) => println!("{}", json),
+                Err(e) => log(LogLevel::ERROR, format!("Failed to serialize the entries :: {}", e).as_str()),
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use serde::Serialize;
+
+/* How does the padding work?
+-----------------------
+Without padding, the size of an encrypted file gives away the exact size of the source file (GCM adds 28 bytes to it), which is enough
+to tell a known document apart. With a padding policy, zero bytes are appended to the content so its size is rounded up to a bucket,
+followed by the count of the padding bytes (8 bytes, little endian).
+The padding goes inside the encrypted payload, after the anonymized file name if any, and the policy is recorded in the header of the
+sealed content (see seal in operations.rs), which GCM authenticates along with it. So the padding is only stripped from the files which
+record it, and a file which was not padded is never looked into for one.
+*/
+
+const TRAILER_LEN: usize = 8;
+
+// How far the size of the encrypted files is rounded up
+#[derive(clap::ValueEnum, Clone, Debug, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PadPolicy {
+    /// Leave the size as it is
+    #[default]
+    None,
+    /// PADMÉ, which rounds up by at most 12% and leaks about log(log(size)) bits of the size
+    Padme,
+    /// The next power of two, which rounds up by at most 100%
+    Pow2,
+}
+
+impl PadPolicy {
+    // The size the padded content is rounded up to
+    fn padded_len(&self, len: u64) -> u64 {
+        match self {
+            PadPolicy::None => len,
+            PadPolicy::Padme => padme(len),
+            PadPolicy::Pow2 => len.checked_next_power_of_two().unwrap_or(len),
+        }
+    }
+}
+
+/* PADMÉ from "Reducing Metadata Leakage from Encrypted Files and Communication with PURBs" (Nikitin et al., 2019).
+The size keeps its top bits, as many as the bit length of its exponent, and the rest of the bits are rounded up
+*/
+fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+
+    let exponent = 63 - len.leading_zeros() as u64;
+    let exponent_bits = 64 - exponent.leading_zeros() as u64;
+    let mask = (1u64 << (exponent - exponent_bits)) - 1;
+
+    len.checked_add(mask).map_or(len, |len| len & !mask)
+}
+
+// Appends the padding and its count to the content. Nothing is appended without a padding policy
+pub(crate) fn pad(mut data: Vec<u8>, policy: PadPolicy) -> Vec<u8> {
+    if let PadPolicy::None = policy {
+        return data;
+    }
+
+    let unpadded_len = (data.len() + TRAILER_LEN) as u64;
+    let padding = policy.padded_len(unpadded_len) - unpadded_len;
+
+    data.resize(data.len() + padding as usize, 0);
+    data.extend_from_slice(&padding.to_le_bytes());
+    data
+}
+
+// Strips the padding and its count from content which records that it was padded
+pub(crate) fn unpad(data: &mut Vec<u8>) -> Result<(), &'static str> {
+    let malformed = "The padding of the file is malformed";
+
+    let count_at = data.len().checked_sub(TRAILER_LEN).ok_or(malformed)?;
+    let padding = u64::from_le_bytes(data[count_at..].try_into().unwrap());
+
+    let content_len = usize::try_from(padding)
+        .ok()
+        .and_then(|padding| count_at.checked_sub(padding))
+        .ok_or(malformed)?;
+
+    if data[content_len..count_at].iter().any(|b| *b != 0) {
+        return Err(malformed);
+    }
+
+    data.truncate(content_len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padme_keeps_the_top_bits() {
+        assert_eq!(padme(0), 0);
+        assert_eq!(padme(1), 1);
+        assert_eq!(padme(9), 10);
+        assert_eq!(padme(1000), 1024);
+        assert_eq!(padme(1025), 1088);
+    }
+
+    #[test]
+    fn pad_and_unpad_round_trip() {
+        for policy in [PadPolicy::Padme, PadPolicy::Pow2] {
+            for len in [0, 1, 100, 4097] {
+                let content: Vec<u8> = (0..len).map(|i| (i % 251) as u8 + 1).collect();
+                let mut padded = pad(content.clone(), policy);
+
+                assert_eq!(padded.len() as u64, policy.padded_len(len as u64 + TRAILER_LEN as u64));
+
+                unpad(&mut padded).unwrap();
+                assert_eq!(padded, content);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_padding_is_rejected() {
+        let mut padded = pad(vec![1u8; 100], PadPolicy::Pow2);
+        let count_at = padded.len() - TRAILER_LEN;
+
+        // A count larger than the content
+        let mut too_long = padded.clone();
+        too_long[count_at..].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(unpad(&mut too_long).is_err());
+
+        // Padding which is not zero bytes
+        padded[count_at - 1] = 1;
+        assert!(unpad(&mut padded).is_err());
+
+        assert!(unpad(&mut vec![0u8; 3]).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use serde::{Serialize, Serializer};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{EXIT_CANCELLED, EXIT_CREDENTIALS, EXIT_PARTIAL_FAILURE, EXIT_SUCCESS},
+    log::{log, LogLevel},
+};
+
+// The step of the pipeline at which a file failed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Read,
+    Encrypt,
+    Decrypt,
+    Write,
+    Verify,
+    Delete,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Success,
+    Failed,
+    Excluded,
+}
+
+// Paths are written as text, and any non UTF-8 bytes are replaced
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// The outcome of a single file. The target is the file which was written (or would be written in a dry run)
+#[derive(Debug, Clone, Serialize)]
+pub struct FileResult {
+    #[serde(serialize_with = "serialize_path")]
+    pub source: PathBuf,
+    #[serde(serialize_with = "serialize_optional_path", skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<Stage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cause: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+impl FileResult {
+    pub fn excluded(source: &Path) -> Self {
+        FileResult {
+            source: source.to_path_buf(),
+            target: None,
+            status: FileStatus::Excluded,
+            stage: None,
+            cause: None,
+            bytes: None,
+            elapsed_ms: None,
+        }
+    }
+
+    pub fn success(source: &Path, target: &Path, bytes: Option<u64>, elapsed_ms: Option<f64>) -> Self {
+        FileResult {
+            source: source.to_path_buf(),
+            target: Some(target.to_path_buf()),
+            status: FileStatus::Success,
+            stage: None,
+            cause: None,
+            bytes,
+            elapsed_ms,
+        }
+    }
+
+    // Also logs the failure, so it is printed right away whether verbose or not
+    pub fn failure(
+        source: &Path,
+        stage: Stage,
+        cause: impl fmt::Display,
+        bytes: Option<u64>,
+        elapsed_ms: Option<f64>,
+    ) -> Self {
+        let cause = cause.to_string();
+
+        log(
+            LogLevel::ERROR,
+            format!("{} failed for {:?} :: {}", stage, source, cause).as_str(),
+        );
+
+        FileResult {
+            source: source.to_path_buf(),
+            target: None,
+            status: FileStatus::Failed,
+            stage: Some(stage),
+            cause: Some(cause),
+            bytes,
+            elapsed_ms,
+        }
+    }
+}
+
+/* Collects the outcome of every file of a run. Files which were never processed because the run was cancelled are only counted as skipped.
+The files and directories left out by the include and exclude patterns or the ignore files are listed as excluded
+*/
+#[derive(Debug, Default, Clone)]
+pub struct RunReport {
+    pub success_count: u64,
+    pub failed_count: u64,
+    pub skipped_count: u64,
+    pub excluded_count: u64,
+    pub bytes_processed: u64,
+    pub cancelled: bool,
+    pub files: Vec<FileResult>,
+}
+
+impl RunReport {
+    pub fn record(&mut self, result: FileResult) {
+        match result.status {
+            FileStatus::Success => {
+                self.success_count += 1;
+                self.bytes_processed += result.bytes.unwrap_or(0);
+            }
+            FileStatus::Failed => self.failed_count += 1,
+            FileStatus::Excluded => self.excluded_count += 1,
+        }
+
+        self.files.push(result);
+    }
+
+    // Adds the files of another run, such as the next batch of the watcher
+    pub fn merge(&mut self, other: RunReport) {
+        self.success_count += other.success_count;
+        self.failed_count += other.failed_count;
+        self.skipped_count += other.skipped_count;
+        self.excluded_count += other.excluded_count;
+        self.bytes_processed += other.bytes_processed;
+        self.cancelled |= other.cancelled;
+        self.files.extend(other.files);
+    }
+
+    pub fn record_excluded(&mut self, excluded: &[PathBuf]) {
+        for path in excluded {
+            self.record(FileResult::excluded(path));
+        }
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FileResult> {
+        self.files.iter().filter(|f| f.status == FileStatus::Failed)
+    }
+
+    // Pairs of (source file, target file) of the files which went through
+    pub fn outputs(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.files
+            .iter()
+            .filter_map(|f| Some((f.source.as_path(), f.target.as_deref()?)))
+    }
+
+    /* The exit code of the run. If every file failed to be decrypted, the credentials are the most likely culprit */
+    pub fn exit_code(&self) -> i32 {
+        if self.cancelled {
+            EXIT_CANCELLED
+        } else if self.failed_count == 0 {
+            EXIT_SUCCESS
+        } else if self.success_count == 0
+            && self.failures().all(|f| f.stage == Some(Stage::Decrypt))
+        {
+            EXIT_CREDENTIALS
+        } else {
+            EXIT_PARTIAL_FAILURE
+        }
+    }
+}
@@ -0,0 +1,344 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+use crate::{
+    container::{MAX_CHUNK_SIZE, MIN_CHUNK_SIZE},
+    error::RufendecError,
+    operations::{Cipher, Mode},
+};
+
+/* How is a stream laid out?
+-----------------------
+A stream is encrypted as it is read, so the input can be a pipe which neither has a size nor can be read twice, and nothing is written to disk.
+1. The header: STREAM_MAGIC, the format version, the mode, the chunk size and a random id of the stream. It is not encrypted.
+2. The chunks. The input is cut into chunks of the chunk size and each chunk is encrypted on its own, so only a chunk is held in memory at a time.
+   Every chunk is written as a flag (1 for the final chunk, 0 otherwise), the size of the encrypted chunk (4 bytes, little endian) and the
+   encrypted chunk. The final chunk is shorter than the chunk size, and may be empty.
+In GCM mode, the header, the index of the chunk and the flag are the associated data of every chunk, so the chunks cannot be swapped, dropped,
+or moved into another stream, and a stream which was cut short (it has no final chunk) is noticed.
+ECB has no authentication, so in ECB mode the final chunk ends with the SHA256 hash of the header and of everything before it instead.
+The decrypted chunks are written out as soon as they are authenticated, so a damaged stream fails part way, with what came before it written.
+*/
+
+const STREAM_MAGIC: &[u8] = b"RUFENDEC-STREAM\0";
+const STREAM_VERSION: u8 = 1;
+// The magic, the version, the mode, the chunk size and the id
+const HEADER_LEN: usize = STREAM_MAGIC.len() + 1 + 1 + 4 + 16;
+
+const FLAG_MORE: u8 = 0;
+const FLAG_FINAL: u8 = 1;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+struct Header {
+    bytes: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl Header {
+    fn new(mode: Mode, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+
+        let mut id = [0u8; 16];
+        rand::rng().fill(&mut id);
+
+        let mode = match mode {
+            Mode::ECB => 0u8,
+            Mode::GCM => 1u8,
+            Mode::OpenPGP | Mode::Age => unreachable!("the OpenPGP and the age modes are refused before a stream is encrypted"),
+        };
+
+        Header {
+            bytes: [
+                STREAM_MAGIC,
+                &[STREAM_VERSION, mode],
+                &(chunk_size as u32).to_le_bytes(),
+                &id,
+            ]
+            .concat(),
+            chunk_size,
+        }
+    }
+
+    fn parse(bytes: Vec<u8>, mode: Mode) -> Result<Header, RufendecError> {
+        let not_a_stream = || RufendecError::Validation("The input is not a stream encrypted by rufendec".to_string());
+
+        let [version, stream_mode, c0, c1, c2, c3, ..] = bytes.strip_prefix(STREAM_MAGIC).ok_or_else(not_a_stream)? else {
+            return Err(not_a_stream());
+        };
+
+        if *version != STREAM_VERSION {
+            return Err(RufendecError::Validation(format!(
+                "The stream has the format version {}, which this version of rufendec cannot read",
+                version
+            )));
+        }
+
+        let stream_mode = match stream_mode {
+            0 => Mode::ECB,
+            1 => Mode::GCM,
+            _ => return Err(not_a_stream()),
+        };
+
+        if stream_mode != mode {
+            return Err(RufendecError::Validation(format!(
+                "The stream was encrypted in the {} mode, but the {} mode was given",
+                stream_mode, mode
+            )));
+        }
+
+        let chunk_size = u32::from_le_bytes([*c0, *c1, *c2, *c3]) as usize;
+
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return Err(not_a_stream());
+        }
+
+        Ok(Header { bytes, chunk_size })
+    }
+
+    fn chunk_aad(&self, index: u64, flag: u8) -> Vec<u8> {
+        [self.bytes.as_slice(), &index.to_le_bytes(), &[flag]].concat()
+    }
+}
+
+fn io_error(e: io::Error) -> RufendecError {
+    RufendecError::Runtime(format!("The stream could not be read or written: {}", e))
+}
+
+// Reads until the buffer is full or the input ends, and returns how much was read
+fn read_full(input: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+// Encrypts everything read from the input into the output. Returns the count of the bytes which were read
+pub fn encrypt_stream(
+    cipher: &Cipher,
+    chunk_size: usize,
+    mut input: impl Read,
+    mut output: impl Write,
+) -> Result<u64, RufendecError> {
+    cipher.require_chunked("Streams")?;
+
+    let header = Header::new(cipher.mode(), chunk_size);
+    output.write_all(&header.bytes).map_err(io_error)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&header.bytes);
+
+    let mut buffer = vec![0u8; header.chunk_size];
+    let mut total = 0u64;
+
+    for index in 0u64.. {
+        let read = read_full(&mut input, &mut buffer).map_err(io_error)?;
+        total += read as u64;
+
+        let flag = if read < buffer.len() { FLAG_FINAL } else { FLAG_MORE };
+
+        let encrypted = match (cipher.mode(), flag) {
+            (Mode::ECB, FLAG_FINAL) => {
+                hasher.update(&buffer[..read]);
+                let digest = hasher.clone().finalize();
+                cipher.encrypt_bytes(&[&buffer[..read], digest.as_slice()].concat())
+            }
+            (Mode::ECB, _) => {
+                hasher.update(&buffer[..read]);
+                cipher.encrypt_bytes(&buffer[..read])
+            }
+            _ => cipher.encrypt_bytes_with_aad(&buffer[..read], &header.chunk_aad(index, flag)),
+        }
+        .ok_or_else(|| RufendecError::Runtime(format!("Chunk {} of the stream could not be encrypted", index)))?;
+
+        output.write_all(&[flag]).map_err(io_error)?;
+        output.write_all(&(encrypted.len() as u32).to_le_bytes()).map_err(io_error)?;
+        output.write_all(&encrypted).map_err(io_error)?;
+
+        if flag == FLAG_FINAL {
+            break;
+        }
+    }
+
+    output.flush().map_err(io_error)?;
+
+    Ok(total)
+}
+
+/* Decrypts a stream read from the input into the output. Returns the count of the bytes which were written.
+A wrong key shows at the first chunk and is a Credentials error. A chunk failing after that means the stream was damaged
+*/
+pub fn decrypt_stream(cipher: &Cipher, mut input: impl Read, mut output: impl Write) -> Result<u64, RufendecError> {
+    cipher.require_chunked("Streams")?;
+
+    let truncated = || RufendecError::Runtime("The stream ends before its final chunk, so it was cut short".to_string());
+
+    let mut header = vec![0u8; HEADER_LEN];
+
+    if read_full(&mut input, &mut header).map_err(io_error)? < HEADER_LEN {
+        return Err(RufendecError::Validation("The input is not a stream encrypted by rufendec".to_string()));
+    }
+
+    let header = Header::parse(header, cipher.mode())?;
+
+    // The final chunk carries the hash in ECB mode
+    let max_encrypted_len = cipher.encrypted_len(header.chunk_size + Sha256::output_size());
+
+    let mut hasher = Sha256::new();
+    hasher.update(&header.bytes);
+
+    let mut total = 0u64;
+
+    for index in 0u64.. {
+        let mut chunk_header = [0u8; 5];
+
+        if read_full(&mut input, &mut chunk_header).map_err(io_error)? < chunk_header.len() {
+            return Err(truncated());
+        }
+
+        let [flag, l0, l1, l2, l3] = chunk_header;
+        let len = u32::from_le_bytes([l0, l1, l2, l3]) as usize;
+
+        if flag > FLAG_FINAL || len > max_encrypted_len {
+            return Err(RufendecError::Runtime(format!("Chunk {} of the stream is damaged", index)));
+        }
+
+        let mut encrypted = vec![0u8; len];
+
+        if read_full(&mut input, &mut encrypted).map_err(io_error)? < len {
+            return Err(truncated());
+        }
+
+        let decrypted = match cipher.mode() {
+            Mode::ECB => cipher.decrypt_bytes(&encrypted),
+            _ => cipher.decrypt_bytes_with_aad(&encrypted, &header.chunk_aad(index, flag)),
+        };
+
+        let Some(mut decrypted) = decrypted else {
+            return Err(match index {
+                0 => RufendecError::Credentials(
+                    "The stream could not be decrypted. Check the password, the salt and the hashing function".to_string(),
+                ),
+                _ => RufendecError::Runtime(format!("Chunk {} of the stream could not be authenticated", index)),
+            });
+        };
+
+        if let (Mode::ECB, FLAG_FINAL) = (cipher.mode(), flag) {
+            let Some(digest_at) = decrypted.len().checked_sub(Sha256::output_size()) else {
+                return Err(RufendecError::Runtime("The final chunk of the stream is damaged".to_string()));
+            };
+
+            let digest = decrypted.split_off(digest_at);
+            hasher.update(&decrypted);
+
+            if hasher.clone().finalize().as_slice() != digest.as_slice() {
+                return Err(RufendecError::Runtime("The hash of the stream does not match, so it was damaged".to_string()));
+            }
+        } else if cipher.mode() == Mode::ECB {
+            hasher.update(&decrypted);
+        }
+
+        output.write_all(&decrypted).map_err(io_error)?;
+        total += decrypted.len() as u64;
+
+        if flag == FLAG_FINAL {
+            break;
+        }
+    }
+
+    // Anything after the final chunk was not written by rufendec
+    if input.read(&mut [0u8; 1]).map_err(io_error)? > 0 {
+        return Err(RufendecError::Runtime("The stream has data after its final chunk".to_string()));
+    }
+
+    output.flush().map_err(io_error)?;
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Key;
+
+    fn cipher(mode: Mode) -> Cipher {
+        Cipher::new(mode, Key::from_bytes([7u8; 32]))
+    }
+
+    // Two full chunks and a short final one
+    fn content() -> Vec<u8> {
+        (0..2 * MIN_CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encrypt(mode: Mode) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        encrypt_stream(&cipher(mode), MIN_CHUNK_SIZE, content().as_slice(), &mut encrypted).unwrap();
+        encrypted
+    }
+
+    fn decrypt(mode: Mode, encrypted: &[u8]) -> Result<Vec<u8>, RufendecError> {
+        let mut decrypted = Vec::new();
+        decrypt_stream(&cipher(mode), encrypted, &mut decrypted).map(|_| decrypted)
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        for mode in [Mode::GCM, Mode::ECB] {
+            assert_eq!(decrypt(mode, &encrypt(mode)).unwrap(), content());
+        }
+
+        let mut encrypted = Vec::new();
+        encrypt_stream(&cipher(Mode::GCM), MIN_CHUNK_SIZE, &[][..], &mut encrypted).unwrap();
+        assert!(decrypt(Mode::GCM, &encrypted).unwrap().is_empty());
+    }
+
+    #[test]
+    fn modified_stream_is_rejected() {
+        for mode in [Mode::GCM, Mode::ECB] {
+            let mut encrypted = encrypt(mode);
+            let last = encrypted.len() - 1;
+            encrypted[last] ^= 1;
+
+            assert!(decrypt(mode, &encrypted).is_err());
+        }
+    }
+
+    #[test]
+    fn modified_header_is_rejected() {
+        let mut encrypted = encrypt(Mode::GCM);
+        // A byte of the id of the stream, which every chunk is bound to
+        encrypted[HEADER_LEN - 1] ^= 1;
+
+        assert!(matches!(decrypt(Mode::GCM, &encrypted), Err(RufendecError::Credentials(_))));
+    }
+
+    #[test]
+    fn truncated_stream_is_rejected() {
+        for mode in [Mode::GCM, Mode::ECB] {
+            let encrypted = encrypt(mode);
+            // Only the header and the first chunk
+            let first_chunk_end = HEADER_LEN + 5 + cipher(mode).encrypted_len(MIN_CHUNK_SIZE);
+
+            assert!(matches!(decrypt(mode, &encrypted[..first_chunk_end]), Err(RufendecError::Runtime(_))));
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_a_credentials_error() {
+        let encrypted = encrypt(Mode::GCM);
+        let other = Cipher::new(Mode::GCM, Key::from_bytes([8u8; 32]));
+
+        assert!(matches!(decrypt_stream(&other, encrypted.as_slice(), Vec::new()), Err(RufendecError::Credentials(_))));
+    }
+}
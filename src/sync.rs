@@ -0,0 +1,374 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    common::{decode_path, encode_path, relative_path, sha256_hex},
+    error::RufendecError,
+    job::Encryptor,
+    log::{log, LogLevel},
+    operations::{write_atomic, Cipher, SourceTree},
+    report::{FileResult, RunReport, Stage},
+};
+
+/* How does the sync work?
+-----------------------
+The target directory holds a state database named SYNC_DB_FILE_NAME. It is encrypted with the same key and mode as the files.
+For every source file it remembers the size, the modified time, the SHA256 hash of the content and the name of the encrypted file.
+On the next run, a file whose size and modified time did not change is skipped. If they changed, the content hash decides whether
+the file really needs to be encrypted again. Entries whose source file is gone are removed along with their encrypted file when prune is chosen.
+An encrypted file which cannot be pruned is a failure of the run, and its entry is kept so the next sync tries again.
+Files excluded by the filter are left alone, and so are their entries.
+*/
+
+pub const SYNC_DB_FILE_NAME: &str = ".rufendec-sync.db";
+const SYNC_DB_HEADER: &str = "rufendec-sync 1";
+
+struct SyncEntry {
+    size: u64,
+    modified: u128,
+    hash: String,
+    encrypted_file: PathBuf,
+}
+
+#[derive(Default, Debug, Serialize)]
+pub struct SyncSummary {
+    pub added: u64,
+    pub modified: u64,
+    pub unchanged: u64,
+    pub pruned: u64,
+}
+
+// The file size and the modified time (in nanoseconds since the UNIX epoch) used for the quick change detection
+fn file_stamp(path: &Path) -> Option<(u64, u128)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    Some((metadata.len(), modified))
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    Some(sha256_hex(&fs::read(path).ok()?))
+}
+
+fn load_sync_db(db_path: &Path, cipher: &Cipher) -> Result<BTreeMap<PathBuf, SyncEntry>, RufendecError> {
+    let mut state = BTreeMap::new();
+
+    let Ok(encrypted_db) = fs::read(db_path) else {
+        return Ok(state);
+    };
+
+    let Some(db) = cipher.decrypt_bytes(&encrypted_db).and_then(|db| String::from_utf8(db).ok())
+    else {
+        return Err(RufendecError::Credentials(format!("Failed to open the sync state database {:?}.\n\nPlease check whether you provided the same password, salt, hashing function and mode you used for the earlier syncs", db_path)));
+    };
+
+    let mut lines = db.lines();
+
+    if lines.next() != Some(SYNC_DB_HEADER) {
+        return Err(RufendecError::Validation(format!("The sync state database {:?} has an unknown format", db_path)));
+    }
+
+    for line in lines {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        let entry = match fields.as_slice() {
+            [path, size, modified, hash, encrypted_file] => (|| {
+                let path = decode_path(path)?;
+                let encrypted_file = decode_path(encrypted_file)?;
+
+                Some((
+                    path,
+                    SyncEntry {
+                        size: size.parse().ok()?,
+                        modified: modified.parse().ok()?,
+                        hash: hash.to_string(),
+                        encrypted_file,
+                    },
+                ))
+            })(),
+            _ => None,
+        };
+
+        match entry {
+            Some((path, entry)) => {
+                state.insert(path, entry);
+            }
+            None => {
+                return Err(RufendecError::Validation(format!("The sync state database {:?} is corrupted", db_path)));
+            }
+        }
+    }
+
+    Ok(state)
+}
+
+fn save_sync_db(db_path: &Path, cipher: &Cipher, state: &BTreeMap<PathBuf, SyncEntry>) {
+    let mut db = String::from(SYNC_DB_HEADER);
+
+    for (path, entry) in state {
+        db.push_str(&format!(
+            "\n{}\t{}\t{}\t{}\t{}",
+            encode_path(path),
+            entry.size,
+            entry.modified,
+            entry.hash,
+            encode_path(&entry.encrypted_file)
+        ));
+    }
+
+    match cipher.encrypt_bytes(db.as_bytes()) {
+        Some(encrypted_db) => {
+            if let Err(e) = write_atomic(db_path, &encrypted_db) {
+                log(LogLevel::ERROR, format!("Failed to write the sync state database :: {}", e).as_str());
+            }
+        }
+        None => log(LogLevel::ERROR, "Failed to encrypt the sync state database"),
+    }
+}
+
+/* Compares the files of the source tree against the state database in the target directory and encrypts only the new or changed files.
+Encrypted files of deleted source files are removed if prune is true. The state database is written back at the end.
+Returns the summary and the report of the run, which also holds the files that could not be read.
+*/
+pub fn sync_files(
+    encryptor: &Encryptor,
+    tree: &SourceTree,
+    prune: bool,
+) -> Result<(SyncSummary, RunReport), RufendecError> {
+    let source_dir = encryptor.source_dir();
+    let target_dir = encryptor.target_dir();
+    let db_path = target_dir.join(SYNC_DB_FILE_NAME);
+    let dry_run = encryptor.dry_run();
+
+    let mut summary = SyncSummary::default();
+    let mut unreadable: Vec<FileResult> = Vec::new();
+    let mut state = load_sync_db(&db_path, encryptor.cipher())?;
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut pending: Vec<(PathBuf, PathBuf, u64, u128, String)> = Vec::new();
+
+    for file in tree.files.iter().cloned() {
+        let path = relative_path(&file, source_dir);
+        seen.insert(path.clone());
+
+        let Some((size, modified)) = file_stamp(&file) else {
+            unreadable.push(FileResult::failure(&file, Stage::Read, "Failed to read the file metadata", None, None));
+            continue;
+        };
+
+        let previous = state.get_mut(&path);
+
+        // Skip the files whose size and modified time are unchanged, as long as their encrypted file still exists
+        if let Some(entry) = &previous {
+            if entry.size == size
+                && entry.modified == modified
+                && target_dir.join(&entry.encrypted_file).exists()
+            {
+                summary.unchanged += 1;
+                continue;
+            }
+        }
+
+        let Some(hash) = file_hash(&file) else {
+            unreadable.push(FileResult::failure(&file, Stage::Read, "Failed to read the file", None, None));
+            continue;
+        };
+
+        // The file was touched but the content is the same. Only remember the new size and modified time
+        if let Some(entry) = previous {
+            if entry.hash == hash && target_dir.join(&entry.encrypted_file).exists() {
+                entry.size = size;
+                entry.modified = modified;
+                summary.unchanged += 1;
+                continue;
+            }
+        }
+
+        pending.push((file, path, size, modified, hash));
+    }
+
+    let mut report = RunReport::default();
+
+    if !pending.is_empty() {
+        encryptor.create_target_dirs(&tree.dirs);
+
+        report = encryptor.encrypt_files(&pending.iter().map(|p| p.0.clone()).collect::<Vec<PathBuf>>())?;
+    }
+
+    let outputs: BTreeMap<PathBuf, PathBuf> = report
+        .outputs()
+        .map(|(source, target)| (source.to_path_buf(), target.to_path_buf()))
+        .collect();
+
+    for (file, path, size, modified, hash) in pending {
+        if dry_run {
+            if state.contains_key(&path) {
+                summary.modified += 1;
+            } else {
+                summary.added += 1;
+            }
+            continue;
+        }
+
+        // The failure is already in the run report. The older entry is kept, so the file is picked up again on the next sync
+        let Some(encrypted_file) = outputs.get(&file) else {
+            continue;
+        };
+
+        let encrypted_file = relative_path(encrypted_file, target_dir);
+
+        let previous = state.insert(
+            path,
+            SyncEntry {
+                size,
+                modified,
+                hash,
+                encrypted_file: encrypted_file.clone(),
+            },
+        );
+
+        match previous {
+            Some(previous) => {
+                // Anonymized files get a new random name every time, so the older encrypted file must go
+                if previous.encrypted_file != encrypted_file {
+                    let _ = fs::remove_file(target_dir.join(previous.encrypted_file));
+                }
+                summary.modified += 1;
+            }
+            None => summary.added += 1,
+        }
+    }
+
+    if prune {
+        // A file which is only excluded now is not deleted, so its encrypted file is kept
+        let deleted: Vec<PathBuf> = state
+            .keys()
+            .filter(|path| !seen.contains(*path) && !source_dir.join(path).exists())
+            .cloned()
+            .collect();
+
+        for path in deleted {
+            let entry = state.remove(&path).unwrap();
+            let encrypted_file = target_dir.join(&entry.encrypted_file);
+
+            log(
+                LogLevel::INFO,
+                format!("Pruning the encrypted file of the deleted source file => {:?}", path).as_str(),
+            );
+
+            if !dry_run {
+                if let Err(e) = fs::remove_file(&encrypted_file) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        // The entry is kept, so the file is pruned again on the next sync
+                        let cause = format!("Failed to prune the file :: {}", e);
+                        report.record(FileResult::failure(&encrypted_file, Stage::Delete, cause, None, None));
+                        state.insert(path, entry);
+                        continue;
+                    }
+                }
+            }
+
+            summary.pruned += 1;
+        }
+    }
+
+    if !dry_run {
+        save_sync_db(&db_path, encryptor.cipher(), &state);
+    }
+
+    for failure in unreadable {
+        report.record(failure);
+    }
+
+    report.record_excluded(&tree.excluded);
+
+    Ok((summary, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{operations::scan, secrets::Key};
+
+    fn sync(source: &Path, target: &Path, prune: bool) -> (SyncSummary, RunReport) {
+        let encryptor = Encryptor::builder().source_dir(source).target_dir(target).key(Key::from_bytes([7u8; 32])).build().unwrap();
+        sync_files(&encryptor, &scan(source), prune).unwrap()
+    }
+
+    fn dirs() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let (source, target) = (root.path().join("source"), root.path().join("target"));
+
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join("a.txt"), b"first").unwrap();
+        fs::write(source.join("nested").join("b.txt"), b"second").unwrap();
+
+        (root, source, target)
+    }
+
+    #[test]
+    fn unchanged_files_are_skipped() {
+        let (_root, source, target) = dirs();
+
+        let (summary, report) = sync(&source, &target, false);
+        assert_eq!((summary.added, report.success_count), (2, 2));
+        assert!(target.join(SYNC_DB_FILE_NAME).exists());
+
+        let (summary, report) = sync(&source, &target, false);
+        assert_eq!((summary.added, summary.modified, summary.unchanged, report.success_count), (0, 0, 2, 0));
+
+        fs::write(source.join("a.txt"), b"first, changed").unwrap();
+
+        let (summary, report) = sync(&source, &target, false);
+        assert_eq!((summary.modified, summary.unchanged, report.success_count), (1, 1, 1));
+    }
+
+    #[test]
+    fn deleted_sources_are_pruned() {
+        let (_root, source, target) = dirs();
+        sync(&source, &target, false);
+        fs::remove_file(source.join("a.txt")).unwrap();
+
+        // Without prune the encrypted file stays
+        let (summary, _) = sync(&source, &target, false);
+        assert_eq!(summary.pruned, 0);
+        assert!(target.join("a.txt.enom").exists());
+
+        let (summary, report) = sync(&source, &target, true);
+        assert_eq!((summary.pruned, report.failed_count), (1, 0));
+        assert!(!target.join("a.txt.enom").exists());
+        assert!(target.join("nested").join("b.txt.enom").exists());
+    }
+
+    #[test]
+    fn failed_prune_is_a_failure() {
+        let (_root, source, target) = dirs();
+        sync(&source, &target, false);
+        fs::remove_file(source.join("a.txt")).unwrap();
+
+        // A directory in the place of the encrypted file cannot be removed as a file
+        fs::remove_file(target.join("a.txt.enom")).unwrap();
+        fs::create_dir_all(target.join("a.txt.enom").join("kept")).unwrap();
+
+        let (summary, report) = sync(&source, &target, true);
+        assert_eq!((summary.pruned, report.failed_count), (0, 1));
+        assert_eq!(report.failures().next().unwrap().stage, Some(Stage::Delete));
+
+        // The entry was kept, so the next sync tries again
+        fs::remove_dir_all(target.join("a.txt.enom")).unwrap();
+        let (summary, report) = sync(&source, &target, true);
+        assert_eq!((summary.pruned, report.failed_count), (1, 0));
+    }
+}
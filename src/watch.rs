@@ -0,0 +1,338 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use inotify::{EventMask, Inotify, WatchMask};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use walkdir::WalkDir;
+
+use rufendec::{
+    error::RufendecError,
+    filter::{Filter, IGNORE_FILE_NAME},
+    log::{log, LogLevel},
+    report::RunReport,
+    Encryptor,
+};
+
+use crate::config::WatchOptions;
+
+/* How does the watch work?
+-----------------------
+Every directory under the source directory gets an inotify watch. A file which is created, written, or moved into a watched directory
+is remembered along with its size and modified time. Once no event arrived for the file during the debounce period and its size and
+modified time are still the same, the file is considered stable and handed over to the Encryptor together with the other stable files.
+The key stays in memory until SIGINT or SIGTERM is received, after which the caller drops the Encryptor to clear it.
+*/
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+struct PendingFile {
+    last_event: Instant,
+    stamp: Option<(u64, SystemTime)>,
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+// Adds a watch to the directory and to all its sub-directories.
+// Files found in a directory which appeared after the watcher started are queued, since their events happened before the watch existed
+fn add_watches(
+    inotify: &mut Inotify,
+    dir: &Path,
+    watched_dirs: &mut HashMap<i32, PathBuf>,
+    pending: &mut HashMap<PathBuf, PendingFile>,
+    queue_files: bool,
+) {
+    let mask = WatchMask::CREATE
+        | WatchMask::MODIFY
+        | WatchMask::CLOSE_WRITE
+        | WatchMask::MOVED_TO
+        | WatchMask::MOVED_FROM
+        | WatchMask::DELETE;
+
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path().to_path_buf();
+
+        if entry.file_type().is_dir() {
+            match inotify.watches().add(&path, mask) {
+                Ok(wd) => {
+                    watched_dirs.insert(wd.get_watch_descriptor_id(), path);
+                }
+                Err(e) => log(
+                    LogLevel::WARN,
+                    format!("Failed to watch the directory {:?} :: {}", path, e).as_str(),
+                ),
+            }
+        } else if queue_files && entry.file_type().is_file() {
+            pending.insert(
+                path.clone(),
+                PendingFile {
+                    last_event: Instant::now(),
+                    stamp: file_stamp(&path),
+                },
+            );
+        }
+    }
+}
+
+// Removes and returns the pending files which did not change during the debounce period
+fn take_stable_files(
+    pending: &mut HashMap<PathBuf, PendingFile>,
+    debounce: Duration,
+) -> Vec<PathBuf> {
+    let mut stable = Vec::new();
+
+    pending.retain(|path, file| {
+        if file.last_event.elapsed() < debounce {
+            return true;
+        }
+
+        match file_stamp(path) {
+            // The file is gone or is not a regular file anymore
+            None => false,
+            Some(stamp) if Some(stamp) == file.stamp => {
+                stable.push(path.clone());
+                false
+            }
+            Some(stamp) => {
+                file.stamp = Some(stamp);
+                file.last_event = Instant::now();
+                true
+            }
+        }
+    });
+
+    stable
+}
+
+/* Watches the source directory until SIGINT or SIGTERM is received, and encrypts the stable files into the target directory
+with the given Encryptor. Returns the report of every batch put together.
+*/
+pub fn watch_dir(encryptor: &Encryptor, options: &WatchOptions) -> Result<RunReport, RufendecError> {
+    let terminate = Arc::new(AtomicBool::new(false));
+
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, terminate.clone()).map_err(|e| {
+            RufendecError::Runtime(format!("Failed to register the signal handler :: {}", e))
+        })?;
+    }
+
+    let report = watch(encryptor, options, &terminate)?;
+
+    log(LogLevel::INFO, "Received a termination signal. Stopping the watcher");
+
+    Ok(report)
+}
+
+// Watches the source directory until terminate is set
+fn watch(encryptor: &Encryptor, options: &WatchOptions, terminate: &AtomicBool) -> Result<RunReport, RufendecError> {
+    let source_dir = options.source_dir.as_path();
+    let debounce = Duration::from_millis(options.debounce_ms);
+    let filter = Filter::new(source_dir, &options.include, &options.exclude)?;
+
+    let mut inotify = Inotify::init()
+        .map_err(|e| RufendecError::Runtime(format!("Failed to initialize inotify :: {}", e)))?;
+
+    let mut watched_dirs: HashMap<i32, PathBuf> = HashMap::new();
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+    let mut buffer = [0; 4096];
+    let mut total = RunReport::default();
+
+    add_watches(&mut inotify, source_dir, &mut watched_dirs, &mut pending, false);
+
+    log(
+        LogLevel::INFO,
+        format!("Watching {} directories under {:?}. Press Ctrl+C to stop", watched_dirs.len(), source_dir).as_str(),
+    );
+
+    while !terminate.load(Ordering::Relaxed) {
+        let mut new_dirs = Vec::new();
+
+        loop {
+            let events = match inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    return Err(RufendecError::Runtime(format!(
+                        "Failed to read the inotify events :: {}",
+                        e
+                    )));
+                }
+            };
+
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    log(LogLevel::WARN, "Too many file events at once. Some files may have been missed");
+                    continue;
+                }
+
+                if event.mask.contains(EventMask::IGNORED) {
+                    watched_dirs.remove(&event.wd.get_watch_descriptor_id());
+                    continue;
+                }
+
+                let (Some(dir), Some(name)) =
+                    (watched_dirs.get(&event.wd.get_watch_descriptor_id()), event.name)
+                else {
+                    continue;
+                };
+
+                let path = dir.join(name);
+
+                if event.mask.contains(EventMask::ISDIR) {
+                    if event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                        new_dirs.push(path);
+                    }
+                } else if event.mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+                    pending.remove(&path);
+                } else if path.extension() != Some("enom".as_ref()) {
+                    pending.insert(
+                        path.clone(),
+                        PendingFile {
+                            last_event: Instant::now(),
+                            stamp: file_stamp(&path),
+                        },
+                    );
+                }
+            }
+        }
+
+        for dir in new_dirs {
+            add_watches(&mut inotify, &dir, &mut watched_dirs, &mut pending, true);
+        }
+
+        let (excluded, stable_files): (Vec<PathBuf>, Vec<PathBuf>) = take_stable_files(&mut pending, debounce)
+            .into_iter()
+            .filter(|file| file.file_name() != Some(IGNORE_FILE_NAME.as_ref()) && !file.is_symlink())
+            .partition(|file| filter.is_file_excluded(source_dir, file));
+
+        for file in &excluded {
+            log(LogLevel::INFO, format!("Excluded {:?}", file).as_str());
+        }
+
+        total.record_excluded(&excluded);
+
+        if !stable_files.is_empty() {
+            total.merge(encrypt_stable_files(encryptor, stable_files)?);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(total)
+}
+
+fn encrypt_stable_files(
+    encryptor: &Encryptor,
+    stable_files: Vec<PathBuf>,
+) -> Result<RunReport, RufendecError> {
+    encryptor.create_target_dirs(
+        &stable_files
+            .iter()
+            .filter_map(|f| f.parent().map(Path::to_path_buf))
+            .collect::<Vec<PathBuf>>(),
+    );
+
+    // The failures are logged as soon as they happen
+    let report = encryptor.encrypt_files(&stable_files)?;
+
+    for (file, encrypted_file) in report.outputs() {
+        log(
+            LogLevel::INFO,
+            format!("Encrypted {:?} => {:?}", file, encrypted_file).as_str(),
+        );
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, Command};
+    use clap::Parser;
+    use rufendec::{operations::DecryptedContent, Decryptor, Key};
+
+    fn options(source_dir: &Path, target_dir: &Path) -> WatchOptions {
+        let args = ["rufendec", "watch", source_dir.to_str().unwrap(), target_dir.to_str().unwrap(), "-w", "100"];
+
+        match Args::parse_from(args).command {
+            Command::Watch(options) => options,
+            _ => unreachable!(),
+        }
+    }
+
+    // Waits up to ten seconds for the file to show up with the wanted content
+    fn wait_for(file: &Path, decrypt: impl Fn(&Path) -> Option<Vec<u8>>, wanted: &[u8]) -> bool {
+        let start = Instant::now();
+
+        while start.elapsed() < Duration::from_secs(10) {
+            if decrypt(file).as_deref() == Some(wanted) {
+                return true;
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        false
+    }
+
+    #[test]
+    fn created_and_modified_files_are_encrypted() {
+        let root = tempfile::tempdir().unwrap();
+        let (source_dir, target_dir) = (root.path().join("source"), root.path().join("target"));
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        let key = || Key::from_bytes([7u8; 32]);
+        let encryptor = Encryptor::builder().source_dir(&source_dir).target_dir(&target_dir).key(key()).build().unwrap();
+        let decryptor = Decryptor::builder().source_dir(&target_dir).key(key()).build().unwrap();
+        let decrypt = |file: &Path| match decryptor.decrypt_to_memory(file).ok()?.content {
+            DecryptedContent::File(content) => Some(content.to_vec()),
+            _ => None,
+        };
+
+        let options = options(&source_dir, &target_dir);
+        let terminate = AtomicBool::new(false);
+
+        let (report, created, modified, nested) = thread::scope(|scope| {
+            let watcher = scope.spawn(|| watch(&encryptor, &options, &terminate));
+            // The watches are added once the watcher runs
+            thread::sleep(Duration::from_millis(300));
+
+            fs::write(source_dir.join("new.txt"), b"created").unwrap();
+            let created = wait_for(&target_dir.join("new.txt.enom"), decrypt, b"created");
+
+            fs::write(source_dir.join("new.txt"), b"modified").unwrap();
+            let modified = wait_for(&target_dir.join("new.txt.enom"), decrypt, b"modified");
+
+            // A directory which appears later is watched as well
+            fs::create_dir_all(source_dir.join("nested")).unwrap();
+            fs::write(source_dir.join("nested").join("deep.txt"), b"nested").unwrap();
+            let nested = wait_for(&target_dir.join("nested").join("deep.txt.enom"), decrypt, b"nested");
+
+            terminate.store(true, Ordering::Relaxed);
+            (watcher.join().unwrap().unwrap(), created, modified, nested)
+        });
+
+        assert!(created && modified && nested);
+        assert_eq!((report.success_count, report.failed_count), (3, 0));
+    }
+}
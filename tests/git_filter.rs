@@ -0,0 +1,64 @@
+// Copyright (c) 2023 Venkatesh Omkaram
+
+use std::{fs, path::Path, process::Command};
+
+const SECRET: &[u8] = b"API_TOKEN=secret\n";
+
+fn run(program: &str, dir: &Path, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(program)
+        .current_dir(dir)
+        .args(args)
+        // Neither the global nor the system git configuration of the machine is read
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{} {:?} failed :: {}", program, args, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn git(dir: &Path, args: &[&str]) -> Vec<u8> {
+    run("git", dir, args)
+}
+
+fn rufendec(dir: &Path, args: &[&str]) -> Vec<u8> {
+    run(env!("CARGO_BIN_EXE_rufendec"), dir, args)
+}
+
+#[test]
+fn committed_blob_is_encrypted_and_checkout_is_plain() {
+    let root = tempfile::tempdir().unwrap();
+    let (repo, password_file) = (root.path().join("repo"), root.path().join("passwordfile.omk"));
+    let password_file = password_file.to_str().unwrap();
+
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(password_file, "a password\na salt for the key\n").unwrap();
+
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["config", "user.name", "Tester"]);
+    git(&repo, &["config", "user.email", "tester@example.com"]);
+    rufendec(&repo, &["git-filter", "init", "-f", password_file, "-x", "pbkdf2", "-i", "1000"]);
+
+    fs::write(repo.join(".gitattributes"), "secret.env filter=rufendec diff=rufendec\n").unwrap();
+    fs::write(repo.join("secret.env"), SECRET).unwrap();
+    fs::write(repo.join("plain.txt"), b"not marked\n").unwrap();
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "-q", "-m", "Add the files"]);
+
+    // The repository holds the cipher text of the marked file only
+    let blob = git(&repo, &["cat-file", "blob", "HEAD:secret.env"]);
+    assert!(blob.starts_with(b"\0RUFENDEC-GIT\0"));
+    assert!(!blob.windows(6).any(|window| window == b"secret"));
+    assert_eq!(git(&repo, &["cat-file", "blob", "HEAD:plain.txt"]), b"not marked\n");
+
+    // A checkout goes through the smudge filter, and the file shows as unchanged
+    fs::remove_file(repo.join("secret.env")).unwrap();
+    git(&repo, &["checkout", "--", "secret.env"]);
+    assert_eq!(fs::read(repo.join("secret.env")).unwrap(), SECRET);
+    assert!(git(&repo, &["status", "--porcelain"]).is_empty());
+
+    // Once locked, the working tree holds the cipher text
+    rufendec(&repo, &["git-filter", "lock"]);
+    assert_eq!(fs::read(repo.join("secret.env")).unwrap(), blob);
+}